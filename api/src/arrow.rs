@@ -0,0 +1,356 @@
+//! Compression of Arrow arrays and record batches, enabled by the `arrow` feature.
+//!
+//! A [Float32Array] or [Float64Array] is compressed straight from its values buffer, its
+//! validity bitmap is stored uncompressed in front of the stream. The decompressed values are
+//! moved into an Arrow [Buffer] without being copied.
+//!
+//! A compressed column is laid out as
+//!
+//! | bytes | content |
+//! |-------|---------|
+//! | 1 | element width, 4 for Float32 and 8 for Float64 |
+//! | 4 | length of the validity bitmap, 0 for a column without one |
+//! | n | validity bitmap |
+//! | .. | chimp stream |
+//!
+//! and a compressed [RecordBatch] as
+//!
+//! | bytes | content |
+//! |-------|---------|
+//! | 4 | magic `CHPA` |
+//! | 2 | container version |
+//! | 8 | row count |
+//! | 4 | column count |
+//! | .. | per column, its name length, name, nullability, length and compressed column |
+//!
+//! with every integer little endian. The metadata of the schema and its fields is not kept.
+use crate::codec::Codec;
+use arrow_array::{Array, ArrayRef, Float32Array, Float64Array, RecordBatch, RecordBatchOptions};
+use arrow_buffer::{BooleanBuffer, Buffer, NullBuffer, ScalarBuffer};
+use arrow_schema::{DataType, Field, Schema};
+use compress_utils::error::ChimpError;
+use compress_utils::format::{FormatError, ValueType, FORMAT_VERSION};
+use std::sync::Arc;
+
+/// The magic bytes that start a compressed [RecordBatch]
+pub const ARROW_MAGIC: [u8; 4] = *b"CHPA";
+
+impl Codec {
+    /// Compress a [Float32Array] or [Float64Array], the codec has to match the element width
+    /// of the array
+    pub async fn compress_array(&self, array: &dyn Array) -> Result<Vec<u8>, ChimpError> {
+        let (width, stream) = match array.data_type() {
+            DataType::Float32 => {
+                let array = array.as_any().downcast_ref::<Float32Array>().unwrap();
+                (4u8, self.compress_f32(array.values()).await?)
+            }
+            DataType::Float64 => {
+                let array = array.as_any().downcast_ref::<Float64Array>().unwrap();
+                (8u8, self.compress_f64(array.values()).await?)
+            }
+            other => return Err(ChimpError::UnsupportedArrowType(other.to_string())),
+        };
+        let validity = array
+            .nulls()
+            .map(|nulls| nulls.inner().sliced())
+            .unwrap_or_else(|| Buffer::from_vec(Vec::<u8>::new()));
+        let stream = stream.compressed_values();
+
+        let mut column = Vec::with_capacity(1 + 4 + validity.len() + stream.len());
+        column.push(width);
+        column.extend((validity.len() as u32).to_le_bytes());
+        column.extend(validity.as_slice());
+        column.extend(stream);
+        Ok(column)
+    }
+
+    /// Decompress a column written by [Codec::compress_array]
+    pub async fn decompress_array(&self, column: &[u8]) -> Result<ArrayRef, ChimpError> {
+        let mut reader = ByteReader::new(column);
+        let width = reader.u8()?;
+        let validity_len = reader.u32()? as usize;
+        let validity = reader.take(validity_len)?;
+        let stream = reader.rest();
+
+        let array: ArrayRef = match width {
+            4 => {
+                let values = self.decompress_f32(stream).await?.un_compressed_value();
+                let nulls = null_buffer(validity, values.len(), &reader)?;
+                Arc::new(Float32Array::new(into_scalar_buffer(values), nulls))
+            }
+            8 => {
+                let values = self.decompress_f64(stream).await?.un_compressed_value();
+                let nulls = null_buffer(validity, values.len(), &reader)?;
+                Arc::new(Float64Array::new(into_scalar_buffer(values), nulls))
+            }
+            _ => return Err(reader.corrupted()),
+        };
+        Ok(array)
+    }
+}
+
+/// Compresses every column of a [RecordBatch], the Float32 ones with [RecordBatchCodec::f32]
+/// and the Float64 ones with [RecordBatchCodec::f64]
+pub struct RecordBatchCodec {
+    f32: Codec,
+    f64: Codec,
+}
+
+impl RecordBatchCodec {
+    pub fn new(f32: Codec, f64: Codec) -> Result<Self, ChimpError> {
+        for (codec, value_type) in [(&f32, ValueType::F32), (&f64, ValueType::F64)] {
+            let variant = codec.variant();
+            if variant.element_width() != value_type.width() {
+                return Err(ChimpError::WrongElementWidth {
+                    codec: variant,
+                    width: variant.element_width(),
+                });
+            }
+            if variant.value_type() != value_type {
+                return Err(ChimpError::WrongValueType {
+                    codec: variant,
+                    value_type: variant.value_type(),
+                });
+            }
+        }
+        Ok(Self { f32, f64 })
+    }
+
+    pub fn f32(&self) -> &Codec {
+        &self.f32
+    }
+
+    pub fn f64(&self) -> &Codec {
+        &self.f64
+    }
+
+    pub async fn compress(&self, batch: &RecordBatch) -> Result<Vec<u8>, ChimpError> {
+        let mut container = Vec::new();
+        container.extend(ARROW_MAGIC);
+        container.extend(FORMAT_VERSION.to_le_bytes());
+        container.extend((batch.num_rows() as u64).to_le_bytes());
+        container.extend((batch.num_columns() as u32).to_le_bytes());
+        for (field, array) in batch.schema().fields().iter().zip(batch.columns()) {
+            let codec = match field.data_type() {
+                DataType::Float64 => &self.f64,
+                _ => &self.f32,
+            };
+            let column = codec.compress_array(array.as_ref()).await?;
+            container.extend((field.name().len() as u32).to_le_bytes());
+            container.extend(field.name().as_bytes());
+            container.push(field.is_nullable() as u8);
+            container.extend((column.len() as u64).to_le_bytes());
+            container.extend(column);
+        }
+        Ok(container)
+    }
+
+    pub async fn decompress(&self, container: &[u8]) -> Result<RecordBatch, ChimpError> {
+        let mut reader = ByteReader::new(container);
+        if container.len() < ARROW_MAGIC.len() {
+            return Err(FormatError::TooShort {
+                len: container.len(),
+            }
+            .into());
+        }
+        if reader.take(ARROW_MAGIC.len())? != ARROW_MAGIC {
+            return Err(FormatError::BadMagic.into());
+        }
+        let version = reader.u16()?;
+        if version > FORMAT_VERSION {
+            return Err(ChimpError::UnsupportedVersion(version));
+        }
+        let row_count = reader.u64()? as usize;
+        let column_count = reader.u32()? as usize;
+
+        let mut fields = Vec::with_capacity(column_count);
+        let mut columns = Vec::with_capacity(column_count);
+        for column_index in 0..column_count {
+            reader.block = column_index;
+            let name_len = reader.u32()? as usize;
+            let name = String::from_utf8(reader.take(name_len)?.to_vec())
+                .map_err(|_| reader.corrupted())?;
+            let nullable = reader.u8()? != 0;
+            let column_len = reader.u64()? as usize;
+            let column = reader.take(column_len)?;
+            let codec = match column.first() {
+                Some(8) => &self.f64,
+                _ => &self.f32,
+            };
+            let array = codec.decompress_array(column).await?;
+            if array.len() != row_count || (!nullable && array.null_count() > 0) {
+                return Err(reader.corrupted());
+            }
+            fields.push(Field::new(name, array.data_type().clone(), nullable));
+            columns.push(array);
+        }
+        let options = RecordBatchOptions::new().with_row_count(Some(row_count));
+        RecordBatch::try_new_with_options(Arc::new(Schema::new(fields)), columns, &options)
+            .map_err(|_| reader.corrupted())
+    }
+}
+
+/// Move [values] into an Arrow [Buffer], which takes over their allocation
+fn into_scalar_buffer<T: arrow_buffer::ArrowNativeType>(values: Vec<T>) -> ScalarBuffer<T> {
+    let len = values.len();
+    ScalarBuffer::new(Buffer::from_vec(values), 0, len)
+}
+
+fn null_buffer(
+    validity: &[u8],
+    len: usize,
+    reader: &ByteReader,
+) -> Result<Option<NullBuffer>, ChimpError> {
+    if validity.is_empty() {
+        return Ok(None);
+    }
+    if validity.len() < len.div_ceil(8) {
+        return Err(reader.corrupted());
+    }
+    let bits = BooleanBuffer::new(Buffer::from(validity), 0, len);
+    Ok(Some(NullBuffer::new(bits)))
+}
+
+/// Reads the little endian fields of a container, a read past its end is reported as a
+/// corruption of the current column
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    block: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+            block: 0,
+        }
+    }
+
+    fn corrupted(&self) -> ChimpError {
+        ChimpError::CorruptInput {
+            block: self.block,
+            byte_offset: self.offset,
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ChimpError> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| self.corrupted())?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let bytes = &self.bytes[self.offset..];
+        self.offset = self.bytes.len();
+        bytes
+    }
+
+    fn u8(&mut self) -> Result<u8, ChimpError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ChimpError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, ChimpError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, ChimpError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factory::CompressorBuilder;
+    use crate::GPUMode;
+    use arrow_array::Int32Array;
+    use compress_utils::format::CodecVariant;
+
+    async fn host_codec(kind: CodecVariant) -> Codec {
+        CompressorBuilder::new()
+            .with_buffer_size(256)
+            .unwrap()
+            .with_gpu_mode(GPUMode::CPU)
+            .await
+            .unwrap()
+            .build(kind)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_array_with_validity_round_trip() {
+        let values = (0..1000)
+            .map(|it| (it % 7 != 0).then_some((it as f32 / 10.0).sin() * 100.0))
+            .collect::<Float32Array>();
+        // a sliced array starts in the middle of its values and validity buffers
+        let array = values.slice(13, 900);
+        let codec = host_codec(CodecVariant::Chimp32).await;
+
+        let column = codec.compress_array(&array).await.unwrap();
+        let decompressed = codec.decompress_array(&column).await.unwrap();
+        let decompressed = decompressed
+            .as_any()
+            .downcast_ref::<Float32Array>()
+            .unwrap();
+        assert_eq!(decompressed, &array);
+        assert_eq!(decompressed.null_count(), array.null_count());
+
+        let error = codec
+            .compress_array(&Int32Array::from(vec![1, 2, 3]))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ChimpError::UnsupportedArrowType(_)));
+    }
+
+    #[tokio::test]
+    async fn test_record_batch_round_trip() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("temperature", DataType::Float32, false),
+            Field::new("pressure", DataType::Float64, true),
+        ]));
+        let temperature = (0..3000)
+            .map(|it| (it as f32 / 10.0).cos() * 20.0)
+            .collect::<Float32Array>();
+        let pressure = (0..3000)
+            .map(|it| (it % 11 != 0).then_some(1000.0 + (it as f64 / 50.0).sin()))
+            .collect::<Float64Array>();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(temperature), Arc::new(pressure)],
+        )
+        .unwrap();
+
+        let codec = RecordBatchCodec::new(
+            host_codec(CodecVariant::ChimpN32).await,
+            host_codec(CodecVariant::Chimp64).await,
+        )
+        .unwrap();
+        let container = codec.compress(&batch).await.unwrap();
+        assert_eq!(container[..4], ARROW_MAGIC);
+        let decompressed = codec.decompress(&container).await.unwrap();
+        assert_eq!(decompressed.schema(), schema);
+        assert_eq!(decompressed, batch);
+
+        let error = codec
+            .decompress(&container[..container.len() - 10])
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ChimpError::CorruptInput { block: 1, .. }));
+        assert!(RecordBatchCodec::new(
+            host_codec(CodecVariant::Chimp64).await,
+            host_codec(CodecVariant::Chimp64).await,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use compress_utils::cpu_compress::{Compressor, Decompressor};
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, ValueType};
+use compress_utils::general_utils::{CompressResult, DecompressResult};
+use compress_utils::integer::decompress_integers;
+use wgpu_compress_16_batched::cpu::decompressor::BatchedDecompressor16Cpu;
+use wgpu_compress_16_batched::decompressor::BatchedGPUDecompressor16;
+use wgpu_compress_16_batched::{ChimpCompressorBatched16, ChimpCompressorBatched16Cpu};
+use wgpu_compress_32_batched::cpu::decompressor::BatchedDecompressorCpu;
+use wgpu_compress_32_batched::decompressor::BatchedGPUDecompressor;
+use wgpu_compress_32_batched::gorilla::cpu::decompressor::GorillaDecompressorCpu;
+use wgpu_compress_32_batched::gorilla::decompressor::GorillaGPUDecompressor;
+use wgpu_compress_32_batched::gorilla::{GorillaCompressorBatched, GorillaCompressorBatchedCpu};
+use wgpu_compress_32_batched::{ChimpCompressorBatched, ChimpCompressorBatchedCpu};
+use wgpu_compress_32_n_batched::chimpn::{ChimpNCpuBatched, ChimpNGPUBatched};
+use wgpu_compress_32_n_batched::cpu::decompressor::BatchedCPUNDecompressor;
+use wgpu_compress_32_n_batched::decompressor::BatchedGPUNDecompressor;
+use wgpu_compress_64_batched::cpu::decompressor::CPUDecompressorBatched64;
+use wgpu_compress_64_batched::decompressor::ChimpDecompressorBatched64;
+use wgpu_compress_64_batched::{ChimpCompressorBatched64, ChimpCompressorBatched64Cpu};
+use wgpu_compress_64_n_batched::chimpn::{ChimpN64CpuBatched, ChimpN64GPUBatched};
+use wgpu_compress_64_n_batched::cpu::decompressor::BatchedCPUN64Decompressor;
+use wgpu_compress_64_n_batched::decompressor::GPUDecompressorBatchedN64;
+use wgpu_compress_timestamps::cpu::decompressor::TimestampDecompressorCpu;
+use wgpu_compress_timestamps::decompressor::TimestampDecompressor;
+use wgpu_compress_timestamps::{TimestampCompressor, TimestampCompressorCpu};
+
+/// Where the compressor and decompressor of a [Codec] run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionPath {
+    Gpu,
+    Host,
+}
+
+/// A compressor and decompressor pair whose variant is chosen at runtime, built by
+/// [crate::factory::CompressorBuilder::build].
+///
+/// The f32, i32 and u32 methods fail with [ChimpError::WrongElementWidth] on the 64 bit
+/// variants and the f64, i64 and u64 methods on the 32 bit ones, a method for values of the
+/// right width but the wrong type fails with [ChimpError::WrongValueType]. The Chimp
+/// variants take integers of their width, see [compress_utils::integer]. The 16 bit variant
+/// takes `half::f16` and `half::bf16` values with the `half` feature.
+pub enum Codec {
+    Chimp16(ChimpCompressorBatched16, BatchedGPUDecompressor16),
+    Chimp32(ChimpCompressorBatched, BatchedGPUDecompressor),
+    Chimp64(ChimpCompressorBatched64, ChimpDecompressorBatched64),
+    ChimpN32(ChimpNGPUBatched, BatchedGPUNDecompressor),
+    ChimpN64(ChimpN64GPUBatched, GPUDecompressorBatchedN64),
+    DeltaOfDelta64(TimestampCompressor, TimestampDecompressor),
+    Gorilla32(GorillaCompressorBatched, GorillaGPUDecompressor),
+    Chimp16Cpu(ChimpCompressorBatched16Cpu, BatchedDecompressor16Cpu),
+    Chimp32Cpu(ChimpCompressorBatchedCpu, BatchedDecompressorCpu),
+    Chimp64Cpu(ChimpCompressorBatched64Cpu, CPUDecompressorBatched64),
+    ChimpN32Cpu(ChimpNCpuBatched, BatchedCPUNDecompressor),
+    ChimpN64Cpu(ChimpN64CpuBatched, BatchedCPUN64Decompressor),
+    DeltaOfDelta64Cpu(TimestampCompressorCpu, TimestampDecompressorCpu),
+    Gorilla32Cpu(GorillaCompressorBatchedCpu, GorillaDecompressorCpu),
+}
+
+impl Codec {
+    pub fn variant(&self) -> CodecVariant {
+        match self {
+            Codec::Chimp16(..) | Codec::Chimp16Cpu(..) => CodecVariant::Chimp16,
+            Codec::Chimp32(..) | Codec::Chimp32Cpu(..) => CodecVariant::Chimp32,
+            Codec::Chimp64(..) | Codec::Chimp64Cpu(..) => CodecVariant::Chimp64,
+            Codec::ChimpN32(..) | Codec::ChimpN32Cpu(..) => CodecVariant::ChimpN32,
+            Codec::ChimpN64(..) | Codec::ChimpN64Cpu(..) => CodecVariant::ChimpN64,
+            Codec::DeltaOfDelta64(..) | Codec::DeltaOfDelta64Cpu(..) => {
+                CodecVariant::DeltaOfDelta64
+            }
+            Codec::Gorilla32(..) | Codec::Gorilla32Cpu(..) => CodecVariant::Gorilla32,
+        }
+    }
+
+    /// Whether the [crate::GPUMode] of the builder settled on the gpu or the host
+    pub fn path(&self) -> ExecutionPath {
+        match self {
+            Codec::Chimp16(..)
+            | Codec::Chimp32(..)
+            | Codec::Chimp64(..)
+            | Codec::ChimpN32(..)
+            | Codec::ChimpN64(..)
+            | Codec::DeltaOfDelta64(..)
+            | Codec::Gorilla32(..) => ExecutionPath::Gpu,
+            _ => ExecutionPath::Host,
+        }
+    }
+
+    pub async fn compress_f32(&self, values: &[f32]) -> Result<CompressResult, ChimpError> {
+        Ok(match self {
+            Codec::Chimp32(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN32(compressor, _) => compressor.compress(values).await?,
+            Codec::Chimp32Cpu(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN32Cpu(compressor, _) => compressor.compress(values).await?,
+            Codec::Gorilla32(compressor, _) => compressor.compress(values).await?,
+            Codec::Gorilla32Cpu(compressor, _) => compressor.compress(values).await?,
+            _ => return Err(self.wrong_value_type(ValueType::F32)),
+        })
+    }
+
+    pub async fn compress_f64(&self, values: &[f64]) -> Result<CompressResult, ChimpError> {
+        Ok(match self {
+            Codec::Chimp64(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN64(compressor, _) => compressor.compress(values).await?,
+            Codec::Chimp64Cpu(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN64Cpu(compressor, _) => compressor.compress(values).await?,
+            _ => return Err(self.wrong_value_type(ValueType::F64)),
+        })
+    }
+
+    pub async fn decompress_f32(&self, bytes: &[u8]) -> Result<DecompressResult<f32>, ChimpError> {
+        self.expect_float_stream(bytes, ValueType::F32)?;
+        Ok(match self {
+            Codec::Chimp32(_, decompressor) => decompressor.decompress(bytes).await?,
+            Codec::ChimpN32(_, decompressor) => decompressor.decompress(bytes).await?,
+            Codec::Chimp32Cpu(_, decompressor) => decompressor.decompress(bytes).await?,
+            Codec::ChimpN32Cpu(_, decompressor) => decompressor.decompress(bytes).await?,
+            Codec::Gorilla32(_, decompressor) => decompressor.decompress(bytes).await?,
+            Codec::Gorilla32Cpu(_, decompressor) => decompressor.decompress(bytes).await?,
+            _ => return Err(self.wrong_value_type(ValueType::F32)),
+        })
+    }
+
+    pub async fn decompress_f64(&self, bytes: &[u8]) -> Result<DecompressResult<f64>, ChimpError> {
+        self.expect_float_stream(bytes, ValueType::F64)?;
+        Ok(match self {
+            Codec::Chimp64(_, decompressor) => decompressor.decompress(bytes).await?,
+            Codec::ChimpN64(_, decompressor) => decompressor.decompress(bytes).await?,
+            Codec::Chimp64Cpu(_, decompressor) => decompressor.decompress(bytes).await?,
+            Codec::ChimpN64Cpu(_, decompressor) => decompressor.decompress(bytes).await?,
+            _ => return Err(self.wrong_value_type(ValueType::F64)),
+        })
+    }
+
+    pub async fn compress_i32(&self, values: &[i32]) -> Result<CompressResult, ChimpError> {
+        Ok(match self {
+            Codec::Chimp32(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN32(compressor, _) => compressor.compress(values).await?,
+            Codec::Chimp32Cpu(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN32Cpu(compressor, _) => compressor.compress(values).await?,
+            _ => return Err(self.wrong_value_type(ValueType::I32)),
+        })
+    }
+
+    pub async fn compress_u32(&self, values: &[u32]) -> Result<CompressResult, ChimpError> {
+        Ok(match self {
+            Codec::Chimp32(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN32(compressor, _) => compressor.compress(values).await?,
+            Codec::Chimp32Cpu(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN32Cpu(compressor, _) => compressor.compress(values).await?,
+            _ => return Err(self.wrong_value_type(ValueType::U32)),
+        })
+    }
+
+    pub async fn decompress_i32(&self, bytes: &[u8]) -> Result<DecompressResult<i32>, ChimpError> {
+        Ok(match self {
+            Codec::Chimp32(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::ChimpN32(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::Chimp32Cpu(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::ChimpN32Cpu(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            _ => return Err(self.wrong_value_type(ValueType::I32)),
+        })
+    }
+
+    pub async fn decompress_u32(&self, bytes: &[u8]) -> Result<DecompressResult<u32>, ChimpError> {
+        Ok(match self {
+            Codec::Chimp32(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::ChimpN32(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::Chimp32Cpu(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::ChimpN32Cpu(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            _ => return Err(self.wrong_value_type(ValueType::U32)),
+        })
+    }
+
+    pub async fn compress_i64(&self, values: &[i64]) -> Result<CompressResult, ChimpError> {
+        Ok(match self {
+            Codec::Chimp64(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN64(compressor, _) => compressor.compress(values).await?,
+            Codec::Chimp64Cpu(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN64Cpu(compressor, _) => compressor.compress(values).await?,
+            Codec::DeltaOfDelta64(compressor, _) => compressor.compress(values).await?,
+            Codec::DeltaOfDelta64Cpu(compressor, _) => compressor.compress(values).await?,
+            _ => return Err(self.wrong_value_type(ValueType::I64)),
+        })
+    }
+
+    pub async fn compress_u64(&self, values: &[u64]) -> Result<CompressResult, ChimpError> {
+        Ok(match self {
+            Codec::Chimp64(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN64(compressor, _) => compressor.compress(values).await?,
+            Codec::Chimp64Cpu(compressor, _) => compressor.compress(values).await?,
+            Codec::ChimpN64Cpu(compressor, _) => compressor.compress(values).await?,
+            _ => return Err(self.wrong_value_type(ValueType::U64)),
+        })
+    }
+
+    pub async fn decompress_i64(&self, bytes: &[u8]) -> Result<DecompressResult<i64>, ChimpError> {
+        Ok(match self {
+            Codec::Chimp64(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::ChimpN64(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::Chimp64Cpu(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::ChimpN64Cpu(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::DeltaOfDelta64(_, decompressor) => decompressor.decompress(bytes).await?,
+            Codec::DeltaOfDelta64Cpu(_, decompressor) => decompressor.decompress(bytes).await?,
+            _ => return Err(self.wrong_value_type(ValueType::I64)),
+        })
+    }
+
+    pub async fn decompress_u64(&self, bytes: &[u8]) -> Result<DecompressResult<u64>, ChimpError> {
+        Ok(match self {
+            Codec::Chimp64(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::ChimpN64(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::Chimp64Cpu(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            Codec::ChimpN64Cpu(_, decompressor) => decompress_integers(decompressor, bytes).await?,
+            _ => return Err(self.wrong_value_type(ValueType::U64)),
+        })
+    }
+
+    #[cfg(feature = "half")]
+    pub async fn compress_f16(&self, values: &[half::f16]) -> Result<CompressResult, ChimpError> {
+        Ok(match self {
+            Codec::Chimp16(compressor, _) => compressor.compress(values).await?,
+            Codec::Chimp16Cpu(compressor, _) => compressor.compress(values).await?,
+            _ => return Err(self.wrong_value_type(ValueType::F16)),
+        })
+    }
+
+    #[cfg(feature = "half")]
+    pub async fn decompress_f16(
+        &self,
+        bytes: &[u8],
+    ) -> Result<DecompressResult<half::f16>, ChimpError> {
+        Ok(match self {
+            Codec::Chimp16(_, decompressor) => decompressor.decompress(bytes).await?,
+            Codec::Chimp16Cpu(_, decompressor) => decompressor.decompress(bytes).await?,
+            _ => return Err(self.wrong_value_type(ValueType::F16)),
+        })
+    }
+
+    #[cfg(feature = "half")]
+    pub async fn compress_bf16(&self, values: &[half::bf16]) -> Result<CompressResult, ChimpError> {
+        Ok(match self {
+            Codec::Chimp16(compressor, _) => compressor.compress(values).await?,
+            Codec::Chimp16Cpu(compressor, _) => compressor.compress(values).await?,
+            _ => return Err(self.wrong_value_type(ValueType::BF16)),
+        })
+    }
+
+    #[cfg(feature = "half")]
+    pub async fn decompress_bf16(
+        &self,
+        bytes: &[u8],
+    ) -> Result<DecompressResult<half::bf16>, ChimpError> {
+        Ok(match self {
+            Codec::Chimp16(_, decompressor) => decompressor.decompress(bytes).await?,
+            Codec::Chimp16Cpu(_, decompressor) => decompressor.decompress(bytes).await?,
+            _ => return Err(self.wrong_value_type(ValueType::BF16)),
+        })
+    }
+
+    /// Fail like [decompress_integers] when [bytes] hold the integers of a float codec of
+    /// [value_type] rather than its floats
+    fn expect_float_stream(&self, bytes: &[u8], value_type: ValueType) -> Result<(), ChimpError> {
+        if self.variant().value_type() != value_type {
+            return Err(self.wrong_value_type(value_type));
+        }
+        let header = ChimpHeader::read(bytes)?;
+        if header.value_type() != value_type {
+            return Err(ChimpError::WrongValueType {
+                codec: header.codec,
+                value_type: header.value_type(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The error of a method for [value_type] values called on a codec of another type
+    fn wrong_value_type(&self, value_type: ValueType) -> ChimpError {
+        let codec = self.variant();
+        if codec.element_width() != value_type.width() {
+            return ChimpError::WrongElementWidth {
+                codec,
+                width: codec.element_width(),
+            };
+        }
+        ChimpError::WrongValueType {
+            codec,
+            value_type: codec.value_type(),
+        }
+    }
+}
@@ -1,22 +1,45 @@
+use crate::codec::Codec;
+use crate::GPUMode;
+use compress_utils::config::{ChimpConfig, ConfigError, DEFAULT_N};
 use compress_utils::context::Context;
-use compress_utils::general_utils::DeviceEnum;
+use compress_utils::error::ChimpError;
+use compress_utils::format::CodecVariant;
+use compress_utils::general_utils::{trace_steps, DeviceEnum};
+use compress_utils::integer::IntegerEncoding;
+use compress_utils::lossy::ErrorBound;
 use std::sync::Arc;
 use wgpu::Adapter;
+use wgpu_compress_16_batched::cpu::decompressor::BatchedDecompressor16Cpu;
+use wgpu_compress_16_batched::decompressor::BatchedGPUDecompressor16;
+use wgpu_compress_16_batched::{ChimpCompressorBatched16, ChimpCompressorBatched16Cpu};
+use wgpu_compress_32_batched::cpu::decompressor::BatchedDecompressorCpu;
 use wgpu_compress_32_batched::decompressor::BatchedGPUDecompressor;
-use wgpu_compress_32_batched::ChimpCompressorBatched;
-use wgpu_compress_32_n_batched::chimpn::ChimpNGPUBatched;
+use wgpu_compress_32_batched::gorilla::cpu::decompressor::GorillaDecompressorCpu;
+use wgpu_compress_32_batched::gorilla::decompressor::GorillaGPUDecompressor;
+use wgpu_compress_32_batched::gorilla::{GorillaCompressorBatched, GorillaCompressorBatchedCpu};
+use wgpu_compress_32_batched::{ChimpCompressorBatched, ChimpCompressorBatchedCpu};
+use wgpu_compress_32_n_batched::chimpn::{ChimpNCpuBatched, ChimpNGPUBatched};
+use wgpu_compress_32_n_batched::cpu::decompressor::BatchedCPUNDecompressor;
 use wgpu_compress_32_n_batched::decompressor::BatchedGPUNDecompressor;
+use wgpu_compress_64_batched::cpu::decompressor::CPUDecompressorBatched64;
 use wgpu_compress_64_batched::decompressor::ChimpDecompressorBatched64;
-use wgpu_compress_64_batched::ChimpCompressorBatched64;
-use wgpu_compress_64_n_batched::chimpn::ChimpN64GPUBatched;
+use wgpu_compress_64_batched::{ChimpCompressorBatched64, ChimpCompressorBatched64Cpu};
+use wgpu_compress_64_n_batched::chimpn::{ChimpN64CpuBatched, ChimpN64GPUBatched};
+use wgpu_compress_64_n_batched::cpu::decompressor::BatchedCPUN64Decompressor;
 use wgpu_compress_64_n_batched::decompressor::GPUDecompressorBatchedN64;
-use wgpu_types::{Backends, Limits};
+use wgpu_compress_timestamps::cpu::decompressor::TimestampDecompressorCpu;
+use wgpu_compress_timestamps::decompressor::TimestampDecompressor;
+use wgpu_compress_timestamps::{TimestampCompressor, TimestampCompressorCpu};
+use wgpu_types::Backends;
 
 // --- State Machine States ---
 #[derive(Debug)]
 pub struct NoAdapter;
 #[derive(Debug)]
 pub struct HasAdapter(pub Adapter);
+/// The context of the gpu chosen by a [GPUMode], [None] when the codecs run on the host
+#[derive(Debug)]
+pub struct HasMode(pub Option<Arc<Context>>);
 
 #[derive(Debug)]
 pub struct NoBufferSize;
@@ -33,6 +56,9 @@ pub struct CompressorBuilder<A, B, C> {
     adapter: A,
     buffer_size: B,
     n: C,
+    error_bound: Option<ErrorBound>,
+    integer_encoding: IntegerEncoding,
+    block_checksums: bool,
 }
 
 impl CompressorBuilder<NoAdapter, NoBufferSize, NoN> {
@@ -41,27 +67,50 @@ impl CompressorBuilder<NoAdapter, NoBufferSize, NoN> {
             adapter: NoAdapter,
             buffer_size: NoBufferSize,
             n: NoN,
+            error_bound: None,
+            integer_encoding: IntegerEncoding::default(),
+            block_checksums: false,
         }
     }
 }
 
+impl<A, B, C> CompressorBuilder<A, B, C> {
+    /// Compress lossily within [error_bound], see [compress_utils::lossy]
+    pub fn with_error_bound(mut self, error_bound: ErrorBound) -> Self {
+        self.error_bound = Some(error_bound);
+        self
+    }
+
+    /// How the integer methods of the built [Codec] encode their values, see
+    /// [compress_utils::integer]
+    pub fn with_integer_encoding(mut self, integer_encoding: IntegerEncoding) -> Self {
+        self.integer_encoding = integer_encoding;
+        self
+    }
+
+    /// Append a checksum of every block to the compressed streams, see
+    /// [ChimpConfig::with_block_checksums]
+    pub fn with_block_checksums(mut self, block_checksums: bool) -> Self {
+        self.block_checksums = block_checksums;
+        self
+    }
+}
+
 impl<B, C> CompressorBuilder<NoAdapter, B, C> {
-    pub async fn with_default_adapter(self) -> CompressorBuilder<HasAdapter, B, C> {
-        let instance = wgpu::Instance::default();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::LowPower,
-                compatible_surface: None,
-                force_fallback_adapter: false,
-            })
-            .await
-            .expect("Failed to find an appropriate adapter");
+    /// Run on the default adapter, failing with [ChimpError::NoAdapter] when there is none
+    pub async fn with_default_adapter(
+        self,
+    ) -> Result<CompressorBuilder<HasAdapter, B, C>, ChimpError> {
+        let adapter = default_adapter().await.ok_or(ChimpError::NoAdapter(None))?;
 
-        CompressorBuilder {
+        Ok(CompressorBuilder {
             adapter: HasAdapter(adapter),
             buffer_size: self.buffer_size,
             n: self.n,
-        }
+            error_bound: self.error_bound,
+            integer_encoding: self.integer_encoding,
+            block_checksums: self.block_checksums,
+        })
     }
 
     pub fn with_adapter(self, adapter: Adapter) -> CompressorBuilder<HasAdapter, B, C> {
@@ -69,155 +118,313 @@ impl<B, C> CompressorBuilder<NoAdapter, B, C> {
             adapter: HasAdapter(adapter),
             buffer_size: self.buffer_size,
             n: self.n,
+            error_bound: self.error_bound,
+            integer_encoding: self.integer_encoding,
+            block_checksums: self.block_checksums,
         }
     }
     pub async fn with_adapter_name(
         self,
-        adapter: &impl Into<String>,
-    ) -> Result<CompressorBuilder<HasAdapter, B, C>, String> {
-        let instance = wgpu::Instance::default();
-        let adapter = instance
-            .enumerate_adapters(Backends::VULKAN)
-            .into_iter()
-            .find(|a| a.get_name() == adapter.into());
-
-        if let None = adapter {
-            return Err("Failed to find an appropriate adapter".to_string());
-        }
+        adapter: impl Into<String>,
+    ) -> Result<CompressorBuilder<HasAdapter, B, C>, ChimpError> {
+        let name = adapter.into();
+        let adapter = find_adapter(&name).ok_or(ChimpError::NoAdapter(Some(name)))?;
+
         Ok(CompressorBuilder {
             adapter: HasAdapter(adapter),
             buffer_size: self.buffer_size,
             n: self.n,
+            error_bound: self.error_bound,
+            integer_encoding: self.integer_encoding,
+            block_checksums: self.block_checksums,
+        })
+    }
+
+    /// Choose where the codecs run. [GPUMode::GPUIfAvailable] falls back to the host when
+    /// the adapter is missing or cannot provide a device, [GPUMode::GPUMust] fails instead.
+    /// An empty adapter name selects the default adapter.
+    pub async fn with_gpu_mode(
+        self,
+        mode: GPUMode,
+    ) -> Result<CompressorBuilder<HasMode, B, C>, ChimpError> {
+        let context = match mode {
+            GPUMode::CPU => None,
+            GPUMode::GPUIfAvailable(name) => try_create_context(&name).await.ok(),
+            GPUMode::GPUMust(name) => Some(try_create_context(&name).await?),
+        };
+
+        Ok(CompressorBuilder {
+            adapter: HasMode(context),
+            buffer_size: self.buffer_size,
+            n: self.n,
+            error_bound: self.error_bound,
+            integer_encoding: self.integer_encoding,
+            block_checksums: self.block_checksums,
         })
     }
 }
 
+async fn default_adapter() -> Option<Adapter> {
+    let instance = wgpu::Instance::default();
+    instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::LowPower,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        })
+        .await
+        .ok()
+}
+
+fn find_adapter(name: &str) -> Option<Adapter> {
+    let instance = wgpu::Instance::default();
+    instance
+        .enumerate_adapters(Backends::VULKAN)
+        .into_iter()
+        .find(|a| a.get_info().name == name)
+}
+
+async fn try_create_context(name: &str) -> Result<Arc<Context>, ChimpError> {
+    let adapter = if name.is_empty() {
+        default_adapter().await.ok_or(ChimpError::NoAdapter(None))?
+    } else {
+        find_adapter(name).ok_or_else(|| ChimpError::NoAdapter(Some(name.to_string())))?
+    };
+    create_context(adapter).await
+}
+
 impl<A, C> CompressorBuilder<A, NoBufferSize, C> {
     pub fn with_buffer_size(
         self,
         size: u32,
-    ) -> Result<CompressorBuilder<A, HasBufferSize, C>, String> {
-        if size % 256 != 0 {
-            return Err("buffer_size must be a multiple of 256".to_string());
-        }
+    ) -> Result<CompressorBuilder<A, HasBufferSize, C>, ChimpError> {
+        ChimpConfig::default().with_buffer_size(size as usize)?;
         Ok(CompressorBuilder {
             adapter: self.adapter,
             buffer_size: HasBufferSize(size),
             n: self.n,
+            error_bound: self.error_bound,
+            integer_encoding: self.integer_encoding,
+            block_checksums: self.block_checksums,
         })
     }
 }
 impl<A, B> CompressorBuilder<A, B, NoN> {
-    pub fn with_n(self, n: u32) -> CompressorBuilder<A, B, HasN> {
-        CompressorBuilder {
+    pub fn with_n(self, n: u32) -> Result<CompressorBuilder<A, B, HasN>, ChimpError> {
+        ChimpConfig::default().with_n(n as usize)?;
+        Ok(CompressorBuilder {
             adapter: self.adapter,
             buffer_size: self.buffer_size,
             n: HasN(n),
-        }
+            error_bound: self.error_bound,
+            integer_encoding: self.integer_encoding,
+            block_checksums: self.block_checksums,
+        })
     }
 }
 
-async fn create_context(adapter: Adapter) -> Arc<Context> {
-    let (device, queue) = adapter
-        .request_device(&wgpu::DeviceDescriptor {
-            label: None,
-            required_features: wgpu::Features::SHADER_F64 | wgpu::Features::SHADER_INT64,
-            required_limits: Limits {
-                max_storage_buffer_binding_size: adapter.limits().max_storage_buffer_binding_size,
-                max_buffer_size: adapter.limits().max_buffer_size,
-                ..Limits::downlevel_defaults()
-            },
-            memory_hints: wgpu::MemoryHints::MemoryUsage,
-            trace: wgpu_types::Trace::Off,
-        })
-        .await
-        .expect("Failed to request a device");
+/// The `n` chosen on the builder, if any
+pub trait NState {
+    fn n(&self) -> Option<u32>;
+}
 
-    Arc::new(Context::new(device, queue, adapter))
+impl NState for NoN {
+    fn n(&self) -> Option<u32> {
+        None
+    }
 }
 
-// --- Final Build State ---
-impl CompressorBuilder<HasAdapter, HasBufferSize, NoN> {
-    pub async fn build32(self) -> (ChimpCompressorBatched, BatchedGPUDecompressor) {
-        let adapter = self.adapter.0;
-        let buffer_size = self.buffer_size.0;
+impl NState for HasN {
+    fn n(&self) -> Option<u32> {
+        Some(self.0)
+    }
+}
 
-        // Set buffer size in environment
-        unsafe {
-            std::env::set_var("CHIMP_BUFFER_SIZE", buffer_size.to_string());
+impl<A, C: NState> CompressorBuilder<A, HasBufferSize, C> {
+    /// The configuration handed to the built compressor and decompressor, the ChimpN
+    /// variants use [DEFAULT_N] when no `n` was chosen. Only the traced steps come from the
+    /// environment, the chosen buffer size overrides `CHIMP_BUFFER_SIZE`
+    pub fn config(&self) -> ChimpConfig {
+        let config = ChimpConfig::new(self.buffer_size.0 as usize)
+            .and_then(|config| config.with_n(self.n.n().unwrap_or(DEFAULT_N as u32) as usize))
+            .expect("buffer_size and n are validated by their builders")
+            .with_integer_encoding(self.integer_encoding)
+            .with_block_checksums(self.block_checksums)
+            .with_trace(trace_steps());
+        match self.error_bound {
+            Some(error_bound) => config.with_error_bound(error_bound),
+            None => config,
         }
+    }
+}
 
-        let context = create_context(adapter).await;
+async fn create_context(adapter: Adapter) -> Result<Arc<Context>, ChimpError> {
+    Ok(Arc::new(Context::from_adapter(adapter).await?))
+}
 
-        let compressor = ChimpCompressorBatched::new(false, context.clone(), DeviceEnum::GPU);
-        let decompressor = BatchedGPUDecompressor::new(context.clone());
+/// The [Codec] of [kind], running on the gpu of [context] or on the host without one
+fn build_codec(
+    context: Option<Arc<Context>>,
+    config: ChimpConfig,
+    kind: CodecVariant,
+) -> Result<Codec, ConfigError> {
+    let n = config.n();
+    let Some(context) = context else {
+        return Ok(match kind {
+            CodecVariant::Chimp16 => Codec::Chimp16Cpu(
+                ChimpCompressorBatched16Cpu::default().with_config(config.clone()),
+                BatchedDecompressor16Cpu::new(config),
+            ),
+            CodecVariant::Chimp32 => Codec::Chimp32Cpu(
+                ChimpCompressorBatchedCpu::default().with_config(config.clone()),
+                BatchedDecompressorCpu::new(config),
+            ),
+            CodecVariant::Chimp64 => Codec::Chimp64Cpu(
+                ChimpCompressorBatched64Cpu::default().with_config(config.clone()),
+                CPUDecompressorBatched64::new(config),
+            ),
+            CodecVariant::ChimpN32 => Codec::ChimpN32Cpu(
+                ChimpNCpuBatched::default().with_config(config.clone()),
+                BatchedCPUNDecompressor::default().with_config(config),
+            ),
+            CodecVariant::ChimpN64 => Codec::ChimpN64Cpu(
+                ChimpN64CpuBatched::default().with_config(config.clone()),
+                BatchedCPUN64Decompressor::default().with_config(config),
+            ),
+            CodecVariant::DeltaOfDelta64 => Codec::DeltaOfDelta64Cpu(
+                TimestampCompressorCpu::default().with_config(config.clone()),
+                TimestampDecompressorCpu::new(config),
+            ),
+            CodecVariant::Gorilla32 => Codec::Gorilla32Cpu(
+                GorillaCompressorBatchedCpu::default().with_config(config.clone()),
+                GorillaDecompressorCpu::new(config),
+            ),
+        });
+    };
+
+    Ok(match kind {
+        CodecVariant::Chimp16 => Codec::Chimp16(
+            ChimpCompressorBatched16::new(context.clone()).with_config(config.clone()),
+            BatchedGPUDecompressor16::new(context).with_config(config),
+        ),
+        CodecVariant::Chimp32 => Codec::Chimp32(
+            ChimpCompressorBatched::new(false, context.clone(), DeviceEnum::GPU)
+                .with_config(config.clone()),
+            BatchedGPUDecompressor::new(context).with_config(config),
+        ),
+        CodecVariant::Chimp64 => Codec::Chimp64(
+            ChimpCompressorBatched64::new(context.clone()).with_config(config.clone()),
+            ChimpDecompressorBatched64::new(context).with_config(config),
+        ),
+        CodecVariant::ChimpN32 => Codec::ChimpN32(
+            ChimpNGPUBatched::new(context.clone(), n)?.with_config(config.clone()),
+            BatchedGPUNDecompressor::new(context, n)?.with_config(config),
+        ),
+        CodecVariant::ChimpN64 => Codec::ChimpN64(
+            ChimpN64GPUBatched::new(context.clone(), n)?.with_config(config.clone()),
+            GPUDecompressorBatchedN64::new(context, n)?.with_config(config),
+        ),
+        CodecVariant::DeltaOfDelta64 => Codec::DeltaOfDelta64(
+            TimestampCompressor::new(context.clone()).with_config(config.clone()),
+            TimestampDecompressor::new(context).with_config(config),
+        ),
+        CodecVariant::Gorilla32 => Codec::Gorilla32(
+            GorillaCompressorBatched::new(context.clone()).with_config(config.clone()),
+            GorillaGPUDecompressor::new(context).with_config(config),
+        ),
+    })
+}
 
-        (compressor, decompressor)
+// --- Final Build State ---
+impl<C: NState> CompressorBuilder<HasAdapter, HasBufferSize, C> {
+    /// Build the compressor and decompressor of [kind], chosen at runtime
+    pub async fn build(self, kind: CodecVariant) -> Result<Codec, ChimpError> {
+        let config = self.config();
+        let context = create_context(self.adapter.0).await?;
+        Ok(build_codec(Some(context), config, kind)?)
     }
+}
 
-    pub async fn build64(self) -> (ChimpCompressorBatched64, ChimpDecompressorBatched64) {
-        let adapter = self.adapter.0;
-        let buffer_size = self.buffer_size.0;
+impl<C: NState> CompressorBuilder<HasMode, HasBufferSize, C> {
+    /// Build the compressor and decompressor of [kind] on the path chosen by the [GPUMode],
+    /// see [Codec::path]
+    pub fn build(self, kind: CodecVariant) -> Result<Codec, ChimpError> {
+        let config = self.config();
+        Ok(build_codec(self.adapter.0, config, kind)?)
+    }
+}
 
-        // Set buffer size in environment
-        unsafe {
-            std::env::set_var("CHIMP_BUFFER_SIZE", buffer_size.to_string());
-        }
+impl CompressorBuilder<HasAdapter, HasBufferSize, NoN> {
+    pub async fn build32(
+        self,
+    ) -> Result<(ChimpCompressorBatched, BatchedGPUDecompressor), ChimpError> {
+        let config = self.config();
+        let context = create_context(self.adapter.0).await?;
+
+        let compressor = ChimpCompressorBatched::new(false, context.clone(), DeviceEnum::GPU)
+            .with_config(config.clone());
+        let decompressor = BatchedGPUDecompressor::new(context.clone()).with_config(config);
 
-        let context = create_context(adapter).await;
+        Ok((compressor, decompressor))
+    }
 
-        let compressor = ChimpCompressorBatched64::default();
-        let decompressor = ChimpDecompressorBatched64::new(context.clone());
+    pub async fn build64(
+        self,
+    ) -> Result<(ChimpCompressorBatched64, ChimpDecompressorBatched64), ChimpError> {
+        let config = self.config();
+        let context = create_context(self.adapter.0).await?;
 
-        (compressor, decompressor)
+        let compressor = ChimpCompressorBatched64::new(context.clone()).with_config(config.clone());
+        let decompressor = ChimpDecompressorBatched64::new(context.clone()).with_config(config);
+
+        Ok((compressor, decompressor))
     }
 }
 
 impl CompressorBuilder<HasAdapter, HasBufferSize, HasN> {
-    pub async fn build32n(self) -> (ChimpNGPUBatched, BatchedGPUNDecompressor) {
-        let adapter = self.adapter.0;
-        let buffer_size = self.buffer_size.0;
-
-        // Set buffer size in environment
-        unsafe {
-            std::env::set_var("CHIMP_BUFFER_SIZE", buffer_size.to_string());
-        }
+    pub async fn build32n(self) -> Result<(ChimpNGPUBatched, BatchedGPUNDecompressor), ChimpError> {
+        let config = self.config();
+        let context = create_context(self.adapter.0).await?;
 
-        let context = create_context(adapter).await;
+        let compressor =
+            ChimpNGPUBatched::new(context.clone(), config.n())?.with_config(config.clone());
+        let decompressor =
+            BatchedGPUNDecompressor::new(context.clone(), config.n())?.with_config(config);
 
-        let compressor = ChimpNGPUBatched::new(context.clone(), &*self.n);
-        let decompressor = BatchedGPUNDecompressor::new(context.clone(), *self.n);
-
-        (compressor, decompressor)
+        Ok((compressor, decompressor))
     }
 
-    pub async fn build64n(self) -> (ChimpN64GPUBatched, GPUDecompressorBatchedN64) {
-        let adapter = self.adapter.0;
-        let buffer_size = self.buffer_size.0;
-
-        // Set buffer size in environment
-        unsafe {
-            std::env::set_var("CHIMP_BUFFER_SIZE", buffer_size.to_string());
-        }
-
-        let context = create_context(adapter).await;
+    pub async fn build64n(
+        self,
+    ) -> Result<(ChimpN64GPUBatched, GPUDecompressorBatchedN64), ChimpError> {
+        let config = self.config();
+        let context = create_context(self.adapter.0).await?;
 
-        let compressor = ChimpN64GPUBatched::new(context.clone(), &*self.n);
-        let decompressor = GPUDecompressorBatchedN64::new(context.clone(), *self.n);
+        let compressor =
+            ChimpN64GPUBatched::new(context.clone(), config.n())?.with_config(config.clone());
+        let decompressor =
+            GPUDecompressorBatchedN64::new(context.clone(), config.n())?.with_config(config);
 
-        (compressor, decompressor)
+        Ok((compressor, decompressor))
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::codec::ExecutionPath;
+    use compress_utils::config::ConfigError;
+    use compress_utils::format::ValueType;
 
     #[test]
     fn test_buffer_size_validation() {
         let builder = CompressorBuilder::new();
         let res = builder.with_buffer_size(100);
-        assert!(res.is_err());
-        assert_eq!(res.unwrap_err(), "buffer_size must be a multiple of 256");
+        assert!(matches!(
+            res,
+            Err(ChimpError::InvalidConfig(ConfigError::InvalidBufferSize(
+                100
+            )))
+        ));
 
         let builder = CompressorBuilder::new();
         let res = builder.with_buffer_size(256);
@@ -228,6 +435,256 @@ mod tests {
         assert!(res.is_ok());
     }
 
+    #[test]
+    fn test_config_is_built_from_the_builder() {
+        let config = CompressorBuilder::new()
+            .with_buffer_size(512)
+            .unwrap()
+            .with_n(64)
+            .unwrap()
+            .config();
+        assert_eq!(config.buffer_size(), 512);
+        assert_eq!(config.n(), 64);
+        assert!(CompressorBuilder::new().with_n(48).is_err());
+
+        let config = CompressorBuilder::new()
+            .with_buffer_size(256)
+            .unwrap()
+            .config();
+        assert_eq!(config.n(), DEFAULT_N);
+        assert_eq!(config.error_bound(), None);
+
+        let bound = ErrorBound::absolute(1e-3).unwrap();
+        let config = CompressorBuilder::new()
+            .with_error_bound(bound)
+            .with_buffer_size(256)
+            .unwrap()
+            .config();
+        assert_eq!(config.error_bound(), Some(bound));
+        assert!(!config.block_checksums());
+
+        let config = CompressorBuilder::new()
+            .with_block_checksums(true)
+            .with_buffer_size(256)
+            .unwrap()
+            .config();
+        assert!(config.block_checksums());
+    }
+
+    #[tokio::test]
+    async fn test_gpu_mode_cpu_runs_on_the_host() {
+        let values = (0..1000)
+            .map(|it| (it as f64 / 10.0).sin() * 100.0)
+            .collect::<Vec<_>>();
+        let kinds = [
+            CodecVariant::Chimp32,
+            CodecVariant::Chimp64,
+            CodecVariant::ChimpN32,
+            CodecVariant::ChimpN64,
+            CodecVariant::Gorilla32,
+        ];
+        for kind in kinds {
+            let codec = CompressorBuilder::new()
+                .with_buffer_size(256)
+                .unwrap()
+                .with_n(16)
+                .unwrap()
+                .with_gpu_mode(GPUMode::CPU)
+                .await
+                .unwrap()
+                .build(kind)
+                .unwrap();
+            assert_eq!(codec.path(), ExecutionPath::Host);
+            assert_eq!(codec.variant(), kind);
+
+            if kind.element_width() == 8 {
+                let compressed = codec
+                    .compress_f64(&values)
+                    .await
+                    .unwrap()
+                    .compressed_values();
+                let decompressed = codec.decompress_f64(&compressed).await.unwrap();
+                assert_eq!(decompressed.un_compressed_value(), values);
+                assert!(codec.compress_f32(&[1.0]).await.is_err());
+            } else {
+                let values = values.iter().map(|it| *it as f32).collect::<Vec<_>>();
+                let compressed = codec
+                    .compress_f32(&values)
+                    .await
+                    .unwrap()
+                    .compressed_values();
+                let decompressed = codec.decompress_f32(&compressed).await.unwrap();
+                assert_eq!(decompressed.un_compressed_value(), values);
+                assert!(codec.compress_f64(&[1.0]).await.is_err());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gpu_mode_cpu_compresses_timestamps() {
+        let values = (0..1000)
+            .map(|it| 1_000_000 + it * 10 + it % 7)
+            .collect::<Vec<i64>>();
+        let codec = CompressorBuilder::new()
+            .with_buffer_size(256)
+            .unwrap()
+            .with_gpu_mode(GPUMode::CPU)
+            .await
+            .unwrap()
+            .build(CodecVariant::DeltaOfDelta64)
+            .unwrap();
+        assert_eq!(codec.path(), ExecutionPath::Host);
+
+        let compressed = codec
+            .compress_i64(&values)
+            .await
+            .unwrap()
+            .compressed_values();
+        let decompressed = codec.decompress_i64(&compressed).await.unwrap();
+        assert_eq!(decompressed.un_compressed_value(), values);
+        assert!(matches!(
+            codec.compress_f64(&[1.0]).await,
+            Err(ChimpError::WrongValueType {
+                value_type: ValueType::I64,
+                ..
+            })
+        ));
+        assert!(matches!(
+            codec.compress_f32(&[1.0]).await,
+            Err(ChimpError::WrongElementWidth { width: 8, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_gpu_mode_cpu_compresses_integers() {
+        let counter = (0..1000).map(|it| 5_000 + it * 3).collect::<Vec<u64>>();
+        let codec = CompressorBuilder::new()
+            .with_buffer_size(256)
+            .unwrap()
+            .with_integer_encoding(IntegerEncoding::ZigZagDelta)
+            .with_gpu_mode(GPUMode::CPU)
+            .await
+            .unwrap()
+            .build(CodecVariant::ChimpN64)
+            .unwrap();
+
+        let compressed = codec
+            .compress_u64(&counter)
+            .await
+            .unwrap()
+            .compressed_values();
+        let decompressed = codec.decompress_u64(&compressed).await.unwrap();
+        assert_eq!(decompressed.un_compressed_value(), counter);
+        assert!(matches!(
+            codec.decompress_i64(&compressed).await,
+            Err(ChimpError::WrongValueType {
+                value_type: ValueType::U64,
+                ..
+            })
+        ));
+        assert!(matches!(
+            codec.compress_i32(&[1]).await,
+            Err(ChimpError::WrongElementWidth { width: 8, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_float_methods_reject_integer_streams() {
+        async fn host_codec(kind: CodecVariant) -> Codec {
+            CompressorBuilder::new()
+                .with_buffer_size(256)
+                .unwrap()
+                .with_gpu_mode(GPUMode::CPU)
+                .await
+                .unwrap()
+                .build(kind)
+                .unwrap()
+        }
+
+        let codec = host_codec(CodecVariant::Chimp32).await;
+        let compressed = codec
+            .compress_u32(&[7, 8, 9])
+            .await
+            .unwrap()
+            .compressed_values();
+        assert!(matches!(
+            codec.decompress_f32(&compressed).await,
+            Err(ChimpError::WrongValueType {
+                codec: CodecVariant::Chimp32,
+                value_type: ValueType::U32,
+            })
+        ));
+
+        let codec = host_codec(CodecVariant::ChimpN64).await;
+        let compressed = codec
+            .compress_i64(&[-7, 8, 9])
+            .await
+            .unwrap()
+            .compressed_values();
+        assert!(matches!(
+            codec.decompress_f64(&compressed).await,
+            Err(ChimpError::WrongValueType {
+                codec: CodecVariant::ChimpN64,
+                value_type: ValueType::I64,
+            })
+        ));
+    }
+
+    #[cfg(feature = "half")]
+    #[tokio::test]
+    async fn test_gpu_mode_cpu_compresses_halves() {
+        let values = (0..1000)
+            .map(|it| half::bf16::from_f32((it / 4) as f32 * 0.25))
+            .collect::<Vec<_>>();
+        let codec = CompressorBuilder::new()
+            .with_buffer_size(256)
+            .unwrap()
+            .with_gpu_mode(GPUMode::CPU)
+            .await
+            .unwrap()
+            .build(CodecVariant::Chimp16)
+            .unwrap();
+        assert_eq!(codec.path(), ExecutionPath::Host);
+
+        let compressed = codec
+            .compress_bf16(&values)
+            .await
+            .unwrap()
+            .compressed_values();
+        let decompressed = codec.decompress_bf16(&compressed).await.unwrap();
+        assert_eq!(decompressed.un_compressed_value(), values);
+        assert!(matches!(
+            codec.decompress_f16(&compressed).await,
+            Err(ChimpError::WrongValueType {
+                value_type: ValueType::BF16,
+                ..
+            })
+        ));
+        assert!(matches!(
+            codec.compress_f32(&[1.0]).await,
+            Err(ChimpError::WrongElementWidth { width: 2, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_gpu_mode_with_a_missing_adapter() {
+        let name = "an adapter that does not exist";
+        let codec = CompressorBuilder::new()
+            .with_buffer_size(256)
+            .unwrap()
+            .with_gpu_mode(GPUMode::GPUIfAvailable(name.to_string()))
+            .await
+            .unwrap()
+            .build(CodecVariant::Chimp32)
+            .unwrap();
+        assert_eq!(codec.path(), ExecutionPath::Host);
+
+        let result = CompressorBuilder::new()
+            .with_gpu_mode(GPUMode::GPUMust(name.to_string()))
+            .await;
+        assert!(matches!(result, Err(ChimpError::NoAdapter(Some(it))) if it == name));
+    }
+
     #[tokio::test]
     async fn test_state_machine_logic() {
         // This test only verifies that the types compile and follow the state machine
@@ -240,8 +697,18 @@ mod tests {
         let builder_with_size = builder.with_buffer_size(256).unwrap();
         // builder_with_size.build32(); // Should NOT compile (missing adapter)
 
-        // Final transition requires an adapter.
-        // let builder_final = builder_with_size.with_default_adapter().await;
-        // builder_final.build32().await; // This would work if we had an adapter.
+        // Final transition requires an adapter, a missing adapter or device is an error
+        // instead of a panic
+        match builder_with_size.with_default_adapter().await {
+            Ok(builder_final) => {
+                if let Err(error) = builder_final.build32().await {
+                    assert!(matches!(
+                        error,
+                        ChimpError::MissingFeatures(_) | ChimpError::RequestDevice(_)
+                    ));
+                }
+            }
+            Err(error) => assert!(matches!(error, ChimpError::NoAdapter(None))),
+        }
     }
 }
@@ -1,8 +1,17 @@
+#[cfg(feature = "arrow")]
+mod arrow;
+mod codec;
 mod factory;
 
-enum GPUMode {
+/// Where the built codecs run, the [String] names the adapter, an empty name selects the
+/// default one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GPUMode {
+    /// Always run on the host
     CPU,
+    /// Run on the adapter when it can be used, on the host otherwise
     GPUIfAvailable(String),
+    /// Run on the adapter or fail to build
     GPUMust(String),
 }
 #[derive(Default)]
@@ -32,8 +41,17 @@ impl Default for GPUMode {
     }
 }
 pub mod api {
+    #[cfg(feature = "arrow")]
+    pub use crate::arrow::{RecordBatchCodec, ARROW_MAGIC};
+    pub use crate::codec::{Codec, ExecutionPath};
     pub use crate::factory::CompressorBuilder;
+    pub use crate::GPUMode;
+    pub use compress_utils::error::ChimpError;
+    pub use compress_utils::format::{CodecVariant, ValueType};
+    pub use compress_utils::integer::{IntegerDecompressor, IntegerEncoding};
+    pub use compress_utils::lossy::{verify_error_bound, ErrorBound};
     use wgpu::Adapter;
+    pub use wgpu_compress_timestamps::series::{SeriesCompressor, SeriesDecompressor};
 
     pub fn list_adapters() -> Vec<Adapter> {
         let instance = wgpu::Instance::default();
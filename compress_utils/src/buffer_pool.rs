@@ -0,0 +1,353 @@
+//! A pool of the gpu buffers of a [Context](crate::context::Context).
+//!
+//! Every batch of a compression allocates its storage, uniform and staging buffers, and for
+//! many medium arrays the allocations take longer than the dispatches. [BufferPool] keeps the
+//! buffers the stages are done with by usage and size bucket, and hands them out again to the
+//! following batches, holding at most [BufferPool::capacity] bytes of idle buffers.
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use wgpu::{Buffer, BufferAddress, BufferUsages, Device};
+
+/// The idle bytes a [BufferPool] holds unless configured otherwise
+pub const DEFAULT_CAPACITY: u64 = 256 << 20;
+
+/// The smallest bucket, smaller buffers are rounded up to it
+pub const MIN_BUCKET_SIZE: BufferAddress = 256;
+
+/// The lookups a [BufferPool] has served
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    /// Lookups served by a buffer released before
+    pub hits: u64,
+    /// Lookups that allocated their buffer
+    pub misses: u64,
+    /// Idle buffers in the pool
+    pub buffers: usize,
+    /// Bytes of the idle buffers in the pool
+    pub bytes: u64,
+}
+
+impl Display for BufferPoolStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses, {} buffers of {} bytes",
+            self.hits, self.misses, self.buffers, self.bytes
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    buffers: HashMap<(BufferUsages, BufferAddress), Vec<Buffer>>,
+    bytes: u64,
+}
+
+/// Idle buffers by usage and size bucket, shared by every stage running on the same context
+#[derive(Debug)]
+pub struct BufferPool {
+    buckets: Mutex<Buckets>,
+    capacity: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl BufferPool {
+    /// A pool holding at most [capacity] bytes of idle buffers, 0 disables the pooling
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            buckets: Mutex::new(Buckets::default()),
+            capacity: AtomicU64::new(capacity),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Hold at most [capacity] bytes of idle buffers from now on, the buffers above it are
+    /// dropped
+    pub fn set_capacity(&self, capacity: u64) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|it| it.into_inner());
+        let Buckets { buffers, bytes } = &mut *buckets;
+        for bucket in buffers.values_mut() {
+            while *bytes > capacity {
+                match bucket.pop() {
+                    Some(buffer) => *bytes -= buffer.size(),
+                    None => break,
+                }
+            }
+        }
+        buffers.retain(|_, bucket| !bucket.is_empty());
+    }
+
+    /// The size of the buffers serving a request of [size] bytes, the next power of two from
+    /// [MIN_BUCKET_SIZE]. Empty buffers are not pooled.
+    pub fn bucket_size(size: BufferAddress) -> Option<BufferAddress> {
+        (size > 0).then(|| size.max(MIN_BUCKET_SIZE).next_power_of_two())
+    }
+
+    /// A buffer with [usage] of at least [size] bytes, one released before when the pool holds
+    /// one of the same bucket. Check [PooledBuffer::reused] before relying on its content.
+    ///
+    /// Requests whose bucket is larger than the device allows or than the pool may hold get a
+    /// buffer of exactly [size] bytes, which is dropped instead of released.
+    pub fn acquire(
+        self: &Arc<Self>,
+        device: &Device,
+        size: BufferAddress,
+        usage: BufferUsages,
+        label: Option<&str>,
+    ) -> PooledBuffer {
+        let bucket = Self::bucket_size(size).filter(|&bucket| {
+            bucket <= device.limits().max_buffer_size && bucket <= self.capacity()
+        });
+        let Some(bucket) = bucket else {
+            return PooledBuffer::from(create_buffer(device, size, usage, label));
+        };
+        let reused = {
+            let mut buckets = self.buckets.lock().unwrap_or_else(|it| it.into_inner());
+            let buffer = buckets
+                .buffers
+                .get_mut(&(usage, bucket))
+                .and_then(|it| it.pop());
+            if let Some(buffer) = &buffer {
+                buckets.bytes -= buffer.size();
+            }
+            buffer
+        };
+        let counter = if reused.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        PooledBuffer {
+            reused: reused.is_some(),
+            buffer: Some(reused.unwrap_or_else(|| create_buffer(device, bucket, usage, label))),
+            pool: Some(self.clone()),
+        }
+    }
+
+    /// Keep [buffer] for a later [BufferPool::acquire] of its usage and bucket, or drop it
+    /// when the pool is full. The buffer must not be mapped.
+    pub fn release(&self, buffer: Buffer) {
+        let size = buffer.size();
+        if Self::bucket_size(size) != Some(size) {
+            return;
+        }
+        let mut buckets = self.buckets.lock().unwrap_or_else(|it| it.into_inner());
+        if buckets.bytes + size > self.capacity() {
+            return;
+        }
+        buckets.bytes += size;
+        buckets
+            .buffers
+            .entry((buffer.usage(), size))
+            .or_default()
+            .push(buffer);
+    }
+
+    pub fn stats(&self) -> BufferPoolStats {
+        let buckets = self.buckets.lock().unwrap_or_else(|it| it.into_inner());
+        BufferPoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            buffers: buckets.buffers.values().map(Vec::len).sum(),
+            bytes: buckets.bytes,
+        }
+    }
+
+    /// Drop every idle buffer, the hit and miss counts are kept
+    pub fn clear(&self) {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|it| it.into_inner());
+        buckets.buffers.clear();
+        buckets.bytes = 0;
+    }
+}
+
+fn create_buffer(
+    device: &Device,
+    size: BufferAddress,
+    usage: BufferUsages,
+    label: Option<&str>,
+) -> Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label,
+        size,
+        usage,
+        mapped_at_creation: false,
+    })
+}
+
+/// A gpu buffer going back to the [BufferPool] it was acquired from when dropped
+#[derive(Debug)]
+pub struct PooledBuffer {
+    buffer: Option<Buffer>,
+    pool: Option<Arc<BufferPool>>,
+    reused: bool,
+}
+
+impl PooledBuffer {
+    /// Whether the buffer served an earlier request, its content is then the one that request
+    /// left instead of zeroes
+    pub fn reused(&self) -> bool {
+        self.reused
+    }
+}
+
+/// A buffer outside of any pool, dropped as usual
+impl From<Buffer> for PooledBuffer {
+    fn from(buffer: Buffer) -> Self {
+        Self {
+            buffer: Some(buffer),
+            pool: None,
+            reused: false,
+        }
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Buffer;
+
+    fn deref(&self) -> &Buffer {
+        self.buffer
+            .as_ref()
+            .expect("the buffer is only taken on drop")
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let (Some(buffer), Some(pool)) = (self.buffer.take(), self.pool.take()) {
+            pool.release(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::BufferPool;
+
+    #[test]
+    fn test_requests_are_rounded_up_to_their_bucket() {
+        assert_eq!(BufferPool::bucket_size(0), None);
+        assert_eq!(BufferPool::bucket_size(1), Some(256));
+        assert_eq!(BufferPool::bucket_size(256), Some(256));
+        assert_eq!(BufferPool::bucket_size(257), Some(512));
+        assert_eq!(BufferPool::bucket_size(3 << 20), Some(4 << 20));
+    }
+
+    #[cfg(feature = "noop")]
+    mod noop {
+        use crate::buffer_pool::{BufferPool, BufferPoolStats};
+        use crate::context::Context;
+        use crate::wgpu_utils::read_buffer;
+        use crate::BufferWrapper;
+        use pollster::FutureExt;
+        use std::sync::Arc;
+        use wgpu::BufferUsages;
+
+        const STORAGE: BufferUsages = BufferUsages::STORAGE
+            .union(BufferUsages::COPY_SRC)
+            .union(BufferUsages::COPY_DST);
+
+        #[test]
+        fn test_released_buffers_serve_the_next_request_of_their_bucket() {
+            let context = Context::initialize_noop().block_on().unwrap();
+            let pool = Arc::new(BufferPool::default());
+            let buffer = pool.acquire(context.device(), 1000, STORAGE, None);
+            assert!(!buffer.reused());
+            assert_eq!(buffer.size(), 1024);
+            drop(buffer);
+            assert_eq!(pool.stats().buffers, 1);
+
+            let buffer = pool.acquire(context.device(), 600, STORAGE, None);
+            assert!(buffer.reused());
+            let other_usage = pool.acquire(context.device(), 600, BufferUsages::UNIFORM, None);
+            assert!(!other_usage.reused());
+            let other_bucket = pool.acquire(context.device(), 2000, STORAGE, None);
+            assert!(!other_bucket.reused());
+            drop((buffer, other_usage, other_bucket));
+            assert_eq!(
+                pool.stats(),
+                BufferPoolStats {
+                    hits: 1,
+                    misses: 3,
+                    buffers: 3,
+                    bytes: 1024 + 1024 + 2048
+                }
+            );
+        }
+
+        #[test]
+        fn test_idle_buffers_stay_within_the_capacity() {
+            let context = Context::initialize_noop().block_on().unwrap();
+            let pool = Arc::new(BufferPool::new(2048));
+            let buffers = (0..3)
+                .map(|_| pool.acquire(context.device(), 1024, STORAGE, None))
+                .collect::<Vec<_>>();
+            drop(buffers);
+            assert_eq!(pool.stats().buffers, 2);
+            assert_eq!(pool.stats().bytes, 2048);
+
+            pool.set_capacity(1024);
+            assert_eq!(pool.stats().bytes, 1024);
+
+            // a bucket above the capacity is allocated to size and never kept
+            let large = pool.acquire(context.device(), 1500, STORAGE, None);
+            assert_eq!(large.size(), 1500);
+            drop(large);
+            assert_eq!(pool.stats().buffers, 1);
+
+            pool.set_capacity(0);
+            assert_eq!(pool.stats().buffers, 0);
+        }
+
+        #[test]
+        fn test_reused_storage_buffers_are_zeroed_past_their_content() {
+            let context = Context::initialize_noop().block_on().unwrap();
+            let values = (1..=100u32).collect::<Vec<_>>();
+            let first = BufferWrapper::pooled_storage_with_content(
+                &context,
+                bytemuck::cast_slice(&values),
+                (0, 0),
+                None,
+            );
+            assert_eq!(
+                read_buffer::<u32>(Some(&context), &first)
+                    .block_on()
+                    .unwrap(),
+                values
+            );
+            drop(first);
+
+            let padded = BufferWrapper::pooled_storage_with_padded_content(
+                &context,
+                bytemuck::cast_slice(&values[..10]),
+                300,
+                (0, 0),
+                None,
+            );
+            assert_eq!(context.buffer_pool().stats().hits, 1);
+            assert_eq!(padded.size(), 300);
+            let read = read_buffer::<u32>(Some(&context), &padded)
+                .block_on()
+                .unwrap();
+            assert_eq!(read[..10], values[..10]);
+            assert!(read[10..].iter().all(|&it| it == 0));
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! CRC32C checksums of the blocks of a stream.
+//!
+//! The checksums are stored in the trailer flagged by [HeaderFlags::BLOCK_CHECKSUMS], one
+//! little endian `u32` per block computed over every byte of the block, prefix included.
+//! [ChimpHeader::split] checks them before a stream reaches the decoders, so a flipped bit
+//! surfaces as [FormatError::Corrupted] instead of wrong values.
+use crate::format::{
+    trailer_bytes, BlockIndex, ChimpHeader, FormatError, HeaderFlags, PayloadSections,
+    BLOCK_PREFIX_SIZE, HEADER_SIZE,
+};
+
+/// Size in bytes of a single checksum in the trailer
+pub(crate) const CHECKSUM_SIZE: usize = size_of::<u32>();
+/// The reflected Castagnoli polynomial
+const CRC32C_POLYNOMIAL: u32 = 0x82F6_3B78;
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ CRC32C_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The CRC32C checksum of [bytes]
+pub fn crc32c(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        CRC32C_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// The checksum of every block of a stream
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockChecksums(Vec<u32>);
+
+impl BlockChecksums {
+    /// Checksum each of the [blocks] located by [index]
+    pub fn compute(blocks: &[u8], index: &BlockIndex) -> Self {
+        let offsets = index
+            .entries()
+            .iter()
+            .map(|it| it.byte_offset as usize)
+            .chain([blocks.len()])
+            .collect::<Vec<_>>();
+        Self(
+            offsets
+                .windows(2)
+                .map(|block| crc32c(&blocks[block[0]..block[1]]))
+                .collect(),
+        )
+    }
+
+    pub fn checksums(&self) -> &[u32] {
+        &self.0
+    }
+
+    /// Add the checksum trailer to [stream] and mark it in the header. The value count of a
+    /// streamed header is filled in from the blocks. Streams that already carry checksums
+    /// are returned unchanged
+    pub fn append_to(stream: Vec<u8>) -> Result<Vec<u8>, FormatError> {
+        let mut header = ChimpHeader::read(&stream)?;
+        if header.flags.contains(HeaderFlags::BLOCK_CHECKSUMS) {
+            return Ok(stream);
+        }
+        let sections = PayloadSections::read(&header, &stream[HEADER_SIZE..])?;
+        let index = BlockIndex::scan(&header, sections.blocks)?;
+        let checksums = Self::compute(sections.blocks, &index);
+        // the trailer can only be told apart from the blocks with a known value count
+        if header.flags.contains(HeaderFlags::STREAMED) {
+            header = header.with_value_count(index.value_count());
+        }
+        let blocks_end = HEADER_SIZE + sections.blocks.len();
+        let header = header.with_flag(HeaderFlags::BLOCK_CHECKSUMS);
+        let mut payload = stream[HEADER_SIZE..blocks_end].to_vec();
+        payload.extend(trailer_bytes(
+            checksums.0.iter().flat_map(|it| it.to_le_bytes()).collect(),
+            checksums.0.len(),
+        ));
+        // the block index, if there is one, stays the last section
+        payload.extend_from_slice(&stream[blocks_end..]);
+        Ok(header.prepend_to(payload))
+    }
+
+    /// Walk the [blocks] checking each one against the stored [checksums] before trusting
+    /// its prefix to find the next one, so the first damaged block is the one reported
+    pub(crate) fn verify(
+        header: &ChimpHeader,
+        blocks: &[u8],
+        checksums: &[u8],
+    ) -> Result<(), FormatError> {
+        let mut byte_offset = 0usize;
+        let mut block = 0usize;
+        for expected in checksums.chunks_exact(CHECKSUM_SIZE) {
+            let corrupted = FormatError::Corrupted {
+                block,
+                byte_offset: HEADER_SIZE + byte_offset,
+            };
+            let (_, rest) = BlockIndex::read_prefix(header.codec, &blocks[byte_offset..])
+                .ok_or(corrupted.clone())?;
+            let block_end = byte_offset + BLOCK_PREFIX_SIZE + rest;
+            let expected = u32::from_le_bytes(expected.try_into().unwrap());
+            if block_end > blocks.len() || crc32c(&blocks[byte_offset..block_end]) != expected {
+                return Err(corrupted);
+            }
+            byte_offset = block_end;
+            block += 1;
+        }
+        if byte_offset != blocks.len() {
+            return Err(FormatError::Corrupted {
+                block,
+                byte_offset: HEADER_SIZE + byte_offset,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::CodecVariant;
+
+    #[test]
+    fn crc32c_check_value() {
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(crc32c(&[]), 0);
+    }
+
+    #[test]
+    fn flipped_bit_is_reported_with_its_block() {
+        let mut payload = Vec::new();
+        for block in 0..3u8 {
+            payload.extend_from_slice(&3u32.to_le_bytes());
+            payload.extend_from_slice(&8u32.to_le_bytes());
+            payload.extend_from_slice(&[block; 8]);
+        }
+        let stream = ChimpHeader::new(CodecVariant::Chimp32, 0, 4, 12).prepend_to(payload);
+        let stream = BlockChecksums::append_to(stream).unwrap();
+        let stream = BlockIndex::append_to(stream).unwrap();
+        let (_, blocks) = ChimpHeader::split(&stream, CodecVariant::Chimp32).unwrap();
+        assert_eq!(blocks.len(), 48);
+
+        let mut damaged = stream.clone();
+        damaged[HEADER_SIZE + 16 + 10] ^= 0b100;
+        assert_eq!(
+            ChimpHeader::split(&damaged, CodecVariant::Chimp32).unwrap_err(),
+            FormatError::Corrupted {
+                block: 1,
+                byte_offset: HEADER_SIZE + 16
+            }
+        );
+
+        let mut damaged = stream;
+        damaged[HEADER_SIZE + 32 + 4] ^= 0xFF;
+        assert_eq!(
+            ChimpHeader::split(&damaged, CodecVariant::Chimp32).unwrap_err(),
+            FormatError::Corrupted {
+                block: 2,
+                byte_offset: HEADER_SIZE + 32
+            }
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! Per instance configuration of the compressors and decompressors.
+//!
+//! Every compressor and decompressor owns a [ChimpConfig] and hands it to each of its
+//! stages, so instances with different settings can coexist in one process. The
+//! `CHIMP_BUFFER_SIZE` and `CHIMP_TRACE` environment variables only provide the defaults.
+#[cfg(feature = "gpu")]
+use crate::context::Context;
+use crate::general_utils::{get_buffer_size, trace_steps, ChimpBufferInfo, Step};
+use crate::integer::IntegerEncoding;
+use crate::lossy::ErrorBound;
+#[cfg(feature = "gpu")]
+use std::cmp::min;
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// The `n` used by the ChimpN variants when none is configured
+pub const DEFAULT_N: usize = 128;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    #[error("Buffer size {0} must be a positive multiple of 256")]
+    InvalidBufferSize(usize),
+    #[error("n={0} must be a power of two greater than 1")]
+    InvalidN(usize),
+    #[error("The maximum workgroup size must be greater than 0")]
+    InvalidWorkgroupSize,
+    #[error("Error bound {0} must be a finite positive number of at least f32::MIN_POSITIVE")]
+    InvalidErrorBound(f64),
+    #[error("Integers are compressed losslessly, an error bound only applies to floats")]
+    ErrorBoundOnIntegers,
+    #[error("16 bit floats are compressed losslessly, an error bound only applies to f32 and f64")]
+    ErrorBoundOnHalves,
+    #[error("Block checksums are not written to a stream, only to the output of compress")]
+    ChecksumsOnStream,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChimpConfig {
+    buffer_size: usize,
+    n: usize,
+    max_workgroup_size: Option<usize>,
+    error_bound: Option<ErrorBound>,
+    integer_encoding: IntegerEncoding,
+    block_checksums: bool,
+    trace: HashSet<Step>,
+}
+
+impl Default for ChimpConfig {
+    /// The configuration described by the environment, see [get_buffer_size] and
+    /// [trace_steps]
+    fn default() -> Self {
+        Self::with_defaults(get_buffer_size().buffer_size()).with_trace(trace_steps())
+    }
+}
+
+impl ChimpConfig {
+    /// A configuration with [buffer_size] values per block and the defaults of every other
+    /// setting, unlike [ChimpConfig::default] it does not read the environment
+    pub fn new(buffer_size: usize) -> Result<Self, ConfigError> {
+        Self::with_defaults(256).with_buffer_size(buffer_size)
+    }
+
+    fn with_defaults(buffer_size: usize) -> Self {
+        Self {
+            buffer_size,
+            n: DEFAULT_N,
+            max_workgroup_size: None,
+            error_bound: None,
+            integer_encoding: IntegerEncoding::default(),
+            block_checksums: false,
+            trace: HashSet::new(),
+        }
+    }
+
+    /// The number of values compressed by a single workgroup, which is also the number of
+    /// values in every block of the stream
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Result<Self, ConfigError> {
+        if buffer_size == 0 || !buffer_size.is_multiple_of(256) {
+            return Err(ConfigError::InvalidBufferSize(buffer_size));
+        }
+        self.buffer_size = buffer_size;
+        Ok(self)
+    }
+
+    /// The number of previous values the ChimpN variants search for a match
+    pub fn with_n(mut self, n: usize) -> Result<Self, ConfigError> {
+        if n < 2 || !n.is_power_of_two() {
+            return Err(ConfigError::InvalidN(n));
+        }
+        self.n = n;
+        Ok(self)
+    }
+
+    /// Cap the number of workgroups dispatched by a single shader invocation below the
+    /// adapter limit
+    pub fn with_max_workgroup_size(
+        mut self,
+        max_workgroup_size: usize,
+    ) -> Result<Self, ConfigError> {
+        if max_workgroup_size == 0 {
+            return Err(ConfigError::InvalidWorkgroupSize);
+        }
+        self.max_workgroup_size = Some(max_workgroup_size);
+        Ok(self)
+    }
+
+    /// Compress lossily, quantizing the values to [error_bound] before the S computation,
+    /// see [crate::lossy]
+    pub fn with_error_bound(mut self, error_bound: ErrorBound) -> Self {
+        self.error_bound = Some(error_bound);
+        self
+    }
+
+    /// How integer values are turned into the floats the pipeline compresses, see
+    /// [crate::integer]
+    pub fn with_integer_encoding(mut self, integer_encoding: IntegerEncoding) -> Self {
+        self.integer_encoding = integer_encoding;
+        self
+    }
+
+    /// Append a checksum of every block to the compressed streams, so decompressing a damaged
+    /// block fails with a corrupted input error, see [crate::checksum]
+    pub fn with_block_checksums(mut self, block_checksums: bool) -> Self {
+        self.block_checksums = block_checksums;
+        self
+    }
+
+    /// The stages whose intermediate output is written to the trace files
+    pub fn with_trace(mut self, trace: impl IntoIterator<Item = Step>) -> Self {
+        self.trace = trace.into_iter().collect();
+        self
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    pub fn buffer_info(&self) -> ChimpBufferInfo {
+        ChimpBufferInfo::with_buffer_size(self.buffer_size)
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// The number of workgroups a single dispatch on [context] may use
+    #[cfg(feature = "gpu")]
+    pub fn max_workgroup_size(&self, context: &Context) -> usize {
+        let adapter_limit = context.get_max_workgroup_size();
+        self.max_workgroup_size
+            .map_or(adapter_limit, |it| min(it, adapter_limit))
+    }
+
+    /// The bound the values are quantized to, [None] when compressing losslessly
+    pub fn error_bound(&self) -> Option<ErrorBound> {
+        self.error_bound
+    }
+
+    pub fn integer_encoding(&self) -> IntegerEncoding {
+        self.integer_encoding
+    }
+
+    pub fn block_checksums(&self) -> bool {
+        self.block_checksums
+    }
+
+    pub fn trace_steps(&self) -> &HashSet<Step> {
+        &self.trace
+    }
+
+    pub fn traces(&self, step: &Step) -> bool {
+        self.trace.contains(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::general_utils::buffer_size_from_env;
+
+    #[test]
+    fn builders_validate_their_input() {
+        let config = ChimpConfig::default()
+            .with_buffer_size(1024)
+            .unwrap()
+            .with_n(64)
+            .unwrap()
+            .with_trace([Step::Finalize]);
+        assert_eq!(config.buffer_size(), 1024);
+        assert_eq!(config.buffer_info().chunks(), 4);
+        assert_eq!(config.n(), 64);
+        assert!(config.traces(&Step::Finalize));
+        assert!(!config.traces(&Step::ComputeS));
+
+        assert_eq!(
+            ChimpConfig::default().with_buffer_size(100),
+            Err(ConfigError::InvalidBufferSize(100))
+        );
+        assert_eq!(
+            ChimpConfig::default().with_n(48),
+            Err(ConfigError::InvalidN(48))
+        );
+        assert_eq!(
+            ChimpConfig::default().with_max_workgroup_size(0),
+            Err(ConfigError::InvalidWorkgroupSize)
+        );
+    }
+
+    #[test]
+    fn invalid_environment_falls_back_to_the_default() {
+        for value in [None, Some("100"), Some("0"), Some("large")] {
+            assert_eq!(buffer_size_from_env(value).buffer_size(), 256);
+        }
+        assert_eq!(buffer_size_from_env(Some("1024")).buffer_size(), 1024);
+        assert_eq!(buffer_size_from_env(Some("1024")).chunks(), 4);
+        assert_eq!(ChimpConfig::new(512).unwrap().buffer_size(), 512);
+        assert!(ChimpConfig::new(512).unwrap().trace.is_empty());
+        assert_eq!(
+            ChimpConfig::new(100),
+            Err(ConfigError::InvalidBufferSize(100))
+        );
+    }
+}
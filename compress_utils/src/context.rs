@@ -1,26 +1,21 @@
+use crate::buffer_pool::BufferPool;
+use crate::error::ChimpError;
+use crate::pipeline_cache::PipelineCache;
 use itertools::Itertools;
-use thiserror::Error;
-use wgpu::{Adapter, Device, Queue, RequestDeviceError};
+use std::sync::Arc;
+use wgpu::{Adapter, Device, Features, Queue};
 use wgpu_types::Limits;
 
+/// The device features every pipeline relies on
+pub const REQUIRED_FEATURES: Features = Features::SHADER_F64.union(Features::SHADER_INT64);
+
 #[derive(Debug)]
 pub struct Context {
     device: Device,
     queue: Queue,
     adapter: Adapter,
-}
-
-#[derive(Error, Debug)]
-pub enum UtilError {
-    #[error("Could not initialize the gpu context")]
-    Unintialized,
-    #[error("Could not find Adapter with this name {0}")]
-    UnknownAdapter(String),
-    #[error("Could not request Adapter with name {name}")]
-    UnbindableAdapter {
-        name: String,
-        source: RequestDeviceError,
-    },
+    pipeline_cache: PipelineCache,
+    buffer_pool: Arc<BufferPool>,
 }
 
 impl Context {
@@ -29,6 +24,8 @@ impl Context {
             device,
             queue,
             adapter,
+            pipeline_cache: PipelineCache::default(),
+            buffer_pool: Arc::new(BufferPool::default()),
         }
     }
 
@@ -52,6 +49,18 @@ impl Context {
         &mut self.adapter
     }
 
+    /// The compute pipelines compiled on this context, see [PipelineCache::stats] for how often
+    /// a stage found its pipeline there
+    pub fn pipeline_cache(&self) -> &PipelineCache {
+        &self.pipeline_cache
+    }
+
+    /// The buffers the stages of this context are done with, see [BufferPool::set_capacity]
+    /// to change how many bytes of them are kept
+    pub fn buffer_pool(&self) -> &Arc<BufferPool> {
+        &self.buffer_pool
+    }
+
     pub fn get_max_workgroup_size(&self) -> usize {
         self.adapter.limits().max_compute_workgroups_per_dimension as usize
     }
@@ -62,14 +71,27 @@ impl Context {
         self.device.limits().max_buffer_size as usize
     }
 
-    pub async fn initialize_default_adapter() -> Result<Self, UtilError> {
+    /// Fail with [ChimpError::BufferExceedsLimits] when a buffer of [size] bytes is larger
+    /// than the device allows
+    pub fn check_buffer_size(&self, size: usize) -> Result<(), ChimpError> {
+        let limit = self.get_max_buffer_size();
+        if size > limit {
+            return Err(ChimpError::BufferExceedsLimits {
+                size: size as u64,
+                limit: limit as u64,
+            });
+        }
+        Ok(())
+    }
+
+    pub async fn initialize_default_adapter() -> Result<Self, ChimpError> {
         Self::_initialize(None).await
     }
-    pub async fn initialize_with_adapter(device: impl Into<String>) -> Result<Self, UtilError> {
+    pub async fn initialize_with_adapter(device: impl Into<String>) -> Result<Self, ChimpError> {
         Self::_initialize(Some(device.into())).await
     }
 
-    async fn _initialize(device_name: Option<String>) -> Result<Self, UtilError> {
+    async fn _initialize(device_name: Option<String>) -> Result<Self, ChimpError> {
         let instance = wgpu::Instance::default();
         let adapter_list: Vec<Adapter>;
         let adapter = if let Some(device) = device_name {
@@ -78,18 +100,47 @@ impl Context {
                 .iter()
                 .filter(|adapter| adapter.get_info().name.contains(device.as_str()))
                 .find_or_first(|_| true)
-                .ok_or(UtilError::UnknownAdapter(device.to_string()))?
+                .ok_or(ChimpError::NoAdapter(Some(device.to_string())))?
                 .to_owned()
         } else {
             instance
                 .request_adapter(&wgpu::RequestAdapterOptionsBase::default())
                 .await
-                .map_err(|_| UtilError::Unintialized)?
+                .map_err(|_| ChimpError::NoAdapter(None))?
         };
+        Self::from_adapter(adapter).await
+    }
+
+    /// A context on the noop backend of wgpu, which validates shaders, pipelines and bind groups
+    /// like any other backend but never runs a dispatch. Buffers keep their content, so stages
+    /// can run on it without a GPU, reading back what they uploaded or zeroes.
+    #[cfg(feature = "noop")]
+    pub async fn initialize_noop() -> Result<Self, ChimpError> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::NOOP,
+            backend_options: wgpu::BackendOptions {
+                noop: wgpu::NoopBackendOptions { enable: true },
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptionsBase::default())
+            .await
+            .map_err(|_| ChimpError::NoAdapter(None))?;
+        Self::from_adapter(adapter).await
+    }
+
+    /// Request a device with the [REQUIRED_FEATURES] and the buffer limits of [adapter]
+    pub async fn from_adapter(adapter: Adapter) -> Result<Self, ChimpError> {
+        let missing = REQUIRED_FEATURES.difference(adapter.features());
+        if !missing.is_empty() {
+            return Err(ChimpError::MissingFeatures(missing));
+        }
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: None,
-                required_features: wgpu::Features::SHADER_F64 | wgpu::Features::SHADER_INT64,
+                required_features: REQUIRED_FEATURES,
                 required_limits: Limits {
                     max_storage_buffer_binding_size: adapter
                         .limits()
@@ -100,11 +151,7 @@ impl Context {
                 memory_hints: wgpu::MemoryHints::MemoryUsage,
                 trace: wgpu_types::Trace::Off,
             })
-            .await
-            .map_err(|source| UtilError::UnbindableAdapter {
-                name: adapter.get_info().name.to_string(),
-                source,
-            })?;
+            .await?;
 
         Ok(Context::new(device, queue, adapter))
     }
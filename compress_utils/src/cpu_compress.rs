@@ -1,35 +1,31 @@
 use crate::bit_utils::{BitReadable, BitWritable, ToBitVec};
+use crate::error::ChimpError;
+use crate::format::{BlockIndex, FormatError};
 use crate::general_utils::{CompressResult, DecompressResult};
 use async_trait::async_trait;
 use bit_vec::BitVec;
-use thiserror::Error;
+use std::ops::Range;
 
 #[derive(Debug, Default, Clone)]
 pub struct CPUCompressor {
     debug: bool,
 }
-#[derive(Error, Debug)]
-pub enum CPUCompressError {
-    #[error("Wrong format on decompression input at bit {index} in byte {byte_index}",byte_index=(.index)/8
-    )]
-    WrongFormat { index: usize },
+/// The plain stream is a single block, so a decoding failure at bit [index] is reported
+/// as a corruption of block 0
+fn corrupt_bit(index: usize) -> ChimpError {
+    ChimpError::CorruptInput {
+        block: 0,
+        byte_offset: index / 8,
+    }
 }
 
-#[derive(Error, Debug)]
-pub enum CompressionError {
-    #[error(transparent)]
-    CpuCompressError(#[from] CPUCompressError),
-    #[error(transparent)]
-    FromBaseError(#[from] Box<dyn std::error::Error + Send>),
-    #[error(transparent)]
-    FromBaseAnyhowError(#[from] anyhow::Error),
-}
-#[derive(Error, Debug)]
-pub enum DecompressionError {
-    #[error(transparent)]
-    CpuCompressError(#[from] CPUCompressError),
-    #[error(transparent)]
-    FromBaseAnyhowError(#[from] anyhow::Error),
+/// The [bit_count] bits of [input_vector] starting at bit [index], a read past its end is a
+/// corruption at [index]
+fn read(input_vector: &BitVec, index: usize, bit_count: usize) -> Result<u32, ChimpError> {
+    if index + bit_count > input_vector.len() {
+        return Err(corrupt_bit(index));
+    }
+    Ok(input_vector.reinterpret_u32(index, bit_count))
 }
 
 impl CPUCompressor {
@@ -42,22 +38,49 @@ impl CPUCompressor {
     }
 }
 
+/// Compresses a borrowed slice of values. The batched compressors neither copy nor pad the
+/// values on the host, so read only or memory mapped values can be passed directly
 #[async_trait]
 pub trait Compressor<T> {
-    async fn compress(&self, vec: &mut Vec<T>) -> Result<CompressResult, CompressionError>;
+    async fn compress(&self, values: &[T]) -> Result<CompressResult, ChimpError>;
 }
 
 #[async_trait]
-pub trait Decompressor<T> {
-    async fn decompress(
+pub trait Decompressor<T: Send> {
+    async fn decompress(&self, bytes: &[u8]) -> Result<DecompressResult<T>, ChimpError>;
+
+    /// Decompress only the values of [range], decoding just the blocks that cover it. The
+    /// blocks are located with the block index footer when the stream has one and by walking
+    /// the block prefixes otherwise
+    async fn decompress_range(
         &self,
-        vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<T>, DecompressionError>;
+        vec: &[u8],
+        range: Range<usize>,
+    ) -> Result<DecompressResult<T>, ChimpError>
+    where
+        Self: Sync,
+    {
+        let index = BlockIndex::read(vec)?;
+        let (sub_stream, skip) = index.extract_range(vec, &range)?;
+        if range.is_empty() {
+            return Ok(Vec::new().into());
+        }
+        let mut result = self.decompress(&sub_stream).await?;
+        let values = result.un_compressed_value_mut();
+        values.drain(..skip);
+        values.truncate(range.len());
+        Ok(result)
+    }
 }
 #[async_trait]
 impl Compressor<f32> for CPUCompressor {
-    async fn compress(&self, vec: &mut Vec<f32>) -> Result<CompressResult, CompressionError> {
-        let mut bit_vec = vec[0].to_bits().to_bit_vec();
+    async fn compress(&self, vec: &[f32]) -> Result<CompressResult, ChimpError> {
+        // the stream starts with its value count, the decoder stops after as many values
+        let mut bit_vec = (vec.len() as u32).to_bit_vec();
+        if vec.is_empty() {
+            return Ok(CompressResult(bit_vec.to_bytes(), 0, 0));
+        }
+        bit_vec.extend(vec[0].to_bits().to_bit_vec());
         let mut last_lead = 0;
         for i in 1..vec.len() {
             let xorred = (vec[i].to_bits()) ^ (vec[i - 1].to_bits());
@@ -70,11 +93,8 @@ impl Compressor<f32> for CPUCompressor {
                 } else {
                     bit_vec.push(true);
                     bit_vec.write_bits(lead as u32, 5);
-                    if lead + trail > 32 {
-                        return Err(CompressionError::from(CPUCompressError::WrongFormat {
-                            index: i,
-                        }));
-                    }
+                    // a non zero xor has at most 31 leading and trailing zeros together
+                    debug_assert!(lead + trail < 32);
                     let center_bits = 32 - lead - trail;
                     bit_vec.write_bits(center_bits as u32, 5);
                     bit_vec.write_bits(xorred >> trail, center_bits as u32);
@@ -98,13 +118,14 @@ impl Compressor<f32> for CPUCompressor {
 
 #[async_trait]
 impl Decompressor<f32> for CPUCompressor {
-    async fn decompress(
-        &self,
-        vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f32>, DecompressionError> {
-        let input_vector = BitVec::from_bytes(vec.as_slice());
+    async fn decompress(&self, vec: &[u8]) -> Result<DecompressResult<f32>, ChimpError> {
+        let input_vector = BitVec::from_bytes(vec);
         let mut input_index: usize;
-        let first_num_u32: u32 = input_vector.reinterpret_u32(0, 32);
+        let value_count = read(&input_vector, 0, 32)? as usize;
+        if value_count == 0 {
+            return Ok(Vec::new().into());
+        }
+        let first_num_u32: u32 = read(&input_vector, 32, 32)?;
         let first_num = f32::from_bits(first_num_u32);
         // if self.debug {
         //     log:: //info!("0:{}", first_num);
@@ -114,76 +135,60 @@ impl Decompressor<f32> for CPUCompressor {
 
         let mut last_num = first_num.to_bits();
         let mut last_lead = 0;
-        input_index = 32;
-        while input_index < input_vector.len() {
+        input_index = 64;
+        while output.len() < value_count {
             if input_index + 1 >= input_vector.len() {
-                break;
+                return Err(corrupt_bit(input_index));
             }
             if input_vector[input_index] {
                 input_index += 1;
                 let mut lead = last_lead;
                 if input_vector[input_index] {
                     input_index += 1;
-                    lead = input_vector.reinterpret_u32(input_index, 5);
+                    lead = read(&input_vector, input_index, 5)?;
                     input_index += 5;
                 } else {
                     input_index += 1;
                 }
-                if lead > 32 {
-                    return Err(DecompressionError::from(CPUCompressError::WrongFormat {
-                        index: input_index,
-                    }));
-                }
                 let mut significant_bits = 32 - lead;
                 if significant_bits == 0 {
                     significant_bits = 32;
                 }
-                let value = input_vector.reinterpret_u32(input_index, significant_bits as usize);
+                let value = read(&input_vector, input_index, significant_bits as usize)?;
                 input_index += (32 - lead) as usize;
                 let value = value ^ last_num;
                 last_num = value;
                 last_lead = lead;
 
-                if value == u32::MAX {
-                    break;
-                } else {
-                    let value_f32 = f32::from_bits(value);
-                    // if self.debug {
-                    //     log:: //info!("{}:{}", output.len(), value_f32);
-                    // }
-                    output.push(value_f32);
-                }
+                let value_f32 = f32::from_bits(value);
+                // if self.debug {
+                //     log:: //info!("{}:{}", output.len(), value_f32);
+                // }
+                output.push(value_f32);
             } else if input_vector[input_index + 1] {
                 input_index += 2;
-                let lead = input_vector.reinterpret_u32(input_index, 5);
+                let lead = read(&input_vector, input_index, 5)?;
                 input_index += 5;
-                let mut significant_bits = input_vector.reinterpret_u32(input_index, 5);
+                let mut significant_bits = read(&input_vector, input_index, 5)?;
                 input_index += 5;
                 if significant_bits == 0 {
                     significant_bits = 32;
                 }
                 if lead + significant_bits > 32 {
-                    return Err(DecompressionError::from(CPUCompressError::WrongFormat {
-                        index: input_index,
-                    }));
+                    return Err(corrupt_bit(input_index));
                 }
                 let trail = 32 - lead - significant_bits;
-                let mut value =
-                    input_vector.reinterpret_u32(input_index, (32 - lead - trail) as usize);
+                let mut value = read(&input_vector, input_index, (32 - lead - trail) as usize)?;
                 input_index += (32 - lead - trail) as usize;
                 value <<= trail;
                 value ^= last_num;
                 last_lead = lead;
                 last_num = value;
-                if value == u32::MAX {
-                    break;
-                } else {
-                    let value_f32 = f32::from_bits(value);
-                    // if self.debug {
-                    //     log:: //info!("{}:{}", output.len(), value_f32);
-                    // }
-                    output.push(value_f32);
-                }
+                let value_f32 = f32::from_bits(value);
+                // if self.debug {
+                //     log:: //info!("{}:{}", output.len(), value_f32);
+                // }
+                output.push(value_f32);
             } else {
                 let value_f32 = f32::from_bits(last_num);
                 last_lead = 32;
@@ -197,6 +202,28 @@ impl Decompressor<f32> for CPUCompressor {
 
         Ok(output.into())
     }
+
+    /// The plain stream has neither a header nor blocks, only its value count, so the whole
+    /// stream is decoded
+    async fn decompress_range(
+        &self,
+        vec: &[u8],
+        range: Range<usize>,
+    ) -> Result<DecompressResult<f32>, ChimpError> {
+        let mut result = self.decompress(vec).await?;
+        let values = result.un_compressed_value_mut();
+        if range.start > range.end || range.end > values.len() {
+            return Err(FormatError::RangeOutOfBounds {
+                start: range.start,
+                end: range.end,
+                value_count: values.len() as u64,
+            }
+            .into());
+        }
+        values.truncate(range.end);
+        values.drain(..range.start);
+        Ok(result)
+    }
 }
 
 pub struct TimedDecompressor<T>
@@ -219,10 +246,7 @@ impl<T> Decompressor<f32> for TimedDecompressor<T>
 where
     T: Decompressor<f32> + Send + Sync,
 {
-    async fn decompress(
-        &self,
-        vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f32>, DecompressionError> {
+    async fn decompress(&self, vec: &[u8]) -> Result<DecompressResult<f32>, ChimpError> {
         // let mut total_millis: u128 = 0;
         // let times = std::time::Instant::now();
         // log:: //info!("Started cpu decompression stage");
@@ -236,6 +260,14 @@ where
         // log:: //info!("============================");
         Ok(output)
     }
+
+    async fn decompress_range(
+        &self,
+        vec: &[u8],
+        range: Range<usize>,
+    ) -> Result<DecompressResult<f32>, ChimpError> {
+        self.decompressor.decompress_range(vec, range).await
+    }
 }
 
 pub struct TimedCompressor<T>
@@ -254,7 +286,7 @@ impl<T> Compressor<f32> for TimedCompressor<T>
 where
     T: Compressor<f32> + Send + Sync,
 {
-    async fn compress(&self, vec: &mut Vec<f32>) -> Result<CompressResult, CompressionError> {
+    async fn compress(&self, vec: &[f32]) -> Result<CompressResult, ChimpError> {
         // let mut total_millis: u128 = 0;
         // let times = std::time::Instant::now();
         // log:: //info!("Started cpu compression stage");
@@ -269,3 +301,91 @@ where
         Ok(output)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pollster::FutureExt;
+
+    #[test]
+    fn special_values_round_trip() {
+        let mut values = vec![
+            f32::from_bits(u32::MAX),
+            1.5,
+            f32::NAN,
+            -f32::NAN,
+            f32::from_bits(0x7fc0_0001),
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            0.0,
+            -0.0,
+            f32::from_bits(1),
+            -f32::from_bits(1),
+            f32::from_bits(0x007f_ffff),
+            f32::MIN_POSITIVE,
+            f32::from_bits(u32::MAX),
+            2.5,
+            2.5,
+            2.5,
+        ];
+        let compressor = CPUCompressor::default();
+        let compressed = compressor
+            .compress(&values)
+            .block_on()
+            .unwrap()
+            .compressed_values();
+        let decompressed = compressor.decompress(&compressed).block_on().unwrap();
+        let bits = |values: &[f32]| values.iter().map(|it| it.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&decompressed.un_compressed_value()), bits(&values));
+
+        // the padding of the last byte does not decode as repeated values
+        values.truncate(values.len() - 2);
+        let compressed = compressor
+            .compress(&values)
+            .block_on()
+            .unwrap()
+            .compressed_values();
+        let decompressed = compressor.decompress(&compressed).block_on().unwrap();
+        assert_eq!(bits(&decompressed.un_compressed_value()), bits(&values));
+    }
+
+    #[test]
+    fn empty_input_round_trip() {
+        let compressor = CPUCompressor::default();
+        let compressed = compressor
+            .compress(&Vec::new())
+            .block_on()
+            .unwrap()
+            .compressed_values();
+        let decompressed = compressor.decompress(&compressed).block_on().unwrap();
+        assert!(decompressed.un_compressed_value().is_empty());
+    }
+
+    #[test]
+    fn damaged_stream_never_panics() {
+        let values = (0..100)
+            .map(|it| (it as f32 / 10.0).sin())
+            .collect::<Vec<_>>();
+        let compressor = CPUCompressor::default();
+        let compressed = compressor
+            .compress(&values)
+            .block_on()
+            .unwrap()
+            .compressed_values();
+        let assert_no_panic = |stream: &[u8]| match compressor.decompress(stream).block_on() {
+            Ok(_) | Err(ChimpError::CorruptInput { .. }) => {}
+            Err(error) => panic!("unexpected {error}"),
+        };
+        // the stream has no header, so every cut is decoded
+        for len in 0..compressed.len() {
+            assert_no_panic(&compressed[..len]);
+        }
+        for byte in (0..compressed.len()).step_by(3) {
+            for bit in 0..8 {
+                let mut corrupted = compressed.clone();
+                corrupted[byte] ^= 1 << bit;
+                assert_no_panic(&corrupted);
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! The error shared by every compressor, decompressor, their pipeline stages and the gpu
+//! plumbing beneath them.
+use crate::config::ConfigError;
+use crate::format::{CodecVariant, FormatError, ValueType};
+use crate::shader_template::TemplateError;
+use thiserror::Error;
+#[cfg(feature = "gpu")]
+use wgpu::{Features, RequestDeviceError};
+
+#[derive(Error, Debug)]
+pub enum ChimpError {
+    #[error("{}", no_adapter_message(.0))]
+    NoAdapter(Option<String>),
+    #[cfg(feature = "gpu")]
+    #[error("The adapter lacks the device features {0:?}")]
+    MissingFeatures(Features),
+    #[cfg(feature = "gpu")]
+    #[error("Could not request a device from the adapter")]
+    RequestDevice(#[from] RequestDeviceError),
+    #[error("A buffer of {size} bytes exceeds the device limit of {limit} bytes")]
+    BufferExceedsLimits { size: u64, limit: u64 },
+    #[error("Shader compilation failed: {0}")]
+    ShaderCompilation(String),
+    #[error(transparent)]
+    ShaderTemplate(#[from] TemplateError),
+    #[error("Could not map a gpu buffer to the host: {0}")]
+    GpuMap(String),
+    #[error("Cannot read an uninitialized buffer")]
+    UninitializedBuffer,
+    #[error("Reading a gpu buffer requires a gpu context")]
+    MissingContext,
+    #[error("The output of {words} words exceeds the {staged_bytes} staged bytes")]
+    StagedOutputTruncated { words: usize, staged_bytes: usize },
+    #[error("Block {block} starting at byte {byte_offset} of the stream is corrupted")]
+    CorruptInput { block: usize, byte_offset: usize },
+    #[error("Unsupported chimp format version {0}")]
+    UnsupportedVersion(u16),
+    #[error(transparent)]
+    InvalidConfig(#[from] ConfigError),
+    #[error(transparent)]
+    Format(FormatError),
+    #[error("{codec:?} works on {width} byte values")]
+    WrongElementWidth { codec: CodecVariant, width: u8 },
+    #[error("{codec} compresses {value_type} values")]
+    WrongValueType {
+        codec: CodecVariant,
+        value_type: ValueType,
+    },
+    #[error("A series of {timestamps} timestamps cannot pair with {values} values")]
+    SeriesLengthMismatch { timestamps: usize, values: usize },
+    #[error("Value {index} is not within the error bound of the stream")]
+    ErrorBoundExceeded { index: usize },
+    #[error("Arrow arrays of type {0} are not supported, only Float32 and Float64 ones")]
+    UnsupportedArrowType(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("The compression thread stopped unexpectedly")]
+    WorkerStopped,
+}
+
+fn no_adapter_message(name: &Option<String>) -> String {
+    match name {
+        Some(name) => format!("Could not find an adapter named {name}"),
+        None => "No gpu adapter is available".to_string(),
+    }
+}
+
+impl From<FormatError> for ChimpError {
+    fn from(value: FormatError) -> Self {
+        match value {
+            FormatError::Corrupted { block, byte_offset } => {
+                ChimpError::CorruptInput { block, byte_offset }
+            }
+            FormatError::UnsupportedVersion(version) => ChimpError::UnsupportedVersion(version),
+            other => ChimpError::Format(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_errors_keep_their_structure_through_the_stages() {
+        let stage = || -> Result<(), ChimpError> {
+            Err(FormatError::Corrupted {
+                block: 2,
+                byte_offset: 64,
+            })?
+        };
+        assert!(matches!(
+            stage(),
+            Err(ChimpError::CorruptInput {
+                block: 2,
+                byte_offset: 64
+            })
+        ));
+
+        let stage = || -> Result<(), ChimpError> { Err(FormatError::UnsupportedVersion(9))? };
+        assert!(matches!(stage(), Err(ChimpError::UnsupportedVersion(9))));
+    }
+}
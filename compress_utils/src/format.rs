@@ -0,0 +1,982 @@
+//! The container header that is written in front of every batched Chimp stream.
+//!
+//! The header is a fixed [HEADER_SIZE] byte little endian record:
+//!
+//! | offset | size | field                 |
+//! |--------|------|-----------------------|
+//! | 0      | 4    | magic (`CHMP`)        |
+//! | 4      | 2    | format version        |
+//! | 6      | 1    | codec variant         |
+//! | 7      | 1    | element width (bytes) |
+//! | 8      | 4    | flags                 |
+//! | 12     | 4    | `n` (0 for plain)     |
+//! | 16     | 4    | buffer size           |
+//! | 20     | 4    | error bound (`f32`)   |
+//! | 24     | 8    | total value count     |
+//!
+//! The size is a multiple of 8 so the payload that follows keeps the alignment
+//! the decompressors rely on when they cast the bytes to `u32`/`u64` words.
+//!
+//! When [HeaderFlags::BLOCK_CHECKSUMS] is set the block stream is followed by the
+//! [BlockChecksums] trailer, one CRC32C per block. When [HeaderFlags::BLOCK_INDEX] is set the
+//! stream ends with a [BlockIndex] footer, one 16 byte entry per block holding the byte
+//! offset of the block in the payload and the ordinal of its first value. Both trailers are
+//! zero padded to a multiple of 8 bytes and end with their number of entries as a `u64`.
+//!
+//! The error bound is only set, and otherwise zero, when [HeaderFlags::LOSSY_ABSOLUTE] or
+//! [HeaderFlags::LOSSY_RELATIVE] tells the values were quantized to it, see [crate::lossy].
+//!
+//! The Chimp variants also compress integers of their element width as the floats with the
+//! same bits, [HeaderFlags::INTEGER] and [HeaderFlags::SIGNED] tell which type the values
+//! have, see [ChimpHeader::value_type] and [crate::integer]. The 16 bit variant holds IEEE
+//! half precision floats unless [HeaderFlags::BFLOAT16] is set, and writes its blocks in the
+//! 32 bit word layout.
+use crate::checksum::{BlockChecksums, CHECKSUM_SIZE};
+use crate::lossy::ErrorBound;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use thiserror::Error;
+
+/// The magic bytes every stream starts with
+pub const CHIMP_MAGIC: [u8; 4] = *b"CHMP";
+/// The format version written by this crate
+pub const FORMAT_VERSION: u16 = 1;
+/// Size of the header in bytes
+pub const HEADER_SIZE: usize = 32;
+
+/// Which compressor produced the stream
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CodecVariant {
+    Chimp32 = 1,
+    Chimp64 = 2,
+    ChimpN32 = 3,
+    ChimpN64 = 4,
+    DeltaOfDelta64 = 5,
+    Chimp16 = 6,
+    /// The Gorilla encoding on the 32 bit pipeline, kept as a baseline for Chimp
+    Gorilla32 = 7,
+}
+
+impl CodecVariant {
+    /// The size in bytes of a single uncompressed value
+    pub fn element_width(&self) -> u8 {
+        match self {
+            CodecVariant::Chimp16 => 2,
+            CodecVariant::Chimp32 | CodecVariant::ChimpN32 | CodecVariant::Gorilla32 => 4,
+            CodecVariant::Chimp64 | CodecVariant::ChimpN64 | CodecVariant::DeltaOfDelta64 => 8,
+        }
+    }
+
+    /// The type of the values the variant compresses
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            CodecVariant::Chimp16 => ValueType::F16,
+            CodecVariant::Chimp32 | CodecVariant::ChimpN32 | CodecVariant::Gorilla32 => {
+                ValueType::F32
+            }
+            CodecVariant::Chimp64 | CodecVariant::ChimpN64 => ValueType::F64,
+            CodecVariant::DeltaOfDelta64 => ValueType::I64,
+        }
+    }
+
+    /// Whether the variant stores a previous value index of `log2(n)` bits
+    pub fn uses_n(&self) -> bool {
+        matches!(self, CodecVariant::ChimpN32 | CodecVariant::ChimpN64)
+    }
+}
+
+impl TryFrom<u8> for CodecVariant {
+    type Error = FormatError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(CodecVariant::Chimp32),
+            2 => Ok(CodecVariant::Chimp64),
+            3 => Ok(CodecVariant::ChimpN32),
+            4 => Ok(CodecVariant::ChimpN64),
+            5 => Ok(CodecVariant::DeltaOfDelta64),
+            6 => Ok(CodecVariant::Chimp16),
+            7 => Ok(CodecVariant::Gorilla32),
+            other => Err(FormatError::UnknownCodec(other)),
+        }
+    }
+}
+
+impl Display for CodecVariant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            CodecVariant::Chimp32 => "chimp32",
+            CodecVariant::Chimp64 => "chimp64",
+            CodecVariant::ChimpN32 => "chimpn32",
+            CodecVariant::ChimpN64 => "chimpn64",
+            CodecVariant::DeltaOfDelta64 => "dod64",
+            CodecVariant::Chimp16 => "chimp16",
+            CodecVariant::Gorilla32 => "gorilla32",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The type of the uncompressed values of a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    F16,
+    BF16,
+    F32,
+    F64,
+    I32,
+    U32,
+    I64,
+    U64,
+}
+
+impl ValueType {
+    /// The size of a value in bytes
+    pub fn width(&self) -> u8 {
+        match self {
+            ValueType::F16 | ValueType::BF16 => 2,
+            ValueType::F32 | ValueType::I32 | ValueType::U32 => 4,
+            ValueType::F64 | ValueType::I64 | ValueType::U64 => 8,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        !matches!(
+            self,
+            ValueType::F16 | ValueType::BF16 | ValueType::F32 | ValueType::F64
+        )
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, ValueType::I32 | ValueType::I64)
+    }
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ValueType::F16 => "f16",
+            ValueType::BF16 => "bf16",
+            ValueType::F32 => "f32",
+            ValueType::F64 => "f64",
+            ValueType::I32 => "i32",
+            ValueType::U32 => "u32",
+            ValueType::I64 => "i64",
+            ValueType::U64 => "u64",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Bit flags stored in the header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct HeaderFlags(pub u32);
+
+impl HeaderFlags {
+    /// The last buffer was filled up with zeros to reach the buffer size
+    pub const PADDED: u32 = 1;
+    /// The block stream is followed by a [BlockIndex] footer
+    pub const BLOCK_INDEX: u32 = 2;
+    /// The block stream is followed by a [BlockChecksums] trailer
+    pub const BLOCK_CHECKSUMS: u32 = 4;
+    /// The header was written before the values were known, the value count is the sum of
+    /// the counts of the blocks
+    pub const STREAMED: u32 = 8;
+    /// The values were quantized to the absolute error bound stored in the header
+    pub const LOSSY_ABSOLUTE: u32 = 16;
+    /// The values were quantized to the relative error bound stored in the header
+    pub const LOSSY_RELATIVE: u32 = 32;
+    /// The values are integers compressed as the floats with the same bits
+    pub const INTEGER: u32 = 64;
+    /// The integer values are signed
+    pub const SIGNED: u32 = 128;
+    /// Every integer but the first of a buffer was replaced by the zig-zag coded difference
+    /// to the one before it, see [crate::integer::IntegerEncoding::ZigZagDelta]
+    pub const ZIGZAG_DELTA: u32 = 256;
+    /// The 16 bit values are bfloat16 rather than IEEE half precision floats
+    pub const BFLOAT16: u32 = 512;
+
+    pub fn contains(&self, flag: u32) -> bool {
+        self.0 & flag == flag
+    }
+
+    pub fn with(self, flag: u32) -> Self {
+        Self(self.0 | flag)
+    }
+
+    pub fn without(self, flag: u32) -> Self {
+        Self(self.0 & !flag)
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    #[error("Input of {len} bytes is too short to hold a chimp header")]
+    TooShort { len: usize },
+    #[error("Input does not start with the chimp magic bytes")]
+    BadMagic,
+    #[error("Unsupported chimp format version {0}, the newest supported is {FORMAT_VERSION}")]
+    UnsupportedVersion(u16),
+    #[error("Unknown codec variant id {0}")]
+    UnknownCodec(u8),
+    #[error("Stream was compressed with {found} but is decompressed as {expected}")]
+    CodecMismatch {
+        expected: CodecVariant,
+        found: CodecVariant,
+    },
+    #[error("Header element width {found} does not match the codec width {expected}")]
+    ElementWidthMismatch { expected: u8, found: u8 },
+    #[error("Stream was compressed with n={found} but the decompressor uses n={expected}")]
+    NMismatch { expected: u32, found: u32 },
+    #[error("Stream was compressed with buffer size {found} but the decompressor uses {expected}")]
+    BufferSizeMismatch { expected: u32, found: u32 },
+    #[error("Header declares a buffer size of 0")]
+    ZeroBufferSize,
+    #[error("Header of a {0} stream declares n=0")]
+    ZeroN(CodecVariant),
+    #[error("Block {block} starting at byte {byte_offset} of the stream is corrupted")]
+    Corrupted { block: usize, byte_offset: usize },
+    #[error("Trailer of the block stream does not fit in a payload of {len} bytes")]
+    TruncatedTrailer { len: usize },
+    #[error("Range {start}..{end} is out of bounds for a stream of {value_count} values")]
+    RangeOutOfBounds {
+        start: usize,
+        end: usize,
+        value_count: u64,
+    },
+}
+
+/// The self-describing header of a compressed stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChimpHeader {
+    pub version: u16,
+    pub codec: CodecVariant,
+    pub element_width: u8,
+    pub flags: HeaderFlags,
+    pub n: u32,
+    pub buffer_size: u32,
+    pub value_count: u64,
+    pub error_bound: Option<ErrorBound>,
+}
+
+impl ChimpHeader {
+    pub fn new(codec: CodecVariant, n: usize, buffer_size: usize, value_count: usize) -> Self {
+        let mut flags = HeaderFlags::default();
+        if !value_count.is_multiple_of(buffer_size) {
+            flags = flags.with(HeaderFlags::PADDED);
+        }
+        Self {
+            version: FORMAT_VERSION,
+            codec,
+            element_width: codec.element_width(),
+            flags,
+            n: if codec.uses_n() { n as u32 } else { 0 },
+            buffer_size: buffer_size as u32,
+            value_count: value_count as u64,
+            error_bound: None,
+        }
+    }
+
+    /// Record the [error_bound] the values were quantized to, [None] for a lossless stream
+    pub fn with_error_bound(mut self, error_bound: Option<ErrorBound>) -> Self {
+        self.flags = self
+            .flags
+            .without(HeaderFlags::LOSSY_ABSOLUTE | HeaderFlags::LOSSY_RELATIVE);
+        match error_bound {
+            Some(ErrorBound::Absolute(_)) => {
+                self.flags = self.flags.with(HeaderFlags::LOSSY_ABSOLUTE)
+            }
+            Some(ErrorBound::Relative(_)) => {
+                self.flags = self.flags.with(HeaderFlags::LOSSY_RELATIVE)
+            }
+            None => {}
+        }
+        self.error_bound = error_bound;
+        self
+    }
+
+    /// Record that the stream holds [value_type] values, which has to be the type of the
+    /// codec, an integer type of its element width or [ValueType::BF16] for [CodecVariant::Chimp16]
+    pub fn with_value_type(mut self, value_type: ValueType) -> Self {
+        self.flags = self
+            .flags
+            .without(HeaderFlags::INTEGER | HeaderFlags::SIGNED | HeaderFlags::BFLOAT16);
+        if value_type == ValueType::BF16 {
+            self.flags = self.flags.with(HeaderFlags::BFLOAT16);
+        } else if value_type != self.codec.value_type() {
+            self.flags = self.flags.with(HeaderFlags::INTEGER);
+            if value_type.is_signed() {
+                self.flags = self.flags.with(HeaderFlags::SIGNED);
+            }
+        }
+        self
+    }
+
+    /// The type of the values of the stream
+    pub fn value_type(&self) -> ValueType {
+        if self.flags.contains(HeaderFlags::BFLOAT16) {
+            return ValueType::BF16;
+        }
+        if !self.flags.contains(HeaderFlags::INTEGER) {
+            return self.codec.value_type();
+        }
+        match (self.element_width, self.flags.contains(HeaderFlags::SIGNED)) {
+            (4, true) => ValueType::I32,
+            (4, false) => ValueType::U32,
+            (_, true) => ValueType::I64,
+            (_, false) => ValueType::U64,
+        }
+    }
+
+    /// The largest size in bytes the rest of a block can have after its prefix. No codec
+    /// spends more than twice the width of a value on it besides the previous value index of
+    /// the ChimpN variants, and a block is padded by at most one word
+    pub(crate) fn max_block_rest(&self) -> usize {
+        let index_bits = self.n.checked_ilog2().unwrap_or(0) as usize;
+        let value_bits = 2 * 8 * self.element_width as usize + index_bits;
+        (self.buffer_size as usize * value_bits).div_ceil(8) + BLOCK_PREFIX_SIZE
+    }
+
+    pub fn with_flag(mut self, flag: u32) -> Self {
+        self.flags = self.flags.with(flag);
+        self
+    }
+
+    /// The header of a stream whose values are not known yet, see [HeaderFlags::STREAMED]
+    pub fn streamed(mut self) -> Self {
+        self.flags = self
+            .flags
+            .without(HeaderFlags::PADDED)
+            .with(HeaderFlags::STREAMED);
+        self.value_count = 0;
+        self
+    }
+
+    /// Replace the value count of a streamed header by the known [value_count]
+    pub fn with_value_count(mut self, value_count: u64) -> Self {
+        self.flags = self
+            .flags
+            .without(HeaderFlags::STREAMED | HeaderFlags::PADDED);
+        if !value_count.is_multiple_of(self.buffer_size as u64) {
+            self.flags = self.flags.with(HeaderFlags::PADDED);
+        }
+        self.value_count = value_count;
+        self
+    }
+
+    /// How many zeros were appended to the last buffer
+    pub fn padding(&self) -> usize {
+        let buffer_size = self.buffer_size as u64;
+        ((buffer_size - self.value_count % buffer_size) % buffer_size) as usize
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&CHIMP_MAGIC);
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6] = self.codec as u8;
+        bytes[7] = self.element_width;
+        bytes[8..12].copy_from_slice(&self.flags.0.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.n.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.buffer_size.to_le_bytes());
+        let error_bound = self.error_bound.map_or(0.0, |bound| bound.value());
+        bytes[20..24].copy_from_slice(&error_bound.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.value_count.to_le_bytes());
+        bytes
+    }
+
+    /// Prepend the header to an already compressed block stream
+    pub fn prepend_to(&self, payload: Vec<u8>) -> Vec<u8> {
+        let mut stream = Vec::with_capacity(HEADER_SIZE + payload.len());
+        stream.extend_from_slice(&self.to_bytes());
+        stream.extend(payload);
+        stream
+    }
+
+    pub fn read(bytes: &[u8]) -> Result<Self, FormatError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(FormatError::TooShort { len: bytes.len() });
+        }
+        if bytes[0..4] != CHIMP_MAGIC {
+            return Err(FormatError::BadMagic);
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+        let codec = CodecVariant::try_from(bytes[6])?;
+        let element_width = bytes[7];
+        if element_width != codec.element_width() {
+            return Err(FormatError::ElementWidthMismatch {
+                expected: codec.element_width(),
+                found: element_width,
+            });
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let n = u32_at(12);
+        if codec.uses_n() && n == 0 {
+            return Err(FormatError::ZeroN(codec));
+        }
+        let buffer_size = u32_at(16);
+        if buffer_size == 0 {
+            return Err(FormatError::ZeroBufferSize);
+        }
+        let flags = HeaderFlags(u32_at(8));
+        let error_bound = f32::from_bits(u32_at(20));
+        let error_bound = if flags.contains(HeaderFlags::LOSSY_ABSOLUTE) {
+            Some(ErrorBound::Absolute(error_bound))
+        } else if flags.contains(HeaderFlags::LOSSY_RELATIVE) {
+            Some(ErrorBound::Relative(error_bound))
+        } else {
+            None
+        };
+        Ok(Self {
+            version,
+            codec,
+            element_width,
+            flags,
+            n,
+            buffer_size,
+            value_count: u64::from_le_bytes(bytes[24..32].try_into().unwrap()),
+            error_bound,
+        })
+    }
+
+    /// Parse the header of [bytes] and check that it was written by [codec],
+    /// returning the header along with the blocks that follow it, without any trailer.
+    ///
+    /// The block prefixes are walked, and the blocks are checked against their checksums
+    /// when the stream has them, so a damaged stream fails with [FormatError::Corrupted]
+    /// instead of reaching the decoders
+    pub fn split(bytes: &[u8], codec: CodecVariant) -> Result<(Self, &[u8]), FormatError> {
+        let header = Self::read(bytes)?;
+        if header.codec != codec {
+            return Err(FormatError::CodecMismatch {
+                expected: codec,
+                found: header.codec,
+            });
+        }
+        let sections = PayloadSections::read(&header, &bytes[HEADER_SIZE..])?;
+        if let Some(checksums) = sections.checksums {
+            BlockChecksums::verify(&header, sections.blocks, checksums)?;
+        }
+        BlockIndex::scan(&header, sections.blocks)?;
+        Ok((header, sections.blocks))
+    }
+
+    pub fn expect_n(&self, n: usize) -> Result<(), FormatError> {
+        if self.n as usize != n {
+            return Err(FormatError::NMismatch {
+                expected: n as u32,
+                found: self.n,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn expect_buffer_size(&self, buffer_size: usize) -> Result<(), FormatError> {
+        if self.buffer_size as usize != buffer_size {
+            return Err(FormatError::BufferSizeMismatch {
+                expected: buffer_size as u32,
+                found: self.buffer_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The position of a single block of the stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockIndexEntry {
+    /// Offset of the block from the start of the payload
+    pub byte_offset: u64,
+    /// Ordinal of the first value stored in the block
+    pub first_value: u64,
+}
+
+/// The location of every block of a stream, either read from the footer or found by
+/// walking the block prefixes.
+///
+/// Every block of every codec starts with an 8 byte prefix holding the number of values
+/// minus one and the size of the rest of the block in bytes, the 32 bit codecs store them as
+/// two `u32` words and the 64 bit codecs as the high and low half of one `u64` word.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockIndex {
+    entries: Vec<BlockIndexEntry>,
+    value_count: u64,
+}
+
+pub(crate) const BLOCK_PREFIX_SIZE: usize = 8;
+const INDEX_ENTRY_SIZE: usize = 16;
+
+impl BlockIndex {
+    /// Walk the block prefixes of the [blocks] of a stream, failing with
+    /// [FormatError::Corrupted] on the first prefix that does not describe a valid block
+    pub fn scan(header: &ChimpHeader, blocks: &[u8]) -> Result<Self, FormatError> {
+        let mut entries = Vec::new();
+        let mut byte_offset = 0usize;
+        let mut value_count = 0u64;
+        while byte_offset < blocks.len() {
+            let corrupted = FormatError::Corrupted {
+                block: entries.len(),
+                byte_offset: HEADER_SIZE + byte_offset,
+            };
+            let (count, rest) =
+                Self::read_prefix(header.codec, &blocks[byte_offset..]).ok_or(corrupted.clone())?;
+            let block_end = byte_offset + BLOCK_PREFIX_SIZE + rest;
+            if count > header.buffer_size as usize
+                || rest % header.element_width as usize != 0
+                || block_end > blocks.len()
+            {
+                return Err(corrupted);
+            }
+            entries.push(BlockIndexEntry {
+                byte_offset: byte_offset as u64,
+                first_value: value_count,
+            });
+            byte_offset = block_end;
+            value_count += count as u64;
+        }
+        Ok(Self {
+            entries,
+            value_count,
+        })
+    }
+
+    /// Read the index of a whole stream, from the footer when the stream has one.
+    ///
+    /// The entries of a footer must start at the first value and move forward through both
+    /// the values and the blocks of the payload, the first entry that does not fails with
+    /// [FormatError::Corrupted]
+    pub fn read(stream: &[u8]) -> Result<Self, FormatError> {
+        let header = ChimpHeader::read(stream)?;
+        let sections = PayloadSections::read(&header, &stream[HEADER_SIZE..])?;
+        let Some(footer) = sections.index else {
+            return Self::scan(&header, sections.blocks);
+        };
+        let entries: Vec<_> = footer
+            .chunks_exact(INDEX_ENTRY_SIZE)
+            .map(|entry| BlockIndexEntry {
+                byte_offset: u64::from_le_bytes(entry[0..8].try_into().unwrap()),
+                first_value: u64::from_le_bytes(entry[8..16].try_into().unwrap()),
+            })
+            .collect();
+        let mut previous: Option<&BlockIndexEntry> = None;
+        for (block, entry) in entries.iter().enumerate() {
+            let in_order = match previous {
+                None => entry.first_value == 0,
+                Some(previous) => {
+                    entry.first_value > previous.first_value
+                        && entry.byte_offset > previous.byte_offset
+                }
+            };
+            if !in_order
+                || entry.first_value >= header.value_count
+                || entry.byte_offset >= sections.blocks.len() as u64
+            {
+                return Err(FormatError::Corrupted {
+                    block,
+                    byte_offset: HEADER_SIZE.saturating_add(entry.byte_offset as usize),
+                });
+            }
+            previous = Some(entry);
+        }
+        Ok(Self {
+            entries,
+            value_count: header.value_count,
+        })
+    }
+
+    /// Append the index of the blocks of [stream] as a footer and mark it in the header.
+    /// The value count of a streamed header is filled in from the blocks. Streams that
+    /// already carry a footer are returned unchanged
+    pub fn append_to(stream: Vec<u8>) -> Result<Vec<u8>, FormatError> {
+        let mut header = ChimpHeader::read(&stream)?;
+        if header.flags.contains(HeaderFlags::BLOCK_INDEX) {
+            return Ok(stream);
+        }
+        let sections = PayloadSections::read(&header, &stream[HEADER_SIZE..])?;
+        let index = Self::scan(&header, sections.blocks)?;
+        if header.flags.contains(HeaderFlags::STREAMED) {
+            header = header.with_value_count(index.value_count);
+        }
+        let header = header.with_flag(HeaderFlags::BLOCK_INDEX);
+        let mut stream = stream;
+        stream[0..HEADER_SIZE].copy_from_slice(&header.to_bytes());
+        stream.extend(index.footer_bytes());
+        Ok(stream)
+    }
+
+    /// The size in bytes of the footer holding this index
+    pub fn footer_size(&self) -> usize {
+        self.entries.len() * INDEX_ENTRY_SIZE + size_of::<u64>()
+    }
+
+    pub fn entries(&self) -> &[BlockIndexEntry] {
+        &self.entries
+    }
+
+    pub fn value_count(&self) -> u64 {
+        self.value_count
+    }
+
+    /// The indexes of the blocks holding the values of [range]
+    pub fn blocks_covering(&self, range: &Range<usize>) -> Range<usize> {
+        if range.is_empty() || self.entries.is_empty() {
+            return 0..0;
+        }
+        let Some(first) = self
+            .entries
+            .partition_point(|it| it.first_value <= range.start as u64)
+            .checked_sub(1)
+        else {
+            return 0..0;
+        };
+        let last = self
+            .entries
+            .partition_point(|it| it.first_value < range.end as u64);
+        first..last
+    }
+
+    /// Build a stream holding only the blocks that cover [range] of [stream], along with the
+    /// number of leading values of that stream that precede [range]. The checksums of the
+    /// blocks are carried over when the stream has them
+    pub fn extract_range(
+        &self,
+        stream: &[u8],
+        range: &Range<usize>,
+    ) -> Result<(Vec<u8>, usize), FormatError> {
+        let header = ChimpHeader::read(stream)?;
+        if range.start > range.end || range.end as u64 > self.value_count {
+            return Err(FormatError::RangeOutOfBounds {
+                start: range.start,
+                end: range.end,
+                value_count: self.value_count,
+            });
+        }
+        let sections = PayloadSections::read(&header, &stream[HEADER_SIZE..])?;
+        let payload = sections.blocks;
+        let blocks = self.blocks_covering(range);
+        let byte_offset_of = |block: usize| {
+            self.entries
+                .get(block)
+                .map_or(payload.len(), |it| it.byte_offset as usize)
+        };
+        let value_of = |block: usize| {
+            self.entries
+                .get(block)
+                .map_or(self.value_count, |it| it.first_value) as usize
+        };
+        let (start_byte, end_byte) = (byte_offset_of(blocks.start), byte_offset_of(blocks.end));
+        if start_byte > end_byte || end_byte > payload.len() {
+            return Err(FormatError::TruncatedTrailer { len: payload.len() });
+        }
+        let first_value = value_of(blocks.start);
+        let mut sub_header = ChimpHeader::new(
+            header.codec,
+            header.n as usize,
+            header.buffer_size as usize,
+            value_of(blocks.end) - first_value,
+        )
+        .with_error_bound(header.error_bound)
+        .with_value_type(header.value_type())
+        .with_flag(header.flags.0 & HeaderFlags::ZIGZAG_DELTA);
+        let mut sub_payload = payload[start_byte..end_byte].to_vec();
+        if let Some(checksums) = sections.checksums {
+            let checksums = checksums
+                .get(blocks.start * CHECKSUM_SIZE..blocks.end * CHECKSUM_SIZE)
+                .ok_or(FormatError::TruncatedTrailer { len: payload.len() })?;
+            sub_header = sub_header.with_flag(HeaderFlags::BLOCK_CHECKSUMS);
+            sub_payload.extend(trailer_bytes(checksums.to_vec(), blocks.len()));
+        }
+        let skip = range
+            .start
+            .checked_sub(first_value)
+            .ok_or(FormatError::Corrupted {
+                block: blocks.start,
+                byte_offset: HEADER_SIZE + start_byte,
+            })?;
+        Ok((sub_header.prepend_to(sub_payload), skip))
+    }
+
+    fn footer_bytes(&self) -> Vec<u8> {
+        let entries = self
+            .entries
+            .iter()
+            .flat_map(|entry| {
+                [entry.byte_offset, entry.first_value]
+                    .into_iter()
+                    .flat_map(u64::to_le_bytes)
+            })
+            .collect();
+        trailer_bytes(entries, self.entries.len())
+    }
+
+    /// The value count and the size of the rest of the block following the prefix
+    pub(crate) fn read_prefix(codec: CodecVariant, block: &[u8]) -> Option<(usize, usize)> {
+        let prefix = block.get(0..BLOCK_PREFIX_SIZE)?;
+        let u32_at = |at: usize| u32::from_le_bytes(prefix[at..at + 4].try_into().unwrap());
+        // the 16 bit variant writes its blocks in 32 bit words like the 32 bit ones
+        let (count, rest) = match codec.element_width() {
+            8 => (u32_at(4), u32_at(0)),
+            _ => (u32_at(0), u32_at(4)),
+        };
+        Some((count as usize + 1, rest as usize))
+    }
+}
+
+/// The parts of the payload following the header. Every optional section is a trailer of
+/// fixed size entries, zero padded to a multiple of 8 bytes and followed by the number of
+/// entries as a `u64`, the checksums come first and the block index last
+pub(crate) struct PayloadSections<'a> {
+    pub(crate) blocks: &'a [u8],
+    pub(crate) checksums: Option<&'a [u8]>,
+    pub(crate) index: Option<&'a [u8]>,
+}
+
+impl<'a> PayloadSections<'a> {
+    pub(crate) fn read(header: &ChimpHeader, payload: &'a [u8]) -> Result<Self, FormatError> {
+        let mut blocks = payload;
+        let mut index = None;
+        let mut checksums = None;
+        if header.flags.contains(HeaderFlags::BLOCK_INDEX) {
+            let (rest, entries) = split_trailer(blocks, INDEX_ENTRY_SIZE)?;
+            blocks = rest;
+            index = Some(entries);
+        }
+        if header.flags.contains(HeaderFlags::BLOCK_CHECKSUMS) {
+            let (rest, entries) = split_trailer(blocks, CHECKSUM_SIZE)?;
+            blocks = rest;
+            checksums = Some(entries);
+        }
+        Ok(Self {
+            blocks,
+            checksums,
+            index,
+        })
+    }
+}
+
+/// The [entries] of a trailer followed by the padding and the entry [count]
+pub(crate) fn trailer_bytes(mut entries: Vec<u8>, count: usize) -> Vec<u8> {
+    entries.resize(entries.len().next_multiple_of(size_of::<u64>()), 0);
+    entries.extend_from_slice(&(count as u64).to_le_bytes());
+    entries
+}
+
+/// Split the trailer of [entry_size] byte entries off the end of [payload]
+fn split_trailer(payload: &[u8], entry_size: usize) -> Result<(&[u8], &[u8]), FormatError> {
+    let truncated = FormatError::TruncatedTrailer { len: payload.len() };
+    let count_offset = payload
+        .len()
+        .checked_sub(size_of::<u64>())
+        .ok_or(truncated.clone())?;
+    let count = u64::from_le_bytes(payload[count_offset..].try_into().unwrap()) as usize;
+    let entries_size = count.checked_mul(entry_size).ok_or(truncated.clone())?;
+    let entries_offset = entries_size
+        .checked_next_multiple_of(size_of::<u64>())
+        .and_then(|size| count_offset.checked_sub(size))
+        .ok_or(truncated)?;
+    Ok((
+        &payload[..entries_offset],
+        &payload[entries_offset..entries_offset + entries_size],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let header = ChimpHeader::new(CodecVariant::ChimpN64, 128, 1024, 5000);
+        let bytes = header.to_bytes();
+        let parsed = ChimpHeader::read(&bytes).unwrap();
+        assert_eq!(header, parsed);
+        assert!(parsed.flags.contains(HeaderFlags::PADDED));
+        assert_eq!(parsed.padding(), 5120 - 5000);
+
+        let bound = ErrorBound::relative(1e-4).unwrap();
+        let lossy = header.with_error_bound(Some(bound));
+        let parsed = ChimpHeader::read(&lossy.to_bytes()).unwrap();
+        assert_eq!(parsed.error_bound, Some(bound));
+        assert!(parsed.flags.contains(HeaderFlags::LOSSY_RELATIVE));
+        assert_eq!(parsed.with_error_bound(None), header);
+
+        let halves = ChimpHeader::new(CodecVariant::Chimp16, 0, 256, 10);
+        assert_eq!(halves.element_width, 2);
+        assert_eq!(halves.value_type(), ValueType::F16);
+        let parsed =
+            ChimpHeader::read(&halves.with_value_type(ValueType::BF16).to_bytes()).unwrap();
+        assert_eq!(parsed.value_type(), ValueType::BF16);
+        assert_eq!(parsed.with_value_type(ValueType::F16), halves);
+    }
+
+    #[test]
+    fn plain_codecs_do_not_store_n() {
+        let header = ChimpHeader::new(CodecVariant::Chimp32, 128, 256, 512);
+        assert_eq!(header.n, 0);
+        assert_eq!(header.padding(), 0);
+        assert!(!header.flags.contains(HeaderFlags::PADDED));
+    }
+
+    #[test]
+    fn split_rejects_other_codecs() {
+        // a single block holding one value and nothing after its prefix
+        let stream = ChimpHeader::new(CodecVariant::ChimpN32, 64, 256, 1).prepend_to(vec![0; 8]);
+        let err = ChimpHeader::split(&stream, CodecVariant::Chimp32).unwrap_err();
+        assert_eq!(
+            err,
+            FormatError::CodecMismatch {
+                expected: CodecVariant::Chimp32,
+                found: CodecVariant::ChimpN32
+            }
+        );
+        let (_, payload) = ChimpHeader::split(&stream, CodecVariant::ChimpN32).unwrap();
+        assert_eq!(payload, &[0; 8]);
+
+        let stream = ChimpHeader::new(CodecVariant::ChimpN32, 64, 256, 10).prepend_to(vec![1, 2]);
+        assert_eq!(
+            ChimpHeader::split(&stream, CodecVariant::ChimpN32).unwrap_err(),
+            FormatError::Corrupted {
+                block: 0,
+                byte_offset: HEADER_SIZE
+            }
+        );
+    }
+
+    #[test]
+    fn read_rejects_garbage() {
+        assert_eq!(
+            ChimpHeader::read(&[0u8; 4]),
+            Err(FormatError::TooShort { len: 4 })
+        );
+        assert_eq!(
+            ChimpHeader::read(&[0u8; HEADER_SIZE]),
+            Err(FormatError::BadMagic)
+        );
+        let mut bytes = ChimpHeader::new(CodecVariant::Chimp64, 0, 256, 1).to_bytes();
+        bytes[4] = 9;
+        assert_eq!(
+            ChimpHeader::read(&bytes),
+            Err(FormatError::UnsupportedVersion(9))
+        );
+
+        let mut bytes = ChimpHeader::new(CodecVariant::Chimp64, 0, 256, 1).to_bytes();
+        bytes[16..20].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(ChimpHeader::read(&bytes), Err(FormatError::ZeroBufferSize));
+        let mut bytes = ChimpHeader::new(CodecVariant::ChimpN32, 64, 256, 1).to_bytes();
+        bytes[12..16].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(
+            ChimpHeader::read(&bytes),
+            Err(FormatError::ZeroN(CodecVariant::ChimpN32))
+        );
+    }
+
+    /// A stream of blocks of the given sizes in the 32 bit layout, each block holding its
+    /// ordinal in every word
+    fn stream_of_blocks(counts: &[usize]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for (block, count) in counts.iter().enumerate() {
+            payload.extend_from_slice(&(*count as u32 - 1).to_le_bytes());
+            payload.extend_from_slice(&8u32.to_le_bytes());
+            payload.extend_from_slice(&[block as u8; 8]);
+        }
+        let total = counts.iter().sum();
+        ChimpHeader::new(CodecVariant::Chimp32, 0, 4, total).prepend_to(payload)
+    }
+
+    #[test]
+    fn block_index_footer_round_trip() {
+        let stream = stream_of_blocks(&[4, 4, 4, 2]);
+        let scanned = BlockIndex::read(&stream).unwrap();
+        assert_eq!(scanned.value_count(), 14);
+        assert_eq!(
+            scanned
+                .entries()
+                .iter()
+                .map(|it| (it.byte_offset, it.first_value))
+                .collect::<Vec<_>>(),
+            vec![(0, 0), (16, 4), (32, 8), (48, 12)]
+        );
+
+        let indexed = BlockIndex::append_to(stream.clone()).unwrap();
+        assert_eq!(indexed.len(), stream.len() + scanned.footer_size());
+        assert_eq!(BlockIndex::read(&indexed).unwrap(), scanned);
+        let (header, payload) = ChimpHeader::split(&indexed, CodecVariant::Chimp32).unwrap();
+        assert!(header.flags.contains(HeaderFlags::BLOCK_INDEX));
+        assert_eq!(payload, &stream[HEADER_SIZE..]);
+    }
+
+    #[test]
+    fn block_index_rejects_malformed_footer() {
+        let stream = BlockIndex::append_to(stream_of_blocks(&[4, 4, 4, 2])).unwrap();
+        let footer = stream.len() - size_of::<u64>() - 4 * INDEX_ENTRY_SIZE;
+        let with_entry = |block: usize, byte_offset: u64, first_value: u64| {
+            let mut stream = stream.clone();
+            let at = footer + block * INDEX_ENTRY_SIZE;
+            stream[at..at + 8].copy_from_slice(&byte_offset.to_le_bytes());
+            stream[at + 8..at + 16].copy_from_slice(&first_value.to_le_bytes());
+            stream
+        };
+        let corrupted = |block: usize, byte_offset: usize| {
+            Err(FormatError::Corrupted {
+                block,
+                byte_offset: HEADER_SIZE + byte_offset,
+            })
+        };
+
+        // the first block does not start at the first value
+        assert_eq!(BlockIndex::read(&with_entry(0, 0, 2)), corrupted(0, 0));
+        // the values go backwards
+        assert_eq!(BlockIndex::read(&with_entry(2, 32, 3)), corrupted(2, 32));
+        // the blocks go backwards
+        assert_eq!(BlockIndex::read(&with_entry(2, 8, 8)), corrupted(2, 8));
+        // a block past the end of the payload
+        assert_eq!(BlockIndex::read(&with_entry(3, 64, 12)), corrupted(3, 64));
+        // a block past the last value
+        assert_eq!(BlockIndex::read(&with_entry(3, 48, 14)), corrupted(3, 48));
+    }
+
+    #[test]
+    fn extract_range_keeps_only_covering_blocks() {
+        let stream = BlockIndex::append_to(stream_of_blocks(&[4, 4, 4, 2])).unwrap();
+        let index = BlockIndex::read(&stream).unwrap();
+        assert_eq!(index.blocks_covering(&(5..9)), 1..3);
+        assert_eq!(index.blocks_covering(&(4..8)), 1..2);
+        assert_eq!(index.blocks_covering(&(13..14)), 3..4);
+
+        let (sub_stream, skip) = index.extract_range(&stream, &(5..9)).unwrap();
+        assert_eq!(skip, 1);
+        let (header, payload) = ChimpHeader::split(&sub_stream, CodecVariant::Chimp32).unwrap();
+        assert_eq!(header.value_count, 8);
+        assert_eq!(payload, &stream[HEADER_SIZE + 16..HEADER_SIZE + 48]);
+
+        let mut integers = stream.clone();
+        let header = ChimpHeader::read(&stream)
+            .unwrap()
+            .with_value_type(ValueType::I32)
+            .with_flag(HeaderFlags::ZIGZAG_DELTA);
+        integers[..HEADER_SIZE].copy_from_slice(&header.to_bytes());
+        let (sub_stream, _) = index.extract_range(&integers, &(5..9)).unwrap();
+        let sub_header = ChimpHeader::read(&sub_stream).unwrap();
+        assert_eq!(sub_header.value_type(), ValueType::I32);
+        assert!(sub_header.flags.contains(HeaderFlags::ZIGZAG_DELTA));
+
+        let checked = BlockChecksums::append_to(stream.clone()).unwrap();
+        let (sub_stream, _) = index.extract_range(&checked, &(5..9)).unwrap();
+        let (header, payload) = ChimpHeader::split(&sub_stream, CodecVariant::Chimp32).unwrap();
+        assert!(header.flags.contains(HeaderFlags::BLOCK_CHECKSUMS));
+        assert_eq!(payload, &stream[HEADER_SIZE + 16..HEADER_SIZE + 48]);
+
+        assert_eq!(
+            index.extract_range(&stream, &(10..15)),
+            Err(FormatError::RangeOutOfBounds {
+                start: 10,
+                end: 15,
+                value_count: 14
+            })
+        );
+    }
+}
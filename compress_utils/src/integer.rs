@@ -0,0 +1,303 @@
+//! Lossless compression of integer series on the floating point pipelines.
+//!
+//! The Chimp encoding only looks at the bits of the values, so an `i32`/`u32` is compressed
+//! as the `f32` with the same bits and an `i64`/`u64` as the `f64`, by the same stages. The
+//! header records the integer type with [HeaderFlags::INTEGER] and [HeaderFlags::SIGNED].
+//!
+//! With [IntegerEncoding::ZigZagDelta] every value but the first of a buffer is replaced by
+//! the zig-zag coded difference to the value before it, so counters and slowly moving gauges
+//! become runs of small, often equal, words whose XOR is empty or short. The deltas restart
+//! at every buffer, so every block still decodes on its own.
+//!
+//! The compressors of the pipelines get their integer impls from [impl_integer_compressor],
+//! their decompressors are wrapped in an [IntegerDecompressor].
+//!
+//! [impl_integer_compressor]: crate::impl_integer_compressor
+use crate::config::{ChimpConfig, ConfigError};
+use crate::cpu_compress::{Compressor, Decompressor};
+use crate::error::ChimpError;
+use crate::format::{ChimpHeader, HeaderFlags, ValueType, HEADER_SIZE};
+use crate::general_utils::{CompressResult, DecompressResult};
+use bytemuck::Pod;
+use std::borrow::Cow;
+
+/// How integers are turned into the words the pipeline compresses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IntegerEncoding {
+    /// The bits of the integers are compressed as they are
+    #[default]
+    Xor,
+    /// The zig-zag coded difference of every integer to the one before it in its buffer is
+    /// compressed, see [HeaderFlags::ZIGZAG_DELTA]
+    ZigZagDelta,
+}
+
+impl IntegerEncoding {
+    /// The encoding of the values of the stream of [header]
+    pub fn of(header: &ChimpHeader) -> Self {
+        if header.flags.contains(HeaderFlags::ZIGZAG_DELTA) {
+            IntegerEncoding::ZigZagDelta
+        } else {
+            IntegerEncoding::Xor
+        }
+    }
+}
+
+/// An integer compressed as the float [Integer::Float] with the same bits
+pub trait Integer: Pod + Send + Sync {
+    type Float: Pod + Send + Sync;
+    const VALUE_TYPE: ValueType;
+
+    /// The zig-zag coded wrapping difference of [self] to [previous]
+    fn zigzag_delta(self, previous: Self) -> Self;
+
+    /// The value whose [Integer::zigzag_delta] to [previous] is [self]
+    fn undo_zigzag_delta(self, previous: Self) -> Self;
+}
+
+macro_rules! impl_integer {
+    ($integer:ty, $unsigned:ty, $signed:ty, $float:ty, $value_type:expr) => {
+        impl Integer for $integer {
+            type Float = $float;
+            const VALUE_TYPE: ValueType = $value_type;
+
+            fn zigzag_delta(self, previous: Self) -> Self {
+                let delta = self.wrapping_sub(previous) as $signed;
+                ((delta << 1) ^ (delta >> (<$signed>::BITS - 1))) as Self
+            }
+
+            fn undo_zigzag_delta(self, previous: Self) -> Self {
+                let zigzag = self as $unsigned;
+                let delta = (zigzag >> 1) as $signed ^ -((zigzag & 1) as $signed);
+                previous.wrapping_add(delta as Self)
+            }
+        }
+    };
+}
+
+impl_integer!(i32, u32, i32, f32, ValueType::I32);
+impl_integer!(u32, u32, i32, f32, ValueType::U32);
+impl_integer!(i64, u64, i64, f64, ValueType::I64);
+impl_integer!(u64, u64, i64, f64, ValueType::U64);
+
+/// The words the pipeline compresses for [values], borrowed as they are for
+/// [IntegerEncoding::Xor]
+pub fn encode<T: Integer>(
+    values: &[T],
+    encoding: IntegerEncoding,
+    buffer_size: usize,
+) -> Cow<'_, [T::Float]> {
+    match encoding {
+        IntegerEncoding::Xor => Cow::Borrowed(bytemuck::cast_slice(values)),
+        IntegerEncoding::ZigZagDelta => values
+            .chunks(buffer_size)
+            .flat_map(|buffer| {
+                buffer.iter().enumerate().map(|(index, value)| match index {
+                    0 => *value,
+                    _ => value.zigzag_delta(buffer[index - 1]),
+                })
+            })
+            .map(bytemuck::cast)
+            .collect(),
+    }
+}
+
+/// The integers of the decoded [words], the inverse of [encode]
+pub fn decode<T: Integer>(
+    words: &[T::Float],
+    encoding: IntegerEncoding,
+    buffer_size: usize,
+) -> Vec<T> {
+    let mut values: Vec<T> = bytemuck::cast_slice(words).to_vec();
+    if encoding == IntegerEncoding::ZigZagDelta {
+        for buffer in values.chunks_mut(buffer_size) {
+            for index in 1..buffer.len() {
+                buffer[index] = buffer[index].undo_zigzag_delta(buffer[index - 1]);
+            }
+        }
+    }
+    values
+}
+
+/// The [header] of a float stream turned into the one of a stream of [T] values
+pub fn integer_header<T: Integer>(header: ChimpHeader, encoding: IntegerEncoding) -> ChimpHeader {
+    let header = header.with_value_type(T::VALUE_TYPE);
+    match encoding {
+        IntegerEncoding::Xor => header,
+        IntegerEncoding::ZigZagDelta => header.with_flag(HeaderFlags::ZIGZAG_DELTA),
+    }
+}
+
+/// Compress [values] with the float [compressor] of their width. Integers are always
+/// compressed losslessly, a [config] with an error bound fails with
+/// [ConfigError::ErrorBoundOnIntegers]
+pub async fn compress_integers<T, C>(
+    compressor: &C,
+    config: &ChimpConfig,
+    values: &[T],
+) -> Result<CompressResult, ChimpError>
+where
+    T: Integer,
+    C: Compressor<T::Float> + Sync,
+{
+    if config.error_bound().is_some() {
+        return Err(ConfigError::ErrorBoundOnIntegers.into());
+    }
+    let words = encode(values, config.integer_encoding(), config.buffer_size());
+    let mut result = Compressor::<T::Float>::compress(compressor, &words).await?;
+    let header = integer_header::<T>(ChimpHeader::read(&result.0)?, config.integer_encoding());
+    result.0[..HEADER_SIZE].copy_from_slice(&header.to_bytes());
+    Ok(result)
+}
+
+/// Decompress a stream of [T] values with the float [decompressor] of their width, failing
+/// with [ChimpError::WrongValueType] when the stream holds values of another type
+pub async fn decompress_integers<T, D>(
+    decompressor: &D,
+    bytes: &[u8],
+) -> Result<DecompressResult<T>, ChimpError>
+where
+    T: Integer,
+    D: Decompressor<T::Float> + Sync,
+{
+    let header = ChimpHeader::read(bytes)?;
+    if header.value_type() != T::VALUE_TYPE {
+        return Err(ChimpError::WrongValueType {
+            codec: header.codec,
+            value_type: header.value_type(),
+        });
+    }
+    let DecompressResult(words, skip_time) =
+        Decompressor::<T::Float>::decompress(decompressor, bytes).await?;
+    let values = decode(
+        &words,
+        IntegerEncoding::of(&header),
+        header.buffer_size as usize,
+    );
+    Ok(DecompressResult(values, skip_time))
+}
+
+/// Decompresses streams of integers with the float decompressor [D] of their width, see
+/// [decompress_integers]. The float decompressors don't implement [Decompressor] for the
+/// integers themselves, so that the value type of their existing calls stays inferred
+#[derive(Debug, Clone, Default)]
+pub struct IntegerDecompressor<D>(D);
+
+impl<D> IntegerDecompressor<D> {
+    pub fn new(decompressor: D) -> Self {
+        Self(decompressor)
+    }
+
+    pub fn into_inner(self) -> D {
+        self.0
+    }
+}
+
+#[async_trait::async_trait]
+impl<T, D> Decompressor<T> for IntegerDecompressor<D>
+where
+    T: Integer,
+    D: Decompressor<T::Float> + Sync,
+{
+    async fn decompress(&self, bytes: &[u8]) -> Result<DecompressResult<T>, ChimpError> {
+        decompress_integers(&self.0, bytes).await
+    }
+}
+
+/// Implement [Compressor] for the integer types on a float compressor with a `config()`
+/// method, see [compress_integers]
+#[macro_export]
+macro_rules! impl_integer_compressor {
+    ($compressor:ty, $($integer:ty),+) => {
+        $(
+            #[async_trait::async_trait]
+            impl $crate::cpu_compress::Compressor<$integer> for $compressor {
+                async fn compress(
+                    &self,
+                    values: &[$integer],
+                ) -> Result<$crate::general_utils::CompressResult, $crate::error::ChimpError> {
+                    $crate::integer::compress_integers(self, self.config(), values).await
+                }
+            }
+        )+
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::CodecVariant;
+
+    #[test]
+    fn zigzag_deltas_round_trip_across_the_whole_range() {
+        let values = [0i64, 1, -1, i64::MAX, i64::MIN, 42, i64::MIN, 0];
+        let encoded = encode(&values, IntegerEncoding::ZigZagDelta, 256);
+        // 1 - 0 zig-zags to 2 and -1 - 1 to 3
+        assert_eq!(encoded[1].to_bits(), 2);
+        assert_eq!(encoded[2].to_bits(), 3);
+        assert_eq!(
+            decode::<i64>(&encoded, IntegerEncoding::ZigZagDelta, 256),
+            values
+        );
+
+        let values = [u32::MAX, 0, 7, 7, u32::MAX, 1];
+        let encoded = encode(&values, IntegerEncoding::ZigZagDelta, 256);
+        // 0 - u32::MAX wraps around to 1, which zig-zags to 2
+        assert_eq!(encoded[1].to_bits(), 2);
+        assert_eq!(encoded[3].to_bits(), 0);
+        assert_eq!(
+            decode::<u32>(&encoded, IntegerEncoding::ZigZagDelta, 256),
+            values
+        );
+        let values = [i32::MIN, i32::MAX, -5, 5];
+        let encoded = encode(&values, IntegerEncoding::ZigZagDelta, 256);
+        assert_eq!(
+            decode::<i32>(&encoded, IntegerEncoding::ZigZagDelta, 256),
+            values
+        );
+        let values = [u64::MAX, 0, 1 << 63];
+        let encoded = encode(&values, IntegerEncoding::ZigZagDelta, 256);
+        assert_eq!(
+            decode::<u64>(&encoded, IntegerEncoding::ZigZagDelta, 256),
+            values
+        );
+    }
+
+    #[test]
+    fn deltas_restart_at_every_buffer() {
+        let values = (0..600u64).map(|it| it * 3 + 1000).collect::<Vec<_>>();
+        let encoded = encode(&values, IntegerEncoding::ZigZagDelta, 256);
+        assert_eq!(encoded[256].to_bits(), values[256]);
+        assert_eq!(encoded[257].to_bits(), 6);
+        assert_eq!(
+            decode::<u64>(&encoded[256..], IntegerEncoding::ZigZagDelta, 256),
+            values[256..]
+        );
+        assert!(matches!(
+            encode(&values, IntegerEncoding::Xor, 256),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn headers_record_the_integer_type() {
+        let header = ChimpHeader::new(CodecVariant::Chimp32, 0, 256, 10);
+        assert_eq!(header.value_type(), ValueType::F32);
+        for value_type in [ValueType::I32, ValueType::U32] {
+            let integers = header.with_value_type(value_type);
+            let read = ChimpHeader::read(&integers.to_bytes()).unwrap();
+            assert_eq!(read.value_type(), value_type);
+            assert_eq!(IntegerEncoding::of(&read), IntegerEncoding::Xor);
+        }
+        let header = integer_header::<u64>(
+            ChimpHeader::new(CodecVariant::ChimpN64, 16, 256, 10),
+            IntegerEncoding::ZigZagDelta,
+        );
+        assert_eq!(header.value_type(), ValueType::U64);
+        assert_eq!(IntegerEncoding::of(&header), IntegerEncoding::ZigZagDelta);
+        assert_eq!(
+            header.with_value_type(ValueType::F64).value_type(),
+            ValueType::F64
+        );
+    }
+}
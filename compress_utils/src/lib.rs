@@ -1,60 +1,99 @@
+#[cfg(feature = "gpu")]
+pub mod buffer_pool;
+pub mod checksum;
+pub mod config;
+#[cfg(feature = "gpu")]
 pub mod context;
 pub mod cpu_compress;
+pub mod error;
+pub mod format;
+pub mod integer;
+pub mod lossy;
+#[cfg(feature = "gpu")]
+pub mod pipeline_cache;
+pub mod shader_template;
+#[cfg(all(feature = "gpu", any(test, feature = "validation")))]
+pub mod shader_validation;
+pub mod stream;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod types;
 
+#[cfg(feature = "gpu")]
+use crate::buffer_pool::PooledBuffer;
+#[cfg(feature = "gpu")]
+use crate::context::Context;
+#[cfg(feature = "gpu")]
 use crate::BufferWrapper::UnInitialized;
+#[cfg(feature = "gpu")]
+use bytemuck::Pod;
+#[cfg(feature = "gpu")]
 use wgpu::util::DeviceExt;
+#[cfg(feature = "gpu")]
 use wgpu::{Buffer, BufferAddress, BufferUsages, Device};
 
+#[cfg(feature = "gpu")]
 #[derive(Debug)]
 /// Buffer Wrapper enum to encapsulate the assignment
 pub enum BufferWrapper {
     StorageBuffer {
-        buffer: Buffer,
+        buffer: PooledBuffer,
         size: usize,
         group: u32,
         binding: u32,
     },
     StagingBuffer {
-        buffer: Buffer,
+        buffer: PooledBuffer,
         size: usize,
     },
     Uniform {
-        buffer: Buffer,
+        buffer: PooledBuffer,
         size: usize,
         group: u32,
         binding: u32,
     },
+    /// A buffer living in host memory, used by the cpu implementations of the stages.
+    /// The content is kept in `u64` words so it can be viewed as any of the stage types
+    Host {
+        data: Vec<u64>,
+        size: usize,
+    },
     UnInitialized,
 }
+#[cfg(feature = "gpu")]
 impl Default for BufferWrapper {
     fn default() -> Self {
         UnInitialized {}
     }
 }
 
+#[cfg(feature = "gpu")]
 pub struct WgpuGroupId {
     group: u32,
     binding: u32,
 }
 
+#[cfg(feature = "gpu")]
 impl From<(i32, i32)> for WgpuGroupId {
     fn from(value: (i32, i32)) -> Self {
         WgpuGroupId::new(value.0 as u32, value.1 as u32)
     }
 }
 
+#[cfg(feature = "gpu")]
 impl WgpuGroupId {
     pub fn new(group: u32, binding: u32) -> Self {
         WgpuGroupId { group, binding }
     }
 }
+#[cfg(feature = "gpu")]
 impl BufferWrapper {
     pub fn size(&self) -> usize {
         match self {
             BufferWrapper::StorageBuffer { size, .. } => *size,
             BufferWrapper::StagingBuffer { size, .. } => *size,
             BufferWrapper::Uniform { size, .. } => *size,
+            BufferWrapper::Host { size, .. } => *size,
             BufferWrapper::UnInitialized => unreachable!(),
         }
     }
@@ -71,6 +110,7 @@ impl BufferWrapper {
                 *binding = id.binding;
                 self
             }
+            BufferWrapper::Host { .. } => self,
             UnInitialized { .. } => self,
         }
     }
@@ -92,9 +132,52 @@ impl BufferWrapper {
                 size: _size,
                 ..
             } => buffer,
+            BufferWrapper::Host { .. } => unreachable!(),
             BufferWrapper::UnInitialized => unreachable!(),
         }
     }
+
+    ///Host content getter, viewing the bytes of a [BufferWrapper::Host] as a slice of [T]
+    pub fn host_slice<T: Pod>(&self) -> &[T] {
+        match self {
+            BufferWrapper::Host { data, size } => {
+                bytemuck::cast_slice(&bytemuck::cast_slice::<u64, u8>(data)[..*size])
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    ///Mutable host content getter, viewing the bytes of a [BufferWrapper::Host] as a slice of [T]
+    pub fn host_slice_mut<T: Pod>(&mut self) -> &mut [T] {
+        match self {
+            BufferWrapper::Host { data, size } => {
+                bytemuck::cast_slice_mut(&mut bytemuck::cast_slice_mut::<u64, u8>(data)[..*size])
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn is_host(&self) -> bool {
+        matches!(self, BufferWrapper::Host { .. })
+    }
+
+    ///Create a host buffer holding a copy of the bytes in [contents]
+    pub fn host_with_content(contents: &[u8]) -> Self {
+        let mut data = vec![0u64; contents.len().div_ceil(size_of::<u64>())];
+        bytemuck::cast_slice_mut::<u64, u8>(&mut data)[..contents.len()].copy_from_slice(contents);
+        BufferWrapper::Host {
+            data,
+            size: contents.len(),
+        }
+    }
+
+    ///Create a zeroed host buffer with [size] in bytes, the host counterpart of [BufferWrapper::storage_with_size]
+    pub fn host_with_size(size: usize) -> Self {
+        BufferWrapper::Host {
+            data: vec![0u64; size.div_ceil(size_of::<u64>())],
+            size,
+        }
+    }
     ///Create a staging buffer with pre-existing-content defined in the bytes in [contents] with an optional [label]
     pub fn stage_with_content(device: &Device, contents: &[u8], label: Option<&str>) -> Self {
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -103,7 +186,7 @@ impl BufferWrapper {
             usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
         });
         BufferWrapper::StagingBuffer {
-            buffer,
+            buffer: buffer.into(),
             size: size_of_val(contents) / size_of::<u8>(),
         }
     }
@@ -117,7 +200,7 @@ impl BufferWrapper {
             mapped_at_creation: false,
         });
         BufferWrapper::StagingBuffer {
-            buffer,
+            buffer: buffer.into(),
             size: size as usize,
         }
     }
@@ -136,13 +219,42 @@ impl BufferWrapper {
         });
         let id = wgpu_group_id.into();
         BufferWrapper::StorageBuffer {
-            buffer,
+            buffer: buffer.into(),
             size: size_of_val(contents) / size_of::<u8>(),
             group: id.group,
             binding: id.binding,
         }
     }
 
+    ///Create a storage buffer of [size] bytes starting with the bytes of [contents], the
+    /// remaining bytes are 0
+    pub fn storage_with_padded_content(
+        device: &Device,
+        contents: &[u8],
+        size: BufferAddress,
+        wgpu_group_id: impl Into<WgpuGroupId>,
+        label: Option<&str>,
+    ) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label,
+            size,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: true,
+        });
+        buffer
+            .slice(..contents.len() as BufferAddress)
+            .get_mapped_range_mut()
+            .copy_from_slice(contents);
+        buffer.unmap();
+        let id = wgpu_group_id.into();
+        BufferWrapper::StorageBuffer {
+            buffer: buffer.into(),
+            size: size as usize,
+            group: id.group,
+            binding: id.binding,
+        }
+    }
+
     ///Create an empty staging buffer with [size] in bytes and an optional [label]
     pub fn storage_with_size(
         device: &Device,
@@ -159,7 +271,7 @@ impl BufferWrapper {
         let id = wgpu_group_id.into();
 
         BufferWrapper::StorageBuffer {
-            buffer,
+            buffer: buffer.into(),
             size: size as usize,
             group: id.group,
             binding: id.binding,
@@ -179,30 +291,171 @@ impl BufferWrapper {
         });
         let id = wgpu_group_id.into();
         BufferWrapper::Uniform {
-            buffer,
+            buffer: buffer.into(),
             size: size_of_val(contents) / size_of::<u8>(),
             group: id.group,
             binding: id.binding,
         }
     }
+
+    ///Create a zeroed storage buffer with [size] in bytes and an optional [label] from the
+    /// [BufferPool](crate::buffer_pool::BufferPool) of [context], the buffer goes back to the
+    /// pool when the wrapper is dropped
+    pub fn pooled_storage_with_size(
+        context: &Context,
+        size: BufferAddress,
+        wgpu_group_id: impl Into<WgpuGroupId>,
+        label: Option<&str>,
+    ) -> Self {
+        Self::pooled_storage_with_padded_content(context, &[], size, wgpu_group_id, label)
+    }
+
+    ///Create a storage buffer with the bytes of [contents] and an optional [label] from the
+    /// [BufferPool](crate::buffer_pool::BufferPool) of [context]
+    pub fn pooled_storage_with_content(
+        context: &Context,
+        contents: &[u8],
+        wgpu_group_id: impl Into<WgpuGroupId>,
+        label: Option<&str>,
+    ) -> Self {
+        let size = contents.len() as BufferAddress;
+        Self::pooled_storage_with_padded_content(context, contents, size, wgpu_group_id, label)
+    }
+
+    ///Create a storage buffer of [size] bytes starting with the bytes of [contents] from the
+    /// [BufferPool](crate::buffer_pool::BufferPool) of [context], the remaining bytes are 0
+    pub fn pooled_storage_with_padded_content(
+        context: &Context,
+        contents: &[u8],
+        size: BufferAddress,
+        wgpu_group_id: impl Into<WgpuGroupId>,
+        label: Option<&str>,
+    ) -> Self {
+        let buffer = context.buffer_pool().acquire(
+            context.device(),
+            size,
+            BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            label,
+        );
+        if buffer.reused() {
+            // a new buffer is zeroed by wgpu, a reused one holds what its last user left
+            write_zero_padded(context, &buffer, contents, size);
+        } else {
+            write_aligned(context, &buffer, contents);
+        }
+        let id = wgpu_group_id.into();
+        BufferWrapper::StorageBuffer {
+            buffer,
+            size: size as usize,
+            group: id.group,
+            binding: id.binding,
+        }
+    }
+
+    ///Create a staging buffer with [size] in bytes and an optional [label] from the
+    /// [BufferPool](crate::buffer_pool::BufferPool) of [context], its content is undefined
+    pub fn pooled_stage_with_size(
+        context: &Context,
+        size: BufferAddress,
+        label: Option<&str>,
+    ) -> Self {
+        let buffer = context.buffer_pool().acquire(
+            context.device(),
+            size,
+            BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            label,
+        );
+        BufferWrapper::StagingBuffer {
+            buffer,
+            size: size as usize,
+        }
+    }
+
+    ///Create a uniform buffer with the bytes of [contents] and an optional [label] from the
+    /// [BufferPool](crate::buffer_pool::BufferPool) of [context]
+    pub fn pooled_uniform_with_content(
+        context: &Context,
+        contents: &[u8],
+        wgpu_group_id: impl Into<WgpuGroupId>,
+        label: Option<&str>,
+    ) -> Self {
+        let buffer = context.buffer_pool().acquire(
+            context.device(),
+            contents.len() as BufferAddress,
+            BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            label,
+        );
+        write_aligned(context, &buffer, contents);
+        let id = wgpu_group_id.into();
+        BufferWrapper::Uniform {
+            buffer,
+            size: contents.len(),
+            group: id.group,
+            binding: id.binding,
+        }
+    }
 }
 
+#[cfg(feature = "gpu")]
+/// Write [contents] at the start of [buffer], padded with zeroes to the copy alignment
+fn write_aligned(context: &Context, buffer: &Buffer, contents: &[u8]) {
+    if contents.is_empty() {
+        return;
+    }
+    let aligned = contents
+        .len()
+        .next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT as usize);
+    if aligned == contents.len() {
+        context.queue().write_buffer(buffer, 0, contents);
+    } else {
+        let mut padded = contents.to_vec();
+        padded.resize(aligned, 0);
+        context.queue().write_buffer(buffer, 0, &padded);
+    }
+}
+
+#[cfg(feature = "gpu")]
+/// Write [contents] at the start of [buffer] followed by zeroes up to [size], in the same
+/// staged upload as the contents so the zeroing needs no submit of its own
+fn write_zero_padded(context: &Context, buffer: &Buffer, contents: &[u8], size: BufferAddress) {
+    let size = size
+        .next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+        .min(buffer.size());
+    let Some(size) = wgpu::BufferSize::new(size) else {
+        return;
+    };
+    if let Some(mut view) = context.queue().write_buffer_with(buffer, 0, size) {
+        let (head, tail) = view.split_at_mut(contents.len());
+        head.copy_from_slice(contents);
+        tail.fill(0);
+    }
+}
+
+#[cfg(feature = "gpu")]
 /// WGPU utility functions
 pub mod wgpu_utils {
     use crate::context::Context;
-    use crate::cpu_compress::CompressionError;
+    use crate::error::ChimpError;
+    use crate::pipeline_cache::{CachedPipeline, PipelineKey};
+    use crate::shader_template::SpecializedShader;
     use crate::wgpu_utils::TimeError::{CouldNotFindResultEntry, CouldNotFindTimerEntry};
     use crate::BufferWrapper;
-    use anyhow::Result;
     use bytemuck::Pod;
+    use pollster::FutureExt;
     use std::collections::HashMap;
     use std::fmt::{Display, Formatter};
     use std::time::Instant;
     use thiserror::Error;
-    use wgpu::{BindGroup, BindGroupLayout, Buffer, Device, Label, ShaderModule};
+    use wgpu::{
+        BindGroup, BindGroupLayout, BindGroupLayoutEntry, Buffer, Device, ErrorFilter, Label,
+        ShaderModule,
+    };
     use wgpu_types::PollType::Wait;
     use wgpu_types::{BindingType, BufferAddress, ShaderStages};
 
+    /// The buffers a batch hands from one stage to the next. The ones drawn from the
+    /// [BufferPool](crate::buffer_pool::BufferPool) of a context go back to it when the next
+    /// batch replaces them or the run buffers are dropped
     #[derive(Default)]
     pub struct RunBuffers {
         input_buffer: BufferWrapper,
@@ -212,9 +465,22 @@ pub mod wgpu_utils {
         compressed_buffer: BufferWrapper,
         index_buffer: BufferWrapper,
         byte_buffer: BufferWrapper,
+        unsubmitted: Vec<BufferWrapper>,
     }
 
     impl RunBuffers {
+        /// Keep [buffer] until [RunBuffers::release_submitted]. A pooled buffer bound by a
+        /// recorded dispatch must not go back to the pool before its encoder is submitted,
+        /// or a later stage could write its own content to it first.
+        pub fn hold_until_submitted(&mut self, buffer: BufferWrapper) {
+            self.unsubmitted.push(buffer);
+        }
+
+        /// Drop the buffers held for the encoders submitted since they were recorded
+        pub fn release_submitted(&mut self) {
+            self.unsubmitted.clear();
+        }
+
         pub fn set_input_buffer(&mut self, input_buffer: BufferWrapper) {
             self.input_buffer = input_buffer;
         }
@@ -297,20 +563,6 @@ pub mod wgpu_utils {
         }
     }
 
-    /// Utility error description
-    #[derive(Error, Debug)]
-    pub enum WgpuUtilsError {
-        #[error(transparent)]
-        InvalidShaderException(#[from] anyhow::Error),
-    }
-
-    impl From<WgpuUtilsError> for CompressionError {
-        /// Error conversion Method
-        fn from(value: WgpuUtilsError) -> Self {
-            CompressionError::FromBaseAnyhowError(anyhow::Error::from(value))
-        }
-    }
-
     #[derive(Debug, Default)]
     pub struct TimeTracker {
         timers: HashMap<String, Instant>,
@@ -368,23 +620,45 @@ pub mod wgpu_utils {
     }
 
     /// Utility function to create a wgsl compute shader module to use in our pipeline from code defined in [shader_content]  
-    pub fn create_shader_module(device: &Device, shader_content: &str) -> Result<ShaderModule> {
+    pub fn create_shader_module(
+        device: &Device,
+        shader_content: &str,
+    ) -> Result<ShaderModule, ChimpError> {
+        device.push_error_scope(ErrorFilter::Validation);
         let cs_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
             source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(shader_content)),
         });
+        check_validation(device)?;
         Ok(cs_module)
     }
 
+    /// Pop the validation scope pushed before creating a shader module or pipeline
+    fn check_validation(device: &Device) -> Result<(), ChimpError> {
+        match device.pop_error_scope().block_on() {
+            Some(error) => Err(ChimpError::ShaderCompilation(error.to_string())),
+            None => Ok(()),
+        }
+    }
+
     /// Utility function to create a bind group layout based on defined wrappers
     ///     
     /// Bind groups define which
     pub fn assign_bind_groups(device: &Device, bindings: Vec<&BufferWrapper>) -> BindGroupLayout {
-        let mut binding_group_layout_entries = Vec::<wgpu::BindGroupLayoutEntry>::new();
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: bind_group_layout_entries(&bindings).as_slice(),
+        })
+    }
+
+    /// The bind group layout entries of the storage and uniform buffers among [bindings], the
+    /// staging and host buffers are not bound
+    pub fn bind_group_layout_entries(bindings: &[&BufferWrapper]) -> Vec<BindGroupLayoutEntry> {
+        let mut binding_group_layout_entries = Vec::<BindGroupLayoutEntry>::new();
         for binding in bindings {
-            match *binding {
+            match **binding {
                 BufferWrapper::StorageBuffer { binding, .. } => {
-                    binding_group_layout_entries.push(wgpu::BindGroupLayoutEntry {
+                    binding_group_layout_entries.push(BindGroupLayoutEntry {
                         binding,
                         visibility: ShaderStages::COMPUTE,
                         ty: BindingType::Buffer {
@@ -397,7 +671,7 @@ pub mod wgpu_utils {
                 }
                 BufferWrapper::StagingBuffer { .. } => {}
                 BufferWrapper::Uniform { binding, .. } => {
-                    binding_group_layout_entries.push(wgpu::BindGroupLayoutEntry {
+                    binding_group_layout_entries.push(BindGroupLayoutEntry {
                         binding,
                         visibility: ShaderStages::COMPUTE,
                         ty: BindingType::Buffer {
@@ -408,21 +682,54 @@ pub mod wgpu_utils {
                         count: None,
                     });
                 }
+                BufferWrapper::Host { .. } => {}
                 BufferWrapper::UnInitialized => {}
             }
         }
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: binding_group_layout_entries.as_slice(),
+        binding_group_layout_entries
+    }
+
+    /// The pipeline of [shader] over the layout of [bindings] from the
+    /// [PipelineCache](crate::pipeline_cache::PipelineCache) of [context], compiling the shader
+    /// module and the pipeline on the first use of the pair.
+    ///
+    /// The [label] of the pipeline is the one of its first use.
+    pub fn cached_compute_pipeline(
+        context: &Context,
+        shader: impl Into<SpecializedShader>,
+        bindings: Vec<&BufferWrapper>,
+        label: Option<&str>,
+    ) -> Result<CachedPipeline, ChimpError> {
+        let key = PipelineKey::new(shader, bind_group_layout_entries(&bindings));
+        context.pipeline_cache().get_or_try_insert_with(key, |key| {
+            let device = context.device();
+            let shader_module = create_shader_module(device, key.shader().source())?;
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: key.entries(),
+            });
+            let constants = key
+                .shader()
+                .constants()
+                .iter()
+                .map(|(name, value)| (name.as_str(), *value as f64))
+                .collect::<Vec<_>>();
+            let pipeline =
+                create_compute_shader_pipeline(device, &shader_module, &layout, &constants, label)?;
+            Ok(CachedPipeline::new(layout, pipeline))
         })
     }
 
+    /// Utility function to create a compute pipeline running the `main` entry point of
+    /// [shader_module], with [constants] as the values of its `override` declarations
     pub fn create_compute_shader_pipeline(
         device: &Device,
         shader_module: &ShaderModule,
         binding_layout: &BindGroupLayout,
+        constants: &[(&str, f64)],
         label: Option<&str>,
-    ) -> Result<wgpu::ComputePipeline> {
+    ) -> Result<wgpu::ComputePipeline, ChimpError> {
+        device.push_error_scope(ErrorFilter::Validation);
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label,
             bind_group_layouts: &[binding_layout],
@@ -433,9 +740,13 @@ pub mod wgpu_utils {
             layout: Some(&pipeline_layout),
             module: shader_module,
             entry_point: Some("main"),
-            compilation_options: Default::default(),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
             cache: None,
         });
+        check_validation(device)?;
         Ok(compute_pipeline)
     }
 
@@ -444,21 +755,86 @@ pub mod wgpu_utils {
         storage_buffer: &Buffer,
         bytes: BufferAddress,
         output_buffer: &Buffer,
-    ) -> Result<Vec<T>> {
+    ) -> Result<Vec<T>, ChimpError> {
         let mut command_encoder = context
             .device()
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
         command_encoder.copy_buffer_to_buffer(storage_buffer, 0, output_buffer, 0, bytes);
         context.queue().submit(Some(command_encoder.finish()));
-        let buffer_slice = output_buffer.slice(..);
+        read_staged(context, output_buffer, bytes).await
+    }
+
+    /// Map the first [bytes] of the staging buffer [output_buffer] once the work submitted
+    /// before is done, and copy them to the host
+    pub async fn read_staged<T: Pod>(
+        context: &Context,
+        output_buffer: &Buffer,
+        bytes: BufferAddress,
+    ) -> Result<Vec<T>, ChimpError> {
+        // a pooled staging buffer can be larger than the copy
+        let buffer_slice = output_buffer.slice(..bytes);
         let (sender, receiver) = flume::bounded(1);
         buffer_slice.map_async(wgpu::MapMode::Read, move |r| sender.send(r).unwrap());
-        let _result = context.device().poll(Wait)?.wait_finished();
-        receiver.recv_async().await??;
+        let gpu_map = |error: &dyn std::error::Error| ChimpError::GpuMap(error.to_string());
+        let _result = context
+            .device()
+            .poll(Wait)
+            .map_err(|it| gpu_map(&it))?
+            .wait_finished();
+        receiver
+            .recv_async()
+            .await
+            .map_err(|it| gpu_map(&it))?
+            .map_err(|it| gpu_map(&it))?;
         let output: Vec<T> =
             bytemuck::cast_slice(buffer_slice.get_mapped_range()[..].iter().as_slice()).to_vec();
         output_buffer.unmap();
-        anyhow::Ok(output)
+        Ok(output)
+    }
+
+    /// Read the content of [buffer] on the host.
+    ///
+    /// Host buffers are copied directly, gpu buffers are downloaded through a staging buffer,
+    /// which requires a [context].
+    pub async fn read_buffer<T: Pod>(
+        context: Option<&Context>,
+        buffer: &BufferWrapper,
+    ) -> Result<Vec<T>, ChimpError> {
+        match buffer {
+            BufferWrapper::Host { .. } => Ok(buffer.host_slice::<T>().to_vec()),
+            BufferWrapper::UnInitialized => Err(ChimpError::UninitializedBuffer),
+            _ => {
+                let context = context.ok_or(ChimpError::MissingContext)?;
+                let staging_buffer = BufferWrapper::stage_with_size(
+                    context.device(),
+                    buffer.size() as BufferAddress,
+                    None,
+                );
+                Ok(get_from_gpu(
+                    context,
+                    buffer.buffer(),
+                    buffer.size() as BufferAddress,
+                    staging_buffer.buffer(),
+                )
+                .await?)
+            }
+        }
+    }
+
+    /// The first [size] bytes of [buffer], pooled buffers are rounded up to their bucket and
+    /// the shaders must only see the requested bytes
+    fn sized_binding(buffer: &Buffer, size: usize) -> wgpu::BindingResource<'_> {
+        let size = (size as BufferAddress).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+        match wgpu::BufferSize::new(size) {
+            Some(size) if size.get() < buffer.size() => {
+                wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: Some(size),
+                })
+            }
+            _ => buffer.as_entire_binding(),
+        }
     }
 
     pub fn create_bind_group(
@@ -472,24 +848,31 @@ pub mod wgpu_utils {
         for buffer_wrap in buffers {
             match buffer_wrap {
                 BufferWrapper::StorageBuffer {
-                    buffer, binding, ..
+                    buffer,
+                    binding,
+                    size,
+                    ..
                 } => {
                     entries.push(wgpu::BindGroupEntry {
                         binding: *binding,
-                        resource: buffer.as_entire_binding(),
+                        resource: sized_binding(buffer, *size),
                     });
                     // count += 1;
                 }
                 BufferWrapper::StagingBuffer { .. } => {}
                 BufferWrapper::Uniform {
-                    buffer, binding, ..
+                    buffer,
+                    binding,
+                    size,
+                    ..
                 } => {
                     entries.push(wgpu::BindGroupEntry {
                         binding: *binding,
-                        resource: buffer.as_entire_binding(),
+                        resource: sized_binding(buffer, *size),
                     });
                     // count += 1;
                 }
+                BufferWrapper::Host { .. } => {}
                 BufferWrapper::UnInitialized => {}
             }
         }
@@ -575,19 +958,15 @@ pub mod bit_utils {
     }
     impl BitWritable<u32> for BitVec {
         fn write_bits(&mut self, number: u32, size: u32) {
-            if number != 0 {
-                for i in (0..size).rev() {
-                    self.push(number & 2u32.pow(i) != 0);
-                }
+            for i in (0..size).rev() {
+                self.push(number & 2u32.pow(i) != 0);
             }
         }
     }
     impl BitWritable<u64> for BitVec {
         fn write_bits(&mut self, number: u64, size: u32) {
-            if number != 0 {
-                for i in (0..size).rev() {
-                    self.push(number & 2u64.pow(i) != 0);
-                }
+            for i in (0..size).rev() {
+                self.push(number & 2u64.pow(i) != 0);
             }
         }
     }
@@ -617,14 +996,20 @@ pub mod bit_utils {
 
 /// General Utility Functions
 pub mod general_utils {
+    use crate::checksum::BlockChecksums;
+    use crate::config::ChimpConfig;
+    use crate::format::{BlockIndex, FormatError};
+    use crate::types::S;
+    use bytemuck::Pod;
     use log::warn;
+    use std::borrow::Cow;
     use std::collections::{HashMap, HashSet};
 
     use serde::{Deserialize, Serialize};
     use std::fs;
     use std::fs::OpenOptions;
-    use std::ops::Div;
     use std::path::PathBuf;
+    use std::slice::Chunks;
     use std::str::FromStr;
 
     #[derive(Debug, Default, Serialize, Deserialize)]
@@ -646,6 +1031,33 @@ pub mod general_utils {
         pub fn skip_time(&self) -> u128 {
             self.2
         }
+
+        /// Append a [BlockIndex] footer to the stream so ranges of it can be decompressed
+        /// without walking every block
+        pub fn with_block_index(self) -> Result<Self, FormatError> {
+            let len = self.0.len();
+            let stream = BlockIndex::append_to(self.0)?;
+            let metadata = self.1 + stream.len() - len;
+            Ok(Self(stream, metadata, self.2))
+        }
+
+        /// Append a [BlockChecksums] trailer to the stream so damaged blocks are reported as
+        /// corrupted when decompressing
+        pub fn with_block_checksums(self) -> Result<Self, FormatError> {
+            let len = self.0.len();
+            let stream = BlockChecksums::append_to(self.0)?;
+            let metadata = self.1 + stream.len() - len;
+            Ok(Self(stream, metadata, self.2))
+        }
+
+        /// Append the trailers [config] asks for to a freshly compressed stream
+        pub fn with_trailers(self, config: &ChimpConfig) -> Result<Self, FormatError> {
+            if config.block_checksums() {
+                self.with_block_checksums()
+            } else {
+                Ok(self)
+            }
+        }
     }
     impl From<CompressResult> for Vec<u8> {
         fn from(value: CompressResult) -> Self {
@@ -699,19 +1111,60 @@ pub mod general_utils {
         buffer_size: usize,
         padding: &mut Padding,
     ) -> Vec<f32> {
-        if values.len() % buffer_size != 0 {
-            let count = (values.len().div(buffer_size) + 1) * buffer_size - values.len();
-            padding.0 = count;
-            for _i in 0..count {
-                values.push(0f32);
-            }
-        }
+        padding.0 = padding_to_fit_buffer_count(values.len(), buffer_size);
+        values.resize(values.len() + padding.0, 0f32);
         values
     }
+
+    /// View [bytes] as a slice of [T], copying them only when they are not aligned for [T]
+    pub fn cast_or_copy<T: Pod>(bytes: &[u8]) -> Cow<'_, [T]> {
+        match bytemuck::try_cast_slice(bytes) {
+            Ok(values) => Cow::Borrowed(values),
+            Err(_) => Cow::Owned(bytemuck::pod_collect_to_vec(bytes)),
+        }
+    }
+
+    /// The number of 0's that follow [value_count] values to fill their last buffer. The
+    /// batched stages upload their values into buffers of the padded size instead of padding
+    /// the values themselves
+    pub fn padding_to_fit_buffer_count(value_count: usize, buffer_size: usize) -> usize {
+        value_count.next_multiple_of(buffer_size) - value_count
+    }
+    /// Split [values] into the batches that are processed by a single pass of the pipeline.
+    ///
+    /// A batch is as large as possible while both the [S] buffer and the output buffer, with
+    /// elements of [output_element_size] bytes, stay below [max_storage_buffer_size]. Every batch
+    /// except the last one holds a multiple of [buffer_size]. The cpu implementations use the
+    /// same split, so their output matches a gpu with the same storage buffer limit.
+    pub fn split_by_max_storage_buffer_size<T>(
+        values: &[T],
+        buffer_size: usize,
+        max_storage_buffer_size: usize,
+        output_element_size: usize,
+    ) -> Chunks<'_, T> {
+        let closest = max_batch_len(buffer_size, max_storage_buffer_size, output_element_size);
+
+        values.chunks(closest)
+    }
+    /// The number of values in every batch but the last of
+    /// [split_by_max_storage_buffer_size], a multiple of [buffer_size]
+    pub fn max_batch_len(
+        buffer_size: usize,
+        max_storage_buffer_size: usize,
+        output_element_size: usize,
+    ) -> usize {
+        let mut split_by = max_storage_buffer_size / size_of::<S>() - buffer_size; //The most costly buffer
+        while ((split_by + 10) * size_of::<S>()) as u64 >= max_storage_buffer_size as u64
+            || ((split_by + 10) * output_element_size) as u64 >= max_storage_buffer_size as u64
+        {
+            split_by -= buffer_size;
+        }
+        split_by - split_by % buffer_size
+    }
     #[macro_export]
     macro_rules! step {
-        ($step:expr,$writer_body:block) => {
-            if trace_steps().contains(&$step) {
+        ($config:expr,$step:expr,$writer_body:block) => {
+            if $config.traces(&$step) {
                 let trace_path = $step.get_trace_file();
                 let mut trace_output = String::new();
 
@@ -793,17 +1246,20 @@ pub mod general_utils {
     /// This macro simplifies the process of creating and executing a compute shader by encapsulating
     /// the creation of a shader module, pipeline, bind group, and command encoder for dispatching
     /// the compute work. It handles various aspects of WGPU utilities, such as binding resources and
-    /// dispatching compute workloads.
+    /// dispatching compute workloads. The shader module and pipeline are taken from the
+    /// `compress_utils::pipeline_cache::PipelineCache` of the context, so a specialized shader is
+    /// only compiled on its first dispatch with a given bind group layout.
     ///
     /// # Parameters
     ///
     /// - `$context`: The WGPU `compress_utils::context::Context` to use.
     ///
-    /// - `$shader_source`: The string source code for the compute shader. It should be in WGSL format.
+    /// - `$shader_source`: The compute shader, either its WGSL source or a
+    ///   `compress_utils::shader_template::SpecializedShader` carrying its pipeline constants.
     ///
     /// - `$buffers`: A list of GPU buffer (wrapped in `compress_utils::BufferWrapper`) objects to be used as bind group bindings in the compute shader. This
-    ///   will be passed to `wgpu_utils::assign_bind_groups` and `wgpu_utils::create_bind_group` to create
-    ///   the bind group layout and the actual bind group for the shader.
+    ///   will be passed to `wgpu_utils::cached_compute_pipeline` and `wgpu_utils::create_bind_group` to
+    ///   look up the bind group layout and create the actual bind group for the shader.
     ///
     /// - `$dispatch_size`: The number of workgroups to dispatch in the compute shader. The macro ensures
     ///   that at least 1 workgroup is dispatched.
@@ -827,39 +1283,53 @@ pub mod general_utils {
     #[macro_export]
     macro_rules! execute_compute_shader {
         ($context:expr,$shader_source:expr,$buffers:expr,$dispatch_size:expr,$binding_label:expr) => {
-            let compute_shader_module =
-                wgpu_utils::create_shader_module($context.device(), $shader_source)?;
-
-            let binding_group_layout = wgpu_utils::assign_bind_groups($context.device(), $buffers);
+            let mut s_encoder = $context
+                .device()
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            $crate::record_compute_shader!(
+                $context,
+                &mut s_encoder,
+                $shader_source,
+                $buffers,
+                $dispatch_size,
+                $binding_label
+            );
+            $context.queue().submit(Some(s_encoder.finish()));
+        };
+    }
 
-            let compute_s_pipeline = wgpu_utils::create_compute_shader_pipeline(
-                $context.device(),
-                &compute_shader_module,
-                &binding_group_layout,
+    /// Record a compute shader dispatch into `$encoder` without submitting it.
+    ///
+    /// Takes the parameters of `execute_compute_shader` along with the `wgpu::CommandEncoder`
+    /// to record the compute pass in, so several dispatches and copies can be submitted as one
+    /// command buffer. Every dispatch is its own usage scope, so the storage writes of a
+    /// dispatch are visible to the ones recorded after it.
+    #[macro_export]
+    macro_rules! record_compute_shader {
+        ($context:expr,$encoder:expr,$shader_source:expr,$buffers:expr,$dispatch_size:expr,$binding_label:expr) => {
+            let compute_s_pipeline = wgpu_utils::cached_compute_pipeline(
+                $context,
+                $shader_source,
+                $buffers,
                 Some("Compute s pipeline"),
             )?;
 
             let binding_group = wgpu_utils::create_bind_group(
                 $context,
-                &binding_group_layout,
+                compute_s_pipeline.layout(),
                 $buffers,
                 $binding_label,
             );
 
-            let mut s_encoder = $context
-                .device()
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
             {
-                let mut s_pass = s_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                let mut s_pass = $encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                     label: Some("s_pass"),
                     timestamp_writes: None,
                 });
-                s_pass.set_pipeline(&compute_s_pipeline);
+                s_pass.set_pipeline(compute_s_pipeline.pipeline());
                 s_pass.set_bind_group(0, &binding_group, &[]);
                 s_pass.dispatch_workgroups(max($dispatch_size, 1) as u32, 1, 1)
             }
-
-            $context.queue().submit(Some(s_encoder.finish()));
         };
     }
 
@@ -887,10 +1357,10 @@ pub mod general_utils {
     /// 1. The buffer size must be greater than 0.
     /// 2. The buffer size must be a multiple of 256.
     ///
-    /// If these conditions are not met, the function will `panic` with an appropriate error message.
+    /// If these conditions are not met, a warning is logged and the default size is used instead.
     ///
-    /// Once the buffer size is determined and validated, it updates the environment variable
-    /// `CHIMP_BUFFER_SIZE` to reflect the finalized value.
+    /// The environment is only read, the result is the default buffer size of a
+    /// [ChimpConfig](crate::config::ChimpConfig).
     ///
     /// # Returns
     /// A `ChimpBufferInfo` struct is returned, containing:
@@ -899,11 +1369,7 @@ pub mod general_utils {
     ///
     /// # Environment Variable
     /// - `CHIMP_BUFFER_SIZE`: Used as an override to set a custom buffer size. If not set or invalid, the
-    ///   default value of 64 bytes will be used.
-    ///
-    /// # Panics
-    /// - Panics if the final buffer size is not greater than 0.
-    /// - Panics if the final buffer size is not a multiple of 256.
+    ///   default value of 256 values will be used.
     ///
     /// # Example
     /// ```rust
@@ -912,24 +1378,32 @@ pub mod general_utils {
     /// println!("Buffer Size: {}, Chunks: {}", buffer_info.buffer_size(), buffer_info.chunks());
     /// ```
     pub fn get_buffer_size() -> ChimpBufferInfo {
+        buffer_size_from_env(std::env::var("CHIMP_BUFFER_SIZE").ok().as_deref())
+    }
+
+    /// The buffer info of a `CHIMP_BUFFER_SIZE` of [value], [None] when the variable is not
+    /// set. A missing or invalid value falls back to 256 as described on [get_buffer_size]
+    pub fn buffer_size_from_env(value: Option<&str>) -> ChimpBufferInfo {
         let default_buffer = 256usize;
-        let final_buffer = match std::env::var("CHIMP_BUFFER_SIZE") {
-            Ok(buffer_str) => buffer_str.parse::<usize>().unwrap_or_else(|_| {
+        let final_buffer = match value {
+            Some(buffer_str) => buffer_str.parse::<usize>().unwrap_or_else(|_| {
                 warn!("Buffer size specified but not in usize format... defaulting to 256");
                 default_buffer
             }),
-            Err(_) => {
+            None => {
                 warn!("No explicit buffer size used... defaulting to 256");
                 default_buffer
             }
         };
-        assert!(final_buffer > 0, "Buffer size must be greater than 0");
-        assert_eq!(
-            final_buffer % 256,
-            0,
-            "Buffer size must be a multiple of 256"
-        );
-        std::env::set_var("CHIMP_BUFFER_SIZE", final_buffer.to_string());
+        let final_buffer = if final_buffer > 0 && final_buffer.is_multiple_of(256) {
+            final_buffer
+        } else {
+            warn!(
+                "Buffer size {} is not a positive multiple of 256... defaulting to 256",
+                final_buffer
+            );
+            default_buffer
+        };
         ChimpBufferInfo(final_buffer, final_buffer / 256)
     }
 
@@ -951,9 +1425,14 @@ pub mod general_utils {
     /// ```
     pub struct ChimpBufferInfo(usize, usize);
     impl ChimpBufferInfo {
+        /// The buffer info described by the environment, compressors and decompressors use
+        /// the one of their [ChimpConfig](crate::config::ChimpConfig) instead
         pub fn get() -> Self {
             get_buffer_size()
         }
+        pub fn with_buffer_size(buffer_size: usize) -> Self {
+            Self(buffer_size, buffer_size / 256)
+        }
         pub fn buffer_size(&self) -> usize {
             self.0
         }
@@ -962,7 +1441,7 @@ pub mod general_utils {
         }
     }
 
-    #[derive(Eq, PartialEq, Hash)]
+    #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
     pub enum Step {
         ComputeS,
         Compress,
@@ -1093,9 +1572,8 @@ pub mod general_utils {
         }
     }
     pub fn build_event_times(iter: impl IntoIterator<Item = EventLogType>) -> Vec<EventTime> {
-        let mut it = iter.into_iter();
         let mut out = HashMap::<u64, EventTime>::new();
-        for log in it {
+        for log in iter {
             let entry = out
                 .entry(*log.values())
                 .or_insert_with(|| EventTime::default());
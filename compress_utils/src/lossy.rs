@@ -0,0 +1,233 @@
+//! Lossy compression within a user given error bound.
+//!
+//! When the [ChimpConfig] of a compressor has an [ErrorBound], the values of every batch are
+//! quantized before the S computation by zeroing the low mantissa bits that the bound allows
+//! to lose. The values keep their sign and exponent, and the XOR of two neighbours ends in
+//! the zeroed bits, so the trailing zeros the Chimp encoding skips grow with the bound.
+//!
+//! The bound is recorded in the header of the stream, see [HeaderFlags::LOSSY_ABSOLUTE] and
+//! [HeaderFlags::LOSSY_RELATIVE], so [verify_error_bound] can check decoded values against
+//! the stream alone. The decompressors are unaffected, they decode the quantized values.
+//!
+//! [ChimpConfig]: crate::config::ChimpConfig
+//! [HeaderFlags::LOSSY_ABSOLUTE]: crate::format::HeaderFlags::LOSSY_ABSOLUTE
+//! [HeaderFlags::LOSSY_RELATIVE]: crate::format::HeaderFlags::LOSSY_RELATIVE
+use crate::config::ConfigError;
+use crate::error::ChimpError;
+use crate::format::ChimpHeader;
+use std::borrow::Cow;
+
+/// The largest error a decoded value may have. The bound is stored as an `f32` in the
+/// header, a requested bound is rounded down to the closest `f32` so the stored one is never
+/// looser than the requested one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorBound {
+    /// `|decoded - value| <= bound`
+    Absolute(f32),
+    /// `|decoded - value| <= bound * |value|`
+    Relative(f32),
+}
+
+// the constructors only accept finite bounds, so the bounds compare like integers
+impl Eq for ErrorBound {}
+
+impl ErrorBound {
+    pub fn absolute(bound: f64) -> Result<Self, ConfigError> {
+        Ok(ErrorBound::Absolute(narrow(bound)?))
+    }
+
+    pub fn relative(bound: f64) -> Result<Self, ConfigError> {
+        Ok(ErrorBound::Relative(narrow(bound)?))
+    }
+
+    pub fn value(&self) -> f32 {
+        match self {
+            ErrorBound::Absolute(bound) | ErrorBound::Relative(bound) => *bound,
+        }
+    }
+
+    /// The base two exponent of the bound, the largest `e` with `2^e <= bound`
+    fn exponent(&self) -> i32 {
+        ((self.value().to_bits() >> 23) & 0xff) as i32 - 127
+    }
+}
+
+/// Round [bound] down to a normal `f32`
+fn narrow(bound: f64) -> Result<f32, ConfigError> {
+    let mut narrowed = bound as f32;
+    if narrowed as f64 > bound {
+        narrowed = narrowed.next_down();
+    }
+    if !bound.is_finite() || narrowed < f32::MIN_POSITIVE {
+        return Err(ConfigError::InvalidErrorBound(bound));
+    }
+    Ok(narrowed)
+}
+
+/// A floating point value whose mantissa can be truncated to an [ErrorBound]
+pub trait Quantize: Copy {
+    /// Zero the low mantissa bits of the value that [bound] allows to lose. Infinities,
+    /// NaNs and, for a relative bound, subnormals are returned unchanged
+    fn quantize(self, bound: ErrorBound) -> Self;
+
+    /// Whether [self] is within [bound] of [original], NaNs only match NaNs
+    fn is_within(self, original: Self, bound: ErrorBound) -> bool;
+
+    /// Whether [self] and [other] have the same bits
+    fn is_identical(self, other: Self) -> bool;
+}
+
+macro_rules! impl_quantize {
+    ($float:ty, $mantissa_bits:expr, $exponent_mask:expr, $bias:expr) => {
+        impl Quantize for $float {
+            fn quantize(self, bound: ErrorBound) -> Self {
+                let bits = self.to_bits();
+                let exponent_bits = (bits >> $mantissa_bits) as i32 & $exponent_mask;
+                if !self.is_finite() {
+                    return self;
+                }
+                let truncated = match bound {
+                    ErrorBound::Absolute(value) => {
+                        if self.abs() <= value as $float {
+                            return (0.0 as $float).copysign(self);
+                        }
+                        // |self| > bound, so the value is normal
+                        bound.exponent() - (exponent_bits - $bias) + $mantissa_bits
+                    }
+                    ErrorBound::Relative(_) => {
+                        if exponent_bits == 0 {
+                            return self;
+                        }
+                        bound.exponent() + $mantissa_bits
+                    }
+                };
+                // dropping k bits loses less than 2^(exponent - mantissa bits + k), which is
+                // at most 2^bound exponent, or that times 2^exponent <= |self| when relative
+                let truncated = truncated.clamp(0, $mantissa_bits) as u32;
+                <$float>::from_bits(bits & !((1 << truncated) - 1))
+            }
+
+            fn is_within(self, original: Self, bound: ErrorBound) -> bool {
+                if original.is_nan() || self.is_nan() {
+                    return original.is_nan() && self.is_nan();
+                }
+                if original.is_infinite() || self.is_infinite() {
+                    return original == self;
+                }
+                let error = (self as f64 - original as f64).abs();
+                match bound {
+                    ErrorBound::Absolute(value) => error <= value as f64,
+                    ErrorBound::Relative(value) => error <= value as f64 * (original as f64).abs(),
+                }
+            }
+
+            fn is_identical(self, other: Self) -> bool {
+                self.to_bits() == other.to_bits()
+            }
+        }
+    };
+}
+
+impl_quantize!(f32, 23, 0xff, 127);
+impl_quantize!(f64, 52, 0x7ff, 1023);
+
+/// The [values] quantized to [bound], borrowed as they are when there is no bound
+pub fn quantize<T: Quantize>(values: &[T], bound: Option<ErrorBound>) -> Cow<'_, [T]> {
+    match bound {
+        Some(bound) => values.iter().map(|value| value.quantize(bound)).collect(),
+        None => Cow::Borrowed(values),
+    }
+}
+
+/// Check that the [decoded] values of [stream] are within the error bound recorded in its
+/// header of the [original] values. A lossless stream has to reproduce them bit for bit.
+///
+/// Fails with [ChimpError::ErrorBoundExceeded] at the first value out of the bound, or at the
+/// first missing value when the lengths differ
+pub fn verify_error_bound<T: Quantize>(
+    stream: &[u8],
+    original: &[T],
+    decoded: &[T],
+) -> Result<(), ChimpError> {
+    let header = ChimpHeader::read(stream)?;
+    let exceeded = original
+        .iter()
+        .zip(decoded)
+        .position(|(original, decoded)| match header.error_bound {
+            Some(bound) => !decoded.is_within(*original, bound),
+            None => !decoded.is_identical(*original),
+        })
+        .or((original.len() != decoded.len()).then(|| original.len().min(decoded.len())));
+    match exceeded {
+        Some(index) => Err(ChimpError::ErrorBoundExceeded { index }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::CodecVariant;
+
+    #[test]
+    fn quantized_values_stay_within_the_bound() {
+        let values: Vec<f64> = (0..1000)
+            .map(|i| (i as f64 * 0.37).sin() * 10f64.powi(i % 7 - 3))
+            .chain([0.0, -0.0, f64::INFINITY, f64::NAN, 5e-310])
+            .collect();
+        for bound in [
+            ErrorBound::absolute(1e-3).unwrap(),
+            ErrorBound::relative(1e-4).unwrap(),
+        ] {
+            let quantized = quantize(&values, Some(bound));
+            for (value, quantized) in values.iter().zip(quantized.iter()) {
+                assert!(quantized.is_within(*value, bound), "{value} {quantized}");
+            }
+            let narrow: Vec<f32> = values.iter().map(|it| *it as f32).collect();
+            for value in narrow {
+                assert!(value.quantize(bound).is_within(value, bound), "{value}");
+            }
+        }
+        // the bits below 2^-10 of 1.5 are dropped, so 1.5 + 2^-11 loses 2^-11
+        let bound = ErrorBound::absolute(1e-3).unwrap();
+        assert_eq!((1.5f32 + 2f32.powi(-11)).quantize(bound), 1.5);
+        assert_eq!(1.5f64.quantize(bound), 1.5);
+        assert_eq!((-4e-4f64).quantize(bound).to_bits(), (-0.0f64).to_bits());
+        assert!(matches!(quantize(&values, None), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn bounds_are_rounded_down_and_validated() {
+        let ErrorBound::Absolute(bound) = ErrorBound::absolute(1e-3).unwrap() else {
+            unreachable!()
+        };
+        assert!(bound as f64 <= 1e-3);
+        for invalid in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e-300] {
+            assert!(matches!(
+                ErrorBound::relative(invalid),
+                Err(ConfigError::InvalidErrorBound(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn verify_reads_the_bound_of_the_stream() {
+        let bound = ErrorBound::absolute(0.01).unwrap();
+        let lossy = ChimpHeader::new(CodecVariant::Chimp64, 0, 256, 3)
+            .with_error_bound(Some(bound))
+            .to_bytes();
+        let lossless = ChimpHeader::new(CodecVariant::Chimp64, 0, 256, 3).to_bytes();
+        let original = [1.0, 2.0, f64::NAN];
+        let decoded = [1.005, 2.0, f64::NAN];
+        verify_error_bound(&lossy, &original, &decoded).unwrap();
+        assert!(matches!(
+            verify_error_bound(&lossless, &original, &decoded),
+            Err(ChimpError::ErrorBoundExceeded { index: 0 })
+        ));
+        verify_error_bound(&lossless, &original, &original).unwrap();
+        assert!(matches!(
+            verify_error_bound(&lossy, &original, &decoded[..2]),
+            Err(ChimpError::ErrorBoundExceeded { index: 2 })
+        ));
+    }
+}
@@ -0,0 +1,221 @@
+//! A cache of the compiled compute pipelines of a [Context](crate::context::Context).
+//!
+//! The stages specialize their WGSL source per configuration and per workgroup offset pass,
+//! the same specialized shader is compiled again for every batch of every compression.
+//! [PipelineCache] keeps the pipeline of each specialized shader and bind group layout, so
+//! only the first batch of a configuration pays for the shader compilation.
+use crate::shader_template::SpecializedShader;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use wgpu::{BindGroupLayout, BindGroupLayoutEntry, ComputePipeline};
+
+/// What a cached pipeline is compiled from, the specialized shader along with its pipeline
+/// constants and the entries of its bind group layout
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    shader: SpecializedShader,
+    entries: Vec<BindGroupLayoutEntry>,
+}
+
+impl PipelineKey {
+    pub fn new(shader: impl Into<SpecializedShader>, entries: Vec<BindGroupLayoutEntry>) -> Self {
+        Self {
+            shader: shader.into(),
+            entries,
+        }
+    }
+
+    pub fn shader(&self) -> &SpecializedShader {
+        &self.shader
+    }
+
+    pub fn entries(&self) -> &[BindGroupLayoutEntry] {
+        &self.entries
+    }
+}
+
+/// A compiled compute pipeline along with the bind group layout its bind groups are created with
+#[derive(Debug, Clone)]
+pub struct CachedPipeline {
+    layout: BindGroupLayout,
+    pipeline: ComputePipeline,
+}
+
+impl CachedPipeline {
+    pub fn new(layout: BindGroupLayout, pipeline: ComputePipeline) -> Self {
+        Self { layout, pipeline }
+    }
+
+    pub fn layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn pipeline(&self) -> &ComputePipeline {
+        &self.pipeline
+    }
+}
+
+/// The lookups a [PipelineCache] has served
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineCacheStats {
+    /// Lookups served by a pipeline compiled before
+    pub hits: u64,
+    /// Lookups that compiled their pipeline
+    pub misses: u64,
+    /// Pipelines in the cache
+    pub entries: usize,
+}
+
+impl Display for PipelineCacheStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses, {} pipelines",
+            self.hits, self.misses, self.entries
+        )
+    }
+}
+
+/// Pipelines by [PipelineKey], shared by every stage running on the same context
+#[derive(Debug)]
+pub struct PipelineCache<V = CachedPipeline> {
+    pipelines: Mutex<HashMap<PipelineKey, V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<V> Default for PipelineCache<V> {
+    fn default() -> Self {
+        Self {
+            pipelines: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<V: Clone> PipelineCache<V> {
+    /// The pipeline of [key], compiled with [compile] when the cache does not hold it yet.
+    ///
+    /// A failed compilation is not cached, the next lookup of [key] compiles it again.
+    pub fn get_or_try_insert_with<E>(
+        &self,
+        key: PipelineKey,
+        compile: impl FnOnce(&PipelineKey) -> Result<V, E>,
+    ) -> Result<V, E> {
+        // the lock is held while compiling so concurrent stages compile a pipeline only once
+        let mut pipelines = self.pipelines.lock().unwrap_or_else(|it| it.into_inner());
+        if let Some(pipeline) = pipelines.get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(pipeline.clone());
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let pipeline = compile(&key)?;
+        pipelines.insert(key, pipeline.clone());
+        Ok(pipeline)
+    }
+}
+
+impl<V> PipelineCache<V> {
+    pub fn stats(&self) -> PipelineCacheStats {
+        PipelineCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.len(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines
+            .lock()
+            .unwrap_or_else(|it| it.into_inner())
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// What each of the cached pipelines was compiled from
+    pub fn keys(&self) -> Vec<PipelineKey> {
+        self.pipelines
+            .lock()
+            .unwrap_or_else(|it| it.into_inner())
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Drop every cached pipeline, the hit and miss counts are kept
+    pub fn clear(&self) {
+        self.pipelines
+            .lock()
+            .unwrap_or_else(|it| it.into_inner())
+            .clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline_cache::{PipelineCache, PipelineCacheStats, PipelineKey};
+    use wgpu::{BindGroupLayoutEntry, BindingType, BufferBindingType, ShaderStages};
+
+    fn entry(binding: u32) -> BindGroupLayoutEntry {
+        BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+
+    #[test]
+    fn test_pipelines_are_compiled_once_per_source_and_layout() {
+        let cache = PipelineCache::<usize>::default();
+        let mut compiled = 0;
+        let mut lookup = |source: &str, bindings: &[u32]| {
+            let key = PipelineKey::new(source, bindings.iter().copied().map(entry).collect());
+            cache
+                .get_or_try_insert_with(key, |_| {
+                    compiled += 1;
+                    Ok::<_, ()>(compiled)
+                })
+                .unwrap()
+        };
+        assert_eq!(lookup("a", &[0, 1]), 1);
+        assert_eq!(lookup("a", &[0, 1]), 1);
+        assert_eq!(lookup("b", &[0, 1]), 2);
+        assert_eq!(lookup("a", &[0, 1, 2]), 3);
+        assert_eq!(lookup("b", &[0, 1]), 2);
+        assert_eq!(
+            cache.stats(),
+            PipelineCacheStats {
+                hits: 2,
+                misses: 3,
+                entries: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_failed_compilations_are_not_cached() {
+        let cache = PipelineCache::<u32>::default();
+        let key = PipelineKey::new("a", vec![entry(0)]);
+        assert!(cache
+            .get_or_try_insert_with(key.clone(), |_| Err("invalid"))
+            .is_err());
+        assert!(cache.is_empty());
+        assert_eq!(
+            cache.get_or_try_insert_with(key, |_| Ok::<_, &str>(7)),
+            Ok(7)
+        );
+        assert_eq!(cache.stats().misses, 2);
+        assert_eq!(cache.stats().hits, 0);
+    }
+}
@@ -0,0 +1,437 @@
+//! Specialization of the WGSL shaders of the pipeline stages.
+//!
+//! A [ShaderTemplate] declares the parameters its shader expects and the sources its
+//! `//#include(name)` lines pull in. Most parameters are WGSL `override` declarations, whose
+//! values reach the shader as pipeline constants without touching its source. The few that a
+//! shader needs as a `const`, such as the size of a function scope array, are written in place
+//! of a `//@name` line of the source.
+//!
+//! Specializing a template fails with a [TemplateError] when the declared parameters and the
+//! shader disagree, or when a parameter is left unbound or bound to a name the shader does not
+//! use, instead of compiling a shader with a missing or stale constant.
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("Shader {shader} has no value bound to its parameter {parameter}")]
+    Unbound {
+        shader: &'static str,
+        parameter: String,
+    },
+    #[error("Shader {shader} does not use the parameter {parameter}")]
+    Unused {
+        shader: &'static str,
+        parameter: String,
+    },
+    #[error("Shader {shader} uses the parameter {parameter} without declaring it")]
+    Undeclared {
+        shader: &'static str,
+        parameter: String,
+    },
+    #[error("Shader {shader} binds the parameter {parameter} more than once")]
+    BoundTwice {
+        shader: &'static str,
+        parameter: String,
+    },
+    #[error("Shader {shader} includes {include} which it does not declare")]
+    UnknownInclude {
+        shader: &'static str,
+        include: String,
+    },
+    #[error("Shader {shader} declares the include {include} without including it")]
+    UnusedInclude {
+        shader: &'static str,
+        include: String,
+    },
+}
+
+/// How the value of a template parameter reaches the shader
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Parameter {
+    /// A module scope `override name: u32;` declaration, set as a pipeline constant
+    Override(&'static str),
+    /// A `//@name` line replaced by `const name=<value>u;`, for the values WGSL requires to be
+    /// known when the shader is created
+    Constant(&'static str),
+}
+
+impl Parameter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Parameter::Override(name) | Parameter::Constant(name) => name,
+        }
+    }
+}
+
+/// A WGSL shader along with the parameters and includes it is specialized with
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderTemplate {
+    name: &'static str,
+    source: &'static str,
+    parameters: &'static [Parameter],
+    includes: &'static [(&'static str, &'static str)],
+}
+
+impl ShaderTemplate {
+    pub const fn new(name: &'static str, source: &'static str) -> Self {
+        Self {
+            name,
+            source,
+            parameters: &[],
+            includes: &[],
+        }
+    }
+
+    pub const fn with_parameters(mut self, parameters: &'static [Parameter]) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    /// The `(name, source)` pairs of the `//#include(name)` lines of the shader
+    pub const fn with_includes(
+        mut self,
+        includes: &'static [(&'static str, &'static str)],
+    ) -> Self {
+        self.includes = includes;
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn parameters(&self) -> &'static [Parameter] {
+        self.parameters
+    }
+
+    /// Start binding the values of the parameters of the template
+    pub fn specialize(&self) -> Specialization {
+        Specialization {
+            template: *self,
+            values: Vec::new(),
+        }
+    }
+
+    /// The source with its includes resolved, checked against the declared parameters
+    pub fn expand(&self) -> Result<String, TemplateError> {
+        let mut used_includes = HashSet::new();
+        let mut source = String::with_capacity(self.source.len());
+        for line in self.source.lines() {
+            match include_of(line) {
+                Some(include) => {
+                    let (name, content) = self
+                        .includes
+                        .iter()
+                        .find(|(name, _)| *name == include)
+                        .ok_or_else(|| TemplateError::UnknownInclude {
+                        shader: self.name,
+                        include: include.to_string(),
+                    })?;
+                    used_includes.insert(*name);
+                    source.push_str(content);
+                }
+                None => source.push_str(line),
+            }
+            source.push('\n');
+        }
+        if let Some((include, _)) = self
+            .includes
+            .iter()
+            .find(|(name, _)| !used_includes.contains(name))
+        {
+            return Err(TemplateError::UnusedInclude {
+                shader: self.name,
+                include: include.to_string(),
+            });
+        }
+
+        let mut used = Vec::new();
+        for line in source.lines() {
+            let declared = match (placeholder_of(line), override_of(line)) {
+                (Some(name), _) => self
+                    .parameters
+                    .iter()
+                    .find(|it| matches!(it, Parameter::Constant(it) if *it == name))
+                    .ok_or(name),
+                (None, Some(name)) => self
+                    .parameters
+                    .iter()
+                    .find(|it| matches!(it, Parameter::Override(it) if *it == name))
+                    .ok_or(name),
+                (None, None) => continue,
+            };
+            match declared {
+                Ok(parameter) => used.push(parameter),
+                Err(name) => {
+                    return Err(TemplateError::Undeclared {
+                        shader: self.name,
+                        parameter: name.to_string(),
+                    })
+                }
+            }
+        }
+        if let Some(parameter) = self.parameters.iter().find(|it| !used.contains(it)) {
+            return Err(TemplateError::Unused {
+                shader: self.name,
+                parameter: parameter.name().to_string(),
+            });
+        }
+        Ok(source)
+    }
+}
+
+/// The values bound to the parameters of a [ShaderTemplate] so far
+#[derive(Debug, Clone)]
+pub struct Specialization {
+    template: ShaderTemplate,
+    values: Vec<(String, u32)>,
+}
+
+impl Specialization {
+    pub fn bind(mut self, parameter: &str, value: u32) -> Self {
+        self.values.push((parameter.to_string(), value));
+        self
+    }
+
+    /// The shader with every parameter of its template bound
+    pub fn finish(self) -> Result<SpecializedShader, TemplateError> {
+        let template = self.template;
+        let mut source = template.expand()?;
+        for (index, (name, _)) in self.values.iter().enumerate() {
+            if self.values[..index].iter().any(|(it, _)| it == name) {
+                return Err(TemplateError::BoundTwice {
+                    shader: template.name,
+                    parameter: name.clone(),
+                });
+            }
+            if !template.parameters.iter().any(|it| it.name() == name) {
+                return Err(TemplateError::Unused {
+                    shader: template.name,
+                    parameter: name.clone(),
+                });
+            }
+        }
+
+        let mut constants = Vec::new();
+        for parameter in template.parameters {
+            let value = self
+                .values
+                .iter()
+                .find(|(name, _)| name == parameter.name())
+                .map(|(_, value)| *value)
+                .ok_or_else(|| TemplateError::Unbound {
+                    shader: template.name,
+                    parameter: parameter.name().to_string(),
+                })?;
+            match parameter {
+                Parameter::Override(name) => constants.push((name.to_string(), value)),
+                Parameter::Constant(name) => {
+                    source = source
+                        .lines()
+                        .map(|line| match placeholder_of(line) {
+                            Some(it) if it == *name => line
+                                .replace(&format!("//@{name}"), &format!("const {name}={value}u;")),
+                            _ => line.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                }
+            }
+        }
+        Ok(SpecializedShader {
+            name: Some(template.name),
+            source,
+            constants,
+        })
+    }
+}
+
+/// A WGSL source along with the values of its `override` declarations
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpecializedShader {
+    name: Option<&'static str>,
+    source: String,
+    constants: Vec<(String, u32)>,
+}
+
+impl SpecializedShader {
+    /// The name of the [ShaderTemplate] the shader was specialized from
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn constants(&self) -> &[(String, u32)] {
+        &self.constants
+    }
+}
+
+impl Display for SpecializedShader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl From<&str> for SpecializedShader {
+    fn from(value: &str) -> Self {
+        value.to_string().into()
+    }
+}
+
+impl From<String> for SpecializedShader {
+    fn from(value: String) -> Self {
+        Self {
+            name: None,
+            source: value,
+            constants: Vec::new(),
+        }
+    }
+}
+
+impl From<&String> for SpecializedShader {
+    fn from(value: &String) -> Self {
+        value.clone().into()
+    }
+}
+
+impl From<&SpecializedShader> for SpecializedShader {
+    fn from(value: &SpecializedShader) -> Self {
+        value.clone()
+    }
+}
+
+fn identifier(text: &str) -> Option<&str> {
+    let is_identifier = text.starts_with(|it: char| it.is_ascii_alphabetic() || it == '_')
+        && text
+            .chars()
+            .all(|it| it.is_ascii_alphanumeric() || it == '_');
+    is_identifier.then_some(text)
+}
+
+/// The name of a `//#include(name)` line
+fn include_of(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix("//#include(")?
+        .strip_suffix(')')
+        .filter(|it| !it.is_empty() && it.chars().all(|it| it.is_ascii_alphanumeric() || it == '_'))
+}
+
+/// The name of a `//@name` line, commented out attributes such as `//@group(0)` are not
+/// placeholders
+fn placeholder_of(line: &str) -> Option<&str> {
+    line.trim().strip_prefix("//@").and_then(identifier)
+}
+
+/// The name declared by an `override name: type;` line
+fn override_of(line: &str) -> Option<&str> {
+    let declaration = line.trim().strip_prefix("override ")?;
+    let end = declaration
+        .find(|it: char| !(it.is_ascii_alphanumeric() || it == '_'))
+        .unwrap_or(declaration.len());
+    identifier(&declaration[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shader_template::{Parameter, ShaderTemplate, TemplateError};
+
+    const UTILS: &str = "fn twice(value:u32)->u32 {\n    return value*2u;\n}";
+
+    const TEMPLATE: ShaderTemplate = ShaderTemplate::new(
+        "test",
+        "//#include(utils)\noverride workgroup_offset: u32;\n\nfn main() {\n    //@indices_size\n    var indices=array<u32,indices_size>();\n    //@group(0)\n}",
+    )
+    .with_parameters(&[
+        Parameter::Override("workgroup_offset"),
+        Parameter::Constant("indices_size"),
+    ])
+    .with_includes(&[("utils", UTILS)]);
+
+    #[test]
+    fn test_parameters_become_constants_and_overrides() {
+        let shader = TEMPLATE
+            .specialize()
+            .bind("workgroup_offset", 256)
+            .bind("indices_size", 8)
+            .finish()
+            .unwrap();
+        assert!(shader.source().starts_with(UTILS));
+        assert!(shader.source().contains("    const indices_size=8u;\n"));
+        assert!(shader.source().contains("//@group(0)"));
+        assert!(!shader.source().contains("//@indices_size"));
+        assert_eq!(shader.constants(), &[("workgroup_offset".to_string(), 256)]);
+    }
+
+    #[test]
+    fn test_unbound_and_unused_parameters_are_rejected() {
+        assert_eq!(
+            TEMPLATE.specialize().bind("workgroup_offset", 0).finish(),
+            Err(TemplateError::Unbound {
+                shader: "test",
+                parameter: "indices_size".to_string()
+            })
+        );
+        assert_eq!(
+            TEMPLATE
+                .specialize()
+                .bind("workgroup_offset", 0)
+                .bind("indices_size", 8)
+                .bind("log2n", 3)
+                .finish(),
+            Err(TemplateError::Unused {
+                shader: "test",
+                parameter: "log2n".to_string()
+            })
+        );
+        assert_eq!(
+            TEMPLATE
+                .specialize()
+                .bind("workgroup_offset", 0)
+                .bind("workgroup_offset", 0)
+                .bind("indices_size", 8)
+                .finish(),
+            Err(TemplateError::BoundTwice {
+                shader: "test",
+                parameter: "workgroup_offset".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_templates_must_match_their_shader() {
+        let misspelled =
+            ShaderTemplate::new("misspelled", "fn main() {\n    //@workgroup_ofset\n}")
+                .with_parameters(&[Parameter::Constant("workgroup_offset")]);
+        assert_eq!(
+            misspelled.expand(),
+            Err(TemplateError::Undeclared {
+                shader: "misspelled",
+                parameter: "workgroup_ofset".to_string()
+            })
+        );
+
+        let stale = ShaderTemplate::new("stale", "override n: u32;")
+            .with_parameters(&[Parameter::Override("n"), Parameter::Override("log2n")]);
+        assert_eq!(
+            stale.expand(),
+            Err(TemplateError::Unused {
+                shader: "stale",
+                parameter: "log2n".to_string()
+            })
+        );
+
+        let missing_include = ShaderTemplate::new("missing_include", "//#include(64_utils)");
+        assert_eq!(
+            missing_include.expand(),
+            Err(TemplateError::UnknownInclude {
+                shader: "missing_include",
+                include: "64_utils".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,454 @@
+//! Validation of the WGSL shaders of the pipeline stages on the host, with naga.
+//!
+//! A shader is otherwise only checked when a device compiles it, so a broken shader goes
+//! unnoticed until a GPU runs its stage. [validate] parses a [SpecializedShader], validates it
+//! with the capabilities of the [REQUIRED_FEATURES](crate::context::REQUIRED_FEATURES),
+//! applies its pipeline constants and writes it as SPIR-V, the way the Vulkan backend compiles
+//! it. [validate_templates] does so for every [ShaderTemplate] of a pipeline over a
+//! [ParameterMatrix] of buffer sizes, `n` values and workgroup offsets.
+//!
+//! [check_cached_layouts] compares the bind group layout each stage built with
+//! [bind_group_layout_entries](crate::wgpu_utils::bind_group_layout_entries) against the
+//! bindings its shader declares, for the pipelines a stage run left in a
+//! [PipelineCache].
+use crate::pipeline_cache::PipelineCache;
+use crate::shader_template::{ShaderTemplate, SpecializedShader, TemplateError};
+use naga::back::pipeline_constants::process_overrides;
+use naga::back::{spv, PipelineConstants};
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::{AddressSpace, ShaderStage, StorageAccess};
+use thiserror::Error;
+use wgpu::{BindGroupLayoutEntry, BindingType, BufferBindingType};
+
+#[derive(Error, Debug)]
+pub enum ShaderValidationError {
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error("Shader {shader} does not parse:\n{message}")]
+    Parse {
+        shader: &'static str,
+        message: String,
+    },
+    #[error("Shader {shader} is invalid:\n{message}")]
+    Invalid {
+        shader: &'static str,
+        message: String,
+    },
+    #[error("Shader {shader} is invalid with the constants {constants:?}: {message}")]
+    Constants {
+        shader: &'static str,
+        constants: Vec<(String, u32)>,
+        message: String,
+    },
+    #[error("Shader {shader} can not be written as SPIR-V: {message}")]
+    SpirV {
+        shader: &'static str,
+        message: String,
+    },
+    #[error(
+        "Shader {shader} uses @group({group}) @binding({binding}) which its layout does not have"
+    )]
+    MissingBinding {
+        shader: &'static str,
+        group: u32,
+        binding: u32,
+    },
+    #[error("Shader {shader} declares the binding {binding} as {declared:?} but its layout has {layout:?}")]
+    BindingType {
+        shader: &'static str,
+        binding: u32,
+        declared: BufferBindingType,
+        layout: BindingType,
+    },
+    #[error(
+        "The layout of shader {shader} has the binding {binding} which the shader does not declare"
+    )]
+    UndeclaredBinding { shader: &'static str, binding: u32 },
+    #[error("Shader {shader} was not dispatched, the layout of its stage is unchecked")]
+    NotDispatched { shader: &'static str },
+}
+
+/// A buffer binding declared by a shader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShaderBinding {
+    pub group: u32,
+    pub binding: u32,
+    /// A storage buffer the shader never writes is read only
+    pub ty: BufferBindingType,
+    /// Whether the `main` entry point accesses the binding
+    pub used: bool,
+}
+
+/// A shader naga validated, along with the buffer bindings it declares
+#[derive(Debug, Clone)]
+pub struct ValidatedShader {
+    name: &'static str,
+    bindings: Vec<ShaderBinding>,
+}
+
+impl ValidatedShader {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn bindings(&self) -> &[ShaderBinding] {
+        &self.bindings
+    }
+
+    /// Fail when a binding the shader uses is missing from [entries] or has another type, or
+    /// when [entries] has a binding the shader does not declare at all.
+    ///
+    /// A writable storage buffer of the layout may back a read only storage buffer of the
+    /// shader, the way wgpu allows it.
+    pub fn check_layout(
+        &self,
+        entries: &[BindGroupLayoutEntry],
+    ) -> Result<(), ShaderValidationError> {
+        for binding in self.bindings.iter().filter(|it| it.used) {
+            let entry = entries
+                .iter()
+                .find(|it| binding.group == 0 && it.binding == binding.binding)
+                .ok_or(ShaderValidationError::MissingBinding {
+                    shader: self.name,
+                    group: binding.group,
+                    binding: binding.binding,
+                })?;
+            let matches = match (binding.ty, entry.ty) {
+                (BufferBindingType::Uniform, BindingType::Buffer { ty, .. }) => {
+                    ty == BufferBindingType::Uniform
+                }
+                (BufferBindingType::Storage { read_only }, BindingType::Buffer { ty, .. }) => {
+                    matches!(ty, BufferBindingType::Storage { read_only: layout } if read_only || !layout)
+                }
+                _ => false,
+            };
+            if !matches {
+                return Err(ShaderValidationError::BindingType {
+                    shader: self.name,
+                    binding: binding.binding,
+                    declared: binding.ty,
+                    layout: entry.ty,
+                });
+            }
+        }
+        if let Some(entry) = entries.iter().find(|entry| {
+            !self
+                .bindings
+                .iter()
+                .any(|it| it.group == 0 && it.binding == entry.binding)
+        }) {
+            return Err(ShaderValidationError::UndeclaredBinding {
+                shader: self.name,
+                binding: entry.binding,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Parse, validate and write [shader] as SPIR-V with its pipeline constants applied
+pub fn validate(shader: &SpecializedShader) -> Result<ValidatedShader, ShaderValidationError> {
+    let name = shader.name().unwrap_or("without a template");
+    let module = naga::front::wgsl::parse_str(shader.source()).map_err(|error| {
+        ShaderValidationError::Parse {
+            shader: name,
+            message: error.emit_to_string(shader.source()),
+        }
+    })?;
+    let info = Validator::new(
+        ValidationFlags::all(),
+        Capabilities::FLOAT64 | Capabilities::SHADER_INT64,
+    )
+    .validate(&module)
+    .map_err(|error| ShaderValidationError::Invalid {
+        shader: name,
+        message: error.emit_to_string(shader.source()),
+    })?;
+
+    let mut constants = PipelineConstants::default();
+    for (parameter, value) in shader.constants() {
+        constants.insert(parameter.clone(), *value as f64);
+    }
+    let (module, info) = process_overrides(
+        &module,
+        &info,
+        Some((ShaderStage::Compute, "main")),
+        &constants,
+    )
+    .map_err(|error| ShaderValidationError::Constants {
+        shader: name,
+        constants: shader.constants().to_vec(),
+        message: error.to_string(),
+    })?;
+    spv::write_vec(
+        &module,
+        &info,
+        &spv::Options::default(),
+        Some(&spv::PipelineOptions {
+            shader_stage: ShaderStage::Compute,
+            entry_point: "main".to_string(),
+        }),
+    )
+    .map_err(|error| ShaderValidationError::SpirV {
+        shader: name,
+        message: error.to_string(),
+    })?;
+
+    let main = module
+        .entry_points
+        .iter()
+        .position(|it| it.name == "main")
+        .map(|index| info.get_entry_point(index));
+    let bindings = module
+        .global_variables
+        .iter()
+        .filter_map(|(handle, variable)| {
+            let resource = variable.binding.as_ref()?;
+            // the stages only ever bind buffers
+            let ty = match variable.space {
+                AddressSpace::Uniform => BufferBindingType::Uniform,
+                AddressSpace::Storage { access } => BufferBindingType::Storage {
+                    read_only: !access.contains(StorageAccess::STORE),
+                },
+                _ => return None,
+            };
+            Some(ShaderBinding {
+                group: resource.group,
+                binding: resource.binding,
+                ty,
+                used: main.is_some_and(|it| !it[handle].is_empty()),
+            })
+        })
+        .collect();
+    Ok(ValidatedShader { name, bindings })
+}
+
+/// The values the parameters of a [ShaderTemplate] are bound to when validating it, every
+/// combination of them is validated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterMatrix {
+    /// The values per buffer, bound to `workgroup_size`, `full_size`, `total_threads` and
+    /// `last_index`
+    pub buffer_sizes: Vec<u32>,
+    /// Bound to `n`, along with the `log2n` and `indices_size` derived from it
+    pub n_values: Vec<u32>,
+    pub workgroup_offsets: Vec<u32>,
+}
+
+impl Default for ParameterMatrix {
+    fn default() -> Self {
+        Self {
+            buffer_sizes: vec![256, 1024, 4096],
+            n_values: vec![8, 32, 128],
+            workgroup_offsets: vec![0, 65535],
+        }
+    }
+}
+
+impl ParameterMatrix {
+    /// Every distinct specialization of [template] over the matrix, a parameter the matrix has
+    /// no values for is left [unbound](TemplateError::Unbound)
+    pub fn specializations(
+        &self,
+        template: &ShaderTemplate,
+    ) -> Result<Vec<SpecializedShader>, TemplateError> {
+        let mut shaders = Vec::new();
+        for &buffer_size in &self.buffer_sizes {
+            for &n in &self.n_values {
+                for &workgroup_offset in &self.workgroup_offsets {
+                    for last_pass in [0, 1] {
+                        let mut specialization = template.specialize();
+                        for parameter in template.parameters() {
+                            let value = match parameter.name() {
+                                "workgroup_offset" => workgroup_offset,
+                                "workgroup_size" | "full_size" | "total_threads" | "last_index" => {
+                                    buffer_size
+                                }
+                                "n" => n,
+                                "log2n" => n.ilog2(),
+                                "indices_size" => 2u32.pow(n.ilog2() + 1),
+                                "last_pass" => last_pass,
+                                name => {
+                                    return Err(TemplateError::Unbound {
+                                        shader: template.name(),
+                                        parameter: name.to_string(),
+                                    })
+                                }
+                            };
+                            specialization = specialization.bind(parameter.name(), value);
+                        }
+                        let shader = specialization.finish()?;
+                        if !shaders.contains(&shader) {
+                            shaders.push(shader);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(shaders)
+    }
+}
+
+/// [validate] every specialization of [templates] over [matrix], returning how many shaders
+/// were validated
+pub fn validate_templates(
+    templates: &[ShaderTemplate],
+    matrix: &ParameterMatrix,
+) -> Result<usize, ShaderValidationError> {
+    let mut validated = 0;
+    for template in templates {
+        for shader in matrix.specializations(template)? {
+            validate(&shader)?;
+            validated += 1;
+        }
+    }
+    Ok(validated)
+}
+
+/// [validate] the shader of every pipeline of [cache] and check the bind group layout it was
+/// compiled with, returning how many pipelines were checked.
+///
+/// Fails with [ShaderValidationError::NotDispatched] when one of [templates] has no pipeline
+/// in [cache], as its stage did not run and its layout was left unchecked.
+pub fn check_cached_layouts<V>(
+    cache: &PipelineCache<V>,
+    templates: &[ShaderTemplate],
+) -> Result<usize, ShaderValidationError> {
+    let keys = cache.keys();
+    for key in &keys {
+        validate(key.shader())?.check_layout(key.entries())?;
+    }
+    if let Some(template) = templates.iter().find(|template| {
+        !keys
+            .iter()
+            .any(|it| it.shader().name() == Some(template.name()))
+    }) {
+        return Err(ShaderValidationError::NotDispatched {
+            shader: template.name(),
+        });
+    }
+    Ok(keys.len())
+}
+
+/// Write increasing offsets to the index buffer of [buffers], in place of the ones the index
+/// calculation dispatch writes on a device. The noop backend runs no dispatch, and the
+/// finalize stages size their output after the last of the offsets.
+#[cfg(feature = "noop")]
+pub fn seed_index_buffer(
+    context: &crate::context::Context,
+    buffers: &crate::wgpu_utils::RunBuffers,
+) {
+    let offsets =
+        (0..(buffers.index_buffer().size() / size_of::<u32>()) as u32).collect::<Vec<_>>();
+    context.queue().write_buffer(
+        buffers.index_buffer().buffer(),
+        0,
+        bytemuck::cast_slice(&offsets),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shader_template::Parameter::Override;
+    use crate::shader_template::{ShaderTemplate, SpecializedShader};
+    use crate::shader_validation::{validate, ParameterMatrix, ShaderValidationError};
+    use wgpu::{BindGroupLayoutEntry, BindingType, BufferBindingType, ShaderStages};
+
+    const TEMPLATE: ShaderTemplate = ShaderTemplate::new(
+        "test",
+        "@group(0) @binding(0) var<storage, read_write> output: array<u32>;
+@group(0) @binding(1) var<storage, read> input: array<u32>;
+@group(0) @binding(2) var<uniform> size: u32;
+override workgroup_offset: u32;
+override n: u32;
+override log2n: u32;
+
+@compute @workgroup_size(1)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>) {
+    let index = (workgroup_id.x + workgroup_offset) * size;
+    output[index] = input[index] * n + log2n;
+}",
+    )
+    .with_parameters(&[
+        Override("workgroup_offset"),
+        Override("n"),
+        Override("log2n"),
+    ]);
+
+    fn entry(binding: u32, ty: BufferBindingType) -> BindGroupLayoutEntry {
+        BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+
+    #[test]
+    fn test_every_specialization_is_validated() {
+        let matrix = ParameterMatrix::default();
+        let shaders = matrix.specializations(&TEMPLATE).unwrap();
+        assert_eq!(shaders.len(), 3 * 2);
+        assert!(shaders
+            .iter()
+            .any(|it| it.constants().contains(&("log2n".to_string(), 7))));
+        for shader in shaders {
+            validate(&shader).unwrap();
+        }
+        assert!(matches!(
+            validate(&SpecializedShader::from("fn main() { let a = ; }")),
+            Err(ShaderValidationError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn test_layouts_must_match_the_shader_bindings() {
+        let shader = validate(
+            &TEMPLATE
+                .specialize()
+                .bind("workgroup_offset", 0)
+                .bind("n", 8)
+                .bind("log2n", 3)
+                .finish()
+                .unwrap(),
+        )
+        .unwrap();
+        let storage = BufferBindingType::Storage { read_only: false };
+        shader
+            .check_layout(&[
+                entry(0, storage),
+                entry(1, storage),
+                entry(2, BufferBindingType::Uniform),
+            ])
+            .unwrap();
+        assert!(matches!(
+            shader.check_layout(&[entry(0, storage), entry(2, BufferBindingType::Uniform)]),
+            Err(ShaderValidationError::MissingBinding { binding: 1, .. })
+        ));
+        assert!(matches!(
+            shader.check_layout(&[entry(0, storage), entry(1, storage), entry(2, storage)]),
+            Err(ShaderValidationError::BindingType { binding: 2, .. })
+        ));
+        assert!(matches!(
+            shader.check_layout(&[
+                entry(0, BufferBindingType::Storage { read_only: true }),
+                entry(1, storage),
+                entry(2, BufferBindingType::Uniform),
+            ]),
+            Err(ShaderValidationError::BindingType { binding: 0, .. })
+        ));
+        assert!(matches!(
+            shader.check_layout(&[
+                entry(0, storage),
+                entry(1, storage),
+                entry(2, BufferBindingType::Uniform),
+                entry(3, storage),
+            ]),
+            Err(ShaderValidationError::UndeclaredBinding { binding: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,315 @@
+//! Incremental compression and decompression of streams that do not fit in memory.
+//!
+//! A [ChimpStreamEncoder] collects pushed values until they fill a batch of the pipeline and
+//! compresses the batch on a background thread, writing its blocks as soon as they are done.
+//! The stream starts with a [HeaderFlags::STREAMED] header since the number of values is only
+//! known on [ChimpStreamEncoder::finish].
+//!
+//! A [ChimpStreamDecoder] reads a stream block by block, so only a window of blocks is held
+//! in memory at a time.
+use crate::config::{ChimpConfig, ConfigError};
+use crate::cpu_compress::{Compressor, Decompressor};
+use crate::error::ChimpError;
+use crate::format::{
+    BlockIndex, ChimpHeader, FormatError, HeaderFlags, PayloadSections, BLOCK_PREFIX_SIZE,
+    HEADER_SIZE,
+};
+use itertools::Either;
+use pollster::FutureExt;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::thread::JoinHandle;
+
+/// A [Compressor] that splits its input into batches of [BatchedCompressor::batch_len]
+/// values, each compressed by a single pass of the pipeline
+pub trait BatchedCompressor<T>: Compressor<T> {
+    /// The configuration the values are compressed with
+    fn config(&self) -> &ChimpConfig;
+
+    /// The number of values compressed by one pass, a multiple of the buffer size
+    fn batch_len(&self) -> usize;
+
+    /// The header of a stream of [value_count] values written by this compressor
+    fn header(&self, value_count: usize) -> ChimpHeader;
+}
+
+/// Compresses the values pushed into it as a single stream written to [W].
+///
+/// Every full batch is handed to a background thread, which compresses it while the next
+/// one is collected, so a push only blocks when a batch is still waiting for the thread.
+/// The remaining values are compressed by [ChimpStreamEncoder::finish], which has to be
+/// called for the stream to be complete. Only the blocks of every batch are written, so a
+/// compressor configured with [ChimpConfig::with_block_checksums] is rejected.
+pub struct ChimpStreamEncoder<T, W> {
+    pending: Vec<T>,
+    batch_len: usize,
+    value_count: usize,
+    sender: Option<flume::Sender<Vec<T>>>,
+    worker: Option<JoinHandle<Result<W, ChimpError>>>,
+}
+
+impl<T, W> ChimpStreamEncoder<T, W>
+where
+    T: Copy + Send + 'static,
+    W: Write + Send + 'static,
+{
+    /// Fails with [ConfigError::ChecksumsOnStream] when the [compressor] writes block
+    /// checksums, which the stream has no place for
+    pub fn new(
+        compressor: impl BatchedCompressor<T> + Send + 'static,
+        writer: W,
+    ) -> Result<Self, ChimpError> {
+        if compressor.config().block_checksums() {
+            return Err(ConfigError::ChecksumsOnStream.into());
+        }
+        let batch_len = compressor.batch_len();
+        let (sender, receiver) = flume::bounded(1);
+        let worker = std::thread::spawn(move || compress_batches(compressor, writer, receiver));
+        Ok(Self {
+            pending: Vec::with_capacity(batch_len),
+            batch_len,
+            value_count: 0,
+            sender: Some(sender),
+            worker: Some(worker),
+        })
+    }
+
+    /// Append [values] to the stream, dispatching every batch they complete
+    pub fn push(&mut self, mut values: &[T]) -> Result<(), ChimpError> {
+        self.value_count += values.len();
+        while !values.is_empty() {
+            let missing = self.batch_len - self.pending.len();
+            let (head, rest) = values.split_at(missing.min(values.len()));
+            self.pending.extend_from_slice(head);
+            values = rest;
+            if self.pending.len() == self.batch_len {
+                let batch =
+                    std::mem::replace(&mut self.pending, Vec::with_capacity(self.batch_len));
+                self.dispatch(batch)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of values pushed so far
+    pub fn value_count(&self) -> usize {
+        self.value_count
+    }
+
+    /// Compress the values of the last, partial batch and wait for every batch to be
+    /// written, returning the writer
+    pub fn finish(mut self) -> Result<W, ChimpError> {
+        if !self.pending.is_empty() {
+            let batch = std::mem::take(&mut self.pending);
+            self.dispatch(batch)?;
+        }
+        self.sender = None;
+        self.join()
+    }
+
+    fn dispatch(&mut self, batch: Vec<T>) -> Result<(), ChimpError> {
+        let sender = self.sender.as_ref().ok_or(ChimpError::WorkerStopped)?;
+        if sender.send(batch).is_err() {
+            // the thread only drops the receiver when it failed
+            self.sender = None;
+            return Err(self.join().err().unwrap_or(ChimpError::WorkerStopped));
+        }
+        Ok(())
+    }
+
+    fn join(&mut self) -> Result<W, ChimpError> {
+        self.worker
+            .take()
+            .ok_or(ChimpError::WorkerStopped)?
+            .join()
+            .map_err(|_| ChimpError::WorkerStopped)?
+    }
+}
+
+fn compress_batches<T, W: Write>(
+    compressor: impl BatchedCompressor<T>,
+    mut writer: W,
+    batches: flume::Receiver<Vec<T>>,
+) -> Result<W, ChimpError> {
+    writer.write_all(&compressor.header(0).streamed().to_bytes())?;
+    for batch in batches.iter() {
+        let compressed = compressor.compress(&batch).block_on()?;
+        let stream = compressed.compressed_value_ref();
+        let header = ChimpHeader::read(stream)?;
+        let sections = PayloadSections::read(&header, &stream[HEADER_SIZE..])?;
+        writer.write_all(sections.blocks)?;
+    }
+    writer.flush()?;
+    Ok(writer)
+}
+
+/// Decodes a stream read from [R] a window of blocks at a time.
+///
+/// The blocks are located by walking their prefixes, each window is handed to the
+/// decompressor [D] as a stream of its own. Iterating yields the values of every window,
+/// [ChimpStreamDecoder::values] yields them one by one. The trailers of the stream are
+/// skipped, so its checksums are not verified.
+pub struct ChimpStreamDecoder<T, D, R> {
+    decompressor: D,
+    reader: R,
+    header: ChimpHeader,
+    window_blocks: usize,
+    block: usize,
+    byte_offset: usize,
+    read_values: u64,
+    done: bool,
+    values: PhantomData<T>,
+}
+
+impl<T, D, R> ChimpStreamDecoder<T, D, R>
+where
+    T: Send,
+    D: Decompressor<T>,
+    R: Read,
+{
+    /// Read the header of the stream from [reader]
+    pub fn new(decompressor: D, mut reader: R) -> Result<Self, ChimpError> {
+        let mut bytes = [0u8; HEADER_SIZE];
+        let len = read_full(&mut reader, &mut bytes)?;
+        let header = ChimpHeader::read(&bytes[..len])?;
+        Ok(Self {
+            decompressor,
+            reader,
+            header,
+            window_blocks: 1,
+            block: 0,
+            byte_offset: 0,
+            read_values: 0,
+            done: false,
+            values: PhantomData,
+        })
+    }
+
+    /// Decode [window_blocks] blocks with each call of the decompressor, which lets the gpu
+    /// decompressors spread a window over several workgroups
+    pub fn with_window_blocks(self, window_blocks: usize) -> Self {
+        Self {
+            window_blocks: window_blocks.max(1),
+            ..self
+        }
+    }
+
+    pub fn header(&self) -> &ChimpHeader {
+        &self.header
+    }
+
+    /// The values of the stream one at a time
+    pub fn values(self) -> impl Iterator<Item = Result<T, ChimpError>> {
+        self.flat_map(|window| match window {
+            Ok(values) => Either::Left(values.into_iter().map(Ok)),
+            Err(err) => Either::Right(std::iter::once(Err(err))),
+        })
+    }
+
+    fn has_more_blocks(&self) -> bool {
+        // a streamed header does not know its value count, but then it has no trailers either
+        self.header.flags.contains(HeaderFlags::STREAMED)
+            || self.read_values < self.header.value_count
+    }
+
+    /// Read the next window of blocks along with the number of values they hold
+    fn read_window(&mut self) -> Result<Option<(Vec<u8>, usize)>, ChimpError> {
+        let mut blocks = Vec::new();
+        let mut value_count = 0;
+        for _ in 0..self.window_blocks {
+            if !self.has_more_blocks() {
+                break;
+            }
+            let corrupted = FormatError::Corrupted {
+                block: self.block,
+                byte_offset: HEADER_SIZE + self.byte_offset,
+            };
+            let mut prefix = [0u8; BLOCK_PREFIX_SIZE];
+            match read_full(&mut self.reader, &mut prefix)? {
+                0 if self.header.flags.contains(HeaderFlags::STREAMED) => {
+                    self.done = true;
+                    break;
+                }
+                BLOCK_PREFIX_SIZE => {}
+                _ => return Err(corrupted.into()),
+            }
+            let (count, rest) = BlockIndex::read_prefix(self.header.codec, &prefix).unwrap();
+            // the size is checked before it is allocated, a corrupted prefix can claim anything
+            if count > self.header.buffer_size as usize
+                || rest % self.header.element_width as usize != 0
+                || rest > self.header.max_block_rest()
+            {
+                return Err(corrupted.into());
+            }
+            let start = blocks.len();
+            blocks.extend_from_slice(&prefix);
+            blocks.resize(start + BLOCK_PREFIX_SIZE + rest, 0);
+            if read_full(&mut self.reader, &mut blocks[start + BLOCK_PREFIX_SIZE..])? < rest {
+                return Err(corrupted.into());
+            }
+            self.block += 1;
+            self.byte_offset += BLOCK_PREFIX_SIZE + rest;
+            self.read_values += count as u64;
+            value_count += count;
+        }
+        Ok((!blocks.is_empty()).then_some((blocks, value_count)))
+    }
+}
+
+impl<T, D, R> Iterator for ChimpStreamDecoder<T, D, R>
+where
+    T: Send,
+    D: Decompressor<T>,
+    R: Read,
+{
+    type Item = Result<Vec<T>, ChimpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let window = match self.read_window() {
+            Ok(Some(window)) => window,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(err) => {
+                self.done = true;
+                return Some(Err(err));
+            }
+        };
+        let (blocks, value_count) = window;
+        // the header of the stream, the value type and the encoding included, without the
+        // trailers the window does not carry
+        let header = ChimpHeader {
+            flags: self
+                .header
+                .flags
+                .without(HeaderFlags::BLOCK_INDEX | HeaderFlags::BLOCK_CHECKSUMS),
+            ..self.header
+        };
+        let stream = header
+            .with_value_count(value_count as u64)
+            .prepend_to(blocks);
+        let decompressed = self.decompressor.decompress(&stream).block_on();
+        if decompressed.is_err() {
+            self.done = true;
+        }
+        Some(decompressed.map(|it| it.un_compressed_value()))
+    }
+}
+
+/// Fill [buffer] from [reader], returning how many bytes were read before the end of input
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(len)
+}
@@ -0,0 +1,234 @@
+//! The host round trips the tests of the codec crates share, behind the testing feature.
+//!
+//! Every batched codec writes the same header and block prefixes, so the checks of a host
+//! compressor against its host decompressor are the same for all of them: bit exact round
+//! trips of [sine_values] and [special_values], the buffer size of the header, range
+//! decompression with and without a [BlockIndex] and the block a checksum reports. A codec
+//! crate only keeps the assertions on its own block layout.
+use crate::cpu_compress::{Compressor, Decompressor};
+use crate::error::ChimpError;
+use crate::format::{BlockIndex, ChimpHeader, HeaderFlags, HEADER_SIZE};
+use itertools::Itertools;
+use pollster::FutureExt;
+use std::fmt::Debug;
+
+/// A value type of the codecs, compared by its bits so NaN payloads and signed zeros count
+pub trait TestValue: Copy + Debug + Send + Sync + 'static {
+    type Bits: Copy + Eq + Debug;
+
+    fn bits(self) -> Self::Bits;
+
+    /// The nearest value to [value]
+    fn from_f64(value: f64) -> Self;
+
+    /// The value whose bits are all ones, a NaN
+    fn all_ones() -> Self;
+
+    /// Bit patterns a decoder must not treat specially: NaN payloads including the all ones
+    /// pattern, infinities, signed zeros and subnormals
+    fn special() -> [Self; 12];
+}
+
+macro_rules! impl_test_value {
+    ($float:ty, $bits:ty, $nan_payload:expr, $largest_subnormal:expr) => {
+        impl TestValue for $float {
+            type Bits = $bits;
+
+            fn bits(self) -> $bits {
+                self.to_bits()
+            }
+
+            fn from_f64(value: f64) -> Self {
+                value as $float
+            }
+
+            fn all_ones() -> Self {
+                <$float>::from_bits(<$bits>::MAX)
+            }
+
+            fn special() -> [Self; 12] {
+                [
+                    Self::all_ones(),
+                    <$float>::NAN,
+                    -<$float>::NAN,
+                    <$float>::from_bits($nan_payload),
+                    <$float>::INFINITY,
+                    <$float>::NEG_INFINITY,
+                    0.0,
+                    -0.0,
+                    <$float>::from_bits(1),
+                    -<$float>::from_bits(1),
+                    <$float>::from_bits($largest_subnormal),
+                    <$float>::MIN_POSITIVE,
+                ]
+            }
+        }
+    };
+}
+
+impl_test_value!(f32, u32, 0x7fc0_0001, 0x007f_ffff);
+impl_test_value!(f64, u64, 0x7ff8_0000_0000_0001, 0x000f_ffff_ffff_ffff);
+
+/// [count] samples of a sine rounded to two decimals, so consecutive values share their
+/// leading and trailing bits
+pub fn sine_values<T: TestValue>(count: usize) -> Vec<T> {
+    (0..count)
+        .map(|it| T::from_f64((f64::sin(it as f64 / 10.0) * 100.0).round() / 100.0))
+        .collect_vec()
+}
+
+/// [sine_values] with the [TestValue::special] ones after every 100 of them. The all ones
+/// pattern also starts the stream, the second block of 256 values and ends the stream
+pub fn special_values<T: TestValue>() -> Vec<T> {
+    let mut values = vec![T::all_ones()];
+    for chunk in sine_values::<T>(600).chunks(100) {
+        values.extend(chunk);
+        values.extend(T::special());
+    }
+    values[256] = T::all_ones();
+    values.push(T::all_ones());
+    values
+}
+
+pub fn assert_same_bits<T: TestValue>(actual: &[T], expected: &[T]) {
+    assert_eq!(
+        actual.iter().map(|it| it.bits()).collect_vec(),
+        expected.iter().map(|it| it.bits()).collect_vec()
+    );
+}
+
+/// Compress [values] and check that [decompressor] returns them bit for bit, the stream is
+/// returned for the assertions of the codec
+pub fn assert_round_trip<T, C, D>(compressor: &C, decompressor: &D, values: &[T]) -> Vec<u8>
+where
+    T: TestValue,
+    C: Compressor<T> + ?Sized,
+    D: Decompressor<T> + ?Sized,
+{
+    let compressed = compressor.compress(values).block_on().unwrap();
+    let decompressed = decompressor
+        .decompress(compressed.compressed_value_ref())
+        .block_on()
+        .unwrap();
+    assert_same_bits(decompressed.un_compressed_value_ref(), values);
+    compressed.compressed_values()
+}
+
+/// Compress [values] with a [compressor] configured with [buffer_size], check that the header
+/// carries it, that a decompressor with the [default] config rejects the stream and that the
+/// [configured] one decodes it. The header is returned for the assertions of the codec
+pub fn assert_config_round_trip<T, C, D>(
+    compressor: &C,
+    configured: &D,
+    default: &D,
+    values: &[T],
+    buffer_size: usize,
+) -> ChimpHeader
+where
+    T: TestValue,
+    C: Compressor<T> + ?Sized,
+    D: Decompressor<T> + ?Sized,
+{
+    let stream = assert_round_trip(compressor, configured, values);
+    let header = ChimpHeader::read(&stream).unwrap();
+    assert_eq!(header.buffer_size as usize, buffer_size);
+    assert!(matches!(
+        default.decompress(&stream).block_on(),
+        Err(ChimpError::Format(_))
+    ));
+    header
+}
+
+/// Check [Decompressor::decompress_range] on the stream [compressor] writes for [values],
+/// with and without its [BlockIndex], for ranges within, across and at the ends of the
+/// blocks of 256 values, and that a range past the end is rejected
+pub fn assert_range_round_trip<T, C, D>(compressor: &C, decompressor: &D, values: &[T])
+where
+    T: TestValue,
+    C: Compressor<T> + ?Sized,
+    D: Decompressor<T> + Sync + ?Sized,
+{
+    let len = values.len();
+    assert!(len > 1300, "the ranges span more than 1300 values");
+    let compressed = compressor.compress(values).block_on().unwrap();
+    let unindexed = compressed.compressed_value_ref().clone();
+    let indexed = compressed.with_block_index().unwrap().compressed_values();
+    for stream in [&unindexed, &indexed] {
+        for range in [0..1, 1000..1300, 255..257, len - 100..len, 7..7] {
+            let decompressed = decompressor
+                .decompress_range(stream, range.clone())
+                .block_on()
+                .unwrap();
+            assert_same_bits(decompressed.un_compressed_value_ref(), &values[range]);
+        }
+    }
+    assert!(decompressor
+        .decompress_range(&indexed, len - 1..len + 1)
+        .block_on()
+        .is_err());
+}
+
+/// Flip a bit in the third block of a [stream] with block checksums and check that
+/// [decompressor] reports that block, at its offset in the stream, as corrupted
+pub fn assert_checksum_reports_corrupted_block<T, D>(mut stream: Vec<u8>, decompressor: &D)
+where
+    T: TestValue,
+    D: Decompressor<T> + ?Sized,
+{
+    let index = BlockIndex::read(&stream).unwrap();
+    let third_block = HEADER_SIZE + index.entries()[2].byte_offset as usize;
+    stream[third_block + 20] ^= 0b1000;
+    let error = decompressor.decompress(&stream).block_on().unwrap_err();
+    assert!(matches!(
+        error,
+        ChimpError::CorruptInput { block: 2, byte_offset } if byte_offset == third_block
+    ));
+}
+
+/// Flip every bit of every seventh byte of the payload of a [stream] without block checksums
+/// one at a time and cut it short after every seventh byte, and check that [decompressor]
+/// never panics: a damaged stream either decodes to some values or is rejected as corrupted
+/// or malformed
+pub fn assert_corruption_never_panics<T, D>(stream: &[u8], decompressor: &D)
+where
+    T: Send,
+    D: Decompressor<T> + ?Sized,
+{
+    let header = ChimpHeader::read(stream).unwrap();
+    assert!(!header.flags.contains(HeaderFlags::BLOCK_CHECKSUMS));
+    for byte in (HEADER_SIZE..stream.len()).step_by(7) {
+        for bit in 0..8 {
+            let mut corrupted = stream.to_vec();
+            corrupted[byte] ^= 1 << bit;
+            assert_decodes_or_is_corrupt(&corrupted, decompressor, || {
+                format!("flipping bit {bit} of byte {byte}")
+            });
+        }
+    }
+    assert_truncation_never_panics(stream, decompressor);
+}
+
+/// Cut a [stream] short after every seventh byte and check that [decompressor] never panics,
+/// for the streams without a header as well
+pub fn assert_truncation_never_panics<T, D>(stream: &[u8], decompressor: &D)
+where
+    T: Send,
+    D: Decompressor<T> + ?Sized,
+{
+    for len in (0..stream.len()).step_by(7) {
+        assert_decodes_or_is_corrupt(&stream[..len], decompressor, || {
+            format!("cutting the stream to {len} bytes")
+        });
+    }
+}
+
+fn assert_decodes_or_is_corrupt<T, D>(stream: &[u8], decompressor: &D, damage: impl Fn() -> String)
+where
+    T: Send,
+    D: Decompressor<T> + ?Sized,
+{
+    match decompressor.decompress(stream).block_on() {
+        Ok(_) | Err(ChimpError::CorruptInput { .. }) | Err(ChimpError::Format(_)) => {}
+        Err(error) => panic!("{} failed with {error}", damage()),
+    }
+}
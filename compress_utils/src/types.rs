@@ -1,11 +1,10 @@
-#[allow(dead_code, unused)]
 use crate::bit_utils::ToBitVec;
 use std::fmt;
 use std::fmt::Formatter;
 
-#[allow(dead_code, unused)]
 #[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "gpu", derive(bytemuck::Pod, bytemuck::Zeroable))]
 pub struct ChimpOutput {
     pub upper_bits: u32,
     pub lower_bits: u32,
@@ -60,9 +59,9 @@ impl fmt::Display for ChimpOutput {
     }
 }
 
-#[allow(dead_code, unused)]
 #[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "gpu", derive(bytemuck::Pod, bytemuck::Zeroable))]
 pub struct ChimpOutput64 {
     pub upper_bits: u64,
     pub lower_bits: u64,
@@ -117,9 +116,9 @@ impl fmt::Display for ChimpOutput64 {
     }
 }
 
-#[allow(dead_code, unused)]
 #[repr(C)]
-#[derive(Clone, Default, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Default, Copy, Debug)]
+#[cfg_attr(feature = "gpu", derive(bytemuck::Pod, bytemuck::Zeroable))]
 pub struct S {
     pub head: i32,
     pub tail: i32,
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use chimp_gpu::api::{Codec, CodecVariant, CompressorBuilder, GPUMode};
+use clap::ValueEnum;
+use compress_utils::config::ChimpConfig;
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CodecKind {
+    /// Batched Chimp over f32
+    Chimp32,
+    /// Batched Chimp over f64
+    Chimp64,
+    /// Batched ChimpN over f32
+    Chimpn32,
+    /// Batched ChimpN over f64
+    Chimpn64,
+    /// Batched Gorilla over f32, the baseline of Chimp
+    Gorilla32,
+    /// The sequential reference Chimp over f32, always runs on the host
+    Cpu,
+}
+
+impl CodecKind {
+    /// The variant of a batched codec, [None] for the sequential one whose stream has no
+    /// header
+    pub fn variant(&self) -> Option<CodecVariant> {
+        match self {
+            CodecKind::Chimp32 => Some(CodecVariant::Chimp32),
+            CodecKind::Chimp64 => Some(CodecVariant::Chimp64),
+            CodecKind::Chimpn32 => Some(CodecVariant::ChimpN32),
+            CodecKind::Chimpn64 => Some(CodecVariant::ChimpN64),
+            CodecKind::Gorilla32 => Some(CodecVariant::Gorilla32),
+            CodecKind::Cpu => None,
+        }
+    }
+}
+
+/// Where the batched codecs run, `cpu`, `gpu` for the default adapter, or the name of an
+/// adapter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Device {
+    Cpu,
+    DefaultGpu,
+    Adapter(String),
+}
+
+impl FromStr for Device {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "cpu" => Device::Cpu,
+            "gpu" => Device::DefaultGpu,
+            _ => Device::Adapter(s.to_string()),
+        })
+    }
+}
+
+impl Display for Device {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Device::Cpu => f.write_str("cpu"),
+            Device::DefaultGpu => f.write_str("gpu"),
+            Device::Adapter(name) => f.write_str(name),
+        }
+    }
+}
+
+impl Device {
+    /// A gpu that was asked for has to be used, the codecs do not fall back to the host
+    fn gpu_mode(&self) -> GPUMode {
+        match self {
+            Device::Cpu => GPUMode::CPU,
+            Device::DefaultGpu => GPUMode::GPUMust(String::new()),
+            Device::Adapter(name) => GPUMode::GPUMust(name.clone()),
+        }
+    }
+}
+
+/// The batched codec of [variant] on [device], set up like [config]
+pub async fn build_codec(
+    variant: CodecVariant,
+    device: &Device,
+    config: &ChimpConfig,
+) -> Result<Codec> {
+    let mut builder = CompressorBuilder::new().with_block_checksums(config.block_checksums());
+    if let Some(error_bound) = config.error_bound() {
+        builder = builder.with_error_bound(error_bound);
+    }
+    let codec = builder
+        .with_gpu_mode(device.gpu_mode())
+        .await?
+        .with_buffer_size(config.buffer_size() as u32)?
+        .with_n(config.n() as u32)?
+        .build(variant)?;
+    Ok(codec)
+}
@@ -1,186 +1,401 @@
-use anyhow::{anyhow, Result};
-use compress_utils::bit_utils::ToBitVec;
-use compress_utils::cpu_compress::{
-    CPUCompressor, Compressor, Decompressor, TimedCompressor, TimedDecompressor,
-};
-use compress_utils::general_utils::check_for_debug_mode;
-use dotenv::dotenv;
-use itertools::Itertools;
+mod codec;
+mod values;
+
+use crate::codec::{build_codec, CodecKind, Device};
+use crate::values::{write_values, Column, ValueFormat, ValueSource};
+use anyhow::{bail, Context, Result};
+use chimp_gpu::api::ValueType;
+use clap::{Args, Parser, Subcommand};
+use compress_utils::config::ChimpConfig;
+use compress_utils::cpu_compress::{CPUCompressor, Compressor, Decompressor};
+use compress_utils::format::ChimpHeader;
+use compress_utils::lossy::ErrorBound;
 use log::info;
-use std::{env, fs};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 
-#[tokio::main]
-pub async fn main() -> Result<()> {
-    // dotenv().expect("Failed to read .env file");
-    env_logger::init();
-    let mut values = get_values().expect("Could not read test values")[..256].to_vec();
+/// Compress and decompress floating point series with the Chimp codecs
+#[derive(Debug, Parser)]
+#[command(name = "compressapi", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Compress a series of values into a Chimp stream
+    Compress(CompressArgs),
+    /// Decompress a stream written by `compress`
+    Decompress(DecompressArgs),
+}
 
-    if check_for_debug_mode().expect("Could not read file system") {
-        for (i, value) in values.iter().enumerate() {
-            info!("{}:{} - {}", i, value, value.to_bits().to_bit_vec());
+#[derive(Debug, Args)]
+struct CodecArgs {
+    /// `cpu`, `gpu` for the default adapter, or the name of an adapter
+    #[arg(long, default_value = "gpu")]
+    device: Device,
+    /// Values per block, a multiple of 256. Decompression takes it from the stream header
+    /// when omitted
+    #[arg(long)]
+    buffer_size: Option<usize>,
+    /// Previous values searched by the chimpn codecs, a power of two. Decompression takes
+    /// it from the stream header when omitted
+    #[arg(long)]
+    n: Option<usize>,
+    /// Read from this file instead of stdin
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+    /// Write to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct CompressArgs {
+    #[arg(long, value_enum, default_value_t = CodecKind::Chimp32)]
+    codec: CodecKind,
+    #[command(flatten)]
+    codec_args: CodecArgs,
+    #[arg(long, value_enum, default_value_t = ValueFormat::Csv)]
+    format: ValueFormat,
+    /// The csv column holding the values, by header name or zero based index
+    #[arg(long)]
+    column: Option<Column>,
+    /// The csv file has no header row
+    #[arg(long)]
+    no_headers: bool,
+    /// Compress lossily, every decoded value within this absolute error of its value
+    #[arg(long, conflicts_with = "relative_error")]
+    absolute_error: Option<f64>,
+    /// Compress lossily, every decoded value within this error relative to its value
+    #[arg(long)]
+    relative_error: Option<f64>,
+    /// Append a checksum of every block, so a damaged stream fails to decompress
+    #[arg(long)]
+    block_checksums: bool,
+}
+
+impl CompressArgs {
+    fn error_bound(&self) -> Result<Option<ErrorBound>> {
+        let error_bound = match (self.absolute_error, self.relative_error) {
+            (Some(bound), _) => ErrorBound::absolute(bound)?,
+            (_, Some(bound)) => ErrorBound::relative(bound)?,
+            (None, None) => return Ok(None),
+        };
+        if self.codec == CodecKind::Cpu {
+            bail!("The cpu codec only compresses losslessly");
         }
+        Ok(Some(error_bound))
     }
 
-    //Scenario for gpu_compress
-    gpu_compress_batched(&mut values).await?;
-    gpu_compress(&mut values).await?;
+    fn config(&self) -> Result<ChimpConfig> {
+        let mut config = self.codec_args.config(None)?;
+        if let Some(error_bound) = self.error_bound()? {
+            config = config.with_error_bound(error_bound);
+        }
+        if self.block_checksums {
+            if self.codec == CodecKind::Cpu {
+                bail!("The cpu codec writes no block checksums");
+            }
+            config = config.with_block_checksums(true);
+        }
+        Ok(config)
+    }
+}
 
-    //Scenario for cpu compression
-    // cpu_compress(&mut values).await?;
-    Ok(())
+#[derive(Debug, Args)]
+struct DecompressArgs {
+    /// Only needed for the `cpu` codec, whose stream has no header, the batched codecs are
+    /// read from the stream header
+    #[arg(long, value_enum)]
+    codec: Option<CodecKind>,
+    #[command(flatten)]
+    codec_args: CodecArgs,
+    #[arg(long, value_enum, default_value_t = ValueFormat::Text)]
+    format: ValueFormat,
 }
 
-//noinspection DuplicatedCode
-async fn cpu_compress(values: &mut Vec<f32>) -> Result<()> {
-    let mut cpu_model = CPUCompressor::new(false);
-    let timed_compressor = TimedCompressor::from(cpu_model.clone());
-    let timed_decompressor = TimedDecompressor::from(cpu_model.clone());
-    if check_for_debug_mode().expect("Could not read file system") {
-        cpu_model.set_debug(true);
+impl CodecArgs {
+    /// The configuration of the codec, the flags take precedence over the [header] of the
+    /// stream being decompressed
+    fn config(&self, header: Option<&ChimpHeader>) -> Result<ChimpConfig> {
+        let mut config = ChimpConfig::default();
+        let buffer_size = self
+            .buffer_size
+            .or(header.map(|header| header.buffer_size as usize));
+        if let Some(buffer_size) = buffer_size {
+            config = config.with_buffer_size(buffer_size)?;
+        }
+        let n = self.n.or(header
+            .filter(|header| header.codec.uses_n())
+            .map(|header| header.n as usize));
+        if let Some(n) = n {
+            config = config.with_n(n)?;
+        }
+        Ok(config)
     }
-
-    println!("Starting compression of {} values", values.len());
-    let mut compressed = timed_compressor
-        .compress(values)
-        .await
-        .map_err(|err| anyhow!("{}", err))?;
-    println!("Finished compression of {} values", values.len());
-
-    println!("Started decompression");
-    let decompressed = timed_decompressor
-        .decompress(&mut compressed)
-        .await
-        .map_err(|err| anyhow!("{}", err))?;
-    println!("Finished decompression");
-    count_matching_values(values, &decompressed);
-    Ok(())
 }
 
-async fn gpu_compress_batched(values: &mut Vec<f32>) -> Result<()> {
-    println!("Starting batched compression of {} values", values.len());
-    let mut compressor = wgpu_compress_32_batched::ChimpCompressorBatched::default();
-    if check_for_debug_mode().expect("Could not read file system") {
-        compressor.set_debug(true);
+#[tokio::main]
+pub async fn main() -> Result<()> {
+    env_logger::init();
+    match Cli::parse().command {
+        Command::Compress(args) => {
+            let input = read_input(args.codec_args.input.as_deref())?;
+            let compressed = compress(&args, &input).await?;
+            info!(
+                "Compressed {} bytes into {} bytes",
+                input.len(),
+                compressed.len()
+            );
+            write_output(args.codec_args.output.as_deref(), &compressed)
+        }
+        Command::Decompress(args) => {
+            let input = read_input(args.codec_args.input.as_deref())?;
+            let decompressed = decompress(&args, &input).await?;
+            write_output(args.codec_args.output.as_deref(), &decompressed)
+        }
     }
-    let cpu_model = TimedDecompressor::from(CPUCompressor::default());
-
-    let mut compressed = compressor
-        .compress(values)
-        .await
-        .map_err(|err| anyhow!("{}", err))?;
-    println!("Finished compression of {} values", values.len());
-
-    // println!("Started decompression");
-    // let decompressed = cpu_model.decompress(&mut compressed).await?;
-    // println!("Finished decompression");
-    // count_matching_values(values, &decompressed);
-    Ok(())
 }
-async fn gpu_compress(values: &mut Vec<f32>) -> Result<()> {
-    println!("Starting compression of {} values", values.len());
-    let mut compressor = wgpu_compress_32::ChimpCompressor::default();
-    if check_for_debug_mode().expect("Could not read file system") {
-        compressor.set_debug(true);
+
+async fn compress(args: &CompressArgs, input: &[u8]) -> Result<Vec<u8>> {
+    let config = args.config()?;
+    let source = ValueSource {
+        format: args.format,
+        column: args.column.clone(),
+        has_headers: !args.no_headers,
+    };
+    let Some(variant) = args.codec.variant() else {
+        let values = source.read::<f32>(input)?;
+        check_not_empty(values.len())?;
+        let result = CPUCompressor::new(false).compress(&values).await?;
+        return Ok(result.compressed_values());
+    };
+    let codec = build_codec(variant, &args.codec_args.device, &config).await?;
+    let result = if variant.element_width() == 8 {
+        let values = source.read::<f64>(input)?;
+        check_not_empty(values.len())?;
+        codec.compress_f64(&values).await
+    } else {
+        let values = source.read::<f32>(input)?;
+        check_not_empty(values.len())?;
+        codec.compress_f32(&values).await
+    };
+    Ok(result?.compressed_values())
+}
+
+async fn decompress(args: &DecompressArgs, input: &[u8]) -> Result<Vec<u8>> {
+    if args.codec == Some(CodecKind::Cpu) {
+        let values = CPUCompressor::new(false).decompress(input).await?;
+        return write_values(&values.un_compressed_value(), args.format);
+    }
+    let header = ChimpHeader::read(input)?;
+    if let Some(variant) = args.codec.and_then(|codec| codec.variant()) {
+        if variant != header.codec {
+            bail!("The stream was written by {}, not {variant}", header.codec);
+        }
+    }
+    let config = args.codec_args.config(Some(&header))?;
+    let codec = build_codec(header.codec, &args.codec_args.device, &config).await?;
+    match header.value_type() {
+        ValueType::F32 => {
+            let values = codec.decompress_f32(input).await?;
+            write_values(&values.un_compressed_value(), args.format)
+        }
+        ValueType::F64 => {
+            let values = codec.decompress_f64(input).await?;
+            write_values(&values.un_compressed_value(), args.format)
+        }
+        value_type => bail!("The stream holds {value_type} values, only floats are written"),
     }
-    let cpu_model = TimedDecompressor::from(CPUCompressor::default());
-
-    let mut compressed = compressor
-        .compress(values)
-        .await
-        .map_err(|err| anyhow!("{}", err))?;
-    println!("Finished compression of {} values", values.len());
-
-    // println!("Started decompression");
-    // let decompressed = cpu_model.decompress(&mut compressed).await?;
-    // println!("Finished decompression");
-    // count_matching_values(values, &decompressed);
-    Ok(())
 }
 
-fn get_values() -> Result<Vec<f32>> {
-    let dir = env::current_dir()?;
-    let file_path = dir.parent().unwrap().join("city_temperature.csv");
-    let file_txt = fs::read_to_string(file_path)?;
-    let values = file_txt
-        .split("\n")
-        .map(get_third)
-        .filter(|p| p.is_some())
-        .map(|s| s.unwrap().parse::<f32>().unwrap())
-        .collect_vec()
-        .to_vec();
-    Ok(values)
+fn check_not_empty(value_count: usize) -> Result<()> {
+    if value_count == 0 {
+        bail!("The input holds no values to compress");
+    }
+    Ok(())
 }
 
-fn count_matching_values(values: &Vec<f32>, decompressed: &[f32]) -> usize {
-    let mut count_equal = 0;
-    let mut count_almost_equal = 0;
-    decompressed.iter().zip(values).for_each(|(a, b)| {
-        if a == b {
-            count_equal += 1;
-        } else if f32::abs(a - b) < 0.01 {
-            count_almost_equal += 1;
+fn read_input(path: Option<&Path>) -> Result<Vec<u8>> {
+    match path {
+        Some(path) => fs::read(path).with_context(|| format!("Could not read {}", path.display())),
+        None => {
+            let mut input = Vec::new();
+            std::io::stdin().read_to_end(&mut input)?;
+            Ok(input)
         }
-    });
-    info!(
-        "The number of values that are equal to the initial dataset is {}({})",
-        count_equal,
-        100 * count_equal / values.len()
-    );
-    info!(
-        "The number of values that are almost equal to the initial dataset is {}({})",
-        count_almost_equal,
-        100 * count_almost_equal / values.len()
-    );
-    count_equal
+    }
 }
 
-#[inline]
-fn get_third(field: &str) -> Option<String> {
-    field
-        .split(",")
-        .collect_vec()
-        .get(2)
-        .map(|it| it.to_string())
+fn write_output(path: Option<&Path>, bytes: &[u8]) -> Result<()> {
+    match path {
+        Some(path) => {
+            fs::write(path, bytes).with_context(|| format!("Could not write {}", path.display()))
+        }
+        None => Ok(std::io::stdout().write_all(bytes)?),
+    }
 }
 
 #[cfg(test)]
-mod compress_test {
-    use crate::{count_matching_values, get_values};
-    use compress_utils::cpu_compress::{
-        CPUCompressor, Compressor, Decompressor, TimedDecompressor,
-    };
-    use compress_utils::general_utils::check_for_debug_mode;
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use compress_utils::format::{HeaderFlags, HEADER_SIZE};
+
+    fn parse(args: &[&str]) -> Command {
+        Cli::try_parse_from(std::iter::once("compressapi").chain(args.iter().copied()))
+            .unwrap()
+            .command
+    }
 
     #[test]
-    pub fn wgpu_compress_32() {
-        env_logger::init();
-
-        let mut values = get_values().expect("Could not read test values");
-        log::info!("Starting compression of {} values", values.len());
-        let mut compressor =
-            wgpu_compress_32::ChimpCompressor::new("NVIDIA".to_string(), false).unwrap();
-        if check_for_debug_mode().expect("Could not read file system") {
-            compressor.set_debug(true);
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_on_the_host() {
+        let csv = (0..1000)
+            .map(|it| format!("{it},{}\n", (it as f32 / 10.0).sin() * 100.0))
+            .collect::<String>();
+        let expected = csv
+            .lines()
+            .map(|line| line.split(',').nth(1).unwrap().parse::<f64>().unwrap())
+            .collect::<Vec<_>>();
+
+        for codec in ["chimp32", "chimp64", "chimpn32", "chimpn64", "cpu"] {
+            let Command::Compress(compress_args) = parse(&[
+                "compress",
+                "--codec",
+                codec,
+                "--device",
+                "cpu",
+                "--buffer-size",
+                "256",
+                "--n",
+                "16",
+                "--column",
+                "1",
+                "--no-headers",
+            ]) else {
+                unreachable!()
+            };
+            let compressed = compress(&compress_args, csv.as_bytes()).await.unwrap();
+
+            // the codec, buffer size and n are read back from the header, the stream of the
+            // cpu codec has none
+            let decompress_flags = match codec {
+                "cpu" => vec!["decompress", "--codec", codec, "--device", "cpu"],
+                _ => vec!["decompress", "--device", "cpu"],
+            };
+            let Command::Decompress(decompress_args) = parse(&decompress_flags) else {
+                unreachable!()
+            };
+            let text = decompress(&decompress_args, &compressed)
+                .await
+                .expect(codec);
+            let decompressed = String::from_utf8(text)
+                .unwrap()
+                .lines()
+                .map(|line| line.parse::<f64>().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(decompressed.len(), expected.len(), "{codec}");
+            // the text of an f32 read back as f64 is not the f64 of the same text
+            let as_f32 = |values: &[f64]| values.iter().map(|it| *it as f32).collect::<Vec<_>>();
+            assert_eq!(as_f32(&decompressed), as_f32(&expected), "{codec}");
         }
-        let cpu_model = TimedDecompressor::from(CPUCompressor::default());
-
-        let mut compressed = pollster::block_on(compressor.compress(&mut values)).unwrap();
-        log::info!("Finished compression of {} values", values.len());
-        // let total_compressed_bytes = compressed.len() * size_of::<u8>();
-        log::info!("Started decompression");
-        let decompressed = pollster::block_on(cpu_model.decompress(&mut compressed)).unwrap();
-        log::info!("Finished decompression");
-
-        let count_equal = count_matching_values(&values, &decompressed);
-        log::info!(
-            "The total size of the compressed dataset is {}",
-            values.len() * size_of::<f32>(),
-        );
-        log::info!(
-            "The mean bit count per number is {}%",
-            100 * compressed.len() / (values.len() * size_of::<f32>()),
-        );
-
-        assert_eq!(count_equal, values.len());
+    }
+
+    #[tokio::test]
+    async fn test_lossy_round_trip_on_the_host() {
+        let values = (0..1000)
+            .map(|it| (it as f64 / 10.0).sin() * 100.0)
+            .collect::<Vec<_>>();
+        let csv = values
+            .iter()
+            .map(|it| format!("{it}\n"))
+            .collect::<String>();
+        let compress_args = |codec: &str| {
+            let Command::Compress(args) = parse(&[
+                "compress",
+                "--codec",
+                codec,
+                "--device",
+                "cpu",
+                "--column",
+                "0",
+                "--no-headers",
+                "--absolute-error",
+                "0.001",
+            ]) else {
+                unreachable!()
+            };
+            args
+        };
+        let compressed = compress(&compress_args("chimp64"), csv.as_bytes())
+            .await
+            .unwrap();
+        let Command::Decompress(decompress_args) = parse(&["decompress", "--device", "cpu"]) else {
+            unreachable!()
+        };
+        let text = decompress(&decompress_args, &compressed).await.unwrap();
+        let decompressed = String::from_utf8(text)
+            .unwrap()
+            .lines()
+            .map(|line| line.parse::<f64>().unwrap())
+            .collect::<Vec<_>>();
+        compress_utils::lossy::verify_error_bound(&compressed, &values, &decompressed).unwrap();
+
+        let Command::Decompress(other_codec) =
+            parse(&["decompress", "--codec", "chimp32", "--device", "cpu"])
+        else {
+            unreachable!()
+        };
+        assert!(decompress(&other_codec, &compressed).await.is_err());
+
+        assert!(compress(&compress_args("cpu"), csv.as_bytes())
+            .await
+            .is_err());
+    }
+    #[tokio::test]
+    async fn test_block_checksums_flag() {
+        let csv = (0..1000)
+            .map(|it| format!("{}\n", (it as f64 / 10.0).sin() * 100.0))
+            .collect::<String>();
+        let compress_args = |codec: &str| {
+            let Command::Compress(args) = parse(&[
+                "compress",
+                "--codec",
+                codec,
+                "--device",
+                "cpu",
+                "--column",
+                "0",
+                "--no-headers",
+                "--block-checksums",
+            ]) else {
+                unreachable!()
+            };
+            args
+        };
+        let mut compressed = compress(&compress_args("chimp32"), csv.as_bytes())
+            .await
+            .unwrap();
+        let header = ChimpHeader::read(&compressed).unwrap();
+        assert!(header.flags.contains(HeaderFlags::BLOCK_CHECKSUMS));
+
+        let Command::Decompress(decompress_args) = parse(&["decompress", "--device", "cpu"]) else {
+            unreachable!()
+        };
+        assert!(decompress(&decompress_args, &compressed).await.is_ok());
+        compressed[HEADER_SIZE + 20] ^= 0b1000;
+        assert!(decompress(&decompress_args, &compressed).await.is_err());
+
+        assert!(compress(&compress_args("cpu"), csv.as_bytes())
+            .await
+            .is_err());
     }
 }
@@ -0,0 +1,200 @@
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use std::fmt::Display;
+use std::num::ParseFloatError;
+use std::str::FromStr;
+
+/// A floating point type the codecs compress
+pub trait Element: Copy + Display + FromStr<Err = ParseFloatError> + Send + 'static {
+    const WIDTH: usize;
+
+    fn from_le_slice(bytes: &[u8]) -> Self;
+    fn to_le_vec(self) -> Vec<u8>;
+}
+
+impl Element for f32 {
+    const WIDTH: usize = size_of::<f32>();
+
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        f32::from_le_bytes(bytes.try_into().unwrap())
+    }
+    fn to_le_vec(self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+}
+
+impl Element for f64 {
+    const WIDTH: usize = size_of::<f64>();
+
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        f64::from_le_bytes(bytes.try_into().unwrap())
+    }
+    fn to_le_vec(self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+}
+
+/// How the uncompressed values are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ValueFormat {
+    /// A single column of a csv file, see `--column`
+    Csv,
+    /// Little endian f32 or f64, whichever the codec compresses
+    Raw,
+    /// One value per line
+    Text,
+}
+
+/// The column of a csv file holding the values, either its header name or its zero based index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for Column {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.parse()
+            .map(Column::Index)
+            .unwrap_or_else(|_| Column::Name(s.to_string())))
+    }
+}
+
+/// Where the values of the input are and how to parse them
+#[derive(Debug, Clone)]
+pub struct ValueSource {
+    pub format: ValueFormat,
+    pub column: Option<Column>,
+    pub has_headers: bool,
+}
+
+impl ValueSource {
+    pub fn read<T: Element>(&self, bytes: &[u8]) -> Result<Vec<T>> {
+        match self.format {
+            ValueFormat::Csv => self.read_csv(bytes),
+            ValueFormat::Raw => read_raw(bytes),
+            ValueFormat::Text => read_text(bytes),
+        }
+    }
+
+    fn read_csv<T: Element>(&self, bytes: &[u8]) -> Result<Vec<T>> {
+        let column = self
+            .column
+            .as_ref()
+            .ok_or_else(|| anyhow!("--column is required for csv input"))?;
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(self.has_headers)
+            .flexible(true)
+            .from_reader(bytes);
+        let index = match column {
+            Column::Index(index) => *index,
+            Column::Name(name) => {
+                if !self.has_headers {
+                    bail!("Column {name} can only be looked up in a file with a header row");
+                }
+                reader
+                    .headers()?
+                    .iter()
+                    .position(|header| header.trim() == name)
+                    .ok_or_else(|| anyhow!("The csv header has no column named {name}"))?
+            }
+        };
+        reader
+            .records()
+            .enumerate()
+            .map(|(row, record)| {
+                let record = record?;
+                let field = record
+                    .get(index)
+                    .ok_or_else(|| anyhow!("Row {} has no column {index}", row + 1))?;
+                parse_value(field, row + 1)
+            })
+            .collect()
+    }
+}
+
+fn read_raw<T: Element>(bytes: &[u8]) -> Result<Vec<T>> {
+    if !bytes.len().is_multiple_of(T::WIDTH) {
+        bail!(
+            "The raw input is {} bytes long, which is not a multiple of {}",
+            bytes.len(),
+            T::WIDTH
+        );
+    }
+    Ok(bytes.chunks_exact(T::WIDTH).map(T::from_le_slice).collect())
+}
+
+fn read_text<T: Element>(bytes: &[u8]) -> Result<Vec<T>> {
+    let text = std::str::from_utf8(bytes).context("The text input is not valid utf-8")?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(line, value)| parse_value(value, line + 1))
+        .collect()
+}
+
+fn parse_value<T: Element>(value: &str, row: usize) -> Result<T> {
+    value
+        .trim()
+        .parse()
+        .with_context(|| format!("Could not parse {value:?} on row {row} as a number"))
+}
+
+/// Write the decompressed values, raw output uses the width of the codec
+pub fn write_values<T: Element>(values: &[T], format: ValueFormat) -> Result<Vec<u8>> {
+    match format {
+        ValueFormat::Raw => Ok(values.iter().flat_map(|it| it.to_le_vec()).collect()),
+        ValueFormat::Text => Ok(values
+            .iter()
+            .map(|it| format!("{it}\n"))
+            .collect::<String>()
+            .into_bytes()),
+        ValueFormat::Csv => bail!("Decompressed values can only be written as raw or text"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "Region,Country,AvgTemperature\n\
+                       Africa,Algeria,64.2\n\
+                       Africa,Algeria,49.4\n\
+                       Africa,Algeria,-99\n";
+
+    fn csv_source(column: &str) -> ValueSource {
+        ValueSource {
+            format: ValueFormat::Csv,
+            column: Some(column.parse().unwrap()),
+            has_headers: true,
+        }
+    }
+
+    #[test]
+    fn test_csv_column_by_name_and_index() {
+        let by_name: Vec<f32> = csv_source("AvgTemperature").read(CSV.as_bytes()).unwrap();
+        let by_index: Vec<f32> = csv_source("2").read(CSV.as_bytes()).unwrap();
+        assert_eq!(by_name, vec![64.2, 49.4, -99.0]);
+        assert_eq!(by_name, by_index);
+
+        assert!(csv_source("Missing").read::<f32>(CSV.as_bytes()).is_err());
+        assert!(csv_source("Country").read::<f32>(CSV.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_raw_and_text_round_trip() {
+        let values = vec![1.5f64, -0.25, f64::MAX, 0.1];
+        for format in [ValueFormat::Raw, ValueFormat::Text] {
+            let source = ValueSource {
+                format,
+                column: None,
+                has_headers: false,
+            };
+            let bytes = write_values(&values, format).unwrap();
+            assert_eq!(source.read::<f64>(&bytes).unwrap(), values);
+        }
+        assert!(read_raw::<f64>(&[0u8; 12]).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::shaders;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{padding_to_fit_buffer_count, MaxGroupGnostic, Step};
+use compress_utils::types::S;
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{execute_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
+use std::cmp::max;
+use std::fs;
+use std::ops::Div;
+use std::sync::Arc;
+use std::time::Instant;
+use wgpu_types::BufferAddress;
+
+#[async_trait]
+pub trait ComputeS: MaxGroupGnostic {
+    /// Compute the [S] of the [values], given by their bits
+    async fn compute_s(
+        &self,
+        values: &[u16],
+        buffers: &mut RunBuffers,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError>;
+}
+
+pub struct ComputeSImpl {
+    context: Arc<Context>,
+    config: ChimpConfig,
+}
+
+impl ComputeSImpl {
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
+    }
+
+    pub fn context(&self) -> &Context {
+        self.context.as_ref()
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        self.context.device()
+    }
+}
+
+impl MaxGroupGnostic for ComputeSImpl {
+    fn get_max_number_of_groups(&self, content_len: usize) -> usize {
+        content_len.div(self.config.buffer_size())
+    }
+}
+
+#[async_trait]
+impl ComputeS for ComputeSImpl {
+    async fn compute_s(
+        &self,
+        values: &[u16],
+        buffers: &mut RunBuffers,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        // the values are padded with 0's to fill their last buffer
+        let padded_len =
+            values.len() + padding_to_fit_buffer_count(values.len(), self.config.buffer_size());
+        let workgroup_count = self.get_max_number_of_groups(padded_len);
+
+        let bytes = padded_len + 1;
+        let s_buffer_size = (size_of::<S>() * bytes) as BufferAddress;
+
+        let instant = Instant::now();
+        // wgsl has no 16 bit integers, the shaders read every value widened to a word
+        let words = values.iter().map(|it| *it as u32).collect::<Vec<_>>();
+        let input_storage_buffer = BufferWrapper::pooled_storage_with_padded_content(
+            self.context(),
+            bytemuck::cast_slice(&words),
+            (size_of::<u32>() * bytes) as BufferAddress,
+            WgpuGroupId::new(0, 1),
+            Some("Storage Input Buffer"),
+        );
+        let s_staging_buffer = BufferWrapper::pooled_stage_with_size(
+            self.context(),
+            s_buffer_size,
+            Some("Staging S Buffer"),
+        );
+        let s_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
+            s_buffer_size,
+            WgpuGroupId::new(0, 0),
+            Some("Storage S Buffer"),
+        );
+        let chunks_buffer = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
+            bytemuck::bytes_of(&self.config.buffer_info().chunks()),
+            WgpuGroupId::new(0, 2),
+            Some("Chunks Buffer"),
+        );
+        *skip_time += instant.elapsed().as_millis();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
+        for i in 0..iterations {
+            let shader_code = shaders::COMPUTE_S
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .finish()?;
+            execute_compute_shader!(
+                self.context(),
+                &shader_code,
+                vec![
+                    &s_storage_buffer,
+                    &input_storage_buffer,
+                    &s_staging_buffer,
+                    &chunks_buffer
+                ],
+                if i == iterations - 1 {
+                    last_size
+                } else {
+                    self.config.max_workgroup_size(&self.context)
+                },
+                Some("compute s layout")
+            );
+        }
+        buffers.set_input_buffer(input_storage_buffer);
+        buffers.set_s_buffer(s_storage_buffer);
+        buffers.set_chunks(chunks_buffer);
+
+        if self.config.traces(&Step::ComputeS) {
+            let output = wgpu_utils::get_from_gpu::<S>(
+                self.context(),
+                buffers.s_buffer().buffer(),
+                s_buffer_size,
+                s_staging_buffer.buffer(),
+            )
+            .await?;
+            let trace_output = output
+                .iter()
+                .map(|it| format!("{it}\n"))
+                .collect::<String>();
+            fs::write(Step::ComputeS.get_trace_file(), trace_output)?;
+        }
+        Ok(())
+    }
+}
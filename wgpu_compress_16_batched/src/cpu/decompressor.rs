@@ -0,0 +1,177 @@
+use crate::Float16;
+use async_trait::async_trait;
+use bit_vec::BitVec;
+use compress_utils::bit_utils::BitReadable;
+use compress_utils::config::ChimpConfig;
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, HEADER_SIZE};
+use compress_utils::general_utils::DecompressResult;
+use compress_utils::time_it;
+use itertools::Itertools;
+use log::info;
+
+/// The error of a block whose bitstream cannot be decoded, [BatchedDecompressor16Cpu::decompress_impl]
+/// replaces it with the position of the block in the stream
+const CORRUPT_BLOCK: ChimpError = ChimpError::CorruptInput {
+    block: 0,
+    byte_offset: 0,
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct BatchedDecompressor16Cpu {
+    config: ChimpConfig,
+}
+
+impl BatchedDecompressor16Cpu {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+
+    /// Read the [bit_count] bits at [index] of [input_vector], failing when the bitstream
+    /// ends before them
+    fn read(input_vector: &BitVec, index: usize, bit_count: usize) -> Result<u16, ChimpError> {
+        if index + bit_count > input_vector.len() {
+            return Err(CORRUPT_BLOCK);
+        }
+        Ok(input_vector.reinterpret_u32(index, bit_count) as u16)
+    }
+
+    /// Decode the bits of the first [value_count] values of the bitstream of a block
+    pub fn decompress_block(
+        &self,
+        input_vector: &BitVec,
+        value_count: usize,
+    ) -> Result<Vec<u16>, ChimpError> {
+        // the first value is written widened to a word
+        let first_num = Self::read(input_vector, 16, 16)?;
+        let mut output = vec![first_num];
+
+        let mut last_num = first_num;
+        let mut last_lead = 0;
+        let mut input_index = 32;
+        while output.len() < value_count {
+            if input_index + 1 >= input_vector.len() {
+                return Err(CORRUPT_BLOCK);
+            }
+            if input_vector[input_index] {
+                let mut lead = last_lead;
+                if input_vector[input_index + 1] {
+                    lead = Self::read(input_vector, input_index + 2, 4)?;
+                    input_index += 4;
+                }
+                input_index += 2;
+                if lead > 16 {
+                    return Err(CORRUPT_BLOCK);
+                }
+                let mut significant_bits = 16 - lead;
+                if significant_bits == 0 {
+                    significant_bits = 16;
+                }
+                let value = Self::read(input_vector, input_index, significant_bits as usize)?;
+                input_index += significant_bits as usize;
+                last_num ^= value;
+                last_lead = lead;
+                output.push(last_num);
+            } else if input_vector[input_index + 1] {
+                input_index += 2;
+                let lead = Self::read(input_vector, input_index, 4)?;
+                input_index += 4;
+                let mut significant_bits = Self::read(input_vector, input_index, 4)?;
+                input_index += 4;
+                if significant_bits == 0 {
+                    significant_bits = 16;
+                }
+                if lead + significant_bits > 16 {
+                    return Err(CORRUPT_BLOCK);
+                }
+                let trail = 16 - lead - significant_bits;
+                let value = Self::read(input_vector, input_index, significant_bits as usize)?;
+                input_index += significant_bits as usize;
+                last_num ^= ((value as u32) << trail) as u16;
+                last_lead = lead;
+                output.push(last_num);
+            } else {
+                last_lead = 16;
+                output.push(last_num);
+                input_index += 2;
+            }
+        }
+        Ok(output)
+    }
+
+    /// Decode the blocks of the payload [vec] of a stream, a block that cannot be decoded is
+    /// reported with its index and its offset in the stream, header included
+    pub fn decompress_impl(&self, vec: &[u8]) -> Result<Vec<u16>, ChimpError> {
+        let mut current_index = 0usize;
+        let mut block = 0usize;
+        let mut output = Vec::new();
+        let mut total_millis = 0;
+        time_it!(
+            {
+                while current_index < vec.len() {
+                    let corrupted = ChimpError::CorruptInput {
+                        block,
+                        byte_offset: HEADER_SIZE + current_index,
+                    };
+                    if current_index + 2 * size_of::<u32>() > vec.len() {
+                        return Err(corrupted);
+                    }
+                    let value_count = u32::from_le_bytes(
+                        vec[current_index..current_index + size_of::<u32>()]
+                            .try_into()
+                            .unwrap(),
+                    ) as usize
+                        + 1;
+                    current_index += size_of::<u32>();
+
+                    let size = u32::from_le_bytes(
+                        vec[current_index..current_index + size_of::<u32>()]
+                            .try_into()
+                            .unwrap(),
+                    ) as usize;
+                    current_index += size_of::<u32>();
+                    if current_index + size > vec.len() {
+                        return Err(corrupted);
+                    }
+                    // the words are written little endian, the bitstream reads them msb first
+                    let vec_view = vec[current_index..current_index + size]
+                        .chunks_exact(size_of::<u32>())
+                        .flat_map(|it| u32::from_le_bytes(it.try_into().unwrap()).to_be_bytes())
+                        .collect_vec();
+                    let bit_vec = BitVec::from_bytes(&vec_view);
+                    let block_values = self
+                        .decompress_block(&bit_vec, value_count)
+                        .map_err(|_| corrupted)?;
+
+                    output.extend(block_values);
+                    current_index += size;
+                    block += 1;
+                }
+            },
+            total_millis,
+            "decompression"
+        );
+        Ok(output)
+    }
+}
+
+#[async_trait]
+impl<T: Float16> Decompressor<T> for BatchedDecompressor16Cpu {
+    async fn decompress(&self, vec: &[u8]) -> Result<DecompressResult<T>, ChimpError> {
+        let (header, payload) = ChimpHeader::split(vec, CodecVariant::Chimp16)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        if header.value_type() != T::VALUE_TYPE {
+            return Err(ChimpError::WrongValueType {
+                codec: header.codec,
+                value_type: header.value_type(),
+            });
+        }
+        let values = self.decompress_impl(payload)?;
+        Ok(values
+            .into_iter()
+            .map(bytemuck::cast::<u16, T>)
+            .collect_vec()
+            .into())
+    }
+}
@@ -0,0 +1,113 @@
+use crate::final_compress::FinalCompress;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{MaxGroupGnostic, Step};
+use compress_utils::types::{ChimpOutput, S};
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{step, BufferWrapper};
+use std::fs;
+use std::ops::Div;
+
+/// Host implementation of the `chimp_compress.wgsl` and `initialize_first_per_buffer.wgsl` stages
+#[derive(Debug, Default)]
+pub struct CPUFinalCompress {
+    config: ChimpConfig,
+}
+
+impl CPUFinalCompress {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+
+    pub(crate) fn compress(v: u16, s: S, v_prev: u16, s_prev: S) -> ChimpOutput {
+        let xorred = v_prev ^ v;
+        let (value, bit_count): (u32, u32) = if s.equal == 1 {
+            // case 1: xor_value=0, code 00
+            (0, 2)
+        } else if s.tail > 4 {
+            // case 2: tail>4 && xor_value!=0, code 01
+            let center_bits = (16 - s.head - s.tail) as u32;
+            let mut value = 1u32;
+            value = (value << 4) + (s.head as u32 & 0xf);
+            value = (value << 4) + (center_bits & 0xf);
+            value = (value << center_bits) + (xorred >> s.tail) as u32;
+            (value, 2 + 4 + 4 + center_bits)
+        } else if s.head == s_prev.head {
+            // case 3: tail<=4 and lead=pr_lead, code 10
+            let significant_bits = (16 - s.head) as u32;
+            (
+                (2u32 << significant_bits) + xorred as u32,
+                2 + significant_bits,
+            )
+        } else {
+            // case 4: tail<=4 and lead!=pr_lead, code 11
+            let significant_bits = (16 - s.head) as u32;
+            let value = (3u32 << 4) + (s.head as u32 & 0xf);
+            (
+                (value << significant_bits) + xorred as u32,
+                2 + 4 + significant_bits,
+            )
+        };
+        ChimpOutput {
+            upper_bits: 0,
+            lower_bits: value,
+            bit_count,
+        }
+    }
+}
+
+impl MaxGroupGnostic for CPUFinalCompress {
+    fn get_max_number_of_groups(&self, content_len: usize) -> usize {
+        content_len.div(self.config.buffer_size())
+    }
+}
+
+#[async_trait]
+impl FinalCompress for CPUFinalCompress {
+    async fn final_compress(
+        &self,
+        buffers: &mut RunBuffers,
+        _skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        let output = {
+            let input = buffers.input_buffer().host_slice::<u32>();
+            let s_values = buffers.s_buffer().host_slice::<S>();
+            let mut output = vec![
+                ChimpOutput {
+                    upper_bits: 0,
+                    lower_bits: 0,
+                    bit_count: 0,
+                };
+                input.len()
+            ];
+            for index in 0..input.len() - 1 {
+                output[index + 1] = Self::compress(
+                    input[index + 1] as u16,
+                    s_values[index + 1],
+                    input[index] as u16,
+                    s_values[index],
+                );
+            }
+            // The first value of each buffer is written as is, in a word of its own
+            for index in (0..input.len() - 1).step_by(self.config.buffer_size()) {
+                output[index] = ChimpOutput {
+                    upper_bits: 0,
+                    lower_bits: input[index],
+                    bit_count: 32,
+                };
+            }
+            output
+        };
+        step!(self.config, Step::Compress, {
+            output
+                .iter()
+                .enumerate()
+                .map(|it| format!("{}:{}\n", it.0, it.1))
+        });
+        buffers.set_compressed_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &output,
+        )));
+        Ok(())
+    }
+}
@@ -0,0 +1,3 @@
+pub mod compute_s;
+pub mod decompressor;
+pub mod final_compress;
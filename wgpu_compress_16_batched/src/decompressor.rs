@@ -0,0 +1,284 @@
+use crate::shaders;
+use crate::Float16;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant};
+use compress_utils::general_utils::{cast_or_copy, DecompressResult, MaxGroupGnostic, Step};
+use compress_utils::{
+    execute_compute_shader, step, time_it, wgpu_utils, BufferWrapper, WgpuGroupId,
+};
+use log::info;
+use pollster::FutureExt;
+use std::cmp::{max, min};
+use std::fs;
+use std::sync::Arc;
+use std::time::Instant;
+use wgpu::{Device, Queue};
+use wgpu_types::BufferAddress;
+
+#[async_trait]
+impl<T: Float16> Decompressor<T> for BatchedGPUDecompressor16 {
+    #[allow(unused)]
+    async fn decompress(
+        &self,
+        compressed_bytes_vec: &[u8],
+    ) -> Result<DecompressResult<T>, ChimpError> {
+        let mut current_index = 0usize;
+        let mut uncompressed_values = Vec::new();
+        let mut total_millis = 0;
+        let mut skip_time = 0;
+        let (header, compressed_bytes_vec) =
+            ChimpHeader::split(compressed_bytes_vec, CodecVariant::Chimp16)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        if header.value_type() != T::VALUE_TYPE {
+            return Err(ChimpError::WrongValueType {
+                codec: header.codec,
+                value_type: header.value_type(),
+            });
+        }
+        let compressed_words = cast_or_copy::<u32>(compressed_bytes_vec);
+        time_it!(
+            {
+                let compressed_bytes_vec: &[u32] = &compressed_words;
+                let mut vec_window = Vec::new();
+                let mut total_uncompressed_values = 0;
+                let mut input_indexes = Vec::new();
+                let mut value_counts = Vec::new();
+                while current_index < compressed_bytes_vec.len() {
+                    while current_index < compressed_bytes_vec.len() {
+                        let old_index = current_index;
+                        let buffer_value_count = compressed_bytes_vec[current_index] + 1;
+                        current_index += 1;
+
+                        let size_in_bytes = compressed_bytes_vec[current_index];
+                        let size = size_in_bytes as usize / size_of::<u32>();
+                        current_index += 1;
+                        if (vec_window.len() + size as usize) * size_of::<u32>() as usize
+                            >= self.context.get_max_storage_buffer_size()
+                        {
+                            current_index = old_index;
+                            break;
+                        }
+
+                        if (input_indexes.len() + 1) * self.config.buffer_size() * 4
+                            >= self.context.get_max_storage_buffer_size()
+                        {
+                            current_index = old_index;
+                            break;
+                        }
+
+                        vec_window.extend(
+                            compressed_bytes_vec[current_index..current_index + (size as usize)]
+                                .to_vec(),
+                        );
+
+                        input_indexes.push(vec_window.len() as u32);
+                        value_counts.push(buffer_value_count as u32);
+                        current_index += size as usize;
+                        total_uncompressed_values += buffer_value_count as usize
+                    }
+                    input_indexes.insert(0, 0);
+                    //Block is as many buffers fit into the gpu the distinction is made for compatibility reasons
+                    let block_values = self
+                        .decompress_block(
+                            vec_window.as_slice(),
+                            input_indexes.as_slice(),
+                            value_counts.as_slice(),
+                            self.config.buffer_size(),
+                            &mut skip_time,
+                        )
+                        .await?;
+
+                    uncompressed_values.extend(block_values[0..total_uncompressed_values].iter());
+                    vec_window.clear();
+                    total_uncompressed_values = 0;
+                    input_indexes.clear();
+                    value_counts.clear();
+                }
+            },
+            total_millis,
+            "decompression"
+        );
+        step!(self.config, Step::Decompress, {
+            uncompressed_values
+                .iter()
+                .map(|it: &u16| format!("{it:#06x}"))
+        });
+        Ok(DecompressResult(
+            uncompressed_values
+                .into_iter()
+                .map(bytemuck::cast::<u16, T>)
+                .collect(),
+            skip_time,
+        ))
+    }
+}
+
+pub struct BatchedGPUDecompressor16 {
+    context: Arc<Context>,
+    config: ChimpConfig,
+}
+impl MaxGroupGnostic for BatchedGPUDecompressor16 {
+    fn get_max_number_of_groups(&self, _content_len: usize) -> usize {
+        self.config.max_workgroup_size(self.context())
+    }
+}
+impl BatchedGPUDecompressor16 {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(Context::initialize_default_adapter().block_on()?))
+    }
+}
+impl BatchedGPUDecompressor16 {
+    pub(crate) async fn decompress_block(
+        &self,
+        compressed_value_slice: &[u32],
+        input_indexes: &[u32],
+        value_counts: &[u32],
+        buffer_value_count: usize,
+        skip_time: &mut u128,
+    ) -> Result<Vec<u16>, ChimpError> {
+        //how many buffers fit into the GPU
+        let workgroup_count = self.get_max_number_of_groups(input_indexes.len()) * 256;
+
+        //how many iterations I need to fully decompress all the buffers
+        let iterator_count = ((input_indexes.len() - 1) / workgroup_count) + 1;
+
+        let instant = Instant::now();
+        let input_storage_buffer = BufferWrapper::storage_with_content(
+            self.device(),
+            bytemuck::cast_slice(compressed_value_slice),
+            WgpuGroupId::new(0, 1),
+            Some("Storage input Buffer"),
+        );
+        //info!("Total output values: {}", buffer_value_count);
+        let blocks = input_indexes
+            .iter()
+            .zip(value_counts)
+            .flat_map(|(start, count)| [*start, *count])
+            .collect::<Vec<u32>>();
+        let block_storage = BufferWrapper::storage_with_content(
+            self.device(),
+            bytemuck::cast_slice(&blocks),
+            WgpuGroupId::new(0, 3),
+            Some("Block starts and value counts"),
+        );
+        let out_buffer_size =
+            (input_indexes.len() - 1) * self.config.buffer_size() * size_of::<u32>();
+        self.context().check_buffer_size(out_buffer_size)?;
+        let out_staging = BufferWrapper::stage_with_size(
+            self.device(),
+            out_buffer_size as BufferAddress,
+            Some("Staging output Buffer"),
+        );
+        let out_storage_buffer = BufferWrapper::storage_with_size(
+            self.device(),
+            out_buffer_size as BufferAddress,
+            WgpuGroupId::new(0, 0),
+            Some("Storage output Buffer"),
+        );
+        *skip_time += instant.elapsed().as_millis();
+
+        let workgroup_count = min(
+            workgroup_count,
+            self.config.max_workgroup_size(&self.context),
+        );
+        for iteration in 0..iterator_count {
+            // let offset_decl = format!(
+            //     "let workgroup_offset={}u;",
+            //     iteration * self.config.max_workgroup_size(&self.context)
+            // );
+            // let shader_code = include_str!("shaders/decompress.wgsl")
+            //     .replace("//@workgroup_offset", &offset_decl);
+
+            //split all the buffers to the chunks each iteration will use
+            let is_last_iteration = iteration == iterator_count - 1;
+            let offset = iteration * workgroup_count;
+            let next = if is_last_iteration {
+                input_indexes.len()
+            } else {
+                ((iteration + 1) * workgroup_count) + 1
+            };
+            let iteration_input_indexes = next - offset;
+            // let iteration_input_indexes = if is_last_iteration {
+            //     input_indexes[iteration * workgroup_count..].to_vec()
+            // } else {
+            //     input_indexes[iteration * workgroup_count..(iteration + 1) * workgroup_count]
+            //         .to_vec()
+            // };
+            // let out_offset = input_indexes[iteration * workgroup_count];
+            // let next_out = input_indexes[next - 1];
+            // let iteration_compressed_values_len = next_out - out_offset;
+
+            let size_uniform = BufferWrapper::uniform_with_content(
+                self.device(),
+                bytemuck::bytes_of(&buffer_value_count),
+                WgpuGroupId::new(0, 2),
+                Some("Total input values"),
+            );
+            let shader_code = shaders::DECOMPRESS
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (iteration * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .bind("total_threads", value_counts.len() as u32)
+                .finish()?;
+            execute_compute_shader!(
+                self.context(),
+                &shader_code,
+                vec![
+                    &out_storage_buffer,
+                    &input_storage_buffer,
+                    &out_staging,
+                    &size_uniform,
+                    &block_storage
+                ],
+                iteration_input_indexes.div_ceil(256),
+                Some("decompress pass")
+            );
+        }
+        let instant = Instant::now();
+        // the shader writes every value widened to a word
+        let result = wgpu_utils::get_from_gpu::<u32>(
+            self.context(),
+            out_storage_buffer.buffer(),
+            out_storage_buffer.size() as BufferAddress,
+            out_staging.buffer(),
+        )
+        .await?;
+        *skip_time += instant.elapsed().as_millis();
+
+        Ok(result.into_iter().map(|it| it as u16).collect())
+    }
+
+    pub fn new(context_builder: impl Into<Arc<Context>>) -> Self {
+        Self {
+            context: context_builder.into(),
+            config: ChimpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn device(&self) -> &Device {
+        self.context.device()
+    }
+    pub fn queue(&self) -> &Queue {
+        self.context.queue()
+    }
+}
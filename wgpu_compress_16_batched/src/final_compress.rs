@@ -0,0 +1,153 @@
+use crate::shaders;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{MaxGroupGnostic, Step};
+use compress_utils::types::ChimpOutput;
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{execute_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
+use std::cmp::max;
+use std::fs;
+use std::ops::Div;
+use std::sync::Arc;
+use std::time::Instant;
+use wgpu_types::BufferAddress;
+
+#[async_trait]
+pub trait FinalCompress: MaxGroupGnostic {
+    async fn final_compress(
+        &self,
+        buffers: &mut RunBuffers,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError>;
+}
+
+pub struct FinalCompressImpl {
+    context: Arc<Context>,
+    config: ChimpConfig,
+}
+
+impl FinalCompressImpl {
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
+    }
+
+    pub fn context(&self) -> &Context {
+        self.context.as_ref()
+    }
+}
+
+impl MaxGroupGnostic for FinalCompressImpl {
+    fn get_max_number_of_groups(&self, content_len: usize) -> usize {
+        content_len.div(self.config.buffer_size())
+    }
+}
+
+#[async_trait]
+impl FinalCompress for FinalCompressImpl {
+    async fn final_compress(
+        &self,
+        buffers: &mut RunBuffers,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        // the input holds every value widened to a word
+        let input_length = buffers.input_buffer().size() / size_of::<u32>();
+        let output_buffer_size = (size_of::<ChimpOutput>() * input_length) as BufferAddress;
+        let workgroup_count = self.get_max_number_of_groups(input_length);
+
+        let instant = Instant::now();
+        let mut output_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
+            output_buffer_size,
+            WgpuGroupId::new(0, 2),
+            Some("Storage Output Buffer"),
+        );
+        *skip_time += instant.elapsed().as_millis();
+        buffers.s_buffer_mut().with_binding(WgpuGroupId::new(0, 0));
+        buffers
+            .input_buffer_mut()
+            .with_binding(WgpuGroupId::new(0, 1));
+        buffers
+            .chunks_uniform_mut()
+            .with_binding(WgpuGroupId::new(0, 3));
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
+        for i in 0..iterations {
+            let shader_code = shaders::CHIMP_COMPRESS
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .finish()?;
+            execute_compute_shader!(
+                self.context(),
+                &shader_code,
+                vec![
+                    buffers.s_buffer(),
+                    buffers.input_buffer(),
+                    &output_storage_buffer,
+                    buffers.chunks_uniform(),
+                ],
+                if i == iterations - 1 {
+                    last_size
+                } else {
+                    self.config.max_workgroup_size(&self.context)
+                },
+                Some("compress pass")
+            );
+        }
+
+        buffers
+            .chunks_uniform_mut()
+            .with_binding(WgpuGroupId::new(0, 2));
+        for i in 0..iterations {
+            let shader_code = shaders::INITIALIZE_FIRST_PER_BUFFER
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .finish()?;
+            execute_compute_shader!(
+                self.context(),
+                &shader_code,
+                vec![
+                    output_storage_buffer.with_binding(WgpuGroupId::new(0, 0)),
+                    buffers.input_buffer(),
+                    buffers.chunks_uniform()
+                ],
+                if i == iterations - 1 {
+                    last_size
+                } else {
+                    self.config.max_workgroup_size(&self.context)
+                },
+                Some("initialize pass")
+            );
+        }
+
+        buffers.set_compressed_buffer(output_storage_buffer);
+        if self.config.traces(&Step::Compress) {
+            let output_staging_buffer = BufferWrapper::pooled_stage_with_size(
+                self.context(),
+                buffers.compressed_buffer().size() as BufferAddress,
+                None,
+            );
+            let output = wgpu_utils::get_from_gpu::<ChimpOutput>(
+                self.context(),
+                buffers.compressed_buffer().buffer(),
+                buffers.compressed_buffer().size() as BufferAddress,
+                output_staging_buffer.buffer(),
+            )
+            .await?;
+            let trace_output = output
+                .iter()
+                .enumerate()
+                .map(|(index, it)| format!("{index}:{it}\n"))
+                .collect::<String>();
+            fs::write(Step::Compress.get_trace_file(), trace_output)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,539 @@
+//! The batched Chimp pipeline for 16 bit floats.
+//!
+//! The values are compressed by their bits with the Chimp encoding narrowed to 16 bits: the
+//! leading zeros and the length of the center bits take 4 bits instead of 5, and an xor keeps
+//! only its center bits once it has more than 4 trailing zeros.
+//!
+//! | code | case                                | payload                                |
+//! |------|-------------------------------------|----------------------------------------|
+//! | `00` | the value equals the previous one   |                                        |
+//! | `01` | more than 4 trailing zeros          | lead (4), center length (4), center    |
+//! | `10` | the leading zeros of the previous   | the 16 - lead meaningful bits          |
+//! | `11` | other leading zeros                 | lead (4), the 16 - lead meaningful bits|
+//!
+//! Every value fits in the lower word of a [ChimpOutput] and the first value of each buffer is
+//! written as a word of its own, so the blocks have the layout of the ones of
+//! [wgpu_compress_32_batched] and the index and finalize stages are the ones of that crate.
+//! Only the compute S and compress stages, and the decompressors, are specific to 16 bits.
+//!
+//! [Compressor], [BatchedCompressor] and
+//! [Decompressor](compress_utils::cpu_compress::Decompressor) are implemented for every
+//! [Float16], which `half::f16` and `half::bf16` are with the `half` feature, so their streams
+//! go through [ChimpStreamEncoder](compress_utils::stream::ChimpStreamEncoder) and
+//! [ChimpStreamDecoder](compress_utils::stream::ChimpStreamDecoder) like the ones of the
+//! other widths.
+mod compute_s_shader;
+pub mod cpu;
+pub mod decompressor;
+mod final_compress;
+pub mod shaders;
+
+use crate::compute_s_shader::{ComputeS, ComputeSImpl};
+use crate::cpu::compute_s::CPUComputeS;
+use crate::cpu::final_compress::CPUFinalCompress;
+use crate::final_compress::{FinalCompress, FinalCompressImpl};
+use async_trait::async_trait;
+use bytemuck::Pod;
+use compress_utils::config::{ChimpConfig, ConfigError};
+use compress_utils::context::Context;
+use compress_utils::cpu_compress::Compressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, ValueType, HEADER_SIZE};
+use compress_utils::general_utils::{
+    max_batch_len, padding_to_fit_buffer_count, split_by_max_storage_buffer_size, CompressResult,
+};
+use compress_utils::stream::BatchedCompressor;
+use compress_utils::types::ChimpOutput;
+use compress_utils::{time_it, wgpu_utils};
+use log::info;
+use pollster::FutureExt;
+use std::slice::Chunks;
+use std::sync::Arc;
+use wgpu_compress_32_batched::calculate_indexes::{CalculateIndexes, GPUCalculateIndexes};
+use wgpu_compress_32_batched::cpu::calculate_indexes::CPUCalculateIndexes;
+use wgpu_compress_32_batched::cpu::finalize::CPUImpl;
+use wgpu_compress_32_batched::finalize::{Finalize, Finalizer};
+use wgpu_compress_32_batched::ChimpCompressorBatched;
+
+/// A 16 bit float, compressed by its bits
+pub trait Float16: Pod + Send + Sync {
+    /// The value type recorded in the header of the streams of [Self]
+    const VALUE_TYPE: ValueType;
+}
+
+#[cfg(feature = "half")]
+impl Float16 for half::f16 {
+    const VALUE_TYPE: ValueType = ValueType::F16;
+}
+
+#[cfg(feature = "half")]
+impl Float16 for half::bf16 {
+    const VALUE_TYPE: ValueType = ValueType::BF16;
+}
+
+/// Reject the error bound of [config], the 16 bit variant is lossless
+fn expect_lossless(config: &ChimpConfig) -> Result<(), ChimpError> {
+    if config.error_bound().is_some() {
+        return Err(ConfigError::ErrorBoundOnHalves.into());
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct ChimpCompressorBatched16 {
+    context: Arc<Context>,
+    config: ChimpConfig,
+}
+
+impl ChimpCompressorBatched16 {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(Arc::new(
+            Context::initialize_default_adapter().block_on()?,
+        )))
+    }
+}
+
+impl ChimpCompressorBatched16 {
+    pub fn new(context: Arc<Context>) -> Self {
+        Self {
+            context,
+            config: ChimpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+}
+
+#[async_trait]
+impl<T: Float16> Compressor<T> for ChimpCompressorBatched16 {
+    async fn compress(&self, values: &[T]) -> Result<CompressResult, ChimpError> {
+        expect_lossless(&self.config)?;
+        let header = BatchedCompressor::<T>::header(self, values.len());
+        let iterations = split_by_max_storage_buffer_size(
+            bytemuck::cast_slice::<T, u16>(values),
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput>(),
+        );
+        let result = compress_batches(
+            iterations,
+            &self.config,
+            &ComputeSImpl::new(self.context.clone(), self.config.clone()),
+            &FinalCompressImpl::new(self.context.clone(), self.config.clone()),
+            &GPUCalculateIndexes::new(self.context.clone(), self.config.clone()),
+            &Finalizer::new(self.context.clone(), self.config.clone()),
+        )
+        .await?;
+
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl<T: Float16> BatchedCompressor<T> for ChimpCompressorBatched16 {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput>(),
+        )
+    }
+
+    /// The header of an error bounded configuration is the one of its values, the
+    /// compression itself fails with [ConfigError::ErrorBoundOnHalves]
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        ChimpHeader::new(
+            CodecVariant::Chimp16,
+            0,
+            self.config.buffer_size(),
+            value_count,
+        )
+        .with_value_type(T::VALUE_TYPE)
+    }
+}
+
+/// A [Compressor] running every stage of the 16 bit pipeline on the host, with an output
+/// byte for byte the one of [ChimpCompressorBatched16] on a device whose storage buffer
+/// binding limit is [ChimpCompressorBatched16Cpu::max_storage_buffer_size]
+#[derive(Debug, Clone)]
+pub struct ChimpCompressorBatched16Cpu {
+    max_storage_buffer_size: usize,
+    config: ChimpConfig,
+}
+
+impl Default for ChimpCompressorBatched16Cpu {
+    fn default() -> Self {
+        Self::new(ChimpCompressorBatched::MAX_BUFFER_SIZE_BYTES)
+    }
+}
+
+impl ChimpCompressorBatched16Cpu {
+    pub fn new(max_storage_buffer_size: usize) -> Self {
+        Self {
+            max_storage_buffer_size,
+            config: ChimpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn max_storage_buffer_size(&self) -> usize {
+        self.max_storage_buffer_size
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+}
+
+#[async_trait]
+impl<T: Float16> Compressor<T> for ChimpCompressorBatched16Cpu {
+    async fn compress(&self, values: &[T]) -> Result<CompressResult, ChimpError> {
+        expect_lossless(&self.config)?;
+        let header = BatchedCompressor::<T>::header(self, values.len());
+        let iterations = split_by_max_storage_buffer_size(
+            bytemuck::cast_slice::<T, u16>(values),
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput>(),
+        );
+        let result = compress_batches(
+            iterations,
+            &self.config,
+            &CPUComputeS::new(self.config.clone()),
+            &CPUFinalCompress::new(self.config.clone()),
+            &CPUCalculateIndexes::new(self.config.clone()),
+            &CPUImpl::new(self.config.clone()),
+        )
+        .await?;
+
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl<T: Float16> BatchedCompressor<T> for ChimpCompressorBatched16Cpu {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput>(),
+        )
+    }
+
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        ChimpHeader::new(
+            CodecVariant::Chimp16,
+            0,
+            self.config.buffer_size(),
+            value_count,
+        )
+        .with_value_type(T::VALUE_TYPE)
+    }
+}
+
+/// Run every stage of the pipeline over each of the [iterations] and concatenate the
+/// resulting buffers, without the stream header
+async fn compress_batches(
+    iterations: Chunks<'_, u16>,
+    config: &ChimpConfig,
+    compute_s_impl: &(impl ComputeS + Sync),
+    final_compress_impl: &(impl FinalCompress + Sync),
+    calculate_indexes_impl: &(impl CalculateIndexes + Sync),
+    finalize_impl: &(impl Finalize + Sync),
+) -> Result<CompressResult, ChimpError> {
+    let mut byte_stream = Vec::new();
+    let mut metadata = 0;
+    let mut buffers = wgpu_utils::RunBuffers::default();
+    let mut skip_time = 0u128;
+    for values in iterations {
+        let buffer_size = config.buffer_size();
+        let padding = padding_to_fit_buffer_count(values.len(), buffer_size);
+        let mut total_millis: u128 = 0;
+        let output_vec;
+        time_it!(
+            {
+                compute_s_impl
+                    .compute_s(values, &mut buffers, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "s computation stage"
+        );
+        time_it!(
+            {
+                final_compress_impl
+                    .final_compress(&mut buffers, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "final output stage"
+        );
+        time_it!(
+            {
+                calculate_indexes_impl
+                    .calculate_indexes(&mut buffers, buffer_size as u32, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "index calculation stage"
+        );
+        time_it!(
+            {
+                output_vec = finalize_impl
+                    .finalize(&mut buffers, padding, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "final Result collection"
+        );
+        byte_stream.extend(output_vec.compressed_value_ref());
+        metadata += output_vec.metadata_size()
+    }
+    Ok(CompressResult(byte_stream, metadata, skip_time))
+}
+
+#[cfg(all(test, feature = "half"))]
+mod tests {
+    use crate::cpu::decompressor::BatchedDecompressor16Cpu;
+    use crate::ChimpCompressorBatched16Cpu;
+    use compress_utils::config::{ChimpConfig, ConfigError};
+    use compress_utils::cpu_compress::{Compressor, Decompressor};
+    use compress_utils::error::ChimpError;
+    use compress_utils::format::{ChimpHeader, CodecVariant, ValueType};
+    use compress_utils::lossy::ErrorBound;
+    use compress_utils::stream::{ChimpStreamDecoder, ChimpStreamEncoder};
+    use compress_utils::testing::assert_corruption_never_panics;
+    use half::{bf16, f16};
+    use pollster::FutureExt;
+
+    fn series(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|it| match it % 7 {
+                0 => 21.5,
+                3 => -0.125,
+                _ => ((it / 8) % 64) as f32 * 0.5,
+            })
+            .collect()
+    }
+
+    fn with_special_values(mut values: Vec<f32>) -> Vec<f32> {
+        values.extend([
+            0.0,
+            -0.0,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+            f32::MIN_POSITIVE,
+            65504.0,
+        ]);
+        values
+    }
+
+    #[test]
+    fn test_cpu_f16_round_trip() {
+        let values = with_special_values(series(3000))
+            .into_iter()
+            .map(f16::from_f32)
+            .collect::<Vec<_>>();
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let compressed = ChimpCompressorBatched16Cpu::default()
+            .with_config(config.clone())
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        let bytes = compressed.compressed_value_ref();
+        let header = ChimpHeader::read(bytes).unwrap();
+        assert_eq!(header.codec, CodecVariant::Chimp16);
+        assert_eq!(header.value_type(), ValueType::F16);
+
+        let decompressed: Vec<f16> = BatchedDecompressor16Cpu::new(config)
+            .decompress(bytes)
+            .block_on()
+            .unwrap()
+            .into();
+        assert_eq!(
+            bytemuck::cast_slice::<f16, u16>(&decompressed),
+            bytemuck::cast_slice::<f16, u16>(&values)
+        );
+        assert!(bytes.len() < values.len() * size_of::<f16>());
+    }
+
+    #[test]
+    fn test_cpu_corrupted_stream_never_panics() {
+        let values = series(600)
+            .into_iter()
+            .map(f16::from_f32)
+            .collect::<Vec<_>>();
+        let compressed = ChimpCompressorBatched16Cpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        assert_corruption_never_panics::<f16, _>(
+            compressed.compressed_value_ref(),
+            &BatchedDecompressor16Cpu::default(),
+        );
+    }
+
+    #[test]
+    fn test_cpu_bf16_round_trip_over_batches() {
+        let values = with_special_values(series(5000))
+            .into_iter()
+            .map(bf16::from_f32)
+            .collect::<Vec<_>>();
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        // a binding limit small enough for the values to take several batches
+        let compressor = ChimpCompressorBatched16Cpu::new(1 << 14).with_config(config.clone());
+        let compressed = compressor.compress(&values).block_on().unwrap();
+
+        let decompressor = BatchedDecompressor16Cpu::new(config);
+        let decompressed: Vec<bf16> = decompressor
+            .decompress(compressed.compressed_value_ref())
+            .block_on()
+            .unwrap()
+            .into();
+        assert_eq!(
+            bytemuck::cast_slice::<bf16, u16>(&decompressed),
+            bytemuck::cast_slice::<bf16, u16>(&values)
+        );
+
+        let range: Vec<bf16> = decompressor
+            .decompress_range(compressed.compressed_value_ref(), 1000..1300)
+            .block_on()
+            .unwrap()
+            .into();
+        assert_eq!(
+            bytemuck::cast_slice::<bf16, u16>(&range),
+            bytemuck::cast_slice::<bf16, u16>(&values[1000..1300])
+        );
+    }
+
+    #[test]
+    fn test_cpu_bf16_stream_round_trip() {
+        let values = with_special_values(series(5000))
+            .into_iter()
+            .map(bf16::from_f32)
+            .collect::<Vec<_>>();
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let mut encoder = ChimpStreamEncoder::new(
+            ChimpCompressorBatched16Cpu::new(1 << 14).with_config(config.clone()),
+            Vec::new(),
+        )
+        .unwrap();
+        for chunk in values.chunks(1234) {
+            encoder.push(chunk).unwrap();
+        }
+        let stream = encoder.finish().unwrap();
+        assert_eq!(
+            ChimpHeader::read(&stream).unwrap().value_type(),
+            ValueType::BF16
+        );
+
+        let decoded = ChimpStreamDecoder::new(BatchedDecompressor16Cpu::new(config), &stream[..])
+            .unwrap()
+            .with_window_blocks(3)
+            .values()
+            .collect::<Result<Vec<bf16>, _>>()
+            .unwrap();
+        assert_eq!(
+            bytemuck::cast_slice::<bf16, u16>(&decoded),
+            bytemuck::cast_slice::<bf16, u16>(&values)
+        );
+    }
+
+    #[test]
+    fn test_cpu_f16_stream_decodes_whole_and_by_range() {
+        let values = series(3000)
+            .into_iter()
+            .map(f16::from_f32)
+            .collect::<Vec<_>>();
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let mut encoder = ChimpStreamEncoder::new(
+            ChimpCompressorBatched16Cpu::new(1 << 14).with_config(config.clone()),
+            Vec::new(),
+        )
+        .unwrap();
+        for chunk in values.chunks(700) {
+            encoder.push(chunk).unwrap();
+        }
+        let stream = encoder.finish().unwrap();
+
+        let decompressor = BatchedDecompressor16Cpu::new(config);
+        let decompressed: Vec<f16> = decompressor.decompress(&stream).block_on().unwrap().into();
+        assert_eq!(
+            bytemuck::cast_slice::<f16, u16>(&decompressed),
+            bytemuck::cast_slice::<f16, u16>(&values)
+        );
+        let range: Vec<f16> = decompressor
+            .decompress_range(&stream, 700..1900)
+            .block_on()
+            .unwrap()
+            .into();
+        assert_eq!(
+            bytemuck::cast_slice::<f16, u16>(&range),
+            bytemuck::cast_slice::<f16, u16>(&values[700..1900])
+        );
+    }
+
+    #[test]
+    fn test_cpu_rejects_other_value_types_and_error_bounds() {
+        let values = series(512)
+            .into_iter()
+            .map(bf16::from_f32)
+            .collect::<Vec<_>>();
+        let compressed = ChimpCompressorBatched16Cpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        let result: Result<_, ChimpError> = Decompressor::<f16>::decompress(
+            &BatchedDecompressor16Cpu::default(),
+            compressed.compressed_value_ref(),
+        )
+        .block_on();
+        assert!(matches!(
+            result,
+            Err(ChimpError::WrongValueType {
+                codec: CodecVariant::Chimp16,
+                value_type: ValueType::BF16
+            })
+        ));
+
+        let lossy = ChimpCompressorBatched16Cpu::default()
+            .with_config(ChimpConfig::default().with_error_bound(ErrorBound::Absolute(0.1)));
+        assert!(matches!(
+            lossy.compress(&values).block_on(),
+            Err(ChimpError::InvalidConfig(ConfigError::ErrorBoundOnHalves))
+        ));
+    }
+}
@@ -0,0 +1,116 @@
+//! The WGSL shaders of the batched 16 bit pipeline along with the parameters each one is
+//! specialized with, see [compress_utils::shader_template]
+use compress_utils::shader_template::Parameter::Override;
+use compress_utils::shader_template::ShaderTemplate;
+
+pub const CHIMP_COMPRESS: ShaderTemplate = ShaderTemplate::new(
+    "chimp_compress",
+    include_str!("shaders/chimp_compress.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")]);
+
+pub const COMPUTE_S: ShaderTemplate =
+    ShaderTemplate::new("compute_s", include_str!("shaders/compute_s.wgsl"))
+        .with_parameters(&[Override("workgroup_offset")]);
+
+pub const DECOMPRESS: ShaderTemplate =
+    ShaderTemplate::new("decompress", include_str!("shaders/decompress.wgsl"))
+        .with_parameters(&[Override("workgroup_offset"), Override("total_threads")]);
+
+pub const INITIALIZE_FIRST_PER_BUFFER: ShaderTemplate = ShaderTemplate::new(
+    "initialize_first_per_buffer",
+    include_str!("shaders/initialize_first_per_buffer.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")]);
+
+/// Every shader of the pipeline, validated by `compress_utils::shader_validation` in the tests
+pub const ALL: &[ShaderTemplate] = &[
+    CHIMP_COMPRESS,
+    COMPUTE_S,
+    DECOMPRESS,
+    INITIALIZE_FIRST_PER_BUFFER,
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::compress_batches;
+    use crate::compute_s_shader::ComputeSImpl;
+    use crate::final_compress::FinalCompressImpl;
+    use crate::shaders;
+    use async_trait::async_trait;
+    use compress_utils::config::ChimpConfig;
+    use compress_utils::context::Context;
+    use compress_utils::error::ChimpError;
+    use compress_utils::general_utils::CompressResult;
+    use compress_utils::shader_validation::{
+        check_cached_layouts, seed_index_buffer, validate_templates, ParameterMatrix,
+    };
+    use compress_utils::wgpu_utils::RunBuffers;
+    use itertools::Itertools;
+    use pollster::FutureExt;
+    use std::sync::Arc;
+    use wgpu_compress_32_batched::calculate_indexes::GPUCalculateIndexes;
+    use wgpu_compress_32_batched::finalize::{Finalize, Finalizer};
+
+    /// The GPU [Finalizer], run over the offsets the index calculation writes on a device
+    struct SeededFinalizer(Finalizer);
+
+    #[async_trait]
+    impl Finalize for SeededFinalizer {
+        async fn finalize(
+            &self,
+            buffers: &mut RunBuffers,
+            padding: usize,
+            skip_time: &mut u128,
+        ) -> Result<CompressResult, ChimpError> {
+            seed_index_buffer(self.0.context(), buffers);
+            self.0.finalize(buffers, padding, skip_time).await
+        }
+    }
+
+    #[test]
+    fn test_shaders_validate_offline() {
+        let validated = validate_templates(shaders::ALL, &ParameterMatrix::default())
+            .unwrap_or_else(|error| panic!("{error}"));
+        assert!(validated > shaders::ALL.len());
+    }
+
+    #[cfg(feature = "half")]
+    #[test]
+    fn test_stage_layouts_match_their_shaders() {
+        use crate::decompressor::BatchedGPUDecompressor16;
+        use crate::ChimpCompressorBatched16Cpu;
+        use compress_utils::cpu_compress::{Compressor, Decompressor};
+        use half::f16;
+
+        let context = Arc::new(Context::initialize_noop().block_on().unwrap());
+        let config = ChimpConfig::default();
+        let values = (0..3000)
+            .map(|it| f16::from_f32(it as f32 / 8.0))
+            .collect_vec();
+        let bits = bytemuck::cast_slice::<f16, u16>(&values);
+        compress_batches(
+            bits.chunks(bits.len()),
+            &config,
+            &ComputeSImpl::new(context.clone(), config.clone()),
+            &FinalCompressImpl::new(context.clone(), config.clone()),
+            &GPUCalculateIndexes::new(context.clone(), config.clone()),
+            &SeededFinalizer(Finalizer::new(context.clone(), config.clone())),
+        )
+        .block_on()
+        .unwrap();
+        let compressed = ChimpCompressorBatched16Cpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        Decompressor::<f16>::decompress(
+            &BatchedGPUDecompressor16::new(context.clone()),
+            compressed.compressed_value_ref(),
+        )
+        .block_on()
+        .unwrap();
+
+        check_cached_layouts(context.pipeline_cache(), shaders::ALL)
+            .unwrap_or_else(|error| panic!("{error}"));
+    }
+}
@@ -0,0 +1,84 @@
+override workgroup_offset: u32;
+
+struct Ss{
+    leading:i32,
+    trailing:i32,
+    equal:u32,
+}
+
+struct Output{
+    upper_bits:u32, // always 0, a 16 bit value takes at most 26 bits
+    lower_bits:u32,
+    useful_size:u32
+}
+
+@group(0)
+@binding(0)
+var<storage, read_write> s_store: array<Ss>;
+
+@group(0)
+@binding(1)
+var<storage, read_write> in: array<u32>; // every 16 bit value widened to a word
+
+@group(0)
+@binding(2)
+var<storage, read_write> out: array<Output>;
+@group(0)
+@binding(3)
+var<uniform> chunks: u32; // how many iterations per buffer
+
+
+
+fn compress(v:u32,s:Ss,v_prev:u32,s_prev:Ss) -> Output{
+
+    //Conditions
+    var trail_gt_4=u32(s.trailing>4);
+    var trail_le_4=u32(s.trailing<=4);
+    var not_equal=u32(!bool(s.equal));
+    var pr_lead_eq_lead=u32(s.leading==s_prev.leading);
+    var pr_lead_ne_lead=u32(s.leading!=s_prev.leading);
+
+    //input
+    var xorred:u32=(v_prev^v) & 0xffffu;
+
+    var center_bits=u32(max(16-s.leading-s.trailing,0));
+    var significant_bits=u32(16-s.leading);
+
+    //case 1: xor_value=0, code 00
+    var case_1:u32=0u;
+    var case_1_bit_count:u32=2;
+
+    // case 2: tail>4 && xor_value!=0(!equal), code 01
+    var case_2:u32=1u;
+    case_2=(case_2<<4u)+extractBits(u32(s.leading),0u,4u);
+    case_2=(case_2<<4u)+extractBits(center_bits,0u,4u);
+    case_2=(case_2<<center_bits)+extractBits(xorred,u32(min(s.trailing,15)),center_bits);
+    var case_2_bit_count= 2+4+4+center_bits;
+
+    // case 3: tail<=4 and lead=pr_lead, code 10
+    var case_3:u32=(2u<<significant_bits)+extractBits(xorred,0u,significant_bits);
+    var case_3_bit_count:u32=2+significant_bits;
+
+    // case 4: tail<=4 and lead!=pr_lead, code 11
+    var case_4:u32=(3u<<4u)+extractBits(u32(s.leading),0u,4u);
+    case_4=(case_4<<significant_bits)+extractBits(xorred,0u,significant_bits);
+    var case_4_bit_count:u32=2+4+significant_bits;
+
+    var final_output=s.equal*case_1;
+    final_output+=(trail_gt_4*not_equal)*case_2;
+    final_output+=(trail_le_4*pr_lead_eq_lead)*case_3;
+    final_output+=(trail_le_4*pr_lead_ne_lead)*case_4;
+
+    var final_bit_count=s.equal*case_1_bit_count+ (trail_gt_4*not_equal)*case_2_bit_count +(trail_le_4*pr_lead_eq_lead)*case_3_bit_count +(trail_le_4*pr_lead_ne_lead)*case_4_bit_count;
+    return Output(0u,final_output,final_bit_count);
+}
+
+@compute
+@workgroup_size(256)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>,@builtin(local_invocation_id) invocation_id: vec3<u32>) {
+
+    for (var i=0u;i<chunks;i++){
+        let index:u32=(workgroup_offset+workgroup_id.x) * 256 * chunks + invocation_id.x+i*256u;
+        out[index+1] = compress(in[index+1],s_store[index+1],in[index],s_store[index]);
+    }
+}
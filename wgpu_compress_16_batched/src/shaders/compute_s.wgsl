@@ -0,0 +1,42 @@
+override workgroup_offset: u32;
+
+struct Ss {
+    leading:i32,
+    trailing:i32,
+    equal:u32,
+}
+
+
+@group(0)
+@binding(0)
+var<storage, read_write> s_store: array<Ss>;
+
+@group(0)
+@binding(1)
+var<storage, read_write> in: array<u32>; // every 16 bit value widened to a word
+
+@group(0)
+@binding(2)
+var<uniform> chunks:u32; // how many iterations per buffer
+
+
+
+fn calculate_s(workgoup_size:u32,id:u32,v_prev:u32,v:u32) -> Ss{
+   var i= (v_prev^v) & 0xffffu;
+
+   // the counts of the 16 bit xor, 16 for both when the values are equal
+   var leading=i32((id % workgoup_size)!=0)*(i32(countLeadingZeros(i)) - 16);
+   var trailing=i32(min(countTrailingZeros(i),16u));
+   var equal=u32(i==0);
+
+   return Ss(leading,trailing,equal);
+}
+
+@compute
+@workgroup_size(256)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>,@builtin(local_invocation_id) invocation_id: vec3<u32>) {
+    for (var i=0u;i<chunks;i++){
+        let index:u32=(workgroup_offset+workgroup_id.x) * 256 * chunks + invocation_id.x+i*256u;
+        s_store[index+1] = calculate_s(chunks*256,index,in[index],in[index+1]);
+    }
+}
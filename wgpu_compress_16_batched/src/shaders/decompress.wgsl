@@ -0,0 +1,148 @@
+override workgroup_offset: u32;
+override total_threads: u32;
+
+@group(0)
+@binding(0)
+var<storage, read_write> out: array<u32>; // every 16 bit value widened to a word
+
+@group(0)
+@binding(1)
+var<storage, read_write> in: array<u32>;
+
+
+@group(0)
+@binding(2)
+var<uniform> size: u32;
+
+
+
+@group(0)
+@binding(3)
+var<storage, read_write> blocks: array<vec2<u32>>; // the first word and the value count of each block
+
+
+struct CurrentInfo{
+    current_index:u32,
+    current_offset:u32,
+}
+
+fn write(input_idx:u32,output_idx:u32,count:u32){
+    //Index of the byte we are in
+    var current_index=input_idx+1u;
+    //Current Remaining offset
+    var current_offset=0u;
+
+    var current_info=CurrentInfo(current_index,current_offset);
+
+    var first_num=in[current_info.current_index - 1u];
+    var last_num:u32=first_num;
+    var last_lead=0u;
+    var significant_bits=0u;
+
+    var output_index=output_idx;
+
+    out[output_index]=first_num;
+    output_index+=1u;
+    current_info.current_offset+=32u;
+    var value=0u;
+    for (var i: u32 = 1u; i < count; i++) {
+
+        //if current bit value==1
+        if get_bit_at_index(current_info.current_index,current_info.current_offset)==1u {
+            current_info=decr_counter_capped_at_32(&current_info,1u);
+            var lead = last_lead;
+            if  get_bit_at_index(current_info.current_index,current_info.current_offset)==1 {
+                current_info=decr_counter_capped_at_32(&current_info,1u);
+                lead = reinterpret_num(current_info.current_index,current_info.current_offset, 4u);
+                current_info=decr_counter_capped_at_32(&current_info,4u);
+            } else {
+                current_info=decr_counter_capped_at_32(&current_info,1u);
+            }
+            significant_bits = 16u - lead;
+            if significant_bits == 0u {
+                significant_bits = 16u;
+            }
+            value = reinterpret_num(current_info.current_index,current_info.current_offset, u32(significant_bits));
+            current_info=decr_counter_capped_at_32(&current_info,u32(significant_bits));
+            value = value ^ last_num;
+            last_num = value;
+            last_lead = lead;
+
+            out[output_index]=value;
+            output_index+=1u;
+        } else if  get_bit_at_index(current_info.current_index,current_info.current_offset - 1u)==1u{
+            current_info=decr_counter_capped_at_32(&current_info,2u);
+
+            let lead = reinterpret_num(current_info.current_index,current_info.current_offset, 4u);
+            current_info=decr_counter_capped_at_32(&current_info,4u);
+
+            var significant_bits = reinterpret_num(current_info.current_index,current_info.current_offset, 4u);
+            current_info=decr_counter_capped_at_32(&current_info,4u);
+
+            if significant_bits == 0u {
+                significant_bits = 16u;
+            }
+
+            let trail = 16u - lead - significant_bits;
+
+            value = reinterpret_num(current_info.current_index,current_info.current_offset, significant_bits);
+
+            current_info=decr_counter_capped_at_32(&current_info,significant_bits);
+
+            value <<= trail;
+            value ^= last_num;
+            last_lead = lead;
+            last_num = value;
+
+            out[output_index]=value;
+            output_index+=1u;
+
+        } else {
+            out[output_index]=last_num;
+            output_index+=1u;
+
+            last_lead = 16u;
+            current_info=decr_counter_capped_at_32(&current_info,2u);
+        }
+    }
+}
+
+
+fn get_bit_at_index(array_index: u32, position: u32) -> u32 {
+    var index=u32(position==0u)*(array_index+1) + u32(position>0u)*array_index;
+    var f_position=u32(position==0u)*32u + u32(position>0u)*position;
+    return (in[index] >> (f_position - 1u)) & 1u;
+}
+
+fn decr_counter_capped_at_32(value:ptr<function,CurrentInfo>,count:u32)->CurrentInfo{
+    let corrected_value=i32((*value).current_offset)-i32(count);
+    (*value).current_offset=u32(corrected_value>0)*u32(corrected_value) + u32(corrected_value<=0)*u32(32+corrected_value);
+    (*value).current_index+=u32(corrected_value<=0); //1 if it's true and 0 otherwise
+    return (*value);
+}
+
+fn reinterpret_num(array_index:u32,index:u32,length:u32)->u32{
+    let len=min(length,32u);
+    if index>=len {
+        // Fully within one u32
+        return extractBits(in[array_index], u32(index-len), len);
+    } else {
+        // Spans two u32 elements
+        let bits_in_second = length-index;
+
+        let first_part = extractBits(in[array_index], 0u, index);
+        let second_part = extractBits(in[array_index + 1], 32u - bits_in_second, bits_in_second);
+        return (first_part << bits_in_second) | second_part;
+    }
+
+}
+
+
+
+@compute
+@workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if(workgroup_offset+global_id.x>=total_threads){return;}
+    let block=workgroup_offset+global_id.x;
+    write(blocks[block].x,block*size,blocks[block].y);
+}
@@ -0,0 +1,26 @@
+override workgroup_offset: u32;
+
+struct Output{
+    upper_bits:u32,
+    lower_bits:u32,
+    useful_size:u32
+}
+
+
+@group(0)
+@binding(0)
+var<storage, read_write> out: array<Output>; // The compression results
+@group(0)
+@binding(1)
+var<storage, read_write> input:array<u32>; // input values, widened to words
+@group(0)
+@binding(2)
+var<uniform> chunks:u32; // how many iterations per buffer
+
+@compute
+@workgroup_size(1)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>) {
+
+    // the first value takes the whole third word of its block
+    out[(workgroup_offset+workgroup_id.x )* 256 * chunks]= Output(0,input[(workgroup_offset+workgroup_id.x) * 256 * chunks],32u);
+}
@@ -1,11 +1,14 @@
+pub mod shaders;
+
 use async_trait::async_trait;
 use bit_vec::BitVec;
 use compress_utils::bit_utils::{BitError, ToBitVec};
-use compress_utils::context::{Context, UtilError};
-use compress_utils::cpu_compress::{CompressionError, Compressor};
-use compress_utils::general_utils::{get_buffer_size, CompressResult, Padding};
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::cpu_compress::Compressor;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{CompressResult, Padding};
 use compress_utils::types::{ChimpOutput, S};
-use compress_utils::wgpu_utils::WgpuUtilsError;
 use compress_utils::{general_utils, time_it, wgpu_utils, BufferWrapper, WgpuGroupId};
 use general_utils::add_padding_to_fit_buffer_count;
 use log::info;
@@ -16,9 +19,22 @@ use wgpu::{BufferAddress, Device, Queue};
 
 ///General methods for ChimpCompressor
 impl ChimpCompressor {
-    pub fn new(device: String, debug: bool) -> Result<Self, UtilError> {
+    pub fn new(device: String, debug: bool) -> Result<Self, ChimpError> {
         let context = Context::initialize_with_adapter(device).block_on()?;
-        Ok(Self { context, debug })
+        Ok(Self {
+            context,
+            debug,
+            config: ChimpConfig::default(),
+        })
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
     }
 
     pub fn context(&self) -> &Context {
@@ -62,13 +78,12 @@ impl ChimpCompressor {
 
 ///Compression Specific method implementations
 impl ChimpCompressor {
-    pub async fn compute_s(&self, values: &mut [f32]) -> Result<Vec<S>, WgpuUtilsError> {
+    pub async fn compute_s(&self, values: &mut [f32]) -> Result<Vec<S>, ChimpError> {
         // Create shader module and pipeline
-        let workgroup_size = format!("@workgroup_size({})", get_buffer_size().buffer_size());
-        let temp = include_str!("shaders/compute_s.wgsl")
-            .replace("#@workgroup_size(1)#", &workgroup_size)
-            .to_string();
-        let compute_s_shader_module = wgpu_utils::create_shader_module(self.device(), &temp)?;
+        let temp = shaders::COMPUTE_S
+            .specialize()
+            .bind("workgroup_size", self.config.buffer_size() as u32)
+            .finish()?;
 
         //Calculating buffer sizes and workgroup counts
 
@@ -79,7 +94,7 @@ impl ChimpCompressor {
         let s_buffer_size = (size_of_s * bytes) as BufferAddress;
         info!("The S buffer size in bytes: {}", s_buffer_size);
 
-        let workgroup_count = values.len().div(get_buffer_size().buffer_size());
+        let workgroup_count = values.len().div(self.config.buffer_size());
         info!("The wgpu workgroup size: {}", &workgroup_count);
 
         let mut padded_values = Vec::from(values);
@@ -99,20 +114,15 @@ impl ChimpCompressor {
             Some("Storage S Buffer"),
         );
 
-        let binding_group_layout = wgpu_utils::assign_bind_groups(
-            self.device(),
+        let compute_s_pipeline = wgpu_utils::cached_compute_pipeline(
+            self.context(),
+            &temp,
             vec![&s_storage_buffer, &input_storage_buffer, &s_staging_buffer],
-        );
-
-        let compute_s_pipeline = wgpu_utils::create_compute_shader_pipeline(
-            self.device(),
-            &compute_s_shader_module,
-            &binding_group_layout,
             Some("Compute s pipeline"),
         )?;
         let binding_group = wgpu_utils::create_bind_group(
             self.context(),
-            &binding_group_layout,
+            compute_s_pipeline.layout(),
             vec![&s_storage_buffer, &input_storage_buffer, &s_staging_buffer],
             None,
         );
@@ -126,7 +136,7 @@ impl ChimpCompressor {
                 label: Some("s_pass"),
                 timestamp_writes: None,
             });
-            s_pass.set_pipeline(&compute_s_pipeline);
+            s_pass.set_pipeline(compute_s_pipeline.pipeline());
             s_pass.set_bind_group(0, &binding_group, &[]);
             s_pass.dispatch_workgroups(max(workgroup_count, 1) as u32, 1, 1)
         }
@@ -157,12 +167,11 @@ impl ChimpCompressor {
         input: &mut Vec<f32>,
         s_values: &mut Vec<S>,
         padding: usize,
-    ) -> Result<Vec<ChimpOutput>, WgpuUtilsError> {
-        let workgroup_size = format!("@workgroup_size({})", get_buffer_size().buffer_size());
-        let temp = include_str!("shaders/chimp_compress.wgsl")
-            .replace("#@workgroup_size(1)#", &workgroup_size)
-            .to_string();
-        let final_compress_module = wgpu_utils::create_shader_module(self.device(), &temp)?;
+    ) -> Result<Vec<ChimpOutput>, ChimpError> {
+        let temp = shaders::CHIMP_COMPRESS
+            .specialize()
+            .bind("workgroup_size", self.config.buffer_size() as u32)
+            .finish()?;
         let size_of_s = size_of::<S>();
         let size_of_output = size_of::<ChimpOutput>();
         let input_length = input.len();
@@ -174,7 +183,7 @@ impl ChimpCompressor {
         let output_buffer_size = (size_of_output * s_values.len()) as BufferAddress;
         info!("The Output buffer size in bytes: {}", &output_buffer_size);
 
-        let workgroup_count = input.len().div(get_buffer_size().buffer_size());
+        let workgroup_count = input.len().div(self.config.buffer_size());
         info!("The wgpu workgroup size: {}", &workgroup_count);
         let output_staging_buffer = BufferWrapper::stage_with_size(
             self.device(),
@@ -201,24 +210,20 @@ impl ChimpCompressor {
             Some("Storage Input Buffer"),
         );
 
-        let binding_group_layout = wgpu_utils::assign_bind_groups(
-            self.device(),
+        let improve_s_pipeline = wgpu_utils::cached_compute_pipeline(
+            self.context(),
+            &temp,
             vec![
                 &s_storage_buffer,
                 &input_storage_buffer,
                 &output_storage_buffer,
                 &output_staging_buffer,
             ],
-        );
-        let improve_s_pipeline = wgpu_utils::create_compute_shader_pipeline(
-            self.device(),
-            &final_compress_module,
-            &binding_group_layout,
             Some("Compress pipeline"),
         )?;
         let binding_group = wgpu_utils::create_bind_group(
             self.context(),
-            &binding_group_layout,
+            improve_s_pipeline.layout(),
             vec![
                 &s_storage_buffer,
                 &input_storage_buffer,
@@ -236,7 +241,7 @@ impl ChimpCompressor {
                 label: Some("compress_pass"),
                 timestamp_writes: None,
             });
-            s_pass.set_pipeline(&improve_s_pipeline);
+            s_pass.set_pipeline(improve_s_pipeline.pipeline());
             s_pass.set_bind_group(0, &binding_group, &[]);
             s_pass.dispatch_workgroups(max(workgroup_count, 1) as u32, 1, 1)
         }
@@ -263,25 +268,25 @@ impl ChimpCompressor {
 pub struct ChimpCompressor {
     context: Context,
     debug: bool,
+    config: ChimpConfig,
 }
-impl Default for ChimpCompressor {
-    fn default() -> Self {
-        let context = Context::initialize_default_adapter().block_on().unwrap();
-        Self {
+impl ChimpCompressor {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        let context = Context::initialize_default_adapter().block_on()?;
+        Ok(Self {
             context,
             debug: false,
-        }
+            config: ChimpConfig::default(),
+        })
     }
 }
 
 #[async_trait]
 impl Compressor<f32> for ChimpCompressor {
-    async fn compress(
-        &self,
-        initial_values: &mut Vec<f32>,
-    ) -> Result<CompressResult, CompressionError> {
+    async fn compress(&self, initial_values: &[f32]) -> Result<CompressResult, ChimpError> {
         let mut padding = Padding(0);
-        let buffer_size = get_buffer_size().buffer_size();
+        let buffer_size = self.config.buffer_size();
 
         let mut values = initial_values.to_owned();
         values = add_padding_to_fit_buffer_count(values, buffer_size, &mut padding);
@@ -310,9 +315,7 @@ impl Compressor<f32> for ChimpCompressor {
             {
                 output_vec = self
                     .collect_to_bit_vec(&mut values, &chimp_vec)
-                    .map_err(|err| {
-                        CompressionError::FromBaseAnyhowError(anyhow::Error::from(err))
-                    })?;
+                    .unwrap_or_else(|err| match err {});
             },
             total_millis,
             "Result collection".to_string()
@@ -349,6 +352,13 @@ mod tests {
         assert_eq!("00110", bit_vec.to_string());
     }
     #[test]
+    fn test_write_zero_bits() {
+        let mut bit_vec = BitVec::new();
+        bit_vec.write_bits(0u32, 5);
+        bit_vec.write_bits(1u32, 2);
+        assert_eq!("0000001", bit_vec.to_string());
+    }
+    #[test]
     fn test4() {
         // let x = 6u32;
         // let mut bit_vec =BitVec::new();
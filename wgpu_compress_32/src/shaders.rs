@@ -0,0 +1,50 @@
+//! The WGSL shaders of the single batch 32 bit pipeline along with the parameters each one is
+//! specialized with, see [compress_utils::shader_template]
+use compress_utils::shader_template::Parameter::Override;
+use compress_utils::shader_template::ShaderTemplate;
+
+pub const CHIMP_COMPRESS: ShaderTemplate = ShaderTemplate::new(
+    "chimp_compress",
+    include_str!("shaders/chimp_compress.wgsl"),
+)
+.with_parameters(&[Override("workgroup_size")]);
+
+pub const COMPUTE_S: ShaderTemplate =
+    ShaderTemplate::new("compute_s", include_str!("shaders/compute_s.wgsl"))
+        .with_parameters(&[Override("workgroup_size")]);
+
+/// Every shader of the pipeline, validated by `compress_utils::shader_validation` in the tests
+pub const ALL: &[ShaderTemplate] = &[CHIMP_COMPRESS, COMPUTE_S];
+
+#[cfg(test)]
+mod tests {
+    use crate::{shaders, ChimpCompressor};
+    use compress_utils::config::ChimpConfig;
+    use compress_utils::context::Context;
+    use compress_utils::cpu_compress::Compressor;
+    use compress_utils::shader_validation::{
+        check_cached_layouts, validate_templates, ParameterMatrix,
+    };
+    use pollster::FutureExt;
+
+    #[test]
+    fn test_shaders_validate_offline() {
+        let validated = validate_templates(shaders::ALL, &ParameterMatrix::default())
+            .unwrap_or_else(|error| panic!("{error}"));
+        assert!(validated > shaders::ALL.len());
+    }
+
+    #[test]
+    fn test_stage_layouts_match_their_shaders() {
+        let compressor = ChimpCompressor {
+            context: Context::initialize_noop().block_on().unwrap(),
+            debug: false,
+            config: ChimpConfig::default(),
+        };
+        let values = (0..3000).map(|it| it as f32 / 8.0).collect::<Vec<_>>();
+        compressor.compress(&values).block_on().unwrap();
+
+        check_cached_layouts(compressor.context().pipeline_cache(), shaders::ALL)
+            .unwrap_or_else(|error| panic!("{error}"));
+    }
+}
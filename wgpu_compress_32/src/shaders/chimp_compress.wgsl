@@ -1,3 +1,4 @@
+override workgroup_size: u32;
 
 struct S{
     leading:i32,
@@ -121,7 +122,7 @@ fn pseudo_u64_add(output:vec2<u32>,number:u32)->vec2<u32>{
 }
 
 @compute
-#@workgroup_size(1)#
+@workgroup_size(workgroup_size)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     out[global_id.x+1] = compress(in[global_id.x+1],s_store[global_id.x+1],in[global_id.x],s_store[global_id.x]);
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
+override workgroup_size: u32;
 
 struct S{
     leading:i32,
@@ -38,7 +39,7 @@ fn calculate_s(v_prev:f32,v:f32) -> S{
 }
 
 @compute
-#@workgroup_size(1)#
+@workgroup_size(workgroup_size)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     s_store[global_id.x+1] = calculate_s(in[global_id.x],in[global_id.x+1]);
 }
\ No newline at end of file
@@ -1,10 +1,11 @@
-use anyhow::Result;
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::general_utils::ChimpBufferInfo;
+use compress_utils::error::ChimpError;
 use compress_utils::types::ChimpOutput;
 use compress_utils::wgpu_utils::RunBuffers;
-use compress_utils::{execute_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
+use compress_utils::{record_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
 use std::cmp::max;
 use std::ops::Div;
 use std::sync::Arc;
@@ -18,39 +19,40 @@ pub trait CalculateIndexes {
         buffers: &mut RunBuffers,
         size: u32,
         skip_time: &mut u128,
-    ) -> Result<()>;
+    ) -> Result<(), ChimpError>;
 }
 
 pub struct GPUCalculateIndexes {
     context: Arc<Context>,
+    config: ChimpConfig,
 }
 
 impl GPUCalculateIndexes {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
     }
 
     pub fn context(&self) -> &Arc<Context> {
         &self.context
     }
-}
 
-#[async_trait]
-impl CalculateIndexes for GPUCalculateIndexes {
-    async fn calculate_indexes(
+    /// Record the computation of the offset of every block into [encoder] without submitting
+    /// it, the offsets are set as the index buffer of [buffers]
+    pub fn record_calculate_indexes(
         &self,
+        encoder: &mut wgpu::CommandEncoder,
         buffers: &mut RunBuffers,
         size: u32,
         skip_time: &mut u128,
-    ) -> Result<()> {
+    ) -> Result<(), ChimpError> {
         // let temp = include_str!("shaders/calculate_final_sizes.wgsl").to_string();
         let workgroup_count =
             (buffers.compressed_buffer().size() / size_of::<ChimpOutput>()).div(size as usize);
         let output_buffer_size = (workgroup_count + 1) * size_of::<u32>();
 
         let instant = Instant::now();
-        let out_storage_buffer = BufferWrapper::storage_with_size(
-            self.context().device(),
+        let out_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
             output_buffer_size as BufferAddress,
             (0, 0),
             Some("Storage Output Buffer"),
@@ -61,29 +63,26 @@ impl CalculateIndexes for GPUCalculateIndexes {
                 .compressed_buffer_mut()
                 .with_binding(WgpuGroupId::new(0, 1));
         }
-        let size_uniform = BufferWrapper::uniform_with_content(
-            self.context().device(),
+        let size_uniform = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
             bytemuck::bytes_of(&size),
             WgpuGroupId::new(0, 2),
             Some("Size Uniform Buffer"),
         );
         *skip_time += instant.elapsed().as_millis();
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let temp = include_str!("shaders/calculate_final_sizes.wgsl")
-                .replace(
-                    "@@workgroup_size",
-                    &ChimpBufferInfo::get().buffer_size().to_string(),
+            let temp = shaders::CALCULATE_FINAL_SIZES
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
                 )
-                .replace("//@workgroup_offset", &offset_decl)
-                .to_string();
-            execute_compute_shader!(
+                .finish()?;
+            record_compute_shader!(
                 self.context(),
+                encoder,
                 &temp,
                 vec![
                     &out_storage_buffer,
@@ -93,21 +92,23 @@ impl CalculateIndexes for GPUCalculateIndexes {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("calculate indexes pass")
             );
         }
 
-        let size_uniform = BufferWrapper::uniform_with_content(
-            self.context().device(),
+        buffers.hold_until_submitted(size_uniform);
+        let size_uniform = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
             bytemuck::bytes_of(&workgroup_count),
             WgpuGroupId::new(0, 1),
             Some("Size Uniform Buffer"),
         );
 
-        execute_compute_shader!(
+        record_compute_shader!(
             self.context(),
+            encoder,
             r#"
              @group(0)
             @binding(0)
@@ -129,6 +130,26 @@ impl CalculateIndexes for GPUCalculateIndexes {
             Some("add sizes pass")
         );
         buffers.set_index_buffer(out_storage_buffer);
+        buffers.hold_until_submitted(size_uniform);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CalculateIndexes for GPUCalculateIndexes {
+    async fn calculate_indexes(
+        &self,
+        buffers: &mut RunBuffers,
+        size: u32,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        let mut encoder = self
+            .context()
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.record_calculate_indexes(&mut encoder, buffers, size, skip_time)?;
+        self.context().queue().submit(Some(encoder.finish()));
+        buffers.release_submitted();
         Ok(())
     }
 }
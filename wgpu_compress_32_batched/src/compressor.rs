@@ -0,0 +1,1049 @@
+//! The batched Chimp compressor over the GPU stages, and its host counterpart
+use crate::calculate_indexes::{CalculateIndexes, GPUCalculateIndexes};
+use crate::compute_s_shader::{ComputeS, ComputeSImpl};
+use crate::cpu::calculate_indexes::CPUCalculateIndexes;
+use crate::cpu::compute_s::CPUComputeS;
+use crate::cpu::final_compress::CPUFinalCompress;
+use crate::cpu::finalize::CPUImpl;
+use crate::final_compress::{FinalCompress, FinalCompressImpl};
+use crate::finalize::{Finalize, Finalizer};
+use crate::fused::FusedStages;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::cpu_compress::Compressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, HEADER_SIZE};
+pub use compress_utils::general_utils::{
+    add_padding_to_fit_buffer_count, ChimpBufferInfo, DeviceEnum, Padding,
+};
+use compress_utils::general_utils::{
+    max_batch_len, padding_to_fit_buffer_count, split_by_max_storage_buffer_size, CompressResult,
+};
+use compress_utils::impl_integer_compressor;
+use compress_utils::lossy::quantize;
+use compress_utils::stream::BatchedCompressor;
+use compress_utils::types::ChimpOutput;
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{time_it, wgpu_utils};
+use log::info;
+use pollster::FutureExt;
+use std::slice::Chunks;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum FinalizerImpl {
+    GPU(Finalizer),
+    CPU(CPUImpl),
+}
+
+#[async_trait]
+impl Finalize for FinalizerImpl {
+    async fn finalize(
+        &self,
+        run_buffers: &mut RunBuffers,
+        padding: usize,
+        skip_time: &mut u128,
+    ) -> Result<CompressResult, ChimpError> {
+        match self {
+            FinalizerImpl::GPU(impll) => impll.finalize(run_buffers, padding, skip_time).await,
+            FinalizerImpl::CPU(impll) => impll.finalize(run_buffers, padding, skip_time).await,
+        }
+    }
+}
+#[derive(Debug)]
+pub struct ChimpCompressorBatched {
+    debug: bool,
+    context: Arc<Context>,
+    finalizer: DeviceEnum,
+    config: ChimpConfig,
+    fused: bool,
+}
+impl ChimpCompressorBatched {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self {
+            debug: false,
+            context: Arc::new(Context::initialize_default_adapter().block_on()?),
+            finalizer: DeviceEnum::GPU,
+            config: ChimpConfig::default(),
+            fused: false,
+        })
+    }
+}
+
+#[async_trait]
+impl Compressor<f32> for ChimpCompressorBatched {
+    async fn compress(&self, values: &[f32]) -> Result<CompressResult, ChimpError> {
+        let header = self.header(values.len());
+        let iterations = self.split_by_max_gpu_buffer_size(values);
+        let result = if self.fused {
+            compress_batches_fused(
+                iterations,
+                &self.config,
+                &FusedStages::new(self.context().clone(), self.config.clone()),
+            )
+            .await?
+        } else {
+            compress_batches(
+                iterations,
+                &self.config,
+                &self.compute_s_factory(),
+                &self.compute_final_compress_factory(),
+                &self.calculate_indexes_factory(),
+                &self.compute_finalize_factory(),
+            )
+            .await?
+        };
+
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl BatchedCompressor<f32> for ChimpCompressorBatched {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput>(),
+        )
+    }
+
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        ChimpHeader::new(
+            CodecVariant::Chimp32,
+            0,
+            self.config.buffer_size(),
+            value_count,
+        )
+        .with_error_bound(self.config.error_bound())
+    }
+}
+
+impl_integer_compressor!(ChimpCompressorBatched, i32, u32);
+
+/// Run every stage of the pipeline over each of the [iterations] and concatenate the
+/// resulting buffers, without the stream header
+pub(crate) async fn compress_batches(
+    iterations: Chunks<'_, f32>,
+    config: &ChimpConfig,
+    compute_s_impl: &(impl ComputeS + Sync),
+    final_compress_impl: &(impl FinalCompress + Sync),
+    calculate_indexes_impl: &(impl CalculateIndexes + Sync),
+    finalize_impl: &(impl Finalize + Sync),
+) -> Result<CompressResult, ChimpError> {
+    let mut byte_stream = Vec::new();
+    let mut metadata = 0;
+    let mut buffers = wgpu_utils::RunBuffers::default();
+    let mut skip_time = 0u128;
+    for iteration_values in iterations {
+        let buffer_size = config.buffer_size();
+        let quantized = quantize(iteration_values, config.error_bound());
+        let values = quantized.as_ref();
+        let padding = padding_to_fit_buffer_count(values.len(), buffer_size);
+        let mut total_millis: u128 = 0;
+        let output_vec;
+        time_it!(
+            {
+                compute_s_impl
+                    .compute_s(values, &mut buffers, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "s computation stage"
+        );
+        time_it!(
+            {
+                final_compress_impl
+                    .final_compress(&mut buffers, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "final output stage"
+        );
+        time_it!(
+            {
+                calculate_indexes_impl
+                    .calculate_indexes(&mut buffers, buffer_size as u32, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "final output stage"
+        );
+        time_it!(
+            {
+                output_vec = finalize_impl
+                    .finalize(&mut buffers, padding, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "final Result collection"
+        );
+        byte_stream.extend(output_vec.compressed_value_ref());
+        metadata += output_vec.metadata_size()
+    }
+    Ok(CompressResult(byte_stream, metadata, skip_time))
+}
+
+/// [compress_batches] with the stages of each batch recorded into a single command buffer,
+/// see [FusedStages]
+pub(crate) async fn compress_batches_fused(
+    iterations: Chunks<'_, f32>,
+    config: &ChimpConfig,
+    stages: &FusedStages,
+) -> Result<CompressResult, ChimpError> {
+    let mut byte_stream = Vec::new();
+    let mut metadata = 0;
+    let mut buffers = wgpu_utils::RunBuffers::default();
+    let mut skip_time = 0u128;
+    for iteration_values in iterations {
+        let quantized = quantize(iteration_values, config.error_bound());
+        let values = quantized.as_ref();
+        let padding = padding_to_fit_buffer_count(values.len(), config.buffer_size());
+        let mut total_millis: u128 = 0;
+        let output_vec;
+        time_it!(
+            {
+                output_vec = stages
+                    .compress_batch(values, &mut buffers, padding, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "fused stages"
+        );
+        byte_stream.extend(output_vec.compressed_value_ref());
+        metadata += output_vec.metadata_size()
+    }
+    Ok(CompressResult(byte_stream, metadata, skip_time))
+}
+
+/// A [Compressor] running every stage of the batched pipeline on the host.
+///
+/// The output is byte for byte the one of [ChimpCompressorBatched] on a device whose storage
+/// buffer binding limit is [ChimpCompressorBatchedCpu::max_storage_buffer_size], so it can be
+/// decompressed by either of the batched decompressors.
+#[derive(Debug, Clone)]
+pub struct ChimpCompressorBatchedCpu {
+    max_storage_buffer_size: usize,
+    config: ChimpConfig,
+}
+
+impl Default for ChimpCompressorBatchedCpu {
+    fn default() -> Self {
+        Self::new(ChimpCompressorBatched::MAX_BUFFER_SIZE_BYTES)
+    }
+}
+
+impl ChimpCompressorBatchedCpu {
+    pub fn new(max_storage_buffer_size: usize) -> Self {
+        Self {
+            max_storage_buffer_size,
+            config: ChimpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn max_storage_buffer_size(&self) -> usize {
+        self.max_storage_buffer_size
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+}
+
+#[async_trait]
+impl Compressor<f32> for ChimpCompressorBatchedCpu {
+    async fn compress(&self, values: &[f32]) -> Result<CompressResult, ChimpError> {
+        let header = self.header(values.len());
+        let iterations = split_by_max_storage_buffer_size(
+            values,
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput>(),
+        );
+        let result = compress_batches(
+            iterations,
+            &self.config,
+            &CPUComputeS::new(self.config.clone()),
+            &CPUFinalCompress::new(self.config.clone()),
+            &CPUCalculateIndexes::new(self.config.clone()),
+            &CPUImpl::new(self.config.clone()),
+        )
+        .await?;
+
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl BatchedCompressor<f32> for ChimpCompressorBatchedCpu {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput>(),
+        )
+    }
+
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        ChimpHeader::new(
+            CodecVariant::Chimp32,
+            0,
+            self.config.buffer_size(),
+            value_count,
+        )
+        .with_error_bound(self.config.error_bound())
+    }
+}
+
+impl_integer_compressor!(ChimpCompressorBatchedCpu, i32, u32);
+
+impl ChimpCompressorBatched {
+    pub const MAX_BUFFER_SIZE_BYTES: usize = 134_217_728;
+
+    pub fn new(debug: bool, context: Arc<Context>, finalizer: DeviceEnum) -> Self {
+        Self {
+            debug,
+            context,
+            finalizer,
+            config: ChimpConfig::default(),
+            fused: false,
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    /// Record every stage of a batch into one command buffer and read back only the
+    /// finalized blocks, see [FusedStages]. The fused stages always finalize on the GPU,
+    /// whatever the finalizer of the compressor.
+    pub fn with_fused_stages(mut self, fused: bool) -> Self {
+        self.fused = fused;
+        self
+    }
+
+    pub fn fused_stages(&self) -> bool {
+        self.fused
+    }
+
+    fn split_by_max_gpu_buffer_size<'a>(&self, values: &'a [f32]) -> Chunks<'a, f32> {
+        split_by_max_storage_buffer_size(
+            values,
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput>(),
+        )
+    }
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+
+    pub fn debug(&self) -> bool {
+        self.debug
+    }
+
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+    fn compute_s_factory(&self) -> impl ComputeS {
+        ComputeSImpl::new(self.context().clone(), self.config.clone())
+    }
+    fn compute_final_compress_factory(&self) -> impl FinalCompress + use<'_> {
+        FinalCompressImpl::new(self.context().clone(), self.config.clone(), self.debug())
+    }
+    fn calculate_indexes_factory(&self) -> impl CalculateIndexes {
+        GPUCalculateIndexes::new(self.context().clone(), self.config.clone())
+    }
+    fn compute_finalize_factory(&self) -> impl Finalize + use<'_> {
+        match self.finalizer {
+            DeviceEnum::GPU => {
+                FinalizerImpl::GPU(Finalizer::new(self.context().clone(), self.config.clone()))
+            }
+            DeviceEnum::CPU => FinalizerImpl::CPU(CPUImpl::with_context(
+                self.context().clone(),
+                self.config.clone(),
+            )),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::cpu::decompressor::{BatchedDecompressorCpu, DebugBatchDecompressorCpu};
+    use crate::decompressor::BatchedGPUDecompressor;
+    use crate::DeviceEnum::GPU;
+    use crate::{ChimpCompressorBatched, ChimpCompressorBatchedCpu};
+    use compress_utils::config::{ChimpConfig, ConfigError};
+    use compress_utils::context::Context;
+    use compress_utils::cpu_compress::{Compressor, Decompressor};
+    use compress_utils::error::ChimpError;
+    use compress_utils::format::{BlockIndex, ChimpHeader, HeaderFlags, HEADER_SIZE};
+    use compress_utils::general_utils::{build_event_times, check_for_debug_mode, EventLogType};
+    use compress_utils::integer::{IntegerDecompressor, IntegerEncoding};
+    use compress_utils::stream::{BatchedCompressor, ChimpStreamEncoder};
+    use compress_utils::testing::{
+        assert_checksum_reports_corrupted_block, assert_config_round_trip,
+        assert_corruption_never_panics, assert_range_round_trip, assert_round_trip, sine_values,
+        special_values,
+    };
+    use itertools::Itertools;
+    use pollster::FutureExt;
+    use std::cmp::min;
+    use std::sync::Arc;
+    use std::{env, fs};
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    fn get_third(field: &str) -> Option<String> {
+        field
+            .split(",")
+            .collect_vec()
+            .get(2)
+            .map(|it| it.to_string())
+    }
+    //noinspection ALL
+    fn get_values(file_name: impl Into<String>) -> anyhow::Result<Vec<f32>> {
+        let dir = env::current_dir()?;
+        let file_path = dir.parent().unwrap().join(file_name.into());
+        let file_txt = fs::read_to_string(file_path)?;
+        let values = file_txt
+            .split("\n")
+            .map(get_third)
+            .filter(|p| p.is_some())
+            .map(|s| s.unwrap().parse::<f32>().unwrap())
+            .collect_vec()
+            .to_vec();
+        Ok(values)
+    }
+    //noinspection DuplicatedCode
+    // #[test]
+    // fn test_matching_outputs() {
+    //     // let subscriber = tracing_subscriber::fmt()
+    //     //     .compact()
+    //     //     .with_env_filter("wgpu_compress_32=info")
+    //     //     // .with_writer(
+    //     //     //     OpenOptions::new()
+    //     //     //         .create(true)
+    //     //     //         .truncate(true)
+    //     //     //         .write(true)
+    //     //     //         .open("run.log")
+    //     //     //         .unwrap(),
+    //     //     // )
+    //     //     .finish();
+    //     // subscriber.init();
+    //
+    //     let mut values = get_values("city_temperature.csv")
+    //         .expect("Could not read test values")
+    //         .to_vec();
+    //     // log:: //info!("Starting compression of {} values", values.len());
+    //     let mut compressor = ChimpCompressorBatched::default();
+    //     if check_for_debug_mode().expect("Could not read file system") {
+    //         compressor.set_debug(true);
+    //     }
+    //     let compressed_values1 = compressor.compress(&values).block_on().unwrap();
+    //
+    //     let mut values = get_values("city_temperature.csv")
+    //         .expect("Could not read test values")
+    //         .to_vec();
+    //     // log:: //info!("Starting compression of {} values", values.len());
+    //     let mut compressor = ChimpCompressorBatched {
+    //         finalizer: GPU,
+    //         ..ChimpCompressorBatched::default()
+    //     };
+    //     // if check_for_debug_mode().expect("Could not read file system") {
+    //     //     compressor.set_debug(true);
+    //     // }
+    //     let compressed_values2 = compressor.compress(&values).block_on().unwrap();
+    //     assert_eq!(
+    //         compressed_values2.compressed_value(),
+    //         compressed_values1.compressed_value()
+    //     );
+    // }
+    fn mixed_values() -> Vec<f32> {
+        let mut values = sine_values(1000);
+        values.extend([0.0, -0.0, f32::MAX, f32::MIN_POSITIVE, 1.5, 1.5, 1.5, 1e-42]);
+        values
+    }
+
+    #[test]
+    fn test_cpu_compressor_round_trip() {
+        assert_round_trip(
+            &ChimpCompressorBatchedCpu::default(),
+            &DebugBatchDecompressorCpu::default(),
+            &mixed_values(),
+        );
+    }
+
+    #[test]
+    fn test_bitstream_decompressor_round_trip() {
+        assert_round_trip(
+            &ChimpCompressorBatchedCpu::default(),
+            &BatchedDecompressorCpu::default(),
+            &mixed_values(),
+        );
+    }
+
+    #[test]
+    fn test_cpu_corrupted_stream_never_panics() {
+        let decompressor = BatchedDecompressorCpu::default();
+        let stream = assert_round_trip(
+            &ChimpCompressorBatchedCpu::default(),
+            &decompressor,
+            &sine_values::<f32>(600),
+        );
+        assert_corruption_never_panics(&stream, &decompressor);
+    }
+
+    #[test]
+    fn test_cpu_checksum_reports_corrupted_block() {
+        let config = ChimpConfig::default().with_block_checksums(true);
+        let compressed = ChimpCompressorBatchedCpu::default()
+            .with_config(config)
+            .compress(&sine_values::<f32>(3000))
+            .block_on()
+            .unwrap();
+        let header = ChimpHeader::read(compressed.compressed_value_ref()).unwrap();
+        assert!(header.flags.contains(HeaderFlags::BLOCK_CHECKSUMS));
+        assert_checksum_reports_corrupted_block(
+            compressed.compressed_values(),
+            &BatchedDecompressorCpu::default(),
+        );
+    }
+
+    #[test]
+    fn test_cpu_decompress_range() {
+        assert_range_round_trip(
+            &ChimpCompressorBatchedCpu::default(),
+            &BatchedDecompressorCpu::default(),
+            &sine_values::<f32>(3000),
+        );
+    }
+
+    #[test]
+    fn test_cpu_integer_round_trip() {
+        // small integers are subnormal floats, so the pipeline has to keep them bit exact
+        let values = (0..3000i32)
+            .map(|it| {
+                if it % 700 == 0 {
+                    i32::MIN + it
+                } else {
+                    it * 3 - 2000
+                }
+            })
+            .collect_vec();
+        let unsigned = values.iter().map(|it| *it as u32).collect_vec();
+        for encoding in [IntegerEncoding::Xor, IntegerEncoding::ZigZagDelta] {
+            let config = ChimpConfig::default()
+                .with_buffer_size(256)
+                .unwrap()
+                .with_integer_encoding(encoding);
+            let compressor = ChimpCompressorBatchedCpu::default().with_config(config.clone());
+            let decompressor = IntegerDecompressor::new(BatchedDecompressorCpu::new(config));
+            let compressed = compressor.compress(&values).block_on().unwrap();
+            let decompressed: Vec<i32> = decompressor
+                .decompress(compressed.compressed_value_ref())
+                .block_on()
+                .unwrap()
+                .into();
+            assert_eq!(decompressed, values);
+            let range: Vec<i32> = decompressor
+                .decompress_range(compressed.compressed_value_ref(), 250..1030)
+                .block_on()
+                .unwrap()
+                .into();
+            assert_eq!(range, values[250..1030]);
+
+            let compressed = compressor.compress(&unsigned).block_on().unwrap();
+            let decompressed: Vec<u32> = decompressor
+                .decompress(compressed.compressed_value_ref())
+                .block_on()
+                .unwrap()
+                .into();
+            assert_eq!(decompressed, unsigned);
+        }
+    }
+
+    #[test]
+    fn test_cpu_special_values_round_trip() {
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let compressor = ChimpCompressorBatchedCpu::default().with_config(config.clone());
+        let decompressors: [Box<dyn Decompressor<f32>>; 2] = [
+            Box::new(BatchedDecompressorCpu::new(config.clone())),
+            Box::new(DebugBatchDecompressorCpu::new(config)),
+        ];
+        for decompressor in decompressors {
+            assert_round_trip(&compressor, decompressor.as_ref(), &special_values());
+        }
+    }
+
+    #[test]
+    fn test_cpu_round_trip_with_config() {
+        let config = ChimpConfig::default().with_buffer_size(512).unwrap();
+        assert_config_round_trip(
+            &ChimpCompressorBatchedCpu::default().with_config(config.clone()),
+            &BatchedDecompressorCpu::new(config),
+            &BatchedDecompressorCpu::default(),
+            &sine_values::<f32>(3000),
+            512,
+        );
+    }
+
+    #[test]
+    fn test_cpu_compresses_a_borrowed_sub_slice() {
+        let values = (0..3000)
+            .map(|it| ((f32::from(it as u16)) / 10.0).sin() * 100.0)
+            .collect_vec();
+        // neither the start nor the length of the slice lines up with a buffer
+        let slice = &values[3..2900];
+        let compressed = ChimpCompressorBatchedCpu::default()
+            .compress(slice)
+            .block_on()
+            .unwrap();
+
+        // the stream does not have to be aligned to be decompressed
+        let mut shifted = vec![0u8];
+        shifted.extend(compressed.compressed_value_ref());
+        let decompressed = BatchedDecompressorCpu::default()
+            .decompress(&shifted[1..])
+            .block_on()
+            .unwrap();
+        assert_eq!(
+            decompressed
+                .un_compressed_value()
+                .iter()
+                .map(|it| it.to_bits())
+                .collect_vec(),
+            slice.iter().map(|it| it.to_bits()).collect_vec()
+        );
+    }
+
+    #[test]
+    fn test_stream_encoder_writes_the_blocks_of_compress() {
+        let values = (0..5000)
+            .map(|it| ((f32::from(it as u16)) / 10.0).sin() * 100.0)
+            .collect_vec();
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let compressor = ChimpCompressorBatchedCpu::new(16384).with_config(config.clone());
+        assert!(compressor.batch_len() < values.len());
+        let compressed = compressor.compress(&values).block_on().unwrap();
+
+        let mut encoder = ChimpStreamEncoder::new(compressor, Vec::new()).unwrap();
+        for chunk in values.chunks(700) {
+            encoder.push(chunk).unwrap();
+        }
+        assert_eq!(encoder.value_count(), values.len());
+        let stream = encoder.finish().unwrap();
+
+        let header = ChimpHeader::read(&stream).unwrap();
+        assert!(header.flags.contains(HeaderFlags::STREAMED));
+        assert_eq!(
+            stream[HEADER_SIZE..],
+            compressed.compressed_value_ref()[HEADER_SIZE..]
+        );
+        let decompressed = BatchedDecompressorCpu::new(config)
+            .decompress(&stream)
+            .block_on()
+            .unwrap();
+        assert_eq!(decompressed.un_compressed_value(), values);
+
+        let indexed = BlockIndex::append_to(stream).unwrap();
+        let header = ChimpHeader::read(&indexed).unwrap();
+        assert!(!header.flags.contains(HeaderFlags::STREAMED));
+        assert_eq!(header.value_count, values.len() as u64);
+    }
+
+    #[test]
+    fn test_stream_encoder_rejects_block_checksums() {
+        let config = ChimpConfig::default()
+            .with_buffer_size(256)
+            .unwrap()
+            .with_block_checksums(true);
+        let compressor = ChimpCompressorBatchedCpu::new(16384).with_config(config);
+        assert!(matches!(
+            ChimpStreamEncoder::<f32, _>::new(compressor, Vec::new()),
+            Err(ChimpError::InvalidConfig(ConfigError::ChecksumsOnStream))
+        ));
+    }
+
+    #[test]
+    fn test_cpu_compressor_block_layout() {
+        let values = vec![1.5f32; 300];
+        let compressed = ChimpCompressorBatchedCpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        assert_eq!(compressed.metadata_size(), HEADER_SIZE + 2 * 8);
+        let words: Vec<u32> = compressed.compressed_value_ref()[HEADER_SIZE..]
+            .chunks(4)
+            .map(|it| u32::from_le_bytes(it.try_into().unwrap()))
+            .collect_vec();
+        // 32 bits for the first value and 2 bits for each of the 255 equal values
+        let block_words = (32 + 255 * 2) / 32 + 3;
+        assert_eq!(words[0], 255);
+        assert_eq!(words[1], (block_words as u32 - 2) * 4);
+        assert_eq!(words[2], 1.5f32.to_bits());
+        assert_eq!(words[block_words], 300 - 256 - 1);
+        assert_eq!(words[block_words + 2], 1.5f32.to_bits());
+    }
+
+    //noinspection DuplicatedCode
+    #[test]
+    fn test_decompress_able_file() {
+        // let subscriber = tracing_subscriber::fmt()
+        //     .compact()
+        //     .with_env_filter("wgpu_compress_32=info")
+        //     // .with_writer(
+        //     //     OpenOptions::new()
+        //     //         .create(true)
+        //     //         .truncate(true)
+        //     //         .write(true)
+        //     //         .open("run.log")
+        //     //         .unwrap(),
+        //     // )
+        //     .finish();
+        // subscriber.init();
+        let adapter = env::var("CHIMP_GPU_ADAPTER").unwrap_or("NVIDIA".to_string());
+        let context = Arc::new(
+            Context::initialize_with_adapter(adapter)
+                .block_on()
+                .unwrap(),
+        );
+        for file_name in vec![
+            "city_temperature.csv",
+            "SSD_HDD_benchmarks.csv",
+            "Stocks-Germany-sample.txt",
+        ]
+        .into_iter()
+        {
+            println!("{file_name}");
+            let filename = format!("{}_chimp32_output.txt", &file_name);
+            if fs::exists(&filename).unwrap() {
+                fs::remove_file(&filename).unwrap();
+            }
+
+            let filename_no_io = format!("{}_chimp32_output_no_io.txt", &file_name);
+            if fs::exists(&filename_no_io).unwrap() {
+                fs::remove_file(&filename_no_io).unwrap();
+            }
+
+            let mut messages = Vec::<EventLogType>::with_capacity(30);
+            let mut messages_no_io = Vec::<EventLogType>::with_capacity(30);
+            let mut values = get_values(file_name)
+                .expect("Could not read test values")
+                .to_vec();
+            let mut reader = TimeSeriesReader::new(500_000, values.clone(), 500_000_000);
+            for size_checkpoint in 1..11 {
+                while let Some(block) = reader.next() {
+                    values.extend(block);
+                    if values.len() >= (size_checkpoint * reader.max_size()) / 100 {
+                        break;
+                    }
+                }
+                let value_new = values.clone();
+                println!("Starting compression of {} values", values.len());
+                let time = std::time::Instant::now();
+                let compressor = ChimpCompressorBatched::new(false, context.clone(), GPU);
+                let compressed_values2 = compressor.compress(&value_new).block_on().unwrap();
+                let compression_time = time.elapsed().as_millis();
+
+                const SIZE_IN_BYTE: usize = 8;
+                let compression_ratio = (compressed_values2.compressed_value_ref().len()
+                    * SIZE_IN_BYTE) as f64
+                    / value_new.len() as f64;
+
+                messages.push(EventLogType::CompressionRatio {
+                    values: value_new.len() as u64,
+                    ratio: compression_ratio,
+                });
+                messages.push(EventLogType::EncodingTime {
+                    values: value_new.len() as u64,
+                    time: compression_time,
+                });
+
+                messages_no_io.push(EventLogType::CompressionRatio {
+                    values: value_new.len() as u64,
+                    ratio: compression_ratio,
+                });
+                messages_no_io.push(EventLogType::EncodingTime {
+                    values: value_new.len() as u64,
+                    time: compression_time - compressed_values2.skip_time(),
+                });
+
+                let time = std::time::Instant::now();
+                let decompressor = BatchedGPUDecompressor::new(context.clone());
+                match decompressor
+                    .decompress(compressed_values2.compressed_value_ref())
+                    .block_on()
+                {
+                    Ok(decompressed_values) => {
+                        let decompression_time = time.elapsed().as_millis();
+                        messages.push(EventLogType::DecompressionTime {
+                            values: value_new.len() as u64,
+                            time: decompression_time,
+                        });
+                        messages_no_io.push(EventLogType::DecompressionTime {
+                            values: value_new.len() as u64,
+                            time: decompression_time - decompressed_values.skip_time(),
+                        });
+
+                        println!(
+                            "Decoding time {} values: {}\n",
+                            value_new.len(),
+                            decompression_time //- decompressed_values.skip_time()
+                        );
+                        // fs::write("actual.log", decompressed_values.0.iter().join("\n")).unwrap();
+                        // fs::write("expected.log", value_new.iter().join("\n")).unwrap();
+                        assert_eq!(decompressed_values.0, value_new);
+                    }
+                    Err(err) => {
+                        eprintln!("Decompression error: {:?}", err);
+                        panic!("{}", err);
+                    }
+                }
+            }
+
+            let mut writer = csv::Writer::from_path(filename).unwrap();
+            let logs = build_event_times(messages);
+            for message in logs.iter().sorted_by_key(|it| it.values) {
+                writer.serialize(message).unwrap();
+            }
+
+            writer.flush().unwrap();
+            let mut writer_no_io = csv::Writer::from_path(filename_no_io).unwrap();
+            let logs_no_io = build_event_times(messages_no_io);
+            for message in logs_no_io.iter().sorted_by_key(|it| it.values) {
+                writer_no_io.serialize(message).unwrap();
+            }
+            writer_no_io.flush().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_decompress_able_buffer() {
+        // let subscriber = tracing_subscriber::fmt()
+        //     .compact()
+        //     .with_env_filter("wgpu_compress_32=info")
+        //     // .with_writer(
+        //     //     OpenOptions::new()
+        //     //         .create(true)
+        //     //         .truncate(true)
+        //     //         .write(true)
+        //     //         .open("run.log")
+        //     //         .unwrap(),
+        //     // )
+        //     .finish();
+        // subscriber.init();
+        let adapter = env::var("CHIMP_GPU_ADAPTER").unwrap_or("NVIDIA".to_string());
+        let context = Arc::new(
+            Context::initialize_with_adapter(adapter)
+                .block_on()
+                .unwrap(),
+        );
+        for buffer_size in vec![256, 512, 1024, 2048].into_iter() {
+            let filename = format!("{}_chimp32_output.txt", buffer_size);
+            if fs::exists(&filename).unwrap() {
+                fs::remove_file(&filename).unwrap();
+            }
+            let config = ChimpConfig::default()
+                .with_buffer_size(buffer_size)
+                .unwrap();
+            println!("Buffer size: {}", config.buffer_size());
+            let mut messages = Vec::<EventLogType>::with_capacity(30);
+            let mut values = get_values("city_temperature.csv")
+                .expect("Could not read test values")
+                .to_vec();
+
+            let mut reader = TimeSeriesReader::new(50_000, values.clone(), 500_000_000);
+
+            for size_checkpoint in 1..11 {
+                while let Some(block) = reader.next() {
+                    values.extend(block);
+                    if values.len() >= (size_checkpoint * reader.max_size()) / 100 {
+                        break;
+                    }
+                }
+                let value_new = values.clone();
+                println!("Starting compression of {} values", value_new.len());
+                let time = std::time::Instant::now();
+                let compressor = ChimpCompressorBatched::new(false, context.clone(), GPU)
+                    .with_config(config.clone());
+                let compressed_values2 = compressor.compress(&value_new).block_on().unwrap();
+                let compression_time = time.elapsed().as_millis();
+
+                const SIZE_IN_BYTE: usize = 8;
+                let compression_ratio = (compressed_values2.compressed_value_ref().len()
+                    * SIZE_IN_BYTE) as f64
+                    / value_new.len() as f64;
+                messages.push(EventLogType::CompressionRatio {
+                    values: value_new.len() as u64,
+                    ratio: compression_ratio,
+                });
+                messages.push(EventLogType::EncodingTime {
+                    values: value_new.len() as u64,
+                    time: compression_time - compressed_values2.skip_time(),
+                });
+
+                let time = std::time::Instant::now();
+                let decompressor =
+                    BatchedGPUDecompressor::new(context.clone()).with_config(config.clone());
+                match decompressor
+                    .decompress(compressed_values2.compressed_value_ref())
+                    .block_on()
+                {
+                    Ok(decompressed_values) => {
+                        let decompression_time = time.elapsed().as_millis();
+                        messages.push(EventLogType::DecompressionTime {
+                            values: value_new.len() as u64,
+                            time: decompression_time - decompressed_values.skip_time(),
+                        });
+
+                        // fs::write("actual.log", decompressed_values.0.iter().join("\n")).unwrap();
+                        // fs::write("expected.log", values.iter().join("\n")).unwrap();
+                        assert_eq!(decompressed_values.0, value_new);
+                    }
+                    Err(err) => {
+                        eprintln!("Decompression error: {:?}", err);
+                        panic!("{}", err);
+                    }
+                }
+            }
+            let mut writer = csv::Writer::from_path(filename).unwrap();
+            let time = build_event_times(messages);
+
+            for message in time.iter().sorted_by_key(|it| it.values) {
+                writer.serialize(message).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_decompress_able_old() {
+        // let value_count = 0..(256 * 109);
+        let subscriber = tracing_subscriber::fmt()
+            .compact()
+            .with_env_filter("wgpu_compress_32=info")
+            // .with_writer(
+            //     OpenOptions::new()
+            //         .create(true)
+            //         .truncate(true)
+            //         .write(true)
+            //         .open("run.log")
+            //         .unwrap(),
+            // )
+            .finish();
+        subscriber.init();
+
+        let mut values = get_values("city_temperature.csv")
+            .expect("Could not read test values")
+            .to_vec();
+        values.extend(
+            get_values("city_temperature.csv")
+                .expect("Could not read test values")
+                .to_vec(),
+        );
+        // log:: //info!("Starting compression of {} values", values.len());
+        let context = Arc::new(
+            Context::initialize_with_adapter("NVIDIA".to_string())
+                .block_on()
+                .unwrap(),
+        );
+        let mut compressor = ChimpCompressorBatched::new(false, context.clone(), GPU);
+        if check_for_debug_mode().expect("Could not read file system") {
+            compressor.set_debug(true);
+        }
+        let compressed_values2 = compressor.compress(&values).block_on().unwrap();
+
+        let decompressor = BatchedGPUDecompressor::new(context);
+        match decompressor
+            .decompress(compressed_values2.compressed_value_ref())
+            .block_on()
+        {
+            Ok(decompressed_values) => {
+                // fs::write("actual.log", decompressed_values.iter().join("\n")).unwrap();
+                // fs::write("expected.log", values.iter().join("\n")).unwrap();
+                assert_eq!(decompressed_values.0, values)
+            }
+            Err(err) => {
+                eprintln!("Decompression error: {:?}", err);
+                panic!("{}", err);
+            }
+        }
+    }
+    struct TimeSeriesReader {
+        minimum_block_size: usize,
+        block_size: usize,
+        source_value: Vec<f32>,
+        current_index: usize,
+    }
+
+    impl TimeSeriesReader {
+        pub fn new(block_size: usize, source_value: Vec<f32>, minimum_block_size: usize) -> Self {
+            Self {
+                minimum_block_size,
+                block_size: min(block_size, source_value.len()),
+                source_value,
+                current_index: 0,
+            }
+        }
+        pub fn max_size(&self) -> usize {
+            let len = self.source_value.len();
+            let mut max = len;
+            while max < self.minimum_block_size {
+                max += self.block_size;
+            }
+            max
+        }
+    }
+    impl Iterator for TimeSeriesReader {
+        type Item = Vec<f32>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let mut block = Vec::<f32>::with_capacity(self.block_size);
+            if self.current_index < self.minimum_block_size {
+                if self.block_size + self.current_index > self.source_value.len() {
+                    let remaining = self.block_size + self.current_index - self.source_value.len();
+                    block.extend(&self.source_value[self.current_index..]);
+                    block.extend(&self.source_value[0..remaining]);
+                    self.current_index = remaining - 1;
+                    Some(block)
+                } else {
+                    block.extend(
+                        &self.source_value
+                            [self.current_index..self.current_index + self.block_size],
+                    );
+                    self.current_index += self.block_size;
+                    Some(block)
+                }
+            } else {
+                None
+            }
+        }
+    }
+}
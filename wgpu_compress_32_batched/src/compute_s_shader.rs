@@ -1,10 +1,12 @@
-use anyhow::Result;
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo, MaxGroupGnostic, Step};
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{padding_to_fit_buffer_count, MaxGroupGnostic, Step};
 use compress_utils::types::S;
 use compress_utils::wgpu_utils::RunBuffers;
-use compress_utils::{execute_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
+use compress_utils::{record_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
 use std::cmp::max;
 use std::fs;
 use std::ops::Div;
@@ -16,19 +18,20 @@ use wgpu_types::BufferAddress;
 pub trait ComputeS: MaxGroupGnostic {
     async fn compute_s(
         &self,
-        values: &mut [f32],
+        values: &[f32],
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> Result<()>;
+    ) -> Result<(), ChimpError>;
 }
 
 pub struct ComputeSImpl {
     context: Arc<Context>,
+    config: ChimpConfig,
 }
 
 impl ComputeSImpl {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
     }
 
     pub fn context(&self) -> &Context {
@@ -38,7 +41,6 @@ impl ComputeSImpl {
         self.context.device()
     }
 
-    #[allow(dead_code)]
     pub fn queue(&self) -> &wgpu::Queue {
         self.context.queue()
     }
@@ -48,89 +50,82 @@ impl ComputeSImpl {
     }
     #[allow(dead_code)]
     pub fn max_work_group_count(&self) -> usize {
-        self.context.get_max_workgroup_size()
+        self.config.max_workgroup_size(&self.context)
     }
 }
 
 impl MaxGroupGnostic for ComputeSImpl {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 
-#[async_trait]
-impl ComputeS for ComputeSImpl {
-    async fn compute_s(
+impl ComputeSImpl {
+    /// Record the S computation of [values] into [encoder] without submitting it, the
+    /// buffers are set on [buffers] for the following stages
+    pub fn record_compute_s(
         &self,
-        values: &mut [f32],
+        encoder: &mut wgpu::CommandEncoder,
+        values: &[f32],
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> Result<()> {
+    ) -> Result<(), ChimpError> {
         // Create a shader module and pipeline
 
         //Calculating buffer sizes and workgroup counts
-        let workgroup_count = self.get_max_number_of_groups(values.len());
+        // the values are padded with 0's to fill their last buffer
+        let padded_len =
+            values.len() + padding_to_fit_buffer_count(values.len(), self.config.buffer_size());
+        let workgroup_count = self.get_max_number_of_groups(padded_len);
         //info!("The wgpu workgroup size: {}", &workgroup_count);
 
         let size_of_s = size_of::<S>();
-        let bytes = values.len() + 1;
+        let bytes = padded_len + 1;
         //info!("The size of the input values vec: {}", bytes);
 
         let s_buffer_size = (size_of_s * bytes) as BufferAddress;
         //info!("The S buffer size in bytes: {}", s_buffer_size);
 
-        let mut padded_values = Vec::from(values);
-        padded_values.push(0f32);
-
         let instant = Instant::now();
-        let input_storage_buffer = BufferWrapper::storage_with_content(
-            self.device(),
-            bytemuck::cast_slice(padded_values.as_slice()),
+        let input_storage_buffer = BufferWrapper::pooled_storage_with_padded_content(
+            self.context(),
+            bytemuck::cast_slice(values),
+            (size_of::<f32>() * bytes) as BufferAddress,
             WgpuGroupId::new(0, 1),
             Some("Storage Input Buffer"),
         );
-        let s_staging_buffer =
-            BufferWrapper::stage_with_size(self.device(), s_buffer_size, Some("Staging S Buffer"));
-        let s_storage_buffer = BufferWrapper::storage_with_size(
-            self.device(),
+        let s_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
             s_buffer_size,
             WgpuGroupId::new(0, 0),
             Some("Storage S Buffer"),
         );
-        let chunks_buffer = BufferWrapper::uniform_with_content(
-            self.device(),
-            bytemuck::bytes_of(&ChimpBufferInfo::get().chunks()),
+        let chunks_buffer = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
+            bytemuck::bytes_of(&self.config.buffer_info().chunks()),
             WgpuGroupId::new(0, 2),
             Some("Chunks Buffer"),
         );
         *skip_time += instant.elapsed().as_millis();
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let temp = include_str!("shaders/compute_s.wgsl")
-                .replace(
-                    "@@workgroup_size",
-                    &ChimpBufferInfo::get().buffer_size().to_string(),
+            let temp = shaders::COMPUTE_S
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
                 )
-                .replace("//@workgroup_offset", &offset_decl)
-                .to_string();
-            execute_compute_shader!(
+                .finish()?;
+            record_compute_shader!(
                 self.context(),
+                encoder,
                 &temp,
-                vec![
-                    &s_storage_buffer,
-                    &input_storage_buffer,
-                    &s_staging_buffer,
-                    &chunks_buffer
-                ],
+                vec![&s_storage_buffer, &input_storage_buffer, &chunks_buffer],
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("compute s layout")
             );
@@ -138,9 +133,21 @@ impl ComputeS for ComputeSImpl {
         buffers.set_input_buffer(input_storage_buffer);
         buffers.set_s_buffer(s_storage_buffer);
         buffers.set_chunks(chunks_buffer);
+        Ok(())
+    }
 
+    /// Write the S values of [buffers] to the trace file when the configuration traces
+    /// [Step::ComputeS], once the work recorded by [ComputeSImpl::record_compute_s] is
+    /// submitted
+    pub async fn trace(&self, buffers: &RunBuffers) -> Result<(), ChimpError> {
         //info!("Output result size: {}", output.len());
-        if trace_steps().contains(&Step::ComputeS) {
+        if self.config.traces(&Step::ComputeS) {
+            let s_buffer_size = buffers.s_buffer().size() as BufferAddress;
+            let s_staging_buffer = BufferWrapper::pooled_stage_with_size(
+                self.context(),
+                s_buffer_size,
+                Some("Staging S Buffer"),
+            );
             let output = wgpu_utils::get_from_gpu::<S>(
                 self.context(),
                 buffers.s_buffer().buffer(),
@@ -161,3 +168,21 @@ impl ComputeS for ComputeSImpl {
         Ok(())
     }
 }
+
+#[async_trait]
+impl ComputeS for ComputeSImpl {
+    async fn compute_s(
+        &self,
+        values: &[f32],
+        buffers: &mut RunBuffers,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        let mut encoder = self
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.record_compute_s(&mut encoder, values, buffers, skip_time)?;
+        self.queue().submit(Some(encoder.finish()));
+        buffers.release_submitted();
+        self.trace(buffers).await
+    }
+}
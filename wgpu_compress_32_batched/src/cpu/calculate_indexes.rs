@@ -0,0 +1,61 @@
+use crate::calculate_indexes::CalculateIndexes;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::Step;
+use compress_utils::types::ChimpOutput;
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{step, BufferWrapper};
+use std::fs;
+
+/// Host implementation of the `calculate_final_sizes.wgsl` stage and the prefix sum that follows it
+#[derive(Debug, Default)]
+pub struct CPUCalculateIndexes {
+    config: ChimpConfig,
+}
+
+impl CPUCalculateIndexes {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl CalculateIndexes for CPUCalculateIndexes {
+    async fn calculate_indexes(
+        &self,
+        buffers: &mut RunBuffers,
+        size: u32,
+        _skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        self.calculate_buffer_indexes(buffers, size)
+    }
+}
+
+impl CPUCalculateIndexes {
+    /// The body of [CalculateIndexes::calculate_indexes], public so the N variants can reuse it
+    /// for their own stage trait
+    pub fn calculate_buffer_indexes(
+        &self,
+        buffers: &mut RunBuffers,
+        size: u32,
+    ) -> Result<(), ChimpError> {
+        let compressed = buffers.compressed_buffer().host_slice::<ChimpOutput>();
+        let workgroup_count = compressed.len() / size as usize;
+
+        let mut indexes = vec![0u32; workgroup_count + 1];
+        for (workgroup, chunk) in compressed.chunks_exact(size as usize).enumerate() {
+            indexes[workgroup + 1] = chunk.iter().map(|it| it.bit_count).sum::<u32>() / 32 + 3;
+        }
+        for i in 1..workgroup_count {
+            indexes[i + 1] += indexes[i];
+        }
+        step!(self.config, Step::CalculateIndexes, {
+            indexes.iter().map(|it| format!("{it}\n"))
+        });
+        buffers.set_index_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &indexes,
+        )));
+        Ok(())
+    }
+}
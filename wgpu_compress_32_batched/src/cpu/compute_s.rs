@@ -0,0 +1,85 @@
+use crate::compute_s_shader::ComputeS;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{padding_to_fit_buffer_count, MaxGroupGnostic, Step};
+use compress_utils::types::S;
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{step, BufferWrapper};
+use std::fs;
+use std::ops::Div;
+
+/// Host implementation of the `compute_s.wgsl` stage
+#[derive(Debug, Default)]
+pub struct CPUComputeS {
+    config: ChimpConfig,
+}
+
+impl CPUComputeS {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl CPUComputeS {
+    pub(crate) fn calculate_s(workgroup_size: usize, id: usize, v_prev: f32, v: f32) -> S {
+        let i = v_prev.to_bits() ^ v.to_bits();
+
+        // The first value of every buffer is stored uncompressed, so the gpu resets the leading
+        // zeros of the value right after it
+        let leading = if !id.is_multiple_of(workgroup_size) {
+            i.leading_zeros()
+        } else {
+            0
+        };
+        S {
+            head: leading as i32,
+            tail: i.trailing_zeros() as i32,
+            equal: (i == 0) as u32,
+        }
+    }
+}
+
+impl MaxGroupGnostic for CPUComputeS {
+    fn get_max_number_of_groups(&self, content_len: usize) -> usize {
+        content_len.div(self.config.buffer_size())
+    }
+}
+
+#[async_trait]
+impl ComputeS for CPUComputeS {
+    async fn compute_s(
+        &self,
+        values: &[f32],
+        buffers: &mut RunBuffers,
+        _skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        let workgroup_size = self.config.buffer_size();
+
+        // the values are padded with 0's to fill their last buffer
+        let padded_len = values.len() + padding_to_fit_buffer_count(values.len(), workgroup_size);
+        let mut padded_values = Vec::from(values);
+        padded_values.resize(padded_len + 1, 0f32);
+
+        let mut s_values = vec![S::default(); padded_values.len()];
+        for index in 0..padded_values.len() - 1 {
+            s_values[index + 1] = Self::calculate_s(
+                workgroup_size,
+                index,
+                padded_values[index],
+                padded_values[index + 1],
+            );
+        }
+        step!(self.config, Step::ComputeS, {
+            s_values.iter().map(|it| format!("{}\n", it))
+        });
+
+        buffers.set_input_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &padded_values,
+        )));
+        buffers.set_s_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &s_values,
+        )));
+        Ok(())
+    }
+}
@@ -1,39 +1,55 @@
-use crate::cpu::finalize::extract_bits;
+use crate::cpu::bits::extract_bits;
 use crate::info;
 use async_trait::async_trait;
 use bit_vec::BitVec;
 use compress_utils::bit_utils::BitReadable;
-use compress_utils::cpu_compress::{DecompressionError, Decompressor};
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo, DecompressResult, Step};
+use compress_utils::config::ChimpConfig;
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, HEADER_SIZE};
+use compress_utils::general_utils::{DecompressResult, Step};
 use compress_utils::time_it;
 use itertools::Itertools;
 use log::trace;
 use std::cmp::{max, min};
 use std::fs;
-use thiserror::Error;
 
-#[derive(Debug, Error)]
-pub enum BatchedDecompressorError {
-    #[error("Invalid Decompression Format")]
-    Default,
+/// The error of a block whose bitstream cannot be decoded, [BatchedDecompressorCpu::decompress_impl]
+/// replaces it with the position of the block in the stream
+const CORRUPT_BLOCK: ChimpError = ChimpError::CorruptInput {
+    block: 0,
+    byte_offset: 0,
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct BatchedDecompressorCpu {
+    config: ChimpConfig,
 }
 
-impl From<BatchedDecompressorError> for DecompressionError {
-    fn from(value: BatchedDecompressorError) -> Self {
-        DecompressionError::FromBaseAnyhowError(anyhow::Error::from(value))
+impl BatchedDecompressorCpu {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
     }
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct BatchedDecompressorCpu {}
-
 impl BatchedDecompressorCpu {
+    /// The [bit_count] bits of [input_vector] starting at bit [index], a read past the end of
+    /// the block is a corruption
+    fn read(input_vector: &BitVec, index: usize, bit_count: u32) -> Result<u32, ChimpError> {
+        if index + bit_count as usize > input_vector.len() {
+            return Err(CORRUPT_BLOCK);
+        }
+        Ok(input_vector.reinterpret_u32(index, bit_count as usize))
+    }
+
+    /// Decode the first [value_count] values of the bitstream of a block
     pub fn decompress_block(
         &self,
         input_vector: &BitVec,
-    ) -> Result<Vec<f32>, BatchedDecompressorError> {
+        value_count: usize,
+    ) -> Result<Vec<f32>, ChimpError> {
         let mut input_index: usize;
-        let first_num_u32: u32 = input_vector.reinterpret_u32(0, 32);
+        let first_num_u32: u32 = Self::read(input_vector, 0, 32)?;
         let first_num = f32::from_bits(first_num_u32);
         // if self.debug {
         //     log::info!("0:{}", first_num);
@@ -44,30 +60,27 @@ impl BatchedDecompressorCpu {
         let mut last_num = first_num.to_bits();
         let mut last_lead = 0;
         input_index = 32;
-        while input_index < input_vector.len() {
+        while output.len() < value_count {
             // let str_temp2 = format!("{:032b}", last_num);
 
             if input_index + 1 >= input_vector.len() {
-                break;
+                return Err(CORRUPT_BLOCK);
             }
             if input_vector[input_index] {
                 input_index += 1;
                 let mut lead = last_lead;
                 if input_vector[input_index] {
                     input_index += 1;
-                    lead = input_vector.reinterpret_u32(input_index, 5);
+                    lead = Self::read(input_vector, input_index, 5)?;
                     input_index += 5;
                 } else {
                     input_index += 1;
                 }
-                if lead > 32 {
-                    return Err(BatchedDecompressorError::Default);
-                }
                 let mut significant_bits = 32 - lead;
                 if significant_bits == 0 {
                     significant_bits = 32;
                 }
-                let value = input_vector.reinterpret_u32(input_index, significant_bits as usize);
+                let value = Self::read(input_vector, input_index, significant_bits)?;
                 // let str_temp = format!("{:032b}", value);
 
                 input_index += significant_bits as usize;
@@ -75,44 +88,35 @@ impl BatchedDecompressorCpu {
                 last_num = value;
                 last_lead = lead;
 
-                if value == u32::MAX {
-                    break;
-                } else {
-                    let value_f32 = f32::from_bits(value);
-                    // if self.debug {
-                    //     log::info!("{}:{}", output.len(), value_f32);
-                    // }
-                    output.push(value_f32);
-                }
+                let value_f32 = f32::from_bits(value);
+                // if self.debug {
+                //     log::info!("{}:{}", output.len(), value_f32);
+                // }
+                output.push(value_f32);
             } else if input_vector[input_index + 1] {
                 input_index += 2;
-                let lead = input_vector.reinterpret_u32(input_index, 5);
+                let lead = Self::read(input_vector, input_index, 5)?;
                 input_index += 5;
-                let mut significant_bits = input_vector.reinterpret_u32(input_index, 5);
+                let mut significant_bits = Self::read(input_vector, input_index, 5)?;
                 input_index += 5;
                 if significant_bits == 0 {
                     significant_bits = 32;
                 }
                 if lead + significant_bits > 32 {
-                    return Err(BatchedDecompressorError::Default);
+                    return Err(CORRUPT_BLOCK);
                 }
                 let trail = 32 - lead - significant_bits;
-                let mut value =
-                    input_vector.reinterpret_u32(input_index, (32 - lead - trail) as usize);
+                let mut value = Self::read(input_vector, input_index, 32 - lead - trail)?;
                 input_index += (32 - lead - trail) as usize;
                 value <<= trail;
                 value ^= last_num;
                 last_lead = lead;
                 last_num = value;
-                if value == u32::MAX {
-                    break;
-                } else {
-                    let value_f32 = f32::from_bits(value);
-                    // if self.debug {
-                    //     log::info!("{}:{}", output.len(), value_f32);
-                    // }
-                    output.push(value_f32);
-                }
+                let value_f32 = f32::from_bits(value);
+                // if self.debug {
+                //     log::info!("{}:{}", output.len(), value_f32);
+                // }
+                output.push(value_f32);
             } else {
                 let value_f32 = f32::from_bits(last_num);
                 last_lead = 32;
@@ -125,38 +129,17 @@ impl BatchedDecompressorCpu {
         }
         Ok(output)
     }
+    /// Decode the blocks of the payload [vec] of a stream, a block that cannot be decoded is
+    /// reported with its index and its offset in the stream, header included
     #[allow(unused)]
-    pub fn decompress_impl(
-        &self,
-        vec: &[u8],
-    ) -> Result<DecompressResult<f32>, BatchedDecompressorError> {
-        let mut current_index = 0usize;
-        let mut output = Vec::new();
+    pub fn decompress_impl(&self, vec: &[u8]) -> Result<DecompressResult<f32>, ChimpError> {
+        let output;
         let mut total_millis = 0;
         time_it!(
             {
-                while current_index < vec.len() {
-                    let buffer_size = u32::from_be_bytes(
-                        vec[current_index..current_index + size_of::<u32>()]
-                            .try_into()
-                            .unwrap(),
-                    ) as usize
-                        + 1;
-                    current_index += size_of::<u32>();
-
-                    let size = u32::from_be_bytes(
-                        vec[current_index..current_index + size_of::<u32>()]
-                            .try_into()
-                            .unwrap(),
-                    );
-                    current_index += size_of::<u32>();
-                    let vec_view = vec[current_index..current_index + (size as usize)].to_vec();
-                    let bit_vec = BitVec::from_bytes(&vec_view);
-                    let block_values = self.decompress_block(&bit_vec)?;
-
-                    output.extend(block_values[0..buffer_size].iter());
-                    current_index += size as usize;
-                }
+                output = decode_blocks(vec, |bit_vec, value_count| {
+                    self.decompress_block(bit_vec, value_count)
+                })?;
             },
             total_millis,
             "decompression"
@@ -165,35 +148,90 @@ impl BatchedDecompressorCpu {
     }
 }
 
+/// Walk the blocks of the payload [vec] of a stream in the 32 bit word layout, decoding the
+/// bitstream of each with [decode_block]. A block that cannot be decoded is reported with its
+/// index and its offset in the stream, header included
+pub(crate) fn decode_blocks(
+    vec: &[u8],
+    decode_block: impl Fn(&BitVec, usize) -> Result<Vec<f32>, ChimpError>,
+) -> Result<Vec<f32>, ChimpError> {
+    let mut current_index = 0usize;
+    let mut block = 0usize;
+    let mut output = Vec::new();
+    while current_index < vec.len() {
+        let corrupted = ChimpError::CorruptInput {
+            block,
+            byte_offset: HEADER_SIZE + current_index,
+        };
+        let value_count = u32::from_le_bytes(
+            vec[current_index..current_index + size_of::<u32>()]
+                .try_into()
+                .unwrap(),
+        ) as usize
+            + 1;
+        current_index += size_of::<u32>();
+
+        let size = u32::from_le_bytes(
+            vec[current_index..current_index + size_of::<u32>()]
+                .try_into()
+                .unwrap(),
+        );
+        current_index += size_of::<u32>();
+        // the words are written little endian, the bitstream reads them msb first
+        let vec_view = vec[current_index..current_index + (size as usize)]
+            .chunks_exact(size_of::<u32>())
+            .flat_map(|it| u32::from_le_bytes(it.try_into().unwrap()).to_be_bytes())
+            .collect_vec();
+        let bit_vec = BitVec::from_bytes(&vec_view);
+        let block_values = decode_block(&bit_vec, value_count).map_err(|_| corrupted)?;
+
+        output.extend(block_values);
+        current_index += size as usize;
+        block += 1;
+    }
+    Ok(output)
+}
+
 #[async_trait]
 impl Decompressor<f32> for BatchedDecompressorCpu {
-    async fn decompress(
-        &self,
-        vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f32>, DecompressionError> {
-        self.decompress_impl(vec).map_err(DecompressionError::from)
+    async fn decompress(&self, vec: &[u8]) -> Result<DecompressResult<f32>, ChimpError> {
+        let (header, payload) = ChimpHeader::split(vec, CodecVariant::Chimp32)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        self.decompress_impl(payload)
     }
 }
 
 #[derive(Debug, Default, Clone)]
-pub struct DebugBatchDecompressorCpu {}
+pub struct DebugBatchDecompressorCpu {
+    config: ChimpConfig,
+}
+
+impl DebugBatchDecompressorCpu {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+}
 #[async_trait]
 impl Decompressor<f32> for DebugBatchDecompressorCpu {
     #[allow(unused)]
     async fn decompress(
         &self,
-        compressed_bytes_vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f32>, DecompressionError> {
+        compressed_bytes_vec: &[u8],
+    ) -> Result<DecompressResult<f32>, ChimpError> {
         let mut current_index = 0usize;
         let uncompressed_values;
         let mut total_millis = 0;
+        let (header, compressed_bytes_vec) =
+            ChimpHeader::split(compressed_bytes_vec, CodecVariant::Chimp32)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
         time_it!(
             {
                 let mut vec_window = Vec::new();
                 let mut total_uncompressed_values = 0;
                 let mut input_indexes = Vec::new();
+                let mut value_counts = Vec::new();
                 while current_index < compressed_bytes_vec.len() {
-                    let buffer_value_count = u32::from_be_bytes(
+                    let buffer_value_count = u32::from_le_bytes(
                         compressed_bytes_vec[current_index..current_index + size_of::<u32>()]
                             .try_into()
                             .unwrap(),
@@ -201,7 +239,7 @@ impl Decompressor<f32> for DebugBatchDecompressorCpu {
                         + 1;
                     current_index += size_of::<u32>();
 
-                    let size_in_bytes = u32::from_be_bytes(
+                    let size_in_bytes = u32::from_le_bytes(
                         compressed_bytes_vec[current_index..current_index + size_of::<u32>()]
                             .try_into()
                             .unwrap(),
@@ -219,10 +257,11 @@ impl Decompressor<f32> for DebugBatchDecompressorCpu {
                     while let Some((first_four_bytes, rest)) = byte_window.split_at_checked(4) {
                         byte_window = rest;
                         //parse u32 from groups of 4 bytes
-                        let value_u32 = u32::from_be_bytes(first_four_bytes.try_into().unwrap());
+                        let value_u32 = u32::from_le_bytes(first_four_bytes.try_into().unwrap());
                         vec_window.push(value_u32);
                     }
                     input_indexes.push(vec_window.len() as u32);
+                    value_counts.push(buffer_value_count as u32);
                     current_index += size_in_bytes as usize;
                     total_uncompressed_values += buffer_value_count
                 }
@@ -232,7 +271,8 @@ impl Decompressor<f32> for DebugBatchDecompressorCpu {
                     .decompress_block(
                         vec_window.as_slice(),
                         input_indexes.as_slice(),
-                        ChimpBufferInfo::get().buffer_size(),
+                        value_counts.as_slice(),
+                        self.config.buffer_size(),
                     )
                     .await?;
 
@@ -253,8 +293,9 @@ impl DebugBatchDecompressorCpu {
         &self,
         compressed_value_slice: &[u32],
         input_indexes: &[u32],
+        value_counts: &[u32],
         buffer_value_count: usize,
-    ) -> Result<Vec<f32>, DecompressionError> {
+    ) -> Result<Vec<f32>, ChimpError> {
         // Number of buffers that fit into the GPU
         let workgroup_count = input_indexes.len() - 1;
 
@@ -288,9 +329,8 @@ impl DebugBatchDecompressorCpu {
                 iteration_compressed_values.len() * size_of::<u8>()
             );
 
-            let out_buffer_size = (iteration_input_indexes.len() - 1)
-                * ChimpBufferInfo::get().buffer_size()
-                * size_of::<u32>();
+            let out_buffer_size =
+                (iteration_input_indexes.len() - 1) * self.config.buffer_size() * size_of::<u32>();
             info!(
                 "The uncompressed output values buffer size in bytes: {}",
                 out_buffer_size
@@ -301,22 +341,22 @@ impl DebugBatchDecompressorCpu {
 
             let workgroups = iteration_input_indexes.len() - 1;
 
-            let output = vec![0f32; workgroups * ChimpBufferInfo::get().buffer_size()];
-            let mut writer = CPUWrite::new(
-                iteration_compressed_values,
-                output,
-                iteration_input_indexes,
-                buffer_value_count as u32,
-            );
+            let output = vec![0f32; workgroups * self.config.buffer_size()];
+            let mut writer =
+                CPUWrite::new(iteration_compressed_values, output, iteration_input_indexes);
             for workgroup in 0..workgroups {
                 let index = writer.input_index[workgroup];
 
-                writer.write(index, (workgroup * buffer_value_count).try_into().unwrap())
+                writer.write(
+                    index,
+                    (workgroup * buffer_value_count).try_into().unwrap(),
+                    value_counts[iteration * workgroup_count + workgroup],
+                )
             }
             result.extend(writer.output());
         }
         //info!("Output result size: {}", result.len());
-        if trace_steps().contains(&Step::Decompress) {
+        if self.config.traces(&Step::Decompress) {
             let trace_path = Step::Decompress.get_trace_file();
             let mut trace_output = String::new();
 
@@ -324,8 +364,7 @@ impl DebugBatchDecompressorCpu {
                 .iter()
                 .for_each(|it| trace_output.push_str(it.to_string().as_str()));
 
-            fs::write(&trace_path, trace_output)
-                .map_err(|it| DecompressionError::FromBaseAnyhowError(anyhow::anyhow!(it)))?;
+            fs::write(&trace_path, trace_output)?;
         }
         Ok(result)
     }
@@ -335,14 +374,15 @@ struct CPUWrite {
     input: Vec<u32>,
     output: Vec<f32>,
     input_index: Vec<u32>,
-    size: u32,
 }
 
 impl CPUWrite {
     fn output(self) -> Vec<f32> {
         self.output
     }
-    pub(crate) fn write(&mut self, input_index: u32, output_index: u32) {
+    /// Decode the [value_count] values of the block at [input_index] into the output from
+    /// [output_index] on
+    pub(crate) fn write(&mut self, input_index: u32, output_index: u32, value_count: u32) {
         let current_index = input_index + 1u32;
         //Current Remaining offset
         let current_offset = 0u32;
@@ -363,14 +403,7 @@ impl CPUWrite {
         current_info.current_offset += 32u32;
         // current_info.current_index+=1;
         let mut value;
-        for _i in 1..self.size {
-            // if we have not finished reading values from the uncompressed buffers
-            if current_info.current_index >= (self.input.len() as u32 - 1u32)
-                && current_info.current_offset - 1u32 == 0
-            {
-                break;
-            }
-
+        for _i in 1..value_count {
             //if current bit value==1
             if self.get_bit_at_index(current_info.current_index, current_info.current_offset) == 1 {
                 current_info = self.decr_counter_capped_at_32(current_info, 1u32);
@@ -457,12 +490,11 @@ impl CPUWrite {
 }
 
 impl CPUWrite {
-    pub fn new(input: Vec<u32>, output: Vec<f32>, input_index: Vec<u32>, size: u32) -> Self {
+    pub fn new(input: Vec<u32>, output: Vec<f32>, input_index: Vec<u32>) -> Self {
         Self {
             input,
             output,
             input_index,
-            size,
         }
     }
     fn get_bit_at_index(&self, array_index: u32, position: u32) -> u32 {
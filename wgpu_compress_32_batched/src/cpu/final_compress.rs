@@ -0,0 +1,115 @@
+use crate::final_compress::FinalCompress;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{MaxGroupGnostic, Step};
+use compress_utils::types::{ChimpOutput, S};
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{step, BufferWrapper};
+use std::fs;
+use std::ops::Div;
+
+/// Host implementation of the `chimp_compress.wgsl` and `initialize_first_per_buffer.wgsl` stages
+#[derive(Debug, Default)]
+pub struct CPUFinalCompress {
+    config: ChimpConfig,
+}
+
+impl CPUFinalCompress {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl CPUFinalCompress {
+    pub(crate) fn compress(v: f32, s: S, v_prev: f32, s_prev: S) -> ChimpOutput {
+        let xorred = v_prev.to_bits() ^ v.to_bits();
+        let (value, bit_count): (u64, u32) = if s.equal == 1 {
+            // case 1: xor_value=0, code 00
+            (0, 2)
+        } else if s.tail > 6 {
+            // case 2: tail>6 && xor_value!=0, code 01
+            let center_bits = (32 - s.head - s.tail) as u32;
+            let mut value = 1u64;
+            value = (value << 5) + (s.head as u64 & 0x1f);
+            value = (value << 5) + (center_bits as u64 & 0x1f);
+            value = (value << center_bits) + (xorred >> s.tail) as u64;
+            (value, 2 + 5 + 5 + center_bits)
+        } else if s.head == s_prev.head {
+            // case 3: tail<=6 and lead=pr_lead, code 10
+            let significant_bits = (32 - s.head) as u32;
+            (
+                (2u64 << significant_bits) + xorred as u64,
+                2 + significant_bits,
+            )
+        } else {
+            // case 4: tail<=6 and lead!=pr_lead, code 11
+            let significant_bits = (32 - s.head) as u32;
+            let value = (3u64 << 5) + (s.head as u64 & 0x1f);
+            (
+                (value << significant_bits) + xorred as u64,
+                2 + 5 + significant_bits,
+            )
+        };
+        ChimpOutput {
+            upper_bits: (value >> 32) as u32,
+            lower_bits: value as u32,
+            bit_count,
+        }
+    }
+}
+
+impl MaxGroupGnostic for CPUFinalCompress {
+    fn get_max_number_of_groups(&self, content_len: usize) -> usize {
+        content_len.div(self.config.buffer_size())
+    }
+}
+
+#[async_trait]
+impl FinalCompress for CPUFinalCompress {
+    async fn final_compress(
+        &self,
+        buffers: &mut RunBuffers,
+        _skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        let output = {
+            let input = buffers.input_buffer().host_slice::<f32>();
+            let s_values = buffers.s_buffer().host_slice::<S>();
+            let mut output = vec![
+                ChimpOutput {
+                    upper_bits: 0,
+                    lower_bits: 0,
+                    bit_count: 0,
+                };
+                input.len()
+            ];
+            for index in 0..input.len() - 1 {
+                output[index + 1] = Self::compress(
+                    input[index + 1],
+                    s_values[index + 1],
+                    input[index],
+                    s_values[index],
+                );
+            }
+            // The first value of each buffer is written as is
+            for index in (0..input.len() - 1).step_by(self.config.buffer_size()) {
+                output[index] = ChimpOutput {
+                    upper_bits: 0,
+                    lower_bits: input[index].to_bits(),
+                    bit_count: 32,
+                };
+            }
+            output
+        };
+        step!(self.config, Step::Compress, {
+            output
+                .iter()
+                .enumerate()
+                .map(|it| format!("{}:{}\n", it.0, it.1))
+        });
+        buffers.set_compressed_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &output,
+        )));
+        Ok(())
+    }
+}
@@ -1,34 +1,75 @@
+use crate::cpu::bits::extract_bits;
+use crate::finalize::Finalize;
+use async_trait::async_trait;
 #[allow(unused)]
 use bytemuck::Contiguous;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{CompressResult, Step};
+use compress_utils::step;
 use compress_utils::types::ChimpOutput;
+use compress_utils::wgpu_utils::{read_buffer, RunBuffers};
+use itertools::Itertools;
 use std::cmp::{max, min};
+use std::fs;
+use std::sync::Arc;
 
+/// Host implementation of the `chimp_finalize_compress.wgsl` stage.
+///
+/// Without a context it only works on host buffers, with one it can also finalize the
+/// buffers produced by the gpu stages.
 #[derive(Debug, Default)]
-pub struct CPUImpl {}
+pub struct CPUImpl {
+    context: Option<Arc<Context>>,
+    config: ChimpConfig,
+}
+
+impl CPUImpl {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self {
+            context: None,
+            config,
+        }
+    }
+
+    pub fn with_context(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self {
+            context: Some(context),
+            config,
+        }
+    }
+}
+
 struct CPUImplHelper {
     size: u32,
-    last_size: Vec<u32>,
+    last_size: u32,
     out: Vec<u32>,
     chimp_input: Vec<ChimpOutput>,
 }
 
 impl CPUImplHelper {
-    // #[allow(unused_variables)]
-
-    /// Write to the output array starting from an offset set as dictated by a previous call,
-    /// Starts
-    fn write(&mut self, out_offset: usize, in_offset: usize) -> u32 {
-        let mut current_i = (out_offset as u32) + 1u32;
+    /// Write the buffer starting at [in_offset] to the output array starting from [out_offset],
+    /// as dictated by the calculated indexes. The buffer starts with the number of values minus
+    /// one, the size of the rest of the buffer in bytes and the first value uncompressed.
+    fn write(
+        &mut self,
+        out_offset: usize,
+        in_offset: usize,
+        is_last: bool,
+        next_offset: u32,
+    ) -> u32 {
+        let mut current_i = (out_offset as u32) + 3u32;
         let mut current_i_bits_left = 32u32;
 
         let input_limit = min(self.chimp_input.len(), in_offset + (self.size as usize));
 
-        self.out[out_offset] = self.chimp_input[in_offset].lower_bits();
+        self.out[out_offset] = if is_last { self.last_size } else { self.size } - 1;
+        self.out[out_offset + 1] = (next_offset - out_offset as u32 - 2) * 4;
+        self.out[out_offset + 2] = self.chimp_input[in_offset].lower_bits();
         for i in 1 + in_offset..input_limit {
             let chimp: ChimpOutput = self.chimp_input[i];
             let overflow_bits = (chimp.bit_count() as i32) - 32;
-            // let current_str1 = Self::format_u32(self.out[current_i as usize]);
-            // let current_str2 = Self::format_u32(self.out[max(current_i - 1, 0) as usize]);
 
             if overflow_bits > 0 {
                 let fitting = get_fitting(overflow_bits as u32, current_i_bits_left);
@@ -39,22 +80,14 @@ impl CPUImplHelper {
                     (overflow_bits - fitting as i32) as u32,
                     fitting,
                 );
-                // let current_str3 = Self::format_u32(bits_to_add);
-                // let current_str4 = Self::format_u32(chimp.lower_bits());
                 self.out[current_i as usize] = insert_bits(
                     self.out[current_i as usize],
                     bits_to_add,
                     insert_index,
                     fitting,
                 );
-                // let current_str1 = Self::format_u32(self.out[current_i as usize]);
-                // let current_str2 = Self::format_u32(self.out[max(current_i - 1, 0) as usize]);
 
                 if current_i_bits_left <= fitting {
-                    // println!(
-                    //     "current_i:{} output:{:032b}",
-                    //     current_i, self.out[current_i as usize]
-                    // );
                     current_i += 1;
                     current_i_bits_left = 32u32
                 } else {
@@ -64,22 +97,14 @@ impl CPUImplHelper {
                     let fitting = get_fitting(remaining, current_i_bits_left);
                     let insert_index = get_insert_index(remaining, current_i_bits_left);
                     let bits_to_add = extract_bits(chimp.upper_bits(), 0, fitting);
-                    // let current_str3 = Self::format_u32(bits_to_add);
-                    // let current_str4 = Self::format_u32(chimp.lower_bits());
                     self.out[current_i as usize] = insert_bits(
                         self.out[current_i as usize],
                         bits_to_add,
                         insert_index,
                         fitting,
                     );
-                    // let current_str1 = Self::format_u32(self.out[current_i as usize]);
-                    // let current_str2 = Self::format_u32(self.out[max(current_i - 1, 0) as usize]);
 
                     if current_i_bits_left <= fitting {
-                        // println!(
-                        //     "current_i:{} output:{:032b}",
-                        //     current_i, self.out[current_i as usize]
-                        // );
                         current_i += 1;
                         current_i_bits_left = 32u32
                     } else {
@@ -93,8 +118,6 @@ impl CPUImplHelper {
             let insert_index = get_insert_index(rest_bits, current_i_bits_left);
             let remaining = get_remaining(rest_bits, current_i_bits_left);
             let bits_to_add = extract_bits(chimp.lower_bits(), rest_bits - fitting, fitting);
-            // let current_str3 = Self::format_u32(bits_to_add);
-            // let current_str4 = Self::format_u32(chimp.lower_bits());
 
             self.out[current_i as usize] = insert_bits(
                 self.out[current_i as usize],
@@ -102,14 +125,8 @@ impl CPUImplHelper {
                 insert_index,
                 fitting,
             );
-            // let current_str1 = Self::format_u32(self.out[current_i as usize]);
-            // let current_str2 = Self::format_u32(self.out[max(current_i - 1, 0) as usize]);
 
             if current_i_bits_left <= fitting {
-                // println!(
-                //     "current_i:{} output:{:032b}",
-                //     current_i, self.out[current_i as usize]
-                // );
                 current_i += 1;
                 current_i_bits_left = 32u32
             } else {
@@ -119,22 +136,14 @@ impl CPUImplHelper {
                 let fitting = get_fitting(remaining, current_i_bits_left);
                 let insert_index = get_insert_index(remaining, current_i_bits_left);
                 let bits_to_add = extract_bits(chimp.lower_bits(), 0, fitting);
-                // let current_str3 = Self::format_u32(bits_to_add);
-                // let current_str4 = Self::format_u32(chimp.lower_bits());
                 self.out[current_i as usize] = insert_bits(
                     self.out[current_i as usize],
                     bits_to_add,
                     insert_index,
                     fitting,
                 );
-                // let current_str1 = Self::format_u32(self.out[current_i as usize]);
-                // let current_str2 = Self::format_u32(self.out[max(current_i - 1, 0) as usize]);
 
                 if current_i_bits_left <= fitting {
-                    // println!(
-                    //     "current_i:{} output:{:032b}",
-                    //     current_i, self.out[current_i as usize]
-                    // );
                     current_i += 1;
                     current_i_bits_left = 32u32
                 } else {
@@ -142,96 +151,80 @@ impl CPUImplHelper {
                 }
             }
         }
-        // println!(
-        //     "current_i:{} output:{:032b}",
-        //     current_i, self.out[current_i as usize]
-        // );
         current_i
     }
 }
-// #[async_trait]
-// impl Finalize for CPUImpl {
-//     async fn finalize(
-//         &self,
-//         chimp_output: &mut Vec<ChimpOutput>,
-//         padding: usize,
-//         _indexes: Vec<u32>,
-//     ) -> anyhow::Result<CompressResult> {
-//         //The number of iterations
-//         let workgroup_count = chimp_output.len() / ChimpBufferInfo::get().buffer_size();
-//
-//         let actual_output = chimp_output[0..chimp_output.len() - padding].to_vec();
-//
-//         let mut metadata_size_in_bytes = 0;
-//
-//         // The output needs at worst  twice the number of 32-bit numbers to be coded along with one
-//         // space for the size of the workgroup in bytes
-//         // We come across this instance when each consecutive number in the series is too different from the other.
-//         let out = vec![0; workgroup_count + (2 * actual_output.len())];
-//
-//         // The index of the final usable u32 in the out vector
-//         let last_size = vec![0; workgroup_count];
-//
-//         let mut helper = CPUImplHelper {
-//             chimp_input: actual_output.to_owned(),
-//             size: ChimpBufferInfo::get().buffer_size() as u32,
-//             out,
-//             last_size,
-//         };
-//
-//         //for each workgroup write the bytes concec
-//         let mut current_index = 0u32;
-//         for i in 0..workgroup_count {
-//             current_index = helper.write(
-//                 current_index as usize,
-//                 i * ChimpBufferInfo::get().buffer_size(),
-//             );
-//             helper.last_size[i] = current_index;
-//             current_index += 1;
-//         }
-//         let mut final_output = Vec::new();
-//
-//         for i in 0..workgroup_count {
-//             let start_index = if i == 0 {
-//                 0
-//             } else {
-//                 helper.last_size[i - 1] + 1
-//             };
-//             let final_iter =
-//                 helper.out[start_index as usize..=(helper.last_size[i] as usize)].to_vec();
-//             let final_byte_vec = final_iter
-//                 .iter()
-//                 .flat_map(|it| it.to_be_bytes())
-//                 .collect_vec();
-//
-//             let batch_size = if i == workgroup_count - 1
-//                 && helper.chimp_input.len() % ChimpBufferInfo::get().buffer_size() != 0
-//             {
-//                 ((helper.chimp_input.len() % ChimpBufferInfo::get().buffer_size()) - 1) as u32
-//             } else {
-//                 (ChimpBufferInfo::get().buffer_size() - 1) as u32
-//             };
-//
-//             metadata_size_in_bytes += 2 * size_of::<u32>();
-//             final_output.extend(batch_size.to_be_bytes());
-//             final_output.extend((final_byte_vec.len() as u32).to_be_bytes());
-//             final_output.extend(&final_byte_vec);
-//         }
-//
-//         if trace_steps().contains(&Step::Finalize) {
-//             let trace_path = Step::Finalize.get_trace_file();
-//             let mut trace_output = String::new();
-//
-//             final_output
-//                 .iter()
-//                 .enumerate()
-//                 .for_each(|it| trace_output.push_str(&format!("{}:{:08b}\n", it.0, it.1)));
-//
-//             fs::write(&trace_path, trace_output)?;
-//         }
-//         Ok(CompressResult(final_output, metadata_size_in_bytes))
-//     }
-// }
+
+#[async_trait]
+impl Finalize for CPUImpl {
+    async fn finalize(
+        &self,
+        buffers: &mut RunBuffers,
+        padding: usize,
+        _skip_time: &mut u128,
+    ) -> Result<CompressResult, ChimpError> {
+        self.finalize_buffers(buffers, padding).await
+    }
+}
+
+impl CPUImpl {
+    /// The body of [Finalize::finalize], public so the N variants can reuse it for their own
+    /// stage trait
+    pub async fn finalize_buffers(
+        &self,
+        buffers: &RunBuffers,
+        padding: usize,
+    ) -> Result<CompressResult, ChimpError> {
+        let context = self.context.as_deref();
+        let indexes = read_buffer::<u32>(context, buffers.index_buffer()).await?;
+        let chimp_input = read_buffer::<ChimpOutput>(context, buffers.compressed_buffer()).await?;
+
+        let chimp_input_len = chimp_input.len() - 1;
+        let metadata_size_in_bytes = (indexes.len() - 1) * 8;
+        let chimp_input_length_no_padding = chimp_input_len - padding;
+        let size = self.config.buffer_size();
+        let last_size = if chimp_input_length_no_padding.is_multiple_of(size) {
+            size
+        } else {
+            chimp_input_length_no_padding % size
+        } as u32;
+
+        let last_index = *indexes.last().unwrap();
+        let mut helper = CPUImplHelper {
+            size: size as u32,
+            last_size,
+            out: vec![0u32; last_index as usize],
+            chimp_input,
+        };
+
+        //for each workgroup write the bytes at the offset calculated by the indexes
+        let workgroup_count = chimp_input_len / size;
+        for workgroup in 0..workgroup_count {
+            helper.write(
+                indexes[workgroup] as usize,
+                workgroup * size,
+                indexes[workgroup + 1] == last_index,
+                indexes[workgroup + 1],
+            );
+        }
+        let final_vec = helper
+            .out
+            .iter()
+            .flat_map(|it| it.to_le_bytes())
+            .collect_vec();
+
+        step!(self.config, Step::Finalize, {
+            final_vec
+                .iter()
+                .chunks(4)
+                .into_iter()
+                .map(|chunk| chunk.map(|it| format!("{:08b}", it)).join(" ") + "\n")
+                .collect_vec()
+                .into_iter()
+        });
+        Ok(CompressResult(final_vec, metadata_size_in_bytes, 0))
+    }
+}
 
 fn get_fitting(bits_rest_to_write: u32, writeable_output_remaining: u32) -> u32 {
     min(bits_rest_to_write, writeable_output_remaining)
@@ -276,33 +269,10 @@ fn insert_bits(input_bits: u32, new_bits: u32, start_index: u32, bit_count: u32)
     output_bits
 }
 
-pub fn extract_bits(input_bits: u32, start_index: u32, bit_count: u32) -> u32 {
-    let mut input_bits = input_bits;
-    // assert!(start_index + bit_count > 32);
-    let end_index = min(start_index + bit_count, 32);
-    let low_bound = if start_index >= 32 {
-        0
-    } else {
-        u32::MAX_VALUE << start_index
-    };
-    let high_bound = if end_index == 0 {
-        0
-    } else {
-        u32::MAX_VALUE >> (32 - end_index)
-    };
-
-    input_bits &= low_bound;
-    input_bits &= high_bound;
-    if start_index >= 32 {
-        0
-    } else {
-        input_bits >> start_index
-    }
-}
-
 #[cfg(test)]
 mod temp_test {
-    use crate::cpu::finalize::{extract_bits, insert_bits};
+    use crate::cpu::bits::extract_bits;
+    use crate::cpu::finalize::insert_bits;
 
     #[test]
     fn test1() {
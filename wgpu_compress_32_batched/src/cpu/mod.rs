@@ -1,2 +1,10 @@
+pub mod bits;
+#[cfg(feature = "gpu")]
+pub mod calculate_indexes;
+#[cfg(feature = "gpu")]
+pub mod compute_s;
 pub mod decompressor;
+#[cfg(feature = "gpu")]
+pub mod final_compress;
+#[cfg(feature = "gpu")]
 pub mod finalize;
@@ -1,9 +1,12 @@
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::cpu_compress::{DecompressionError, Decompressor};
-use compress_utils::general_utils::{
-    trace_steps, ChimpBufferInfo, DecompressResult, MaxGroupGnostic, Step,
-};
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant};
+use compress_utils::general_utils::{cast_or_copy, DecompressResult, MaxGroupGnostic, Step};
+use compress_utils::shader_template::ShaderTemplate;
 use compress_utils::{
     execute_compute_shader, step, time_it, wgpu_utils, BufferWrapper, WgpuGroupId,
 };
@@ -18,21 +21,43 @@ use wgpu_types::BufferAddress;
 
 #[async_trait]
 impl Decompressor<f32> for BatchedGPUDecompressor {
-    #[allow(unused)]
     async fn decompress(
         &self,
-        compressed_bytes_vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f32>, DecompressionError> {
+        compressed_bytes_vec: &[u8],
+    ) -> Result<DecompressResult<f32>, ChimpError> {
+        self.decompress_stream(
+            compressed_bytes_vec,
+            CodecVariant::Chimp32,
+            shaders::DECOMPRESS,
+        )
+        .await
+    }
+}
+
+impl BatchedGPUDecompressor {
+    /// Decompress a stream of [codec], whose blocks are in the 32 bit word layout, with the
+    /// one thread per block [shader]
+    #[allow(unused)]
+    pub(crate) async fn decompress_stream(
+        &self,
+        compressed_bytes_vec: &[u8],
+        codec: CodecVariant,
+        shader: ShaderTemplate,
+    ) -> Result<DecompressResult<f32>, ChimpError> {
         let mut current_index = 0usize;
         let mut uncompressed_values = Vec::new();
         let mut total_millis = 0;
         let mut skip_time = 0;
+        let (header, compressed_bytes_vec) = ChimpHeader::split(compressed_bytes_vec, codec)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        let compressed_words = cast_or_copy::<u32>(compressed_bytes_vec);
         time_it!(
             {
-                let compressed_bytes_vec: &[u32] = bytemuck::cast_slice(&compressed_bytes_vec);
+                let compressed_bytes_vec: &[u32] = &compressed_words;
                 let mut vec_window = Vec::new();
                 let mut total_uncompressed_values = 0;
                 let mut input_indexes = Vec::new();
+                let mut value_counts = Vec::new();
                 while current_index < compressed_bytes_vec.len() {
                     while current_index < compressed_bytes_vec.len() {
                         let old_index = current_index;
@@ -49,7 +74,7 @@ impl Decompressor<f32> for BatchedGPUDecompressor {
                             break;
                         }
 
-                        if (input_indexes.len() + 1) * ChimpBufferInfo::get().buffer_size() * 4
+                        if (input_indexes.len() + 1) * self.config.buffer_size() * 4
                             >= self.context.get_max_storage_buffer_size()
                         {
                             current_index = old_index;
@@ -62,6 +87,7 @@ impl Decompressor<f32> for BatchedGPUDecompressor {
                         );
 
                         input_indexes.push(vec_window.len() as u32);
+                        value_counts.push(buffer_value_count);
                         current_index += size as usize;
                         total_uncompressed_values += buffer_value_count as usize
                     }
@@ -71,7 +97,9 @@ impl Decompressor<f32> for BatchedGPUDecompressor {
                         .decompress_block(
                             vec_window.as_slice(),
                             input_indexes.as_slice(),
-                            ChimpBufferInfo::get().buffer_size(),
+                            value_counts.as_slice(),
+                            self.config.buffer_size(),
+                            shader,
                             &mut skip_time,
                         )
                         .await?;
@@ -80,12 +108,13 @@ impl Decompressor<f32> for BatchedGPUDecompressor {
                     vec_window.clear();
                     total_uncompressed_values = 0;
                     input_indexes.clear();
+                    value_counts.clear();
                 }
             },
             total_millis,
             "decompression"
         );
-        step!(Step::Decompress, {
+        step!(self.config, Step::Decompress, {
             uncompressed_values
                 .iter()
                 .map(|it: &f32| it.to_string())
@@ -97,17 +126,17 @@ impl Decompressor<f32> for BatchedGPUDecompressor {
 
 pub struct BatchedGPUDecompressor {
     context: Arc<Context>,
+    config: ChimpConfig,
 }
 impl MaxGroupGnostic for BatchedGPUDecompressor {
     fn get_max_number_of_groups(&self, _content_len: usize) -> usize {
-        self.context().get_max_workgroup_size()
+        self.config.max_workgroup_size(self.context())
     }
 }
-impl Default for BatchedGPUDecompressor {
-    fn default() -> Self {
-        Self {
-            context: Arc::new(Context::initialize_default_adapter().block_on().unwrap()),
-        }
+impl BatchedGPUDecompressor {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(Context::initialize_default_adapter().block_on()?))
     }
 }
 impl BatchedGPUDecompressor {
@@ -115,9 +144,11 @@ impl BatchedGPUDecompressor {
         &self,
         compressed_value_slice: &[u32],
         input_indexes: &[u32],
+        value_counts: &[u32],
         buffer_value_count: usize,
+        shader: ShaderTemplate,
         skip_time: &mut u128,
-    ) -> Result<Vec<f32>, DecompressionError> {
+    ) -> Result<Vec<f32>, ChimpError> {
         //how many buffers fit into the GPU
         let workgroup_count = self.get_max_number_of_groups(input_indexes.len()) * 256;
 
@@ -132,14 +163,20 @@ impl BatchedGPUDecompressor {
             Some("Storage input Buffer"),
         );
         //info!("Total output values: {}", buffer_value_count);
-        let in_size = BufferWrapper::storage_with_content(
+        let blocks = input_indexes
+            .iter()
+            .zip(value_counts)
+            .flat_map(|(start, count)| [*start, *count])
+            .collect::<Vec<u32>>();
+        let block_storage = BufferWrapper::storage_with_content(
             self.device(),
-            bytemuck::cast_slice(&input_indexes),
+            bytemuck::cast_slice(&blocks),
             WgpuGroupId::new(0, 3),
-            Some("Total bytes input"),
+            Some("Block starts and value counts"),
         );
         let out_buffer_size =
-            (input_indexes.len() - 1) * ChimpBufferInfo::get().buffer_size() * size_of::<f32>();
+            (input_indexes.len() - 1) * self.config.buffer_size() * size_of::<f32>();
+        self.context().check_buffer_size(out_buffer_size)?;
         let out_staging = BufferWrapper::stage_with_size(
             self.device(),
             out_buffer_size as BufferAddress,
@@ -153,11 +190,14 @@ impl BatchedGPUDecompressor {
         );
         *skip_time += instant.elapsed().as_millis();
 
-        let workgroup_count = min(workgroup_count, self.context.get_max_workgroup_size());
+        let workgroup_count = min(
+            workgroup_count,
+            self.config.max_workgroup_size(&self.context),
+        );
         for iteration in 0..iterator_count {
             // let offset_decl = format!(
             //     "let workgroup_offset={}u;",
-            //     iteration * self.context.get_max_workgroup_size()
+            //     iteration * self.config.max_workgroup_size(&self.context)
             // );
             // let shader_code = include_str!("shaders/decompress.wgsl")
             //     .replace("//@workgroup_offset", &offset_decl);
@@ -187,22 +227,14 @@ impl BatchedGPUDecompressor {
                 WgpuGroupId::new(0, 2),
                 Some("Total input values"),
             );
-
-            let input_size_uniform = BufferWrapper::uniform_with_content(
-                self.device(),
-                bytemuck::bytes_of(&compressed_value_slice.len()),
-                WgpuGroupId::new(0, 4),
-                Some("Total input buffer length"),
-            );
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                iteration * self.context.get_max_workgroup_size()
-            );
-            let total_threads = format!("let total_threads={}u;", input_indexes.len());
-            let shader_code = include_str!("shaders/decompress.wgsl")
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@total_threads", &total_threads)
-                .to_string();
+            let shader_code = shader
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (iteration * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .bind("total_threads", value_counts.len() as u32)
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &shader_code,
@@ -211,8 +243,7 @@ impl BatchedGPUDecompressor {
                     &input_storage_buffer,
                     &out_staging,
                     &size_uniform,
-                    &in_size,
-                    &input_size_uniform
+                    &block_storage
                 ],
                 iteration_input_indexes.div_ceil(256),
                 Some("decompress pass")
@@ -235,9 +266,19 @@ impl BatchedGPUDecompressor {
     pub fn new(context_builder: impl Into<Arc<Context>>) -> Self {
         Self {
             context: context_builder.into(),
+            config: ChimpConfig::default(),
         }
     }
 
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
     pub fn context(&self) -> &Context {
         &self.context
     }
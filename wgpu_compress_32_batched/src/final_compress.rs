@@ -1,9 +1,12 @@
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo, MaxGroupGnostic, Step};
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{MaxGroupGnostic, Step};
 use compress_utils::types::ChimpOutput;
 use compress_utils::wgpu_utils::RunBuffers;
-use compress_utils::{execute_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
+use compress_utils::{record_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
 use std::cmp::max;
 use std::fs;
 use std::ops::Div;
@@ -17,18 +20,20 @@ pub trait FinalCompress: MaxGroupGnostic {
         &self,
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> anyhow::Result<()>;
+    ) -> Result<(), ChimpError>;
 }
 
 pub struct FinalCompressImpl {
     context: Arc<Context>,
+    config: ChimpConfig,
     // debug: bool,
 }
 
 impl FinalCompressImpl {
-    pub fn new(context: Arc<Context>, _debug: bool) -> Self {
+    pub fn new(context: Arc<Context>, config: ChimpConfig, _debug: bool) -> Self {
         Self {
             context,
+            config,
             // debug
         }
     }
@@ -40,17 +45,19 @@ impl FinalCompressImpl {
 
 impl MaxGroupGnostic for FinalCompressImpl {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 
-#[async_trait]
-impl FinalCompress for FinalCompressImpl {
-    async fn final_compress(
+impl FinalCompressImpl {
+    /// Record the compression of the S values of [buffers] into [encoder] without submitting
+    /// it, the compressed buffer is set on [buffers] for the following stages
+    pub fn record_final_compress(
         &self,
+        encoder: &mut wgpu::CommandEncoder,
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> anyhow::Result<()> {
+    ) -> Result<(), ChimpError> {
         let size_of_output = size_of::<ChimpOutput>();
         let input_length = buffers.input_buffer().size() / size_of::<f32>();
 
@@ -59,8 +66,8 @@ impl FinalCompress for FinalCompressImpl {
         let workgroup_count = self.get_max_number_of_groups(input_length);
 
         let instant = Instant::now();
-        let mut output_storage_buffer = BufferWrapper::storage_with_size(
-            self.context().device(),
+        let mut output_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
             output_buffer_size,
             WgpuGroupId::new(0, 2),
             Some("Storage Output Buffer"),
@@ -79,23 +86,19 @@ impl FinalCompress for FinalCompressImpl {
                 .chunks_uniform_mut()
                 .with_binding(WgpuGroupId::new(0, 3));
         }
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let last_pass = format!(
-                "let last_pass={}u;",
-                if i == iterations - 1 { 1 } else { 0 }
-            );
-            let temp = include_str!("shaders/chimp_compress.wgsl")
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@last_pass", &last_pass)
-                .to_string();
-            execute_compute_shader!(
+            let temp = shaders::CHIMP_COMPRESS
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .finish()?;
+            record_compute_shader!(
                 self.context(),
+                encoder,
                 &temp,
                 vec![
                     buffers.s_buffer(),
@@ -106,7 +109,7 @@ impl FinalCompress for FinalCompressImpl {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("compress pass")
             );
@@ -122,23 +125,19 @@ impl FinalCompress for FinalCompressImpl {
                 .input_buffer_mut()
                 .with_binding(WgpuGroupId::new(0, 1));
         }
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let last_pass = format!(
-                "let last_pass={}u;",
-                if i == iterations - 1 { 1 } else { 0 }
-            );
-            let temp = include_str!("shaders/initialize_first_per_buffer.wgsl")
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@last_pass", &last_pass)
-                .to_string();
-            execute_compute_shader!(
+            let temp = shaders::INITIALIZE_FIRST_PER_BUFFER
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .finish()?;
+            record_compute_shader!(
                 self.context(),
+                encoder,
                 &temp,
                 vec![
                     output_storage_buffer.with_binding(WgpuGroupId::new(0, 0)),
@@ -148,16 +147,23 @@ impl FinalCompress for FinalCompressImpl {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("initialize pass")
             );
         }
 
         buffers.set_compressed_buffer(output_storage_buffer);
-        if trace_steps().contains(&Step::Compress) {
-            let output_staging_buffer = BufferWrapper::stage_with_size(
-                self.context().device(),
+        Ok(())
+    }
+
+    /// Write the compressed values of [buffers] to the trace file when the configuration
+    /// traces [Step::Compress], once the work recorded by
+    /// [FinalCompressImpl::record_final_compress] is submitted
+    pub async fn trace(&self, buffers: &RunBuffers) -> Result<(), ChimpError> {
+        if self.config.traces(&Step::Compress) {
+            let output_staging_buffer = BufferWrapper::pooled_stage_with_size(
+                self.context(),
                 buffers.compressed_buffer().size() as BufferAddress,
                 None,
             );
@@ -181,3 +187,21 @@ impl FinalCompress for FinalCompressImpl {
         Ok(())
     }
 }
+
+#[async_trait]
+impl FinalCompress for FinalCompressImpl {
+    async fn final_compress(
+        &self,
+        buffers: &mut RunBuffers,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        let mut encoder = self
+            .context()
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.record_final_compress(&mut encoder, buffers, skip_time)?;
+        self.context().queue().submit(Some(encoder.finish()));
+        buffers.release_submitted();
+        self.trace(buffers).await
+    }
+}
@@ -1,11 +1,13 @@
-use anyhow::Result;
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo, CompressResult, Step};
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{CompressResult, Step};
 use compress_utils::types::ChimpOutput;
 use compress_utils::wgpu_utils::RunBuffers;
 use compress_utils::{
-    execute_compute_shader, general_utils, step, wgpu_utils, BufferWrapper, WgpuGroupId,
+    general_utils, record_compute_shader, step, wgpu_utils, BufferWrapper, WgpuGroupId,
 };
 use itertools::Itertools;
 use std::cmp::max;
@@ -22,17 +24,18 @@ pub trait Finalize {
         buffers: &mut RunBuffers,
         padding: usize,
         skip_time: &mut u128,
-    ) -> Result<general_utils::CompressResult>;
+    ) -> Result<general_utils::CompressResult, ChimpError>;
 }
 
 #[derive(Debug)]
 pub struct Finalizer {
     context: Arc<Context>,
+    config: ChimpConfig,
 }
 
 impl Finalizer {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
     }
 
     pub fn context(&self) -> &Context {
@@ -46,6 +49,131 @@ impl Finalizer {
     pub fn queue(&self) -> &wgpu::Queue {
         self.context.queue()
     }
+
+    /// The size in bytes of the block offsets the finalized output of [buffers] carries
+    pub fn metadata_size(&self, buffers: &RunBuffers) -> usize {
+        let index_len = buffers.index_buffer().size() / size_of::<u32>();
+        (index_len - 1) * 8
+    }
+
+    /// An upper bound of the u32 words [block_count] finalized blocks take, each starts with
+    /// 3 header words and none of its values takes more than 64 bits
+    pub fn max_output_words(&self, block_count: usize) -> usize {
+        block_count * (2 * self.config.buffer_size() + 3)
+    }
+
+    /// Record the packing of the compressed values of [buffers] into [encoder] without
+    /// submitting it, and return the storage buffer of [output_buffer_size] bytes the packed
+    /// blocks are written to.
+    ///
+    /// The blocks are placed after the offsets of the index buffer of [buffers], which only
+    /// have to be written by a dispatch recorded before, so [output_buffer_size] may be any
+    /// bound of the last of them, see [Finalizer::max_output_words].
+    pub fn record_finalize(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        buffers: &mut RunBuffers,
+        padding: usize,
+        output_buffer_size: BufferAddress,
+        skip_time: &mut u128,
+    ) -> Result<BufferWrapper, ChimpError> {
+        let chimp_input_len = buffers.compressed_buffer().size() / size_of::<ChimpOutput>() - 1;
+
+        let chimp_input_length_no_padding = chimp_input_len - padding;
+
+        let size = self.config.buffer_size();
+
+        let last_size = if chimp_input_length_no_padding % self.config.buffer_size() != 0 {
+            chimp_input_length_no_padding % self.config.buffer_size()
+        } else {
+            size
+        } as u32;
+
+        let workgroup_count = chimp_input_len.div(self.config.buffer_size());
+
+        let instant = Instant::now();
+        let out_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
+            output_buffer_size,
+            WgpuGroupId::new(0, 0),
+            Some("Staging Output Buffer"),
+        );
+        {
+            buffers
+                .compressed_buffer_mut()
+                .with_binding(WgpuGroupId::new(0, 1));
+        }
+
+        let size_uniform = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
+            bytemuck::bytes_of(&(self.config.buffer_size() as u32)),
+            WgpuGroupId::new(0, 2),
+            Some("Size Uniform Buffer"),
+        );
+        {
+            buffers
+                .index_buffer_mut()
+                .with_binding(WgpuGroupId::new(0, 3));
+        }
+
+        let last_size_uniform = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
+            bytemuck::bytes_of(&last_size),
+            WgpuGroupId::new(0, 4),
+            Some("Useful Staging Buffer"),
+        );
+        *skip_time += instant.elapsed().as_millis();
+
+        let iterations =
+            workgroup_count / (256 * self.config.max_workgroup_size(&self.context)) + 1;
+        let last_size = workgroup_count % (256 * self.config.max_workgroup_size(&self.context));
+        for i in 0..iterations {
+            let temp = shaders::CHIMP_FINALIZE_COMPRESS
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .bind("total_threads", workgroup_count as u32)
+                .bind("last_pass", u32::from(i == iterations - 1))
+                .finish()?;
+            record_compute_shader!(
+                self.context(),
+                encoder,
+                &temp,
+                vec![
+                    &out_storage_buffer,
+                    buffers.compressed_buffer(),
+                    &size_uniform,
+                    buffers.index_buffer(),
+                    &last_size_uniform,
+                ],
+                if i == iterations - 1 {
+                    last_size.div_ceil(256)
+                } else {
+                    self.config.max_workgroup_size(&self.context).div_ceil(256)
+                },
+                Some("trim pass")
+            );
+        }
+        buffers.hold_until_submitted(size_uniform);
+        buffers.hold_until_submitted(last_size_uniform);
+        Ok(out_storage_buffer)
+    }
+
+    /// Write the finalized [output] to the trace file when the configuration traces
+    /// [Step::Finalize]
+    pub fn trace(&self, output: &[u8]) {
+        step!(self.config, Step::Finalize, {
+            output
+                .iter()
+                .chunks(4)
+                .into_iter()
+                .map(|chunk| chunk.map(|it| format!("{:08b}", it)).join(" ") + "\n")
+                .collect_vec()
+                .into_iter()
+        });
+    }
 }
 
 #[async_trait]
@@ -117,27 +245,11 @@ impl Finalize for Finalizer {
         buffers: &mut RunBuffers,
         padding: usize,
         skip_time: &mut u128,
-    ) -> Result<CompressResult> {
+    ) -> Result<CompressResult, ChimpError> {
         let size_of_out = size_of::<u32>();
 
-        let index_len = buffers.index_buffer().size() / size_of::<u32>();
-        let chimp_input_len = buffers.compressed_buffer().size() / size_of::<ChimpOutput>() - 1;
-
-        let metadata_size_in_bytes = (index_len - 1) * 8;
-
-        let chimp_input_length_no_padding = chimp_input_len - padding;
-
-        let size = ChimpBufferInfo::get().buffer_size();
-
-        let last_size = if chimp_input_length_no_padding % ChimpBufferInfo::get().buffer_size() != 0
-        {
-            chimp_input_length_no_padding % ChimpBufferInfo::get().buffer_size()
-        } else {
-            size
-        } as u32;
-
-        let index_staging = BufferWrapper::stage_with_size(
-            self.context().device(),
+        let index_staging = BufferWrapper::pooled_stage_with_size(
+            self.context(),
             buffers.index_buffer().size() as BufferAddress,
             None,
         );
@@ -153,84 +265,23 @@ impl Finalize for Finalizer {
         let output_buffer_size =
             (size_of_out * (*indexes.last().unwrap() as usize)) as BufferAddress;
 
-        let workgroup_count = chimp_input_len.div(ChimpBufferInfo::get().buffer_size());
-
-        let instant = Instant::now();
-        let out_stage_buffer = BufferWrapper::stage_with_size(
-            self.context().device(),
+        let out_stage_buffer = BufferWrapper::pooled_stage_with_size(
+            self.context(),
             output_buffer_size,
             Some("Staging Output Buffer"),
         );
-        let out_storage_buffer = BufferWrapper::storage_with_size(
-            self.context().device(),
+        let mut encoder = self
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let out_storage_buffer = self.record_finalize(
+            &mut encoder,
+            buffers,
+            padding,
             output_buffer_size,
-            WgpuGroupId::new(0, 0),
-            Some("Staging Output Buffer"),
-        );
-        {
-            buffers
-                .compressed_buffer_mut()
-                .with_binding(WgpuGroupId::new(0, 1));
-        }
-
-        let size_uniform = BufferWrapper::uniform_with_content(
-            self.device(),
-            bytemuck::bytes_of(&(ChimpBufferInfo::get().buffer_size() as u32)),
-            WgpuGroupId::new(0, 2),
-            Some("Size Uniform Buffer"),
-        );
-        {
-            buffers
-                .index_buffer_mut()
-                .with_binding(WgpuGroupId::new(0, 3));
-        }
-
-        let last_size_uniform = BufferWrapper::uniform_with_content(
-            self.device(),
-            bytemuck::bytes_of(&last_size),
-            WgpuGroupId::new(0, 4),
-            Some("Useful Staging Buffer"),
-        );
-        *skip_time += instant.elapsed().as_millis();
-
-        let iterations = workgroup_count / (256 * self.context.get_max_workgroup_size()) + 1;
-        let last_size = workgroup_count % (256 * self.context.get_max_workgroup_size());
-        for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let last_pass = format!(
-                "let last_pass={}u;",
-                if i == iterations - 1 { 1 } else { 0 }
-            );
-            let total_threads = format!("let total_threads={}u;", workgroup_count);
-            let last_index = format!("let last_index={}u;", (*indexes).last().unwrap());
-            let temp = include_str!("shaders/chimp_finalize_compress.wgsl")
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@last_pass", &last_pass)
-                .replace("//@total_threads", &total_threads)
-                .replace("//@last_index", &last_index)
-                .to_string();
-            execute_compute_shader!(
-                self.context(),
-                &temp,
-                vec![
-                    &out_stage_buffer,
-                    &out_storage_buffer,
-                    buffers.compressed_buffer(),
-                    &size_uniform,
-                    buffers.index_buffer(),
-                    &last_size_uniform,
-                ],
-                if i == iterations - 1 {
-                    last_size.div_ceil(256)
-                } else {
-                    self.context.get_max_workgroup_size().div_ceil(256)
-                },
-                Some("trim pass")
-            );
-        }
+            skip_time,
+        )?;
+        self.queue().submit(Some(encoder.finish()));
+        buffers.release_submitted();
 
         //Calculate time elapsed
         let instant = Instant::now();
@@ -247,15 +298,7 @@ impl Finalize for Finalizer {
 
         let final_vec = output;
 
-        step!(&Step::Finalize, {
-            final_vec
-                .iter()
-                .chunks(4)
-                .into_iter()
-                .map(|chunk| chunk.map(|it| format!("{:08b}", it)).join(" ") + "\n")
-                .collect_vec()
-                .into_iter()
-        });
-        Ok(CompressResult(final_vec, metadata_size_in_bytes, 0))
+        self.trace(&final_vec);
+        Ok(CompressResult(final_vec, self.metadata_size(buffers), 0))
     }
 }
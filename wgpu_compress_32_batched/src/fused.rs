@@ -0,0 +1,172 @@
+//! Every stage of a batch recorded into a single command buffer.
+//!
+//! The stages of [ChimpCompressorBatched](crate::ChimpCompressorBatched) submit their own
+//! encoder each, and the finalize stage reads the block offsets back to size its output. The
+//! [FusedStages] record the S computation, the compression, the offset prefix sum and the
+//! finalize dispatches in one encoder instead. The output is sized on the host after
+//! [Finalizer::max_output_words], and the count of words actually written is copied from the
+//! index buffer into the same staging buffer as the words, so a batch takes a single submit and
+//! a single readback.
+use crate::calculate_indexes::GPUCalculateIndexes;
+use crate::compute_s_shader::ComputeSImpl;
+use crate::final_compress::FinalCompressImpl;
+use crate::finalize::Finalizer;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::CompressResult;
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{wgpu_utils, BufferWrapper};
+use std::sync::Arc;
+use std::time::Instant;
+use wgpu_types::BufferAddress;
+
+const WORD_SIZE: usize = size_of::<u32>();
+
+/// The GPU stages of the batched pipeline, recorded into one command buffer per batch
+pub struct FusedStages {
+    context: Arc<Context>,
+    config: ChimpConfig,
+    compute_s: ComputeSImpl,
+    final_compress: FinalCompressImpl,
+    calculate_indexes: GPUCalculateIndexes,
+    finalizer: Finalizer,
+}
+
+impl FusedStages {
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self {
+            compute_s: ComputeSImpl::new(context.clone(), config.clone()),
+            final_compress: FinalCompressImpl::new(context.clone(), config.clone(), false),
+            calculate_indexes: GPUCalculateIndexes::new(context.clone(), config.clone()),
+            finalizer: Finalizer::new(context.clone(), config.clone()),
+            context,
+            config,
+        }
+    }
+
+    pub fn context(&self) -> &Context {
+        self.context.as_ref()
+    }
+
+    /// Compress [values], padded with [padding] zeroes to fill their last block, into the
+    /// blocks of the stream
+    pub async fn compress_batch(
+        &self,
+        values: &[f32],
+        buffers: &mut RunBuffers,
+        padding: usize,
+        skip_time: &mut u128,
+    ) -> Result<CompressResult, ChimpError> {
+        let mut encoder = self
+            .context()
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.compute_s
+            .record_compute_s(&mut encoder, values, buffers, skip_time)?;
+        self.final_compress
+            .record_final_compress(&mut encoder, buffers, skip_time)?;
+        self.calculate_indexes.record_calculate_indexes(
+            &mut encoder,
+            buffers,
+            self.config.buffer_size() as u32,
+            skip_time,
+        )?;
+
+        let block_count = buffers.index_buffer().size() / WORD_SIZE - 1;
+        let output_buffer_size =
+            (WORD_SIZE * self.finalizer.max_output_words(block_count)) as BufferAddress;
+        let out_storage_buffer = self.finalizer.record_finalize(
+            &mut encoder,
+            buffers,
+            padding,
+            output_buffer_size,
+            skip_time,
+        )?;
+
+        // the offset past the last block, the word count of the output, is staged before it
+        let staged_size = WORD_SIZE as BufferAddress + output_buffer_size;
+        let out_stage_buffer = BufferWrapper::pooled_stage_with_size(
+            self.context(),
+            staged_size,
+            Some("Staging Output Buffer"),
+        );
+        encoder.copy_buffer_to_buffer(
+            buffers.index_buffer().buffer(),
+            (block_count * WORD_SIZE) as BufferAddress,
+            out_stage_buffer.buffer(),
+            0,
+            WORD_SIZE as BufferAddress,
+        );
+        encoder.copy_buffer_to_buffer(
+            out_storage_buffer.buffer(),
+            0,
+            out_stage_buffer.buffer(),
+            WORD_SIZE as BufferAddress,
+            output_buffer_size,
+        );
+        self.context().queue().submit(Some(encoder.finish()));
+        buffers.release_submitted();
+
+        let instant = Instant::now();
+        let staged =
+            wgpu_utils::read_staged::<u8>(self.context(), out_stage_buffer.buffer(), staged_size)
+                .await?;
+        *skip_time += instant.elapsed().as_millis();
+        let output = trim_staged(staged)?;
+
+        self.compute_s.trace(buffers).await?;
+        self.final_compress.trace(buffers).await?;
+        self.finalizer.trace(&output);
+        Ok(CompressResult(
+            output,
+            self.finalizer.metadata_size(buffers),
+            0,
+        ))
+    }
+}
+
+/// The words of a staged output, without the word count staged before them
+fn trim_staged(mut staged: Vec<u8>) -> Result<Vec<u8>, ChimpError> {
+    let Some((count, output)) = staged.split_first_chunk::<WORD_SIZE>() else {
+        return Err(ChimpError::StagedOutputTruncated {
+            words: 0,
+            staged_bytes: 0,
+        });
+    };
+    let count = u32::from_le_bytes(*count) as usize;
+    let len = count * WORD_SIZE;
+    if len > output.len() {
+        return Err(ChimpError::StagedOutputTruncated {
+            words: count,
+            staged_bytes: output.len(),
+        });
+    }
+    staged.drain(..WORD_SIZE);
+    staged.truncate(len);
+    Ok(staged)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fused::trim_staged;
+    use compress_utils::error::ChimpError;
+
+    #[test]
+    fn test_staged_output_is_trimmed_to_its_word_count() {
+        let mut staged = 2u32.to_le_bytes().to_vec();
+        staged.extend(1..=16u8);
+        assert_eq!(trim_staged(staged).unwrap(), (1..=8u8).collect::<Vec<_>>());
+
+        let mut staged = 5u32.to_le_bytes().to_vec();
+        staged.extend(1..=16u8);
+        assert!(matches!(
+            trim_staged(staged),
+            Err(ChimpError::StagedOutputTruncated {
+                words: 5,
+                staged_bytes: 16
+            })
+        ));
+        assert!(trim_staged(vec![0, 0]).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use crate::cpu::decompressor::decode_blocks;
+use crate::info;
+use async_trait::async_trait;
+use bit_vec::BitVec;
+use compress_utils::bit_utils::BitReadable;
+use compress_utils::config::ChimpConfig;
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant};
+use compress_utils::general_utils::DecompressResult;
+use compress_utils::time_it;
+
+/// The error of a block whose bitstream cannot be decoded, [decode_blocks] replaces it with
+/// the position of the block in the stream
+const CORRUPT_BLOCK: ChimpError = ChimpError::CorruptInput {
+    block: 0,
+    byte_offset: 0,
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct GorillaDecompressorCpu {
+    config: ChimpConfig,
+}
+
+impl GorillaDecompressorCpu {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+
+    /// Read the [bit_count] bits at [index] of [input_vector], failing when the bitstream
+    /// ends before them
+    fn read(input_vector: &BitVec, index: usize, bit_count: u32) -> Result<u32, ChimpError> {
+        if index + bit_count as usize > input_vector.len() {
+            return Err(CORRUPT_BLOCK);
+        }
+        Ok(input_vector.reinterpret_u32(index, bit_count as usize))
+    }
+
+    /// Decode the first [value_count] values of the bitstream of a block
+    pub fn decompress_block(
+        &self,
+        input_vector: &BitVec,
+        value_count: usize,
+    ) -> Result<Vec<f32>, ChimpError> {
+        let mut last_num = Self::read(input_vector, 0, 32)?;
+        let mut output = vec![f32::from_bits(last_num)];
+        // the leading and trailing zeros of the last value written with its own
+        let mut window: Option<(u32, u32)> = None;
+        let mut input_index = 32;
+        while output.len() < value_count {
+            if Self::read(input_vector, input_index, 1)? == 0 {
+                // code 0, the previous value
+                input_index += 1;
+                output.push(f32::from_bits(last_num));
+                continue;
+            }
+            let (lead, trail) = if Self::read(input_vector, input_index + 1, 1)? == 0 {
+                // code 10, the window of the previous values
+                input_index += 2;
+                window.ok_or(CORRUPT_BLOCK)?
+            } else {
+                // code 11, a new window
+                input_index += 2;
+                let lead = Self::read(input_vector, input_index, 5)?;
+                input_index += 5;
+                let mut meaningful = Self::read(input_vector, input_index, 5)?;
+                input_index += 5;
+                if meaningful == 0 {
+                    meaningful = 32;
+                }
+                if lead + meaningful > 32 {
+                    return Err(CORRUPT_BLOCK);
+                }
+                window = Some((lead, 32 - lead - meaningful));
+                (lead, 32 - lead - meaningful)
+            };
+            let meaningful = 32 - lead - trail;
+            let value = Self::read(input_vector, input_index, meaningful)?;
+            input_index += meaningful as usize;
+            last_num ^= value << trail;
+            output.push(f32::from_bits(last_num));
+        }
+        Ok(output)
+    }
+}
+
+#[async_trait]
+impl Decompressor<f32> for GorillaDecompressorCpu {
+    async fn decompress(&self, vec: &[u8]) -> Result<DecompressResult<f32>, ChimpError> {
+        let (header, payload) = ChimpHeader::split(vec, CodecVariant::Gorilla32)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        let output;
+        let mut total_millis = 0;
+        time_it!(
+            {
+                output = decode_blocks(payload, |bit_vec, value_count| {
+                    self.decompress_block(bit_vec, value_count)
+                })?;
+            },
+            total_millis,
+            "decompression"
+        );
+        Ok(output.into())
+    }
+}
@@ -0,0 +1,124 @@
+use crate::final_compress::FinalCompress;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{MaxGroupGnostic, Step};
+use compress_utils::types::{ChimpOutput, S};
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{step, BufferWrapper};
+use std::fs;
+use std::ops::Div;
+
+/// Host implementation of the `gorilla_compress.wgsl` and `initialize_first_per_buffer.wgsl`
+/// stages
+#[derive(Debug, Default)]
+pub struct CPUGorillaFinalCompress {
+    config: ChimpConfig,
+}
+
+/// The leading and trailing zeros of the last value that was written with its own
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Window {
+    lead: u32,
+    trail: u32,
+}
+
+impl CPUGorillaFinalCompress {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+
+    /// Encode [v] after [v_prev] in the [window] of the values before it, which is updated
+    /// when [v] does not fit in it
+    pub(crate) fn compress(v: f32, s: S, v_prev: f32, window: &mut Option<Window>) -> ChimpOutput {
+        let xorred = v_prev.to_bits() ^ v.to_bits();
+        // the leading zeros of S are reset after the first value of a buffer for chimp
+        let lead = xorred.leading_zeros();
+        let trail = s.tail as u32;
+        let (value, bit_count): (u64, u32) = match *window {
+            _ if s.equal == 1 => {
+                // xor_value=0, code 0
+                (0, 1)
+            }
+            Some(current) if lead >= current.lead && trail >= current.trail => {
+                // the meaningful bits fit in the window, code 10
+                let meaningful = 32 - current.lead - current.trail;
+                (
+                    (2u64 << meaningful) + (xorred >> current.trail) as u64,
+                    2 + meaningful,
+                )
+            }
+            _ => {
+                // a new window, code 11
+                let meaningful = 32 - lead - trail;
+                let mut value = 3u64;
+                value = (value << 5) + lead as u64;
+                value = (value << 5) + (meaningful as u64 & 0x1f);
+                value = (value << meaningful) + (xorred >> trail) as u64;
+                *window = Some(Window { lead, trail });
+                (value, 2 + 5 + 5 + meaningful)
+            }
+        };
+        ChimpOutput {
+            upper_bits: (value >> 32) as u32,
+            lower_bits: value as u32,
+            bit_count,
+        }
+    }
+}
+
+impl MaxGroupGnostic for CPUGorillaFinalCompress {
+    fn get_max_number_of_groups(&self, content_len: usize) -> usize {
+        content_len.div(self.config.buffer_size())
+    }
+}
+
+#[async_trait]
+impl FinalCompress for CPUGorillaFinalCompress {
+    async fn final_compress(
+        &self,
+        buffers: &mut RunBuffers,
+        _skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        let output = {
+            let input = buffers.input_buffer().host_slice::<f32>();
+            let s_values = buffers.s_buffer().host_slice::<S>();
+            let mut output = vec![
+                ChimpOutput {
+                    upper_bits: 0,
+                    lower_bits: 0,
+                    bit_count: 0,
+                };
+                input.len()
+            ];
+            for first in (0..input.len() - 1).step_by(self.config.buffer_size()) {
+                // The first value of each buffer is written as is and starts without a window
+                output[first] = ChimpOutput {
+                    upper_bits: 0,
+                    lower_bits: input[first].to_bits(),
+                    bit_count: 32,
+                };
+                let mut window = None;
+                for index in first + 1..first + self.config.buffer_size() {
+                    output[index] = Self::compress(
+                        input[index],
+                        s_values[index],
+                        input[index - 1],
+                        &mut window,
+                    );
+                }
+            }
+            output
+        };
+        step!(self.config, Step::Compress, {
+            output
+                .iter()
+                .enumerate()
+                .map(|it| format!("{}:{}\n", it.0, it.1))
+        });
+        buffers.set_compressed_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &output,
+        )));
+        Ok(())
+    }
+}
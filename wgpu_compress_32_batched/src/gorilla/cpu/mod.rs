@@ -0,0 +1,2 @@
+pub mod decompressor;
+pub mod final_compress;
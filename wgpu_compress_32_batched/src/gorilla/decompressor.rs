@@ -0,0 +1,54 @@
+use crate::decompressor::BatchedGPUDecompressor;
+use crate::shaders;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::CodecVariant;
+use compress_utils::general_utils::DecompressResult;
+use pollster::FutureExt;
+use std::sync::Arc;
+
+/// Decompresses Gorilla streams on the gpu, one thread per block like
+/// [BatchedGPUDecompressor] whose stream handling it shares
+pub struct GorillaGPUDecompressor {
+    inner: BatchedGPUDecompressor,
+}
+
+impl GorillaGPUDecompressor {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(Context::initialize_default_adapter().block_on()?))
+    }
+}
+
+impl GorillaGPUDecompressor {
+    pub fn new(context_builder: impl Into<Arc<Context>>) -> Self {
+        Self {
+            inner: BatchedGPUDecompressor::new(context_builder),
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.inner = self.inner.with_config(config);
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        self.inner.config()
+    }
+
+    pub fn context(&self) -> &Context {
+        self.inner.context()
+    }
+}
+
+#[async_trait]
+impl Decompressor<f32> for GorillaGPUDecompressor {
+    async fn decompress(&self, bytes: &[u8]) -> Result<DecompressResult<f32>, ChimpError> {
+        self.inner
+            .decompress_stream(bytes, CodecVariant::Gorilla32, shaders::GORILLA_DECOMPRESS)
+            .await
+    }
+}
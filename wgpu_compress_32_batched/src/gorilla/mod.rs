@@ -0,0 +1,312 @@
+//! The Gorilla encoding on the stages of the batched pipeline, the baseline Chimp improves on.
+//!
+//! A value equal to the previous one is written as `0`. Any other value is written with the
+//! meaningful bits of its xor with the previous one, as `10` followed by the bits inside the
+//! window of the last value that set one, or as `11` followed by its leading zeros (5 bits),
+//! the number of meaningful bits (5 bits, 32 written as 0) and the bits themselves, which
+//! sets the window. The first value of each buffer is written as is and starts without a
+//! window.
+//!
+//! Only the compress stage and the block decoders differ from Chimp, [compress_batches] runs
+//! the S computation, index calculation and finalize stages of the Chimp pipeline, so the
+//! blocks have the same layout and both codecs are measured under the same conditions.
+pub mod cpu;
+pub mod decompressor;
+pub(crate) mod final_compress;
+
+use crate::calculate_indexes::GPUCalculateIndexes;
+use crate::compressor::compress_batches;
+use crate::compute_s_shader::ComputeSImpl;
+use crate::cpu::calculate_indexes::CPUCalculateIndexes;
+use crate::cpu::compute_s::CPUComputeS;
+use crate::cpu::finalize::CPUImpl;
+use crate::finalize::Finalizer;
+use crate::gorilla::cpu::final_compress::CPUGorillaFinalCompress;
+use crate::gorilla::final_compress::GorillaFinalCompressImpl;
+use crate::ChimpCompressorBatched;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::cpu_compress::Compressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, HEADER_SIZE};
+use compress_utils::general_utils::{
+    max_batch_len, split_by_max_storage_buffer_size, CompressResult,
+};
+use compress_utils::stream::BatchedCompressor;
+use compress_utils::types::ChimpOutput;
+use pollster::FutureExt;
+use std::sync::Arc;
+
+/// The header of a Gorilla stream of [value_count] values compressed with [config]
+fn header(config: &ChimpConfig, value_count: usize) -> ChimpHeader {
+    ChimpHeader::new(
+        CodecVariant::Gorilla32,
+        0,
+        config.buffer_size(),
+        value_count,
+    )
+    .with_error_bound(config.error_bound())
+}
+
+#[derive(Debug)]
+pub struct GorillaCompressorBatched {
+    context: Arc<Context>,
+    config: ChimpConfig,
+}
+
+impl GorillaCompressorBatched {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(Arc::new(
+            Context::initialize_default_adapter().block_on()?,
+        )))
+    }
+}
+
+impl GorillaCompressorBatched {
+    pub fn new(context: Arc<Context>) -> Self {
+        Self {
+            context,
+            config: ChimpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+}
+
+#[async_trait]
+impl Compressor<f32> for GorillaCompressorBatched {
+    async fn compress(&self, values: &[f32]) -> Result<CompressResult, ChimpError> {
+        let header = header(&self.config, values.len());
+        let iterations = split_by_max_storage_buffer_size(
+            values,
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput>(),
+        );
+        let result = compress_batches(
+            iterations,
+            &self.config,
+            &ComputeSImpl::new(self.context.clone(), self.config.clone()),
+            &GorillaFinalCompressImpl::new(self.context.clone(), self.config.clone()),
+            &GPUCalculateIndexes::new(self.context.clone(), self.config.clone()),
+            &Finalizer::new(self.context.clone(), self.config.clone()),
+        )
+        .await?;
+
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl BatchedCompressor<f32> for GorillaCompressorBatched {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput>(),
+        )
+    }
+
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        header(&self.config, value_count)
+    }
+}
+
+/// A [Compressor] running every stage of the Gorilla pipeline on the host, with an output
+/// byte for byte the one of [GorillaCompressorBatched] on a device whose storage buffer
+/// binding limit is [GorillaCompressorBatchedCpu::max_storage_buffer_size]
+#[derive(Debug, Clone)]
+pub struct GorillaCompressorBatchedCpu {
+    max_storage_buffer_size: usize,
+    config: ChimpConfig,
+}
+
+impl Default for GorillaCompressorBatchedCpu {
+    fn default() -> Self {
+        Self::new(ChimpCompressorBatched::MAX_BUFFER_SIZE_BYTES)
+    }
+}
+
+impl GorillaCompressorBatchedCpu {
+    pub fn new(max_storage_buffer_size: usize) -> Self {
+        Self {
+            max_storage_buffer_size,
+            config: ChimpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn max_storage_buffer_size(&self) -> usize {
+        self.max_storage_buffer_size
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+}
+
+#[async_trait]
+impl Compressor<f32> for GorillaCompressorBatchedCpu {
+    async fn compress(&self, values: &[f32]) -> Result<CompressResult, ChimpError> {
+        let header = header(&self.config, values.len());
+        let iterations = split_by_max_storage_buffer_size(
+            values,
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput>(),
+        );
+        let result = compress_batches(
+            iterations,
+            &self.config,
+            &CPUComputeS::new(self.config.clone()),
+            &CPUGorillaFinalCompress::new(self.config.clone()),
+            &CPUCalculateIndexes::new(self.config.clone()),
+            &CPUImpl::new(self.config.clone()),
+        )
+        .await?;
+
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl BatchedCompressor<f32> for GorillaCompressorBatchedCpu {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput>(),
+        )
+    }
+
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        header(&self.config, value_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gorilla::cpu::decompressor::GorillaDecompressorCpu;
+    use crate::gorilla::GorillaCompressorBatchedCpu;
+    use crate::ChimpCompressorBatchedCpu;
+    use compress_utils::config::ChimpConfig;
+    use compress_utils::cpu_compress::{Compressor, Decompressor};
+    use compress_utils::error::ChimpError;
+    use compress_utils::format::{ChimpHeader, CodecVariant, FormatError};
+    use compress_utils::testing::assert_corruption_never_panics;
+    use pollster::FutureExt;
+
+    fn series(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|it| match it % 11 {
+                0 => 21.5,
+                5 => it as f32 * 0.01,
+                _ => ((it / 6) % 40) as f32 * 0.25,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cpu_gorilla_round_trip() {
+        let mut values = series(5000);
+        values.extend([
+            0.0,
+            -0.0,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+            f32::MIN_POSITIVE,
+            f32::MAX,
+        ]);
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        // a binding limit small enough for the values to take several batches
+        let compressor = GorillaCompressorBatchedCpu::new(1 << 14).with_config(config.clone());
+        let compressed = compressor.compress(&values).block_on().unwrap();
+        let bytes = compressed.compressed_value_ref();
+        assert_eq!(
+            ChimpHeader::read(bytes).unwrap().codec,
+            CodecVariant::Gorilla32
+        );
+
+        let decompressor = GorillaDecompressorCpu::new(config);
+        let decompressed = decompressor.decompress(bytes).block_on().unwrap();
+        assert_eq!(
+            decompressed
+                .un_compressed_value()
+                .iter()
+                .map(|it| it.to_bits())
+                .collect::<Vec<_>>(),
+            values.iter().map(|it| it.to_bits()).collect::<Vec<_>>()
+        );
+        let range = decompressor
+            .decompress_range(bytes, 1000..1300)
+            .block_on()
+            .unwrap();
+        assert_eq!(range.un_compressed_value(), values[1000..1300]);
+        assert!(bytes.len() < values.len() * size_of::<f32>());
+    }
+
+    #[test]
+    fn test_cpu_gorilla_corrupted_stream_never_panics() {
+        let compressed = GorillaCompressorBatchedCpu::default()
+            .compress(&series(600))
+            .block_on()
+            .unwrap();
+        assert_corruption_never_panics(
+            compressed.compressed_value_ref(),
+            &GorillaDecompressorCpu::default(),
+        );
+    }
+
+    #[test]
+    fn test_cpu_gorilla_and_chimp_streams_are_told_apart() {
+        let values = series(1000);
+        let chimp = ChimpCompressorBatchedCpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        let result = GorillaDecompressorCpu::default()
+            .decompress(chimp.compressed_value_ref())
+            .block_on();
+        assert!(matches!(
+            result,
+            Err(ChimpError::Format(FormatError::CodecMismatch {
+                expected: CodecVariant::Gorilla32,
+                found: CodecVariant::Chimp32
+            }))
+        ));
+    }
+}
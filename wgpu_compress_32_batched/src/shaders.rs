@@ -0,0 +1,220 @@
+//! The WGSL shaders of the batched 32 bit pipelines along with the parameters each one is
+//! specialized with, see [compress_utils::shader_template]
+use compress_utils::shader_template::Parameter::Override;
+use compress_utils::shader_template::ShaderTemplate;
+
+pub const COMPUTE_S: ShaderTemplate =
+    ShaderTemplate::new("compute_s", include_str!("shaders/compute_s.wgsl"))
+        .with_parameters(&[Override("workgroup_offset")]);
+
+pub const CHIMP_COMPRESS: ShaderTemplate = ShaderTemplate::new(
+    "chimp_compress",
+    include_str!("shaders/chimp_compress.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")]);
+
+pub const INITIALIZE_FIRST_PER_BUFFER: ShaderTemplate = ShaderTemplate::new(
+    "initialize_first_per_buffer",
+    include_str!("shaders/initialize_first_per_buffer.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")]);
+
+pub const CALCULATE_FINAL_SIZES: ShaderTemplate = ShaderTemplate::new(
+    "calculate_final_sizes",
+    include_str!("shaders/calculate_final_sizes.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")]);
+
+pub const CHIMP_FINALIZE_COMPRESS: ShaderTemplate = ShaderTemplate::new(
+    "chimp_finalize_compress",
+    include_str!("shaders/chimp_finalize_compress.wgsl"),
+)
+.with_parameters(&[
+    Override("workgroup_offset"),
+    Override("total_threads"),
+    Override("last_pass"),
+]);
+
+pub const DECOMPRESS: ShaderTemplate =
+    ShaderTemplate::new("decompress", include_str!("shaders/decompress.wgsl"))
+        .with_parameters(&[Override("workgroup_offset"), Override("total_threads")]);
+
+/// The Gorilla variant of [CHIMP_COMPRESS]
+pub const GORILLA_COMPRESS: ShaderTemplate = ShaderTemplate::new(
+    "gorilla_compress",
+    include_str!("shaders/gorilla_compress.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")]);
+
+/// The Gorilla variant of [DECOMPRESS]
+pub const GORILLA_DECOMPRESS: ShaderTemplate = ShaderTemplate::new(
+    "gorilla_decompress",
+    include_str!("shaders/gorilla_decompress.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset"), Override("total_threads")]);
+
+/// Every shader of the pipeline, validated by `compress_utils::shader_validation` in the tests
+pub const ALL: &[ShaderTemplate] = &[
+    COMPUTE_S,
+    CHIMP_COMPRESS,
+    INITIALIZE_FIRST_PER_BUFFER,
+    CALCULATE_FINAL_SIZES,
+    CHIMP_FINALIZE_COMPRESS,
+    DECOMPRESS,
+    GORILLA_COMPRESS,
+    GORILLA_DECOMPRESS,
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::calculate_indexes::GPUCalculateIndexes;
+    use crate::compressor::{compress_batches, compress_batches_fused};
+    use crate::compute_s_shader::ComputeSImpl;
+    use crate::decompressor::BatchedGPUDecompressor;
+    use crate::final_compress::FinalCompressImpl;
+    use crate::finalize::{Finalize, Finalizer};
+    use crate::fused::FusedStages;
+    use crate::gorilla::decompressor::GorillaGPUDecompressor;
+    use crate::gorilla::final_compress::GorillaFinalCompressImpl;
+    use crate::gorilla::GorillaCompressorBatchedCpu;
+    use crate::{shaders, ChimpCompressorBatchedCpu};
+    use async_trait::async_trait;
+    use compress_utils::config::ChimpConfig;
+    use compress_utils::context::Context;
+    use compress_utils::cpu_compress::{Compressor, Decompressor};
+    use compress_utils::error::ChimpError;
+    use compress_utils::general_utils::CompressResult;
+    use compress_utils::shader_validation::{
+        check_cached_layouts, seed_index_buffer, validate_templates, ParameterMatrix,
+    };
+    use compress_utils::wgpu_utils::RunBuffers;
+    use itertools::Itertools;
+    use pollster::FutureExt;
+    use std::sync::Arc;
+
+    /// The GPU [Finalizer], run over the offsets the index calculation writes on a device
+    struct SeededFinalizer(Finalizer);
+
+    #[async_trait]
+    impl Finalize for SeededFinalizer {
+        async fn finalize(
+            &self,
+            buffers: &mut RunBuffers,
+            padding: usize,
+            skip_time: &mut u128,
+        ) -> Result<CompressResult, ChimpError> {
+            seed_index_buffer(self.0.context(), buffers);
+            self.0.finalize(buffers, padding, skip_time).await
+        }
+    }
+
+    #[test]
+    fn test_shaders_validate_offline() {
+        let validated = validate_templates(shaders::ALL, &ParameterMatrix::default())
+            .unwrap_or_else(|error| panic!("{error}"));
+        assert!(validated > shaders::ALL.len());
+    }
+
+    #[test]
+    fn test_stage_layouts_match_their_shaders() {
+        let context = Arc::new(Context::initialize_noop().block_on().unwrap());
+        let config = ChimpConfig::default();
+        let values = (0..3000).map(|it| it as f32 / 8.0).collect_vec();
+        let finalizer = SeededFinalizer(Finalizer::new(context.clone(), config.clone()));
+        compress_batches(
+            values.chunks(values.len()),
+            &config,
+            &ComputeSImpl::new(context.clone(), config.clone()),
+            &FinalCompressImpl::new(context.clone(), config.clone(), false),
+            &GPUCalculateIndexes::new(context.clone(), config.clone()),
+            &finalizer,
+        )
+        .block_on()
+        .unwrap();
+        compress_batches(
+            values.chunks(values.len()),
+            &config,
+            &ComputeSImpl::new(context.clone(), config.clone()),
+            &GorillaFinalCompressImpl::new(context.clone(), config.clone()),
+            &GPUCalculateIndexes::new(context.clone(), config.clone()),
+            &finalizer,
+        )
+        .block_on()
+        .unwrap();
+        let chimp = ChimpCompressorBatchedCpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        BatchedGPUDecompressor::new(context.clone())
+            .decompress(chimp.compressed_value_ref())
+            .block_on()
+            .unwrap();
+        let gorilla = GorillaCompressorBatchedCpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        GorillaGPUDecompressor::new(context.clone())
+            .decompress(gorilla.compressed_value_ref())
+            .block_on()
+            .unwrap();
+
+        check_cached_layouts(context.pipeline_cache(), shaders::ALL)
+            .unwrap_or_else(|error| panic!("{error}"));
+    }
+
+    #[test]
+    fn test_fused_stages_match_the_layouts_of_their_shaders() {
+        let context = Arc::new(Context::initialize_noop().block_on().unwrap());
+        let config = ChimpConfig::default();
+        let values = (0..3000).map(|it| it as f32 / 8.0).collect_vec();
+        let result = compress_batches_fused(
+            values.chunks(1000),
+            &config,
+            &FusedStages::new(context.clone(), config.clone()),
+        )
+        .block_on()
+        .unwrap();
+        // no dispatch runs on the noop backend, every staged word count is 0
+        assert!(result.compressed_value_ref().is_empty());
+        assert_eq!(
+            result.metadata_size(),
+            3 * 1000usize.div_ceil(config.buffer_size()) * 8
+        );
+
+        let stages = [
+            shaders::COMPUTE_S,
+            shaders::CHIMP_COMPRESS,
+            shaders::INITIALIZE_FIRST_PER_BUFFER,
+            shaders::CALCULATE_FINAL_SIZES,
+            shaders::CHIMP_FINALIZE_COMPRESS,
+        ];
+        check_cached_layouts(context.pipeline_cache(), &stages)
+            .unwrap_or_else(|error| panic!("{error}"));
+    }
+
+    #[test]
+    fn test_repeated_compressions_draw_their_buffers_from_the_pool() {
+        let context = Arc::new(Context::initialize_noop().block_on().unwrap());
+        let config = ChimpConfig::default();
+        let values = (0..3000).map(|it| it as f32 / 8.0).collect_vec();
+        let compress = || {
+            compress_batches(
+                values.chunks(1000),
+                &config,
+                &ComputeSImpl::new(context.clone(), config.clone()),
+                &FinalCompressImpl::new(context.clone(), config.clone(), false),
+                &GPUCalculateIndexes::new(context.clone(), config.clone()),
+                &SeededFinalizer(Finalizer::new(context.clone(), config.clone())),
+            )
+            .block_on()
+            .unwrap()
+        };
+        compress();
+        let first = context.buffer_pool().stats();
+        assert!(first.hits > 0, "{first}");
+        compress();
+        let second = context.buffer_pool().stats();
+        assert_eq!(second.misses, first.misses, "{second}");
+        assert_eq!(second.buffers, first.buffers, "{second}");
+    }
+}
@@ -1,3 +1,4 @@
+override workgroup_offset: u32;
 
 struct Ss{
     leading:i32,
@@ -126,7 +127,6 @@ fn pseudo_u64_add(output:vec2<u32>,number:u32)->vec2<u32>{
 @compute
 @workgroup_size(256)
 fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>,@builtin(local_invocation_id) invocation_id: vec3<u32>) {
-    //@workgroup_offset
 
     for (var i=0u;i<chunks;i++){
         let index:u32=(workgroup_offset+workgroup_id.x) * 256 * chunks + invocation_id.x+i*256u;
@@ -1,3 +1,7 @@
+override workgroup_offset: u32;
+override total_threads: u32;
+override last_pass: u32;
+
 @group(0)
 @binding(2)
 var<uniform> size: u32;
@@ -134,10 +138,6 @@ fn write(idx:u32,out_idx:u32,is_last:u32,next_idx:u32)->u32{
 @compute
 @workgroup_size(256)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>,@builtin(num_workgroups) count: vec3<u32>) {
-    //@workgroup_offset
-    //@last_pass
-    //@last_index
-    //@total_threads
     
     if(workgroup_offset+global_id.x>=total_threads){return;}
     
@@ -147,6 +147,8 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>,@builtin(num_workgro
     
     let current_index=workgroup_offset+global_id.x;
     
+    // the offset past the last block, read here so the host needs no readback to dispatch
+    let last_index=last_byte_index[total_threads];
     let is_last_pass=last_pass*u32(last_byte_index[current_index+1u]==last_index);  
     
     write(current_index*size,last_byte_index[current_index],is_last_pass,last_byte_index[current_index+1u]);
@@ -0,0 +1,90 @@
+override workgroup_offset: u32;
+
+struct Ss{
+    leading:i32,
+    trailing:i32,
+    equal:u32,
+}
+
+struct Output{
+    upper_bits:u32,
+    lower_bits:u32,//because there is a scenario where 32 bits are not enough to reprisent the outcome
+    useful_size:u32
+}
+
+@group(0)
+@binding(0)
+var<storage, read_write> s_store: array<Ss>;
+
+@group(0)
+@binding(1)
+var<storage, read_write> in: array<f32>;
+
+@group(0)
+@binding(2)
+var<storage, read_write> out: array<Output>;
+@group(0)
+@binding(3)
+var<uniform> chunks: u32; // how many iterations per buffer
+
+// Append the [count] lowest bits of [bits] to [output]
+fn append(output:vec2<u32>,bits:u32,count:u32)->vec2<u32>{
+    var new_output=pseudo_u64_shift(output,count);
+    new_output.y+=extractBits(bits,0u,count);
+    return new_output;
+}
+
+fn pseudo_u64_shift(output:vec2<u32>,number:u32)->vec2<u32>{
+    var first_number_bits:u32=extractBits(output.y,32-number,number);
+    var new_output=vec2(output.x,output.y);
+    var check = u32(number < 32);
+    new_output.x = check*(output.x << number);
+    new_output.x += first_number_bits;
+    new_output.y = check*(output.y<<number);
+
+    return new_output;
+}
+
+// The window of a value is the one of the last value written with its leading zeros and
+// length, so a single invocation encodes every value of a buffer in order
+@compute
+@workgroup_size(1)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>) {
+    let buffer_size=256u*chunks;
+    let first=(workgroup_offset+workgroup_id.x)*buffer_size;
+
+    // no window before the first value with its leading zeros, none has 32 of them
+    var window_lead=32u;
+    var window_trail=0u;
+    for (var i=1u;i<buffer_size;i++){
+        let index=first+i;
+        if s_store[index].equal==1u {
+            // code 0
+            out[index]=Output(0u,0u,1u);
+            continue;
+        }
+        let xorred=bitcast<u32>(in[index-1u])^bitcast<u32>(in[index]);
+        // the leading zeros of S are reset after the first value of a buffer for chimp
+        let lead=countLeadingZeros(xorred);
+        let trail=u32(s_store[index].trailing);
+
+        var output=vec2(0u,0u);
+        var bit_count=0u;
+        if lead>=window_lead && trail>=window_trail {
+            // code 10, the meaningful bits fit in the window
+            let meaningful=32u-window_lead-window_trail;
+            output=append(vec2(0u,2u),xorred>>window_trail,meaningful);
+            bit_count=2u+meaningful;
+        } else {
+            // code 11, a new window
+            let meaningful=32u-lead-trail;
+            output=append(vec2(0u,3u),lead,5u);
+            output=append(output,meaningful,5u);
+            output=append(output,xorred>>trail,meaningful);
+            bit_count=2u+5u+5u+meaningful;
+            window_lead=lead;
+            window_trail=trail;
+        }
+        out[index]=Output(output.x,output.y,bit_count);
+    }
+}
@@ -0,0 +1,111 @@
+override workgroup_offset: u32;
+override total_threads: u32;
+
+@group(0)
+@binding(0)
+var<storage, read_write> out: array<f32>;
+
+@group(0)
+@binding(1)
+var<storage, read_write> in: array<u32>;
+
+
+@group(0)
+@binding(2)
+var<uniform> size: u32;
+
+
+
+@group(0)
+@binding(3)
+var<storage, read_write> blocks: array<vec2<u32>>; // the first word and the value count of each block
+
+
+struct CurrentInfo{
+    current_index:u32,
+    current_offset:u32,
+}
+
+fn write(input_idx:u32,output_idx:u32,count:u32){
+    //Index of the word we are in, the first one holds the first value
+    var current_index=input_idx+1u;
+    //Bits left in the current word
+    var current_offset=32u;
+
+    var current_info=CurrentInfo(current_index,current_offset);
+
+    var last_num:u32=in[input_idx];
+    // a value with a new window always comes before one that reuses it
+    var window_lead=32u;
+    var window_trail=0u;
+
+    var output_index=output_idx;
+
+    out[output_index]=bitcast<f32>(last_num);
+    output_index+=1u;
+    for (var i: u32 = 1u; i < count; i++) {
+
+        if get_bit_at_index(current_info.current_index,current_info.current_offset)==1u {
+            current_info=decr_counter_capped_at_32(&current_info,1u);
+            if get_bit_at_index(current_info.current_index,current_info.current_offset)==1u {
+                current_info=decr_counter_capped_at_32(&current_info,1u);
+                window_lead=reinterpret_num(current_info.current_index,current_info.current_offset,5u);
+                current_info=decr_counter_capped_at_32(&current_info,5u);
+                var meaningful=reinterpret_num(current_info.current_index,current_info.current_offset,5u);
+                current_info=decr_counter_capped_at_32(&current_info,5u);
+                if meaningful==0u {
+                    meaningful=32u;
+                }
+                window_trail=32u-window_lead-meaningful;
+            } else {
+                current_info=decr_counter_capped_at_32(&current_info,1u);
+            }
+            let meaningful=32u-window_lead-window_trail;
+            let value=reinterpret_num(current_info.current_index,current_info.current_offset,meaningful);
+            current_info=decr_counter_capped_at_32(&current_info,meaningful);
+            last_num^=value<<window_trail;
+        } else {
+            current_info=decr_counter_capped_at_32(&current_info,1u);
+        }
+        out[output_index]=bitcast<f32>(last_num);
+        output_index+=1u;
+    }
+}
+
+
+fn get_bit_at_index(array_index: u32, position: u32) -> u32 {
+    return (in[array_index] >> (position - 1u)) & 1u;
+}
+
+fn decr_counter_capped_at_32(value:ptr<function,CurrentInfo>,count:u32)->CurrentInfo{
+    let corrected_value=i32((*value).current_offset)-i32(count);
+    (*value).current_offset=u32(corrected_value>0)*u32(corrected_value) + u32(corrected_value<=0)*u32(32+corrected_value);
+    (*value).current_index+=u32(corrected_value<=0); //1 if it's true and 0 otherwise
+    return (*value);
+}
+
+fn reinterpret_num(array_index:u32,index:u32,length:u32)->u32{
+    let len=min(length,32u);
+    if index>=len {
+        // Fully within one u32
+        return extractBits(in[array_index], u32(index-len), len);
+    } else {
+        // Spans two u32 elements
+        let bits_in_first = index;
+        let bits_in_second = length-index;
+
+        let first_part = extractBits(in[array_index], 0u, index);
+        let second_part = extractBits(in[array_index + 1], 32u - bits_in_second, bits_in_second);
+        return (first_part << bits_in_second) | second_part;
+    }
+}
+
+
+
+@compute
+@workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if(workgroup_offset+global_id.x>=total_threads){return;}
+    let block=workgroup_offset+global_id.x;
+    write(blocks[block].x,block*size,blocks[block].y);
+}
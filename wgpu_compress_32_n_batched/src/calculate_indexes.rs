@@ -1,7 +1,8 @@
-use anyhow::Result;
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::general_utils::ChimpBufferInfo;
+use compress_utils::error::ChimpError;
 use compress_utils::types::ChimpOutput;
 use compress_utils::wgpu_utils::RunBuffers;
 use compress_utils::{execute_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
@@ -18,16 +19,17 @@ pub trait CalculateIndexes {
         buffers: &mut RunBuffers,
         size: u32,
         skip_time: &mut u128,
-    ) -> Result<()>;
+    ) -> Result<(), ChimpError>;
 }
 
 pub struct GPUCalculateIndexes {
     context: Arc<Context>,
+    config: ChimpConfig,
 }
 
 impl GPUCalculateIndexes {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
     }
 
     pub fn context(&self) -> &Arc<Context> {
@@ -42,14 +44,14 @@ impl CalculateIndexes for GPUCalculateIndexes {
         buffers: &mut RunBuffers,
         size: u32,
         skip_time: &mut u128,
-    ) -> Result<()> {
+    ) -> Result<(), ChimpError> {
         let workgroup_count =
             (buffers.compressed_buffer().size() / size_of::<ChimpOutput>()).div(size as usize);
         let output_buffer_size = (workgroup_count + 1) * size_of::<u32>();
 
         let instant = Instant::now();
-        let out_storage_buffer = BufferWrapper::storage_with_size(
-            self.context().device(),
+        let out_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
             output_buffer_size as BufferAddress,
             WgpuGroupId::new(0, 0),
             Some("Storage Output Buffer"),
@@ -60,27 +62,23 @@ impl CalculateIndexes for GPUCalculateIndexes {
                 .compressed_buffer_mut()
                 .with_binding(WgpuGroupId::new(0, 1));
         }
-        let size_uniform = BufferWrapper::uniform_with_content(
-            self.context().device(),
+        let size_uniform = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
             bytemuck::bytes_of(&size),
             WgpuGroupId::new(0, 2),
             Some("Size Uniform Buffer"),
         );
         *skip_time += instant.elapsed().as_millis();
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let temp = include_str!("shaders/calculate_final_sizes.wgsl")
-                .replace(
-                    "@@workgroup_size",
-                    &ChimpBufferInfo::get().buffer_size().to_string(),
+            let temp = shaders::CALCULATE_FINAL_SIZES
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
                 )
-                .replace("//@workgroup_offset", &offset_decl)
-                .to_string();
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &temp,
@@ -92,14 +90,14 @@ impl CalculateIndexes for GPUCalculateIndexes {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("calculate indexes pass")
             );
         }
 
-        let size_uniform = BufferWrapper::uniform_with_content(
-            self.context().device(),
+        let size_uniform = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
             bytemuck::bytes_of(&workgroup_count),
             WgpuGroupId::new(0, 1),
             Some("Size Uniform Buffer"),
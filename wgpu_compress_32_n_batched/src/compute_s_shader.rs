@@ -1,7 +1,9 @@
-use anyhow::Result;
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo, MaxGroupGnostic, Step};
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{padding_to_fit_buffer_count, MaxGroupGnostic, Step};
 use compress_utils::types::S;
 use compress_utils::wgpu_utils::RunBuffers;
 use compress_utils::{execute_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
@@ -16,20 +18,20 @@ use wgpu_types::BufferAddress;
 pub trait ComputeS: MaxGroupGnostic {
     async fn compute_s(
         &self,
-        values: &mut [f32],
+        values: &[f32],
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> Result<()>;
+    ) -> Result<(), ChimpError>;
 }
 
 pub struct ComputeSNImpl {
     context: Arc<Context>,
-    n: usize,
+    config: ChimpConfig,
 }
 
 impl ComputeSNImpl {
-    pub fn new(context: Arc<Context>, n: usize) -> Self {
-        Self { context, n }
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
     }
 
     pub fn context(&self) -> &Context {
@@ -49,13 +51,13 @@ impl ComputeSNImpl {
     }
     #[allow(dead_code)]
     pub fn max_work_group_count(&self) -> usize {
-        self.context.get_max_workgroup_size()
+        self.config.max_workgroup_size(&self.context)
     }
 }
 
 impl MaxGroupGnostic for ComputeSNImpl {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 
@@ -63,59 +65,55 @@ impl MaxGroupGnostic for ComputeSNImpl {
 impl ComputeS for ComputeSNImpl {
     async fn compute_s(
         &self,
-        values: &mut [f32],
+        values: &[f32],
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> Result<()> {
+    ) -> Result<(), ChimpError> {
         // Create a shader module and pipeline
 
         //Calculating buffer sizes and workgroup counts
-        let workgroup_count = self.get_max_number_of_groups(values.len());
+        // the values are padded with 0's to fill their last buffer
+        let padded_len =
+            values.len() + padding_to_fit_buffer_count(values.len(), self.config.buffer_size());
+        let workgroup_count = self.get_max_number_of_groups(padded_len);
         //info!("The wgpu workgroup size: {}", &workgroup_count);
 
         let size_of_s = size_of::<S>();
-        let bytes = values.len() + 1;
+        let bytes = padded_len + 1;
 
         let s_buffer_size = (size_of_s * bytes) as BufferAddress;
 
         let instant = Instant::now();
-        let s_staging_buffer =
-            BufferWrapper::stage_with_size(self.device(), s_buffer_size, Some("Staging S Buffer"));
-        let s_storage_buffer = BufferWrapper::storage_with_size(
-            self.device(),
+        let s_staging_buffer = BufferWrapper::pooled_stage_with_size(
+            self.context(),
+            s_buffer_size,
+            Some("Staging S Buffer"),
+        );
+        let s_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
             s_buffer_size,
             WgpuGroupId::new(0, 0),
             Some("Storage S Buffer"),
         );
-        let chunks_buffer = BufferWrapper::uniform_with_content(
-            self.device(),
-            bytemuck::bytes_of(&ChimpBufferInfo::get().chunks()),
+        let chunks_buffer = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
+            bytemuck::bytes_of(&self.config.buffer_info().chunks()),
             WgpuGroupId::new(0, 2),
             Some("Chunks Buffer"),
         );
         *skip_time += instant.elapsed().as_millis();
 
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
 
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-
-            let n = format!("let n={}u;", self.n);
-            let full_size = format!("let full_size={}u;", ChimpBufferInfo::get().buffer_size());
-
-            let temp = include_str!("shaders/compute_s.wgsl")
-                .replace(
-                    "@@workgroup_size",
-                    &ChimpBufferInfo::get().buffer_size().to_string(),
+            let temp = shaders::COMPUTE_S
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
                 )
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@n", &n)
-                .replace("//@full_size", &full_size)
-                .to_string();
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &temp,
@@ -129,7 +127,7 @@ impl ComputeS for ComputeSNImpl {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("compute s layout")
             );
@@ -139,7 +137,7 @@ impl ComputeS for ComputeSNImpl {
         buffers.set_chunks(chunks_buffer);
 
         //info!("Output result size: {}", output.len());
-        if trace_steps().contains(&Step::ComputeS) {
+        if self.config.traces(&Step::ComputeS) {
             let output = wgpu_utils::get_from_gpu::<S>(
                 self.context(),
                 buffers.s_buffer().buffer(),
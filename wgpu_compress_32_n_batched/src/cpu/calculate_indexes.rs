@@ -0,0 +1,17 @@
+use crate::calculate_indexes::CalculateIndexes;
+use async_trait::async_trait;
+use compress_utils::error::ChimpError;
+use compress_utils::wgpu_utils::RunBuffers;
+use wgpu_compress_32_batched::cpu::calculate_indexes::CPUCalculateIndexes;
+
+#[async_trait]
+impl CalculateIndexes for CPUCalculateIndexes {
+    async fn calculate_indexes(
+        &self,
+        buffers: &mut RunBuffers,
+        size: u32,
+        _skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        self.calculate_buffer_indexes(buffers, size)
+    }
+}
@@ -3,24 +3,27 @@
 
 use crate::compute_s_shader::ComputeS;
 use async_trait::async_trait;
-use compress_utils::context::Context;
-use compress_utils::general_utils::trace_steps;
-use compress_utils::general_utils::{ChimpBufferInfo, MaxGroupGnostic, Step};
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{padding_to_fit_buffer_count, MaxGroupGnostic, Step};
 use compress_utils::types::S;
-use compress_utils::wgpu_utils::{get_from_gpu, RunBuffers};
-use compress_utils::{step, BufferWrapper, WgpuGroupId};
-use itertools::Itertools;
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{step, BufferWrapper};
 use std::fs;
 use std::ops::Div;
-use std::sync::Arc;
-use wgpu_types::BufferAddress;
 
+/// Host implementation of the `compute_s.wgsl` stage, comparing every value with the one
+/// picked by the previous indexes stage
+#[derive(Debug)]
 pub struct CPUBatchedNComputeSImpl {
-    pub(crate) context: Arc<Context>,
-    pub(crate) n: usize,
+    config: ChimpConfig,
 }
 
 impl CPUBatchedNComputeSImpl {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+
     pub(crate) fn calculate_s(
         &self,
         input_array: &Vec<f32>,
@@ -30,8 +33,8 @@ impl CPUBatchedNComputeSImpl {
         v: f32,
         v_prev: f32,
     ) -> S {
-        let n = self.n as u32;
-        let full_size = ChimpBufferInfo::get().buffer_size() as u32;
+        let n = self.config.n() as u32;
+        let full_size = self.config.buffer_size() as u32;
         let v_u32: u32 = bytemuck::cast(v);
         let v_prev_u32: u32 = bytemuck::cast(v_prev);
         let i = v_prev_u32 ^ v_u32;
@@ -53,7 +56,7 @@ impl CPUBatchedNComputeSImpl {
 
 impl MaxGroupGnostic for CPUBatchedNComputeSImpl {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 
@@ -61,34 +64,26 @@ impl MaxGroupGnostic for CPUBatchedNComputeSImpl {
 impl ComputeS for CPUBatchedNComputeSImpl {
     async fn compute_s(
         &self,
-        values: &mut [f32],
+        values: &[f32],
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> anyhow::Result<()> {
-        let workgroup_count = self.get_max_number_of_groups(values.len());
+    ) -> Result<(), ChimpError> {
+        // the values are padded with 0's to fill their last buffer
+        let padded_len =
+            values.len() + padding_to_fit_buffer_count(values.len(), self.config.buffer_size());
+        let workgroup_count = self.get_max_number_of_groups(padded_len);
         //info!("The wgpu workgroup size: {}", &workgroup_count);
 
         let size_of_s = size_of::<S>();
-        let bytes = values.len() + 1;
+        let bytes = padded_len + 1;
 
-        let staging_buffer = BufferWrapper::stage_with_size(
-            self.context.device(),
-            buffers.previous_index_buffer().size() as BufferAddress,
-            None,
-        );
-        let mut previous_index = get_from_gpu::<u32>(
-            self.context.as_ref(),
-            buffers.previous_index_buffer().buffer(),
-            buffers.previous_index_buffer().size() as BufferAddress,
-            staging_buffer.buffer(),
-        )
-        .await?;
+        let mut previous_index = buffers.previous_index_buffer().host_slice::<u32>().to_vec();
 
         let mut padded_values = Vec::from(values);
-        padded_values.push(0f32);
+        padded_values.resize(padded_len + 1, 0f32);
         let mut s_array = vec![S::default(); bytes];
         // let mut previous_index = vec![0u32; bytes];
-        let chunks = ChimpBufferInfo::get().chunks();
+        let chunks = self.config.buffer_info().chunks();
         for workgroup in (0..workgroup_count) {
             for invocation in 0..256 {
                 for i in (0..chunks) {
@@ -106,28 +101,10 @@ impl ComputeS for CPUBatchedNComputeSImpl {
             }
         }
 
-        // let input_buffer = BufferWrapper::storage_with_content(
-        //     self.context.device(),
-        //     bytemuck::cast_slice(&padded_values),
-        //     WgpuGroupId::new(0, 0),
-        //     Some("Input buffer"),
-        // );
-        // buffers.set_input_buffer(input_buffer);
-        let s_buffer = BufferWrapper::storage_with_content(
-            self.context.device(),
-            bytemuck::cast_slice(&s_array),
-            WgpuGroupId::new(0, 0),
-            Some("Input buffer"),
-        );
-        buffers.set_s_buffer(s_buffer);
-        let chunk_buffer = BufferWrapper::uniform_with_content(
-            self.context.device(),
-            bytemuck::bytes_of(&ChimpBufferInfo::get().chunks()),
-            WgpuGroupId::new(0, 3),
-            Some("Input buffer"),
-        );
-        buffers.set_chunks(chunk_buffer);
-        step!(Step::ComputeS, {
+        buffers.set_s_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &s_array,
+        )));
+        step!(self.config, Step::ComputeS, {
             s_array.into_iter().map(|s| format!("{}\n", s))
         });
         Ok(())
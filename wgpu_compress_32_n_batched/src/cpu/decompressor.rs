@@ -1,253 +1,156 @@
 use async_trait::async_trait;
-use compress_utils::context::Context;
-use compress_utils::cpu_compress::{DecompressionError, Decompressor};
-use compress_utils::general_utils::{
-    trace_steps, ChimpBufferInfo, DecompressResult, MaxGroupGnostic, Step,
-};
-use compress_utils::{
-    execute_compute_shader, step, time_it, wgpu_utils, BufferWrapper, WgpuGroupId,
-};
+use compress_utils::config::{ChimpConfig, ConfigError};
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, HEADER_SIZE};
+use compress_utils::general_utils::{DecompressResult, MaxGroupGnostic, Step};
+use compress_utils::{step, time_it};
 use itertools::Itertools;
 use log::info;
-use pollster::FutureExt;
 use std::cmp::{max, min};
 use std::fs;
-use std::sync::Arc;
-use std::time::Instant;
-use wgpu::{Device, Queue};
-use wgpu_compress_32_batched::cpu::finalize::extract_bits;
-use wgpu_types::BufferAddress;
+use wgpu_compress_32_batched::cpu::bits::extract_bits;
+
+/// The error of a block whose bitstream cannot be decoded, [BatchedCPUNDecompressor::decompress_block]
+/// replaces it with the position of the block in the stream
+const CORRUPT_BLOCK: ChimpError = ChimpError::CorruptInput {
+    block: 0,
+    byte_offset: 0,
+};
 
 #[async_trait]
 impl Decompressor<f32> for BatchedCPUNDecompressor {
-    #[allow(unused)]
     async fn decompress(
         &self,
-        compressed_bytes_vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f32>, DecompressionError> {
+        compressed_bytes_vec: &[u8],
+    ) -> Result<DecompressResult<f32>, ChimpError> {
         let mut current_index = 0usize;
-        let mut uncompressed_values = Vec::new();
+        let uncompressed_values;
         let mut total_millis = 0;
-        let mut skip_time = 0;
+        let (header, compressed_bytes_vec) =
+            ChimpHeader::split(compressed_bytes_vec, CodecVariant::ChimpN32)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        header.expect_n(self.config.n())?;
         time_it!(
             {
-                let compressed_bytes_vec: &[u32] = bytemuck::cast_slice(&compressed_bytes_vec);
+                let compressed_bytes_vec = compressed_bytes_vec
+                    .chunks_exact(size_of::<u32>())
+                    .map(|it| u32::from_le_bytes(it.try_into().unwrap()))
+                    .collect_vec();
                 let mut vec_window = Vec::new();
                 let mut total_uncompressed_values = 0;
                 let mut input_indexes = Vec::new();
+                let mut value_counts = Vec::new();
                 while current_index < compressed_bytes_vec.len() {
-                    while current_index < compressed_bytes_vec.len() {
-                        let old_index = current_index;
-                        let buffer_value_count = compressed_bytes_vec[current_index] + 1;
-                        current_index += 1;
-
-                        let size_in_bytes = compressed_bytes_vec[current_index];
-                        let size = size_in_bytes as usize / size_of::<u32>();
-                        current_index += 1;
-                        if (vec_window.len() + size as usize) * size_of::<u32>() as usize
-                            >= self.context.get_max_storage_buffer_size()
-                        {
-                            current_index = old_index;
-                            break;
-                        }
-
-                        if (input_indexes.len() + 1) * ChimpBufferInfo::get().buffer_size() * 4
-                            >= self.context.get_max_storage_buffer_size()
-                        {
-                            current_index = old_index;
-                            break;
-                        }
-
-                        vec_window.extend(
-                            compressed_bytes_vec[current_index..current_index + (size as usize)]
-                                .to_vec(),
-                        );
-
-                        input_indexes.push(vec_window.len() as u32);
-                        current_index += size as usize;
-                        total_uncompressed_values += buffer_value_count as usize
-                    }
-                    input_indexes.insert(0, 0);
-                    //Block is as many buffers fit into the gpu the distinction is made for compatibility reasons
-                    let block_values = self
-                        .decompress_block(
-                            vec_window.as_slice(),
-                            input_indexes.as_slice(),
-                            ChimpBufferInfo::get().buffer_size(),
-                            &mut skip_time,
-                        )
-                        .await?;
-
-                    uncompressed_values.extend(block_values[0..total_uncompressed_values].iter());
-                    vec_window.clear();
-                    total_uncompressed_values = 0;
-                    input_indexes.clear();
+                    let buffer_value_count = compressed_bytes_vec[current_index] + 1;
+                    current_index += 1;
+
+                    let size_in_bytes = compressed_bytes_vec[current_index];
+                    let size = size_in_bytes as usize / size_of::<u32>();
+                    current_index += 1;
+
+                    vec_window.extend_from_slice(
+                        &compressed_bytes_vec[current_index..current_index + size],
+                    );
+
+                    input_indexes.push(vec_window.len() as u32);
+                    value_counts.push(buffer_value_count as u32);
+                    current_index += size;
+                    total_uncompressed_values += buffer_value_count as usize
                 }
+                input_indexes.insert(0, 0);
+                let block_values = self.decompress_block(
+                    vec_window.as_slice(),
+                    input_indexes.as_slice(),
+                    value_counts.as_slice(),
+                    self.config.buffer_size(),
+                )?;
+
+                uncompressed_values = block_values[0..total_uncompressed_values].to_vec();
             },
             total_millis,
             "decompression"
         );
-        step!(Step::Decompress, {
+        step!(self.config, Step::Decompress, {
             uncompressed_values
                 .iter()
                 .map(|it: &f32| it.to_string())
                 .into_iter()
         });
-        Ok(DecompressResult(uncompressed_values, skip_time))
+        Ok(uncompressed_values.into())
     }
 }
 
+/// Host decoder of the ChimpN 32 bit format, it needs no wgpu device
+#[derive(Debug, Clone)]
 pub struct BatchedCPUNDecompressor {
-    context: Arc<Context>,
-    n: usize,
+    config: ChimpConfig,
 }
 impl MaxGroupGnostic for BatchedCPUNDecompressor {
-    fn get_max_number_of_groups(&self, _content_len: usize) -> usize {
-        self.context().get_max_workgroup_size()
+    fn get_max_number_of_groups(&self, content_len: usize) -> usize {
+        max(content_len, 1)
     }
 }
 impl Default for BatchedCPUNDecompressor {
     fn default() -> Self {
         Self {
-            context: Arc::new(Context::initialize_default_adapter().block_on().unwrap()),
-            n: 64,
+            config: ChimpConfig::default(),
         }
     }
 }
 impl BatchedCPUNDecompressor {
-    pub(crate) async fn decompress_block(
+    /// Decode the blocks starting at the [input_indexes] of [compressed_value_slice], a block that
+    /// cannot be decoded is reported with its index and its offset in the stream, header included
+    pub(crate) fn decompress_block(
         &self,
         compressed_value_slice: &[u32],
         input_indexes: &[u32],
+        value_counts: &[u32],
         buffer_value_count: usize,
-        skip_time: &mut u128,
-    ) -> Result<Vec<f32>, DecompressionError> {
-        //how many buffers fit into the GPU
-        let workgroup_count = self.get_max_number_of_groups(input_indexes.len());
-
-        //how many iterations I need to fully decompress all the buffers
-        let iterator_count = ((input_indexes.len() - 1) / workgroup_count) + 1;
-
-        let instant = Instant::now();
-        let input_storage_buffer = BufferWrapper::storage_with_content(
-            self.device(),
-            bytemuck::cast_slice(&compressed_value_slice),
-            WgpuGroupId::new(0, 1),
-            Some("Storage input Buffer"),
-        );
-        //info!("Total output values: {}", buffer_value_count);
-        let in_size = BufferWrapper::storage_with_content(
-            self.device(),
-            bytemuck::cast_slice(&input_indexes),
-            WgpuGroupId::new(0, 3),
-            Some("Total bytes input"),
-        );
-        let out_buffer_size =
-            (input_indexes.len() - 1) * ChimpBufferInfo::get().buffer_size() * size_of::<f32>();
-        let out_staging = BufferWrapper::stage_with_size(
-            self.device(),
-            out_buffer_size as BufferAddress,
-            Some("Staging output Buffer"),
-        );
-        let out_storage_buffer = BufferWrapper::storage_with_size(
-            self.device(),
-            out_buffer_size as BufferAddress,
-            WgpuGroupId::new(0, 0),
-            Some("Storage output Buffer"),
-        );
-        *skip_time += instant.elapsed().as_millis();
-
-        let mut output =
-            vec![0f32; (input_indexes.len() - 1) * ChimpBufferInfo::get().buffer_size()];
-        let mut last_lead_array =
-            vec![0u32; (input_indexes.len() - 1) * ChimpBufferInfo::get().buffer_size()];
+    ) -> Result<Vec<f32>, ChimpError> {
+        let workgroup_count = self.get_max_number_of_groups(input_indexes.len() - 1);
+        let output = vec![0f32; workgroup_count * self.config.buffer_size()];
+        let last_lead_array = vec![0u32; workgroup_count * self.config.buffer_size()];
         let mut writer = CPUDecompressorNWriter {
-            n: self.n,
-            size: buffer_value_count,
-            input_indexes: input_indexes.to_vec(),
+            n: self.config.n(),
             input: compressed_value_slice.to_vec(),
-            input_size: input_indexes.len() - 1,
             output,
             last_lead_array,
         };
-        let workgroup_count = min(workgroup_count, self.context.get_max_workgroup_size());
-        for iteration in 0..iterator_count {
-            let is_last_iteration = iteration == iterator_count - 1;
-            let offset = iteration * workgroup_count;
-            let next = if is_last_iteration {
-                input_indexes.len()
-            } else {
-                ((iteration + 1) * workgroup_count) + 1
-            };
-            let iteration_input_indexes = if is_last_iteration {
-                input_indexes[iteration * workgroup_count..].to_vec()
-            } else {
-                input_indexes[iteration * workgroup_count..(iteration + 1) * workgroup_count]
-                    .to_vec()
-            };
-            let first_index = iteration_input_indexes[0] as usize;
-
-            let iteration_compressed_values = if is_last_iteration {
-                compressed_value_slice[first_index..].to_vec()
-            } else {
-                compressed_value_slice
-                    [first_index..(iteration_input_indexes.last().unwrap().to_owned() as usize)]
-                    .to_vec()
-            };
-
-            let size_uniform = BufferWrapper::uniform_with_content(
-                self.device(),
-                bytemuck::bytes_of(&buffer_value_count),
-                WgpuGroupId::new(0, 2),
-                Some("Total input values"),
-            );
-
-            let input_size_uniform = BufferWrapper::uniform_with_content(
-                self.device(),
-                bytemuck::bytes_of(&compressed_value_slice.len()),
-                WgpuGroupId::new(0, 4),
-                Some("Total input buffer length"),
-            );
-
-            for workgroup in 0..(iteration_input_indexes.len() - 1) {
-                let offset = iteration * self.context.get_max_workgroup_size();
-                writer.input_size = iteration_compressed_values.len();
-                writer.write(
-                    input_indexes[offset + workgroup] as usize,
-                    (offset + workgroup) * buffer_value_count,
+        for workgroup in 0..input_indexes.len() - 1 {
+            writer
+                .write(
+                    input_indexes[workgroup] as usize,
+                    workgroup * buffer_value_count,
+                    value_counts[workgroup],
                 )
-            }
+                .map_err(|_| ChimpError::CorruptInput {
+                    block: workgroup,
+                    // every block before it adds its two word prefix to the payload
+                    byte_offset: HEADER_SIZE
+                        + (input_indexes[workgroup] as usize + 2 * workgroup) * size_of::<u32>(),
+                })?;
         }
-        // let instant = Instant::now();
-        // let result = wgpu_utils::get_from_gpu::<f32>(
-        //     self.context(),
-        //     out_storage_buffer.buffer(),
-        //     out_storage_buffer.size() as BufferAddress,
-        //     out_staging.buffer(),
-        // )
-        // .await?;
-        // *skip_time += instant.elapsed().as_millis();
-
-        //info!("Output result size: {}", result.len());
         Ok(writer.output)
     }
 
-    pub fn new(context_builder: impl Into<Arc<Context>>, n: usize) -> Self {
-        Self {
-            context: context_builder.into(),
-            n,
-        }
+    pub fn new(n: usize) -> Result<Self, ConfigError> {
+        Ok(Self {
+            config: ChimpConfig::default().with_n(n)?,
+        })
     }
 
-    pub fn context(&self) -> &Context {
-        &self.context
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
     }
 
-    pub fn device(&self) -> &Device {
-        self.context.device()
+    pub fn n(&self) -> usize {
+        self.config.n()
     }
-    pub fn queue(&self) -> &Queue {
-        self.context.queue()
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
     }
 }
 
@@ -255,27 +158,22 @@ pub struct CPUDecompressorNWriter {
     input: Vec<u32>,
     n: usize,
     output: Vec<f32>,
-    input_indexes: Vec<u32>,
     last_lead_array: Vec<u32>,
-    size: usize,
-    input_size: usize,
 }
 impl CPUDecompressorNWriter {
-    pub fn write(&mut self, input_idx: usize, output_idx: usize) {
-        let n = self.n;
+    /// Decode the [value_count] values of the block at [input_idx] into the output from
+    /// [output_idx] on
+    pub fn write(
+        &mut self,
+        input_idx: usize,
+        output_idx: usize,
+        value_count: u32,
+    ) -> Result<(), ChimpError> {
         let log2n = self.n.ilog2();
 
-        //Index of the byte we are in
-        let mut current_index = input_idx + 1usize;
-        //Current Remaining offset
-        let mut current_offset = 0u32;
+        let mut current_info = CurrentInfo::new(input_idx as u32 + 1, 0u32);
 
-        let mut current_info = CurrentInfo::new(current_index as u32, current_offset);
-
-        let mut first_num = self.input[(current_info.current_index - 1u32) as usize];
-        let mut last_num: u32 = first_num;
-        let mut last_lead = 0u32;
-        let mut significant_bits = 0u32;
+        let first_num = self.word(current_info.current_index - 1u32)?;
 
         let mut output_index = output_idx;
 
@@ -283,22 +181,14 @@ impl CPUDecompressorNWriter {
         output_index += 1usize;
         current_info.current_offset += 32u32;
 
-        let mut value = 0u32;
-        for i in 1..self.size {
-            // if we have not finished reading values from the uncompressed buffers
-            if current_info.current_index >= (self.input_size as u32 - 1u32)
-                && current_info.current_offset <= 1u32
-            {
-                break;
-            }
-
+        for _ in 1..value_count {
             //if current bit value==1
-            if self.get_bit_at_index(current_info.current_index, current_info.current_offset)
+            if self.get_bit_at_index(current_info.current_index, current_info.current_offset)?
                 == 1u32
             {
                 current_info = self.decr_counter_capped_at_32(current_info, 1u32);
                 let recalc_lead = self
-                    .get_bit_at_index(current_info.current_index, current_info.current_offset)
+                    .get_bit_at_index(current_info.current_index, current_info.current_offset)?
                     == 1;
                 current_info = self.decr_counter_capped_at_32(current_info, 1u32);
 
@@ -306,31 +196,29 @@ impl CPUDecompressorNWriter {
                     current_info.current_index,
                     current_info.current_offset,
                     log2n,
-                ) as usize;
+                )? as usize;
                 current_info = self.decr_counter_capped_at_32(current_info, log2n);
-                let mut last_num = bytemuck::cast(self.output[output_index - compare_offset]);
-                let mut lead = self.last_lead_array[output_index - compare_offset];
+                let reference = Self::reference(output_idx, output_index, compare_offset)?;
+                let last_num: u32 = bytemuck::cast(self.output[reference]);
+                let mut lead = self.last_lead_array[reference];
                 if recalc_lead {
                     lead = self.reinterpret_num(
                         current_info.current_index,
                         current_info.current_offset,
                         5u32,
-                    );
+                    )?;
                     current_info = self.decr_counter_capped_at_32(current_info, 5u32);
                 }
-                significant_bits = 32u32 - lead;
+                let mut significant_bits = 32u32 - lead;
                 if significant_bits == 0u32 {
                     significant_bits = 32u32;
                 }
-                value = self.reinterpret_num(
+                let value = self.reinterpret_num(
                     current_info.current_index,
                     current_info.current_offset,
-                    (significant_bits) as u32,
-                );
-                current_info =
-                    self.decr_counter_capped_at_32(current_info, (significant_bits) as u32);
-                value = value ^ last_num;
-                last_num = value;
+                    significant_bits,
+                )? ^ last_num;
+                current_info = self.decr_counter_capped_at_32(current_info, significant_bits);
                 self.last_lead_array[output_index] = lead;
 
                 self.output[output_index] = bytemuck::cast(value);
@@ -338,50 +226,51 @@ impl CPUDecompressorNWriter {
             } else if self.get_bit_at_index(
                 current_info.current_index,
                 current_info.current_offset - 1u32,
-            ) == 1u32
+            )? == 1u32
             {
                 current_info = self.decr_counter_capped_at_32(current_info, 2u32);
                 let compare_offset = self.reinterpret_num(
                     current_info.current_index,
                     current_info.current_offset,
                     log2n,
-                ) as usize;
+                )? as usize;
                 current_info = self.decr_counter_capped_at_32(current_info, log2n);
-                let mut last_num = bytemuck::cast(self.output[output_index - compare_offset]);
+                let reference = Self::reference(output_idx, output_index, compare_offset)?;
+                let last_num: u32 = bytemuck::cast(self.output[reference]);
 
                 let lead = self.reinterpret_num(
                     current_info.current_index,
                     current_info.current_offset,
                     5u32,
-                );
+                )?;
                 current_info = self.decr_counter_capped_at_32(current_info, 5u32);
 
                 let mut significant_bits = self.reinterpret_num(
                     current_info.current_index,
                     current_info.current_offset,
                     5u32,
-                );
+                )?;
                 current_info = self.decr_counter_capped_at_32(current_info, 5u32);
 
                 if significant_bits == 0u32 {
                     significant_bits = 32u32;
                 }
+                if lead + significant_bits > 32 {
+                    return Err(CORRUPT_BLOCK);
+                }
 
                 let trail = 32u32 - lead - significant_bits;
 
-                value = self.reinterpret_num(
+                let value = self.reinterpret_num(
                     current_info.current_index,
                     current_info.current_offset,
-                    (32u32 - lead - trail) as u32,
-                );
+                    significant_bits,
+                )?;
 
-                current_info =
-                    self.decr_counter_capped_at_32(current_info, (32u32 - lead - trail) as u32);
+                current_info = self.decr_counter_capped_at_32(current_info, significant_bits);
 
-                value <<= trail;
-                value ^= last_num;
+                let value = (value << trail) ^ last_num;
                 self.last_lead_array[output_index] = lead;
-                last_num = value;
 
                 self.output[output_index] = bytemuck::cast(value);
                 output_index += 1usize;
@@ -391,55 +280,77 @@ impl CPUDecompressorNWriter {
                     current_info.current_index,
                     current_info.current_offset,
                     log2n,
-                ) as usize;
+                )? as usize;
                 current_info = self.decr_counter_capped_at_32(current_info, log2n);
-                let mut last_num: u32 = bytemuck::cast(self.output[output_index - compare_offset]);
-                let mut lead = self.last_lead_array[output_index - compare_offset];
-                self.output[output_index] = bytemuck::cast(last_num);
+                let reference = Self::reference(output_idx, output_index, compare_offset)?;
+                self.output[output_index] = self.output[reference];
                 self.last_lead_array[output_index] = 32u32;
                 output_index += 1usize;
             }
         }
+        Ok(())
     }
 
-    fn get_bit_at_index(&self, array_index: u32, position: u32) -> u32 {
-        let mut index = if (position == 0u32) {
+    /// The word at [index], a bitstream that reads past the input is corrupted
+    fn word(&self, index: u32) -> Result<u32, ChimpError> {
+        self.input.get(index as usize).copied().ok_or(CORRUPT_BLOCK)
+    }
+
+    /// The index of the value [compare_offset] values before [output_index], a reference before
+    /// the first value of the block starting at [output_idx] is corrupted
+    fn reference(
+        output_idx: usize,
+        output_index: usize,
+        compare_offset: usize,
+    ) -> Result<usize, ChimpError> {
+        output_index
+            .checked_sub(compare_offset)
+            .filter(|it| *it >= output_idx)
+            .ok_or(CORRUPT_BLOCK)
+    }
+
+    fn get_bit_at_index(&self, array_index: u32, position: u32) -> Result<u32, ChimpError> {
+        let index = if position == 0u32 {
             array_index + 1
         } else {
             array_index
         };
-        let mut f_position = if (position == 0u32) { 32u32 } else { position };
-        return (self.input[index as usize] >> (f_position - 1u32)) & 1u32;
+        let f_position = if position == 0u32 { 32u32 } else { position };
+        Ok((self.word(index)? >> (f_position - 1u32)) & 1u32)
     }
 
     fn decr_counter_capped_at_32(&self, mut value: CurrentInfo, count: u32) -> CurrentInfo {
         let corrected_value = (value.current_offset as i32) - count as i32;
-        (value).current_offset = if (corrected_value > 0) {
+        value.current_offset = if corrected_value > 0 {
             corrected_value as u32
         } else {
             (32 + corrected_value) as u32
         };
-        (value).current_index += if (corrected_value <= 0) { 1 } else { 0 }; //1 if it's true and 0 otherwise
-        return (value);
+        value.current_index += if corrected_value <= 0 { 1 } else { 0 }; //1 if it's true and 0 otherwise
+        value
     }
 
-    fn reinterpret_num(&self, array_index: u32, index: u32, length: u32) -> u32 {
+    fn reinterpret_num(
+        &self,
+        array_index: u32,
+        index: u32,
+        length: u32,
+    ) -> Result<u32, ChimpError> {
         let len = min(length, 32u32);
         if index >= len {
             // Fully within one u32
-            return extract_bits(self.input[array_index as usize], (index - len) as u32, len);
+            Ok(extract_bits(self.word(array_index)?, index - len, len))
         } else {
             // Spans two u32 elements
-            let bits_in_first = index;
             let bits_in_second = length - index;
 
-            let first_part = extract_bits(self.input[array_index as usize], 0u32, index);
+            let first_part = extract_bits(self.word(array_index)?, 0u32, index);
             let second_part = extract_bits(
-                self.input[array_index as usize + 1],
+                self.word(array_index + 1)?,
                 32u32 - bits_in_second,
                 bits_in_second,
             );
-            return (first_part << bits_in_second) | second_part;
+            Ok((first_part << bits_in_second) | second_part)
         }
     }
 }
@@ -0,0 +1,18 @@
+use crate::finalize::Finalize;
+use async_trait::async_trait;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::CompressResult;
+use compress_utils::wgpu_utils::RunBuffers;
+use wgpu_compress_32_batched::cpu::finalize::CPUImpl;
+
+#[async_trait]
+impl Finalize for CPUImpl {
+    async fn finalize(
+        &self,
+        buffers: &mut RunBuffers,
+        padding: usize,
+        _skip_time: &mut u128,
+    ) -> Result<CompressResult, ChimpError> {
+        self.finalize_buffers(buffers, padding).await
+    }
+}
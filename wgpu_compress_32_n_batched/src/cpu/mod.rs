@@ -1,4 +1,11 @@
+#[cfg(feature = "gpu")]
+pub mod calculate_indexes;
+#[cfg(feature = "gpu")]
 pub mod compress;
+#[cfg(feature = "gpu")]
 pub mod compute_s;
 pub mod decompressor;
+#[cfg(feature = "gpu")]
+pub mod finalize;
+#[cfg(feature = "gpu")]
 pub mod previous_indexes;
@@ -1,9 +1,11 @@
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::{ChimpConfig, ConfigError, DEFAULT_N};
 use compress_utils::context::Context;
-use compress_utils::cpu_compress::{DecompressionError, Decompressor};
-use compress_utils::general_utils::{
-    trace_steps, ChimpBufferInfo, DecompressResult, MaxGroupGnostic, Step,
-};
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant};
+use compress_utils::general_utils::{cast_or_copy, DecompressResult, MaxGroupGnostic, Step};
 use compress_utils::{
     execute_compute_shader, step, time_it, wgpu_utils, BufferWrapper, WgpuGroupId,
 };
@@ -19,18 +21,24 @@ use wgpu_types::BufferAddress;
 impl Decompressor<f32> for BatchedGPUNDecompressor {
     async fn decompress(
         &self,
-        compressed_bytes_vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f32>, DecompressionError> {
+        compressed_bytes_vec: &[u8],
+    ) -> Result<DecompressResult<f32>, ChimpError> {
         let mut current_index = 0usize;
         let mut uncompressed_values = Vec::new();
         let mut total_millis = 0;
         let mut skip_time = 0;
+        let (header, compressed_bytes_vec) =
+            ChimpHeader::split(compressed_bytes_vec, CodecVariant::ChimpN32)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        header.expect_n(self.config.n())?;
+        let compressed_words = cast_or_copy::<u32>(compressed_bytes_vec);
         time_it!(
             {
-                let compressed_bytes_vec: &[u32] = bytemuck::cast_slice(&compressed_bytes_vec);
+                let compressed_bytes_vec: &[u32] = &compressed_words;
                 let mut vec_window = Vec::new();
                 let mut total_uncompressed_values = 0;
                 let mut input_indexes = Vec::new();
+                let mut value_counts = Vec::new();
                 while current_index < compressed_bytes_vec.len() {
                     while current_index < compressed_bytes_vec.len() {
                         let old_index = current_index;
@@ -47,7 +55,7 @@ impl Decompressor<f32> for BatchedGPUNDecompressor {
                             break;
                         }
 
-                        if (input_indexes.len() + 1) * ChimpBufferInfo::get().buffer_size() * 4
+                        if (input_indexes.len() + 1) * self.config.buffer_size() * 4
                             >= self.context.get_max_storage_buffer_size()
                         {
                             current_index = old_index;
@@ -60,6 +68,7 @@ impl Decompressor<f32> for BatchedGPUNDecompressor {
                         );
 
                         input_indexes.push(vec_window.len() as u32);
+                        value_counts.push(buffer_value_count as u32);
                         current_index += size as usize;
                         total_uncompressed_values += buffer_value_count as usize
                     }
@@ -69,7 +78,8 @@ impl Decompressor<f32> for BatchedGPUNDecompressor {
                         .decompress_block(
                             vec_window.as_slice(),
                             input_indexes.as_slice(),
-                            ChimpBufferInfo::get().buffer_size(),
+                            value_counts.as_slice(),
+                            self.config.buffer_size(),
                             &mut skip_time,
                         )
                         .await?;
@@ -78,12 +88,13 @@ impl Decompressor<f32> for BatchedGPUNDecompressor {
                     vec_window.clear();
                     total_uncompressed_values = 0;
                     input_indexes.clear();
+                    value_counts.clear();
                 }
             },
             total_millis,
             "decompression"
         );
-        step!(Step::Decompress, {
+        step!(self.config, Step::Decompress, {
             uncompressed_values
                 .iter()
                 .map(|it: &f32| it.to_string())
@@ -95,19 +106,20 @@ impl Decompressor<f32> for BatchedGPUNDecompressor {
 
 pub struct BatchedGPUNDecompressor {
     context: Arc<Context>,
-    n: usize,
+    config: ChimpConfig,
 }
 impl MaxGroupGnostic for BatchedGPUNDecompressor {
     fn get_max_number_of_groups(&self, _content_len: usize) -> usize {
-        self.context().get_max_workgroup_size()
+        self.config.max_workgroup_size(self.context())
     }
 }
-impl Default for BatchedGPUNDecompressor {
-    fn default() -> Self {
-        Self {
-            context: Arc::new(Context::initialize_default_adapter().block_on().unwrap()),
-            n: 128,
-        }
+impl BatchedGPUNDecompressor {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(
+            Context::initialize_default_adapter().block_on()?,
+            DEFAULT_N,
+        )?)
     }
 }
 impl BatchedGPUNDecompressor {
@@ -115,9 +127,10 @@ impl BatchedGPUNDecompressor {
         &self,
         compressed_value_slice: &[u32],
         input_indexes: &[u32],
+        value_counts: &[u32],
         buffer_value_count: usize,
         skip_time: &mut u128,
-    ) -> Result<Vec<f32>, DecompressionError> {
+    ) -> Result<Vec<f32>, ChimpError> {
         //how many buffers fit into the GPU
         let workgroup_count = self.get_max_number_of_groups(input_indexes.len()) * 256;
 
@@ -133,15 +146,21 @@ impl BatchedGPUNDecompressor {
             Some("Storage input Buffer"),
         );
 
-        let in_size = BufferWrapper::storage_with_content(
+        let blocks = input_indexes
+            .iter()
+            .zip(value_counts)
+            .flat_map(|(start, count)| [*start, *count])
+            .collect::<Vec<u32>>();
+        let block_storage = BufferWrapper::storage_with_content(
             self.device(),
-            bytemuck::cast_slice(&input_indexes),
+            bytemuck::cast_slice(&blocks),
             WgpuGroupId::new(0, 3),
-            Some("Total bytes input"),
+            Some("Block starts and value counts"),
         );
 
         let out_buffer_size =
-            (input_indexes.len() - 1) * ChimpBufferInfo::get().buffer_size() * size_of::<f32>();
+            (input_indexes.len() - 1) * self.config.buffer_size() * size_of::<f32>();
+        self.context().check_buffer_size(out_buffer_size)?;
 
         let out_staging = BufferWrapper::stage_with_size(
             self.device(),
@@ -151,7 +170,7 @@ impl BatchedGPUNDecompressor {
         let last_lead_storage = BufferWrapper::storage_with_size(
             self.device(),
             out_buffer_size as BufferAddress,
-            WgpuGroupId::new(0, 5),
+            WgpuGroupId::new(0, 4),
             Some("Last Lead storage Buffer"),
         );
 
@@ -164,7 +183,10 @@ impl BatchedGPUNDecompressor {
 
         *skip_time += instant.elapsed().as_millis();
 
-        let workgroup_count = min(workgroup_count, self.context.get_max_workgroup_size());
+        let workgroup_count = min(
+            workgroup_count,
+            self.config.max_workgroup_size(&self.context),
+        );
         for iteration in 0..iterator_count {
             //split all the buffers to the chunks each iteration will use
             let is_last_iteration = iteration == iterator_count - 1;
@@ -191,26 +213,16 @@ impl BatchedGPUNDecompressor {
                 WgpuGroupId::new(0, 2),
                 Some("Total input values"),
             );
-
-            let input_size_uniform = BufferWrapper::uniform_with_content(
-                self.device(),
-                bytemuck::bytes_of(&compressed_value_slice.len()),
-                WgpuGroupId::new(0, 4),
-                Some("Total input buffer length"),
-            );
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                iteration * self.context.get_max_workgroup_size()
-            );
-            let n = format!("let n={}u;", self.n);
-            let log2n = format!("let log2n={}u;", self.n.ilog2());
-            let total_threads = format!("let total_threads={}u;", input_indexes.len());
-            let shader_code = include_str!("shaders/decompress.wgsl")
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@n", &n)
-                .replace("//@log2n", &log2n)
-                .replace("//@total_threads", &total_threads)
-                .to_string();
+            let shader_code = shaders::DECOMPRESS
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (iteration * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .bind("n", self.config.n() as u32)
+                .bind("log2n", self.config.n().ilog2() as u32)
+                .bind("total_threads", value_counts.len() as u32)
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &shader_code,
@@ -220,8 +232,7 @@ impl BatchedGPUNDecompressor {
                     &input_storage_buffer,
                     &out_staging,
                     &size_uniform,
-                    &in_size,
-                    &input_size_uniform
+                    &block_storage
                 ],
                 iteration_input_indexes.div_ceil(256),
                 Some("decompress pass")
@@ -241,11 +252,20 @@ impl BatchedGPUNDecompressor {
         Ok(result)
     }
 
-    pub fn new(context_builder: impl Into<Arc<Context>>, n: usize) -> Self {
-        Self {
+    pub fn new(context_builder: impl Into<Arc<Context>>, n: usize) -> Result<Self, ConfigError> {
+        Ok(Self {
             context: context_builder.into(),
-            n,
-        }
+            config: ChimpConfig::default().with_n(n)?,
+        })
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
     }
 
     pub fn context(&self) -> &Context {
@@ -1,9 +1,12 @@
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::general_utils::{ChimpBufferInfo, MaxGroupGnostic, Step, trace_steps};
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{MaxGroupGnostic, Step};
 use compress_utils::types::ChimpOutput;
 use compress_utils::wgpu_utils::RunBuffers;
-use compress_utils::{BufferWrapper, WgpuGroupId, execute_compute_shader, wgpu_utils};
+use compress_utils::{execute_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
 use std::cmp::max;
 use std::fs;
 use std::ops::Div;
@@ -17,17 +20,17 @@ pub trait FinalCompress: MaxGroupGnostic {
         &self,
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> anyhow::Result<()>;
+    ) -> Result<(), ChimpError>;
 }
 
 pub struct FinalCompressImpl {
     context: Arc<Context>,
-    n: usize,
+    config: ChimpConfig,
 }
 
 impl FinalCompressImpl {
-    pub fn new(context: Arc<Context>, n: usize) -> Self {
-        Self { context, n }
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
     }
 
     pub fn context(&self) -> &Context {
@@ -37,7 +40,7 @@ impl FinalCompressImpl {
 
 impl MaxGroupGnostic for FinalCompressImpl {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 
@@ -47,7 +50,7 @@ impl FinalCompress for FinalCompressImpl {
         &self,
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> anyhow::Result<()> {
+    ) -> Result<(), ChimpError> {
         let size_of_output = size_of::<ChimpOutput>();
         let input_length = buffers.input_buffer().size() / size_of::<f32>();
 
@@ -56,8 +59,8 @@ impl FinalCompress for FinalCompressImpl {
         let workgroup_count = self.get_max_number_of_groups(input_length);
 
         let instant = Instant::now();
-        let mut output_storage_buffer = BufferWrapper::storage_with_size(
-            self.context().device(),
+        let mut output_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
             output_buffer_size,
             WgpuGroupId::new(0, 2),
             Some("Storage Output Buffer"),
@@ -81,23 +84,17 @@ impl FinalCompress for FinalCompressImpl {
                 .chunks_uniform_mut()
                 .with_binding(WgpuGroupId::new(0, 3));
         }
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let last_pass = format!(
-                "let last_pass={}u;",
-                if i == iterations - 1 { 1 } else { 0 }
-            );
-            let log2n = format!("let log2n={}u;", self.n.ilog2());
-            let temp = include_str!("shaders/chimp_compress.wgsl")
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@last_pass", &last_pass)
-                .replace("//@log2n", &log2n)
-                .to_string();
+            let temp = shaders::CHIMP_COMPRESS
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .bind("log2n", self.config.n().ilog2() as u32)
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &temp,
@@ -111,7 +108,7 @@ impl FinalCompress for FinalCompressImpl {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("compress pass")
             );
@@ -128,23 +125,16 @@ impl FinalCompress for FinalCompressImpl {
                 .input_buffer_mut()
                 .with_binding(WgpuGroupId::new(0, 1));
         }
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let last_pass = format!(
-                "let last_pass={}u;",
-                if i == iterations - 1 { 1 } else { 0 }
-            );
-            let log2n = format!("let log2n={}u;", self.n.ilog2());
-            let temp = include_str!("shaders/initialize_first_per_buffer.wgsl")
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@last_pass", &last_pass)
-                .replace("//@log2n", &log2n)
-                .to_string();
+            let temp = shaders::INITIALIZE_FIRST_PER_BUFFER
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &temp,
@@ -156,16 +146,16 @@ impl FinalCompress for FinalCompressImpl {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("initialize pass")
             );
         }
 
         buffers.set_compressed_buffer(output_storage_buffer);
-        if trace_steps().contains(&Step::Compress) {
-            let output_staging_buffer = BufferWrapper::stage_with_size(
-                self.context().device(),
+        if self.config.traces(&Step::Compress) {
+            let output_staging_buffer = BufferWrapper::pooled_stage_with_size(
+                self.context(),
                 buffers.compressed_buffer().size() as BufferAddress,
                 None,
             );
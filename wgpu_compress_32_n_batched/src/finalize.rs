@@ -1,7 +1,9 @@
-use anyhow::Result;
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo, CompressResult, Step};
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{CompressResult, Step};
 use compress_utils::types::ChimpOutput;
 use compress_utils::wgpu_utils::RunBuffers;
 use compress_utils::{
@@ -22,17 +24,18 @@ pub trait Finalize {
         buffers: &mut RunBuffers,
         padding: usize,
         skip_time: &mut u128,
-    ) -> Result<general_utils::CompressResult>;
+    ) -> Result<general_utils::CompressResult, ChimpError>;
 }
 
 #[derive(Debug)]
 pub struct Finalizer {
     context: Arc<Context>,
+    config: ChimpConfig,
 }
 
 impl Finalizer {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
     }
 
     pub fn context(&self) -> &Context {
@@ -55,7 +58,7 @@ impl Finalize for Finalizer {
         buffers: &mut RunBuffers,
         padding: usize,
         skip_time: &mut u128,
-    ) -> Result<CompressResult> {
+    ) -> Result<CompressResult, ChimpError> {
         let size_of_out = size_of::<u32>();
 
         let index_len = buffers.index_buffer().size() / size_of::<u32>();
@@ -66,18 +69,17 @@ impl Finalize for Finalizer {
 
         let chimp_input_length_no_padding = chimp_input_len - padding;
 
-        let size = ChimpBufferInfo::get().buffer_size();
+        let size = self.config.buffer_size();
 
-        let last_size = if chimp_input_length_no_padding % ChimpBufferInfo::get().buffer_size() != 0
-        {
-            chimp_input_length_no_padding % ChimpBufferInfo::get().buffer_size()
+        let last_size = if chimp_input_length_no_padding % self.config.buffer_size() != 0 {
+            chimp_input_length_no_padding % self.config.buffer_size()
         } else {
             size
         } as u32;
 
         //info!("The length of the input vec: {}", chimp_input_length);
-        let index_staging = BufferWrapper::stage_with_size(
-            self.context().device(),
+        let index_staging = BufferWrapper::pooled_stage_with_size(
+            self.context(),
             buffers.index_buffer().size() as BufferAddress,
             None,
         );
@@ -94,17 +96,17 @@ impl Finalize for Finalizer {
             (size_of_out * (*indexes.last().unwrap() as usize)) as BufferAddress;
         //info!("The Output buffer size in bytes: {}", &output_buffer_size);
 
-        let workgroup_count = chimp_input_len.div(ChimpBufferInfo::get().buffer_size());
+        let workgroup_count = chimp_input_len.div(self.config.buffer_size());
         //info!("The wgpu workgroup size: {}", &workgroup_count);
 
         let instant = Instant::now();
-        let out_stage_buffer = BufferWrapper::stage_with_size(
-            self.context().device(),
+        let out_stage_buffer = BufferWrapper::pooled_stage_with_size(
+            self.context(),
             output_buffer_size,
             Some("Staging Output Buffer"),
         );
-        let out_storage_buffer = BufferWrapper::storage_with_size(
-            self.context().device(),
+        let out_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
             output_buffer_size,
             WgpuGroupId::new(0, 0),
             Some("Staging Output Buffer"),
@@ -121,9 +123,9 @@ impl Finalize for Finalizer {
         //     WgpuGroupId::new(0, 1),
         //     Some("Staging Output Buffer"),
         // );
-        let size_uniform = BufferWrapper::uniform_with_content(
-            self.device(),
-            bytemuck::bytes_of(&(ChimpBufferInfo::get().buffer_size() as u32)),
+        let size_uniform = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
+            bytemuck::bytes_of(&(self.config.buffer_size() as u32)),
             WgpuGroupId::new(0, 2),
             Some("Size Uniform Buffer"),
         );
@@ -139,33 +141,28 @@ impl Finalize for Finalizer {
         //     WgpuGroupId::new(0, 3),
         //     Some("Useful Storage Buffer"),
         // );
-        let last_size_uniform = BufferWrapper::uniform_with_content(
-            self.device(),
+        let last_size_uniform = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
             bytemuck::bytes_of(&last_size),
             WgpuGroupId::new(0, 4),
             Some("Useful Staging Buffer"),
         );
         *skip_time += instant.elapsed().as_millis();
 
-        let iterations = workgroup_count / (256 * self.context.get_max_workgroup_size()) + 1;
-        let last_size = workgroup_count % (256 * self.context.get_max_workgroup_size());
+        let iterations =
+            workgroup_count / (256 * self.config.max_workgroup_size(&self.context)) + 1;
+        let last_size = workgroup_count % (256 * self.config.max_workgroup_size(&self.context));
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let last_pass = format!(
-                "let last_pass={}u;",
-                if i == iterations - 1 { 1 } else { 0 }
-            );
-            let total_threads = format!("let total_threads={}u;", workgroup_count);
-            let last_index = format!("let last_index={}u;", (*indexes).last().unwrap());
-            let temp = include_str!("shaders/chimp_finalize_compress.wgsl")
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@last_pass", &last_pass)
-                .replace("//@total_threads", &total_threads)
-                .replace("//@last_index", &last_index)
-                .to_string();
+            let temp = shaders::CHIMP_FINALIZE_COMPRESS
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .bind("total_threads", workgroup_count as u32)
+                .bind("last_pass", u32::from(i == iterations - 1))
+                .bind("last_index", *indexes.last().unwrap())
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &temp,
@@ -180,7 +177,7 @@ impl Finalize for Finalizer {
                 if i == iterations - 1 {
                     last_size.div_ceil(256)
                 } else {
-                    self.context.get_max_workgroup_size().div_ceil(256)
+                    self.config.max_workgroup_size(&self.context).div_ceil(256)
                 },
                 Some("trim pass")
             );
@@ -196,7 +193,7 @@ impl Finalize for Finalizer {
         *skip_time += instant.elapsed().as_millis();
 
         let final_vec = output;
-        step!(&Step::Finalize, {
+        step!(self.config, Step::Finalize, {
             final_vec
                 .iter()
                 .chunks(4)
@@ -1,7 +1,9 @@
-use anyhow::Result;
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo, MaxGroupGnostic, Step};
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{padding_to_fit_buffer_count, MaxGroupGnostic, Step};
 use compress_utils::wgpu_utils::RunBuffers;
 use compress_utils::{execute_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
 use std::cmp::max;
@@ -15,20 +17,20 @@ use wgpu_types::BufferAddress;
 pub trait PreviousIndexes: MaxGroupGnostic {
     async fn calculate_previous_indexes(
         &self,
-        values: &mut [f32],
+        values: &[f32],
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> Result<()>;
+    ) -> Result<(), ChimpError>;
 }
 
 pub struct PreviousIndexesNImpl {
     context: Arc<Context>,
-    n: usize,
+    config: ChimpConfig,
 }
 
 impl PreviousIndexesNImpl {
-    pub fn new(context: Arc<Context>, n: usize) -> Self {
-        Self { context, n }
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
     }
 
     pub fn context(&self) -> &Context {
@@ -48,13 +50,13 @@ impl PreviousIndexesNImpl {
     }
     #[allow(dead_code)]
     pub fn max_work_group_count(&self) -> usize {
-        self.context.get_max_workgroup_size()
+        self.config.max_workgroup_size(&self.context)
     }
 }
 
 impl MaxGroupGnostic for PreviousIndexesNImpl {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 
@@ -62,70 +64,60 @@ impl MaxGroupGnostic for PreviousIndexesNImpl {
 impl PreviousIndexes for PreviousIndexesNImpl {
     async fn calculate_previous_indexes(
         &self,
-        values: &mut [f32],
+        values: &[f32],
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> Result<()> {
+    ) -> Result<(), ChimpError> {
         // Create a shader module and pipeline
 
         //Calculating buffer sizes and workgroup counts
-        let workgroup_count = self.get_max_number_of_groups(values.len());
+        // the values are padded with 0's to fill their last buffer
+        let padded_len =
+            values.len() + padding_to_fit_buffer_count(values.len(), self.config.buffer_size());
+        let workgroup_count = self.get_max_number_of_groups(padded_len);
         //info!("The wgpu workgroup size: {}", &workgroup_count);
 
-        let bytes = values.len() + 1;
+        let bytes = padded_len + 1;
 
         let previous_index_size = (size_of::<u32>() * bytes) as BufferAddress;
 
-        let mut padded_values = Vec::from(values);
-        padded_values.push(0f32);
-
         let instant = Instant::now();
-        let input_storage_buffer = BufferWrapper::storage_with_content(
-            self.device(),
-            bytemuck::cast_slice(padded_values.as_slice()),
+        let input_storage_buffer = BufferWrapper::pooled_storage_with_padded_content(
+            self.context(),
+            bytemuck::cast_slice(values),
+            (size_of::<f32>() * bytes) as BufferAddress,
             (0, 1),
             Some("Storage Input Buffer"),
         );
 
-        let size_uniform = BufferWrapper::uniform_with_content(
-            self.context.device(),
-            bytemuck::bytes_of(&ChimpBufferInfo::get().buffer_size()),
+        let size_uniform = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
+            bytemuck::bytes_of(&self.config.buffer_size()),
             (0, 2),
             None,
         );
-        let previous_index_buffer = BufferWrapper::storage_with_size(
-            self.device(),
+        let previous_index_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
             previous_index_size,
             WgpuGroupId::new(0, 3),
             Some("Storage S Buffer"),
         );
         *skip_time += instant.elapsed().as_millis();
 
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
 
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-
-            let n = format!("let n={}u;", self.n);
-
-            let full_size = format!("let full_size={}u;", ChimpBufferInfo::get().buffer_size());
-
-            let index_size = format!("const indices_size={}u;", 2u32.pow(self.n.ilog2() + 1));
-
-            let temp = include_str!("shaders/calculate_previous_efficient_index.wgsl")
-                .replace(
-                    "@@workgroup_size",
-                    &ChimpBufferInfo::get().buffer_size().to_string(),
+            let temp = shaders::CALCULATE_PREVIOUS_EFFICIENT_INDEX
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
                 )
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@n", &n)
-                .replace("//@indices_size", &index_size)
-                .replace("//@full_size", &full_size)
-                .to_string();
+                .bind("n", self.config.n() as u32)
+                .bind("full_size", self.config.buffer_size() as u32)
+                .bind("indices_size", 2u32.pow(self.config.n().ilog2() + 1))
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &temp,
@@ -133,7 +125,7 @@ impl PreviousIndexes for PreviousIndexesNImpl {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("compute s layout")
             );
@@ -143,9 +135,9 @@ impl PreviousIndexes for PreviousIndexesNImpl {
         buffers.set_previous_index_buffer(previous_index_buffer);
 
         //info!("Output result size: {}", output.len());
-        if trace_steps().contains(&Step::PreviousIndexes) {
-            let previous_index_staging = BufferWrapper::stage_with_size(
-                self.context().device(),
+        if self.config.traces(&Step::PreviousIndexes) {
+            let previous_index_staging = BufferWrapper::pooled_stage_with_size(
+                self.context(),
                 previous_index_size,
                 Some("previous index staging buffer"),
             );
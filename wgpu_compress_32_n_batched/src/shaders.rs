@@ -0,0 +1,167 @@
+//! The WGSL shaders of the batched 32 bit ChimpN pipeline along with the parameters each one is
+//! specialized with, see [compress_utils::shader_template]
+use compress_utils::shader_template::Parameter::{Constant, Override};
+use compress_utils::shader_template::ShaderTemplate;
+
+pub const CALCULATE_FINAL_SIZES: ShaderTemplate = ShaderTemplate::new(
+    "calculate_final_sizes",
+    include_str!("shaders/calculate_final_sizes.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")]);
+
+pub const CALCULATE_PREVIOUS_EFFICIENT_INDEX: ShaderTemplate = ShaderTemplate::new(
+    "calculate_previous_efficient_index",
+    include_str!("shaders/calculate_previous_efficient_index.wgsl"),
+)
+.with_parameters(&[
+    Override("workgroup_offset"),
+    Override("n"),
+    Override("full_size"),
+    Constant("indices_size"),
+]);
+
+/// The variant of [CALCULATE_PREVIOUS_EFFICIENT_INDEX] matching on the trailing zeros of the
+/// values, which no stage runs at the moment
+pub const CALCULATE_PREVIOUS_EFFICIENT_INDEX_BY_TRAILLING: ShaderTemplate = ShaderTemplate::new(
+    "calculate_previous_efficient_index_by_trailling",
+    include_str!("shaders/calculate_previous_efficient_index_by_trailling.wgsl"),
+)
+.with_parameters(&[
+    Override("workgroup_offset"),
+    Override("n"),
+    Override("full_size"),
+]);
+
+pub const CHIMP_COMPRESS: ShaderTemplate = ShaderTemplate::new(
+    "chimp_compress",
+    include_str!("shaders/chimp_compress.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset"), Override("log2n")]);
+
+pub const CHIMP_FINALIZE_COMPRESS: ShaderTemplate = ShaderTemplate::new(
+    "chimp_finalize_compress",
+    include_str!("shaders/chimp_finalize_compress.wgsl"),
+)
+.with_parameters(&[
+    Override("workgroup_offset"),
+    Override("total_threads"),
+    Override("last_pass"),
+    Override("last_index"),
+]);
+
+pub const COMPUTE_S: ShaderTemplate =
+    ShaderTemplate::new("compute_s", include_str!("shaders/compute_s.wgsl"))
+        .with_parameters(&[Override("workgroup_offset")]);
+
+pub const DECOMPRESS: ShaderTemplate =
+    ShaderTemplate::new("decompress", include_str!("shaders/decompress.wgsl")).with_parameters(&[
+        Override("workgroup_offset"),
+        Override("n"),
+        Override("log2n"),
+        Override("total_threads"),
+    ]);
+
+pub const INITIALIZE_FIRST_PER_BUFFER: ShaderTemplate = ShaderTemplate::new(
+    "initialize_first_per_buffer",
+    include_str!("shaders/initialize_first_per_buffer.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")]);
+
+/// Every shader of the pipeline, validated by `compress_utils::shader_validation` in the tests
+pub const ALL: &[ShaderTemplate] = &[
+    CALCULATE_FINAL_SIZES,
+    CALCULATE_PREVIOUS_EFFICIENT_INDEX,
+    CALCULATE_PREVIOUS_EFFICIENT_INDEX_BY_TRAILLING,
+    CHIMP_COMPRESS,
+    CHIMP_FINALIZE_COMPRESS,
+    COMPUTE_S,
+    DECOMPRESS,
+    INITIALIZE_FIRST_PER_BUFFER,
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::calculate_indexes::GPUCalculateIndexes;
+    use crate::chimpn::{compress_batches, ChimpNCpuBatched};
+    use crate::compute_s_shader::ComputeSNImpl;
+    use crate::decompressor::BatchedGPUNDecompressor;
+    use crate::final_compress::FinalCompressImpl;
+    use crate::finalize::{Finalize, Finalizer};
+    use crate::previous_indexes::PreviousIndexesNImpl;
+    use crate::shaders;
+    use async_trait::async_trait;
+    use compress_utils::config::ChimpConfig;
+    use compress_utils::context::Context;
+    use compress_utils::cpu_compress::{Compressor, Decompressor};
+    use compress_utils::error::ChimpError;
+    use compress_utils::general_utils::CompressResult;
+    use compress_utils::shader_validation::{
+        check_cached_layouts, seed_index_buffer, validate_templates, ParameterMatrix,
+    };
+    use compress_utils::wgpu_utils::RunBuffers;
+    use itertools::Itertools;
+    use pollster::FutureExt;
+    use std::sync::Arc;
+
+    /// The GPU [Finalizer], run over the offsets the index calculation writes on a device
+    struct SeededFinalizer(Finalizer);
+
+    #[async_trait]
+    impl Finalize for SeededFinalizer {
+        async fn finalize(
+            &self,
+            buffers: &mut RunBuffers,
+            padding: usize,
+            skip_time: &mut u128,
+        ) -> Result<CompressResult, ChimpError> {
+            seed_index_buffer(self.0.context(), buffers);
+            self.0.finalize(buffers, padding, skip_time).await
+        }
+    }
+
+    #[test]
+    fn test_shaders_validate_offline() {
+        let validated = validate_templates(shaders::ALL, &ParameterMatrix::default())
+            .unwrap_or_else(|error| panic!("{error}"));
+        assert!(validated > shaders::ALL.len());
+    }
+
+    #[test]
+    fn test_stage_layouts_match_their_shaders() {
+        let n = 32;
+        let context = Arc::new(Context::initialize_noop().block_on().unwrap());
+        let config = ChimpConfig::default().with_n(n).unwrap();
+        let values = (0..3000).map(|it| it as f32 / 8.0).collect_vec();
+        compress_batches(
+            values.chunks(values.len()),
+            &config,
+            &PreviousIndexesNImpl::new(context.clone(), config.clone()),
+            &ComputeSNImpl::new(context.clone(), config.clone()),
+            &FinalCompressImpl::new(context.clone(), config.clone()),
+            &GPUCalculateIndexes::new(context.clone(), config.clone()),
+            &SeededFinalizer(Finalizer::new(context.clone(), config.clone())),
+        )
+        .block_on()
+        .unwrap();
+        let compressed = ChimpNCpuBatched::new(n)
+            .unwrap()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        BatchedGPUNDecompressor::new(context.clone(), n)
+            .unwrap()
+            .decompress(compressed.compressed_value_ref())
+            .block_on()
+            .unwrap();
+
+        // no stage runs the trailing zeros variant, so it is only validated offline
+        let skipped = shaders::CALCULATE_PREVIOUS_EFFICIENT_INDEX_BY_TRAILLING.name();
+        let dispatched = shaders::ALL
+            .iter()
+            .filter(|it| it.name() != skipped)
+            .copied()
+            .collect_vec();
+        check_cached_layouts(context.pipeline_cache(), &dispatched)
+            .unwrap_or_else(|error| panic!("{error}"));
+    }
+}
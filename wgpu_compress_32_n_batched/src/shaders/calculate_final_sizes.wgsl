@@ -1,3 +1,5 @@
+override workgroup_offset: u32;
+
 @group(0)
 @binding(0)
 var<storage,read_write> last_byte_index: array<u32>;
@@ -21,7 +23,6 @@ struct Output{
 @compute
 @workgroup_size(1)
 fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>) {
-    //@workgroup_offset
 
     var sum=0u;
      for (var i=0u;i<size;i++){
@@ -1,3 +1,6 @@
+override workgroup_offset: u32;
+override n: u32;
+override full_size: u32;
 
 struct Ss {
     leading:i32,
@@ -25,9 +28,6 @@ var<storage, read_write> id_to_write: array<u32>; // this is used as both input
 
 
 fn find_most_similar_previous_value(workgroup_start:u32) {
-   //@n
-   //@size
-   //@full_size
    for (var step=1u+workgroup_start;step<=size+workgroup_start;step++){
        var v_u32=bitcast<u32>(in[step]);
        var min_id=1u;
@@ -53,7 +53,6 @@ fn find_most_similar_previous_value(workgroup_start:u32) {
 @compute
 @workgroup_size(1)
 fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>) {
-    //@workgroup_offset
 //    for (var i=0u;i<chunks;i++){
 //        let index:u32=(workgroup_offset+workgroup_id.x) * 256 * chunks + invocation_id.x+i*256u;
         find_most_similar_previous_value((workgroup_offset+workgroup_id.x)*size);
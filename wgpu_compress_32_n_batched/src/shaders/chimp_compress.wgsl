@@ -1,3 +1,5 @@
+override workgroup_offset: u32;
+override log2n: u32;
 
 struct Ss{
     leading:i32,
@@ -33,7 +35,6 @@ var<storage, read_write> comprare_with: array<u32>; // this is used as both inpu
 
 
 fn compress(v:f32,s:Ss,v_prev:f32,s_prev:Ss,c:u32) -> Output{
-    //@log2n
     //Conditions
     var trail_gt_6=u32(s.trailing>6);
     var trail_le_6=u32(s.trailing<=6);
@@ -136,7 +137,6 @@ fn pseudo_u64_add(output:vec2<u32>,number:u32)->vec2<u32>{
 @compute
 @workgroup_size(256)
 fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>,@builtin(local_invocation_id) invocation_id: vec3<u32>) {
-    //@workgroup_offset
 
     for (var i=0u;i<chunks;i++){
         let index:u32=(workgroup_offset+workgroup_id.x) * 256 * chunks + invocation_id.x+i*256u;
@@ -1,3 +1,8 @@
+override workgroup_offset: u32;
+override total_threads: u32;
+override last_pass: u32;
+override last_index: u32;
+
 @group(0)
 @binding(2)
 var<uniform> size: u32;
@@ -136,13 +141,9 @@ fn write(idx:u32,out_idx:u32,is_last:u32,next_idx:u32)->u32{
 @compute
 @workgroup_size(256)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>,@builtin(num_workgroups) count: vec3<u32>) {
-    //@workgroup_offset
-    //@last_pass
 //    write((workgroup_offset+global_id.x)*size,last_byte_index[(workgroup_offset+global_id.x)],last_pass*u32((global_id.x)==count.x- 1u),last_byte_index[(workgroup_offset+global_id.x)+1u]);
     
 
-    //@last_index
-    //@total_threads
     
     if(workgroup_offset+global_id.x>=total_threads){return;}
     
@@ -1,3 +1,7 @@
+override workgroup_offset: u32;
+override n: u32;
+override log2n: u32;
+override total_threads: u32;
 
 @group(0)
 @binding(0)
@@ -16,13 +20,10 @@ var<uniform> size: u32;
 
 @group(0)
 @binding(3)
-var<storage, read_write> input_index: array<u32>;
+var<storage, read_write> blocks: array<vec2<u32>>; // the first word and the value count of each block
 
 @group(0)
 @binding(4)
-var<uniform> input_size: u32;
-@group(0)
-@binding(5)
 var<storage,read_write> last_lead_array: array<u32>;
 
 struct CurrentInfo{
@@ -30,10 +31,8 @@ struct CurrentInfo{
     current_offset:u32,
 }
 
-fn write(input_idx:u32,output_idx:u32){
+fn write(input_idx:u32,output_idx:u32,count:u32){
 
-    //@n
-    //@log2n
     
     //Index of the byte we are in
     var current_index=input_idx+1u;
@@ -54,11 +53,7 @@ fn write(input_idx:u32,output_idx:u32){
     current_info.current_offset+=32u;
 
     var value=0u;
-    for (var i: u32 = 1u; i < size; i++) {
-        // if we have not finished reading values from the uncompressed buffers
-        if current_info.current_index>=(input_size - 1u) && (current_info.current_offset - 1u) <=0u{
-            break;
-        }
+    for (var i: u32 = 1u; i < count; i++) {
         
         //if current bit value==1
         if get_bit_at_index(current_info.current_index,current_info.current_offset)==1u {
@@ -172,8 +167,7 @@ fn reinterpret_num(array_index:u32,index:u32,length:u32)->u32{
 @compute
 @workgroup_size(256)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    //@workgroup_offset
-    //@total_threads
     if(workgroup_offset+global_id.x>=total_threads){return;}
-    write(input_index[workgroup_offset+global_id.x],(workgroup_offset+global_id.x)*size);
+    let block=workgroup_offset+global_id.x;
+    write(blocks[block].x,block*size,blocks[block].y);
 }
\ No newline at end of file
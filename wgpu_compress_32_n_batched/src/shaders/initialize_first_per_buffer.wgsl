@@ -1,3 +1,5 @@
+override workgroup_offset: u32;
+
 struct Output{
     upper_bits:u32,
     lower_bits:u32,//because there is a scenario where 32 bits are not enough to reprisent the outcome
@@ -18,6 +20,5 @@ var<uniform> chunks:u32; // how many iterations per buffer
 @compute
 @workgroup_size(1)
 fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>) {
-    //@workgroup_offset
     out[(workgroup_offset+workgroup_id.x )* 256 * chunks]= Output(0,bitcast<u32>(input[(workgroup_offset+workgroup_id.x) * 256 * chunks]),32u);
 }
\ No newline at end of file
@@ -0,0 +1,1159 @@
+//! The batched 64 bit Chimp compressor over the GPU stages, and its host counterpart
+use crate::calculate_indexes::{CalculateIndexes64, GPUCalculateIndexes64};
+use crate::compute_s_shader::{ComputeS, ComputeSImpl};
+use crate::cpu;
+use crate::final_compress::{FinalCompress, FinalCompressImpl64};
+use crate::finalize::{Finalize, Finalizer64};
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::cpu_compress::Compressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, HEADER_SIZE};
+use compress_utils::general_utils::{
+    max_batch_len, padding_to_fit_buffer_count, split_by_max_storage_buffer_size, CompressResult,
+    DeviceEnum, MaxGroupGnostic, Padding,
+};
+use compress_utils::impl_integer_compressor;
+use compress_utils::lossy::quantize;
+use compress_utils::stream::BatchedCompressor;
+use compress_utils::types::ChimpOutput64;
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{time_it, wgpu_utils};
+use log::info;
+use pollster::FutureExt;
+use std::ops::Div;
+use std::slice::Chunks;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct ChimpCompressorBatched64 {
+    context: Arc<Context>,
+    device_type: DeviceEnum,
+    config: ChimpConfig,
+}
+impl ChimpCompressorBatched64 {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(Context::initialize_default_adapter().block_on()?))
+    }
+}
+
+pub fn add_padding_to_fit_buffer_count_64(
+    mut values: Vec<f64>,
+    buffer_size: usize,
+    padding: &mut Padding,
+) -> Vec<f64> {
+    if values.len() % buffer_size != 0 {
+        let count = (values.len().div(buffer_size) + 1) * buffer_size - values.len();
+        padding.0 = count;
+        for _i in 0..count {
+            values.push(0f64);
+        }
+    }
+    values
+}
+
+#[async_trait]
+impl Compressor<f64> for ChimpCompressorBatched64 {
+    async fn compress(&self, values: &[f64]) -> Result<CompressResult, ChimpError> {
+        let header = self.header(values.len());
+        let iterations = self.split_by_max_gpu_buffer_size(values);
+        let result = compress_batches(
+            iterations,
+            &self.config,
+            &self.compute_s_factory(),
+            &self.compute_final_compress_factory(),
+            &self.calculate_index_factory(),
+            &self.compute_finalize_factory(),
+        )
+        .await?;
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl BatchedCompressor<f64> for ChimpCompressorBatched64 {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput64>(),
+        )
+    }
+
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        ChimpHeader::new(
+            CodecVariant::Chimp64,
+            0,
+            self.config.buffer_size(),
+            value_count,
+        )
+        .with_error_bound(self.config.error_bound())
+    }
+}
+
+impl_integer_compressor!(ChimpCompressorBatched64, i64, u64);
+
+/// Run every stage of the pipeline over each of the [iterations] and concatenate the
+/// resulting buffers, without the stream header
+pub(crate) async fn compress_batches(
+    iterations: Chunks<'_, f64>,
+    config: &ChimpConfig,
+    compute_s_impl: &(impl ComputeS + Sync),
+    final_compress_impl: &(impl FinalCompress + Sync),
+    indexes_impl: &(impl CalculateIndexes64 + Sync),
+    finalize_impl: &(impl Finalize + Sync),
+) -> Result<CompressResult, ChimpError> {
+    let mut byte_stream = Vec::new();
+    let mut metadata = 0;
+    let mut skip_time = 0;
+    let mut buffers = wgpu_utils::RunBuffers::default();
+    for iteration_values in iterations {
+        let mut total_millis = 0;
+        let quantized = quantize(iteration_values, config.error_bound());
+        let values = quantized.as_ref();
+        let output_vec: CompressResult;
+        let buffer_size = config.buffer_size();
+        let padding = padding_to_fit_buffer_count(values.len(), buffer_size);
+        time_it!(
+            {
+                compute_s_impl
+                    .compute_s(values, &mut buffers, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "s computation stage"
+        );
+        time_it!(
+            {
+                final_compress_impl
+                    .final_compress(&mut buffers, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "compression stage"
+        );
+        time_it!(
+            {
+                indexes_impl
+                    .calculate_indexes(&mut buffers, buffer_size as u32, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "calculate trim size stage"
+        );
+        time_it!(
+            {
+                output_vec = finalize_impl
+                    .finalize(&mut buffers, padding, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "trimming stage"
+        );
+        byte_stream.extend(output_vec.compressed_value_ref());
+        metadata += output_vec.metadata_size();
+    }
+    Ok(CompressResult(byte_stream, metadata, skip_time))
+}
+
+/// A [Compressor] running every stage of the batched pipeline on the host.
+///
+/// The output is byte for byte the one of [ChimpCompressorBatched64] on a device whose storage
+/// buffer binding limit is [ChimpCompressorBatched64Cpu::max_storage_buffer_size].
+#[derive(Debug, Clone)]
+pub struct ChimpCompressorBatched64Cpu {
+    max_storage_buffer_size: usize,
+    config: ChimpConfig,
+}
+
+impl Default for ChimpCompressorBatched64Cpu {
+    fn default() -> Self {
+        Self::new(ChimpCompressorBatched64::MAX_BUFFER_SIZE_BYTES)
+    }
+}
+
+impl ChimpCompressorBatched64Cpu {
+    pub fn new(max_storage_buffer_size: usize) -> Self {
+        Self {
+            max_storage_buffer_size,
+            config: ChimpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn max_storage_buffer_size(&self) -> usize {
+        self.max_storage_buffer_size
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+}
+
+#[async_trait]
+impl Compressor<f64> for ChimpCompressorBatched64Cpu {
+    async fn compress(&self, values: &[f64]) -> Result<CompressResult, ChimpError> {
+        let header = self.header(values.len());
+        let iterations = split_by_max_storage_buffer_size(
+            values,
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput64>(),
+        );
+        let result = compress_batches(
+            iterations,
+            &self.config,
+            &cpu::compute_s::CpuComputeSImpl::new(self.config.clone()),
+            &cpu::chimp_compress::CPUFinalCompressImpl64::new(self.config.clone(), false),
+            &cpu::calculate_indexes::CPUCalculateIndexes64::new(self.config.clone()),
+            &cpu::finalize::CPUFinalizer64::new(self.config.clone()),
+        )
+        .await?;
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl BatchedCompressor<f64> for ChimpCompressorBatched64Cpu {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput64>(),
+        )
+    }
+
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        ChimpHeader::new(
+            CodecVariant::Chimp64,
+            0,
+            self.config.buffer_size(),
+            value_count,
+        )
+        .with_error_bound(self.config.error_bound())
+    }
+}
+
+impl_integer_compressor!(ChimpCompressorBatched64Cpu, i64, u64);
+
+enum ComputeS64Impls {
+    GPU(ComputeSImpl),
+    CPU(cpu::compute_s::CpuComputeSImpl),
+}
+
+impl MaxGroupGnostic for ComputeS64Impls {
+    fn get_max_number_of_groups(&self, content_len: usize) -> usize {
+        match self {
+            ComputeS64Impls::GPU(c) => c.get_max_number_of_groups(content_len),
+            ComputeS64Impls::CPU(c) => c.get_max_number_of_groups(content_len),
+        }
+    }
+}
+
+#[async_trait]
+impl ComputeS for ComputeS64Impls {
+    async fn compute_s(
+        &self,
+        values: &[f64],
+        buffers: &mut RunBuffers,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        match self {
+            ComputeS64Impls::GPU(c) => c.compute_s(values, buffers, skip_time).await,
+            ComputeS64Impls::CPU(c) => c.compute_s(values, buffers, skip_time).await,
+        }
+    }
+}
+
+enum CalculateIndexesImpls {
+    GPU(GPUCalculateIndexes64),
+    CPU(cpu::calculate_indexes::CPUCalculateIndexes64),
+}
+#[async_trait]
+impl CalculateIndexes64 for CalculateIndexesImpls {
+    async fn calculate_indexes(
+        &self,
+        buffers: &mut RunBuffers,
+        size: u32,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        match self {
+            CalculateIndexesImpls::GPU(c) => c.calculate_indexes(buffers, size, skip_time).await,
+            CalculateIndexesImpls::CPU(c) => c.calculate_indexes(buffers, size, skip_time).await,
+        }
+    }
+}
+
+enum Compress64Impls {
+    GPU(FinalCompressImpl64),
+    CPU(cpu::chimp_compress::CPUFinalCompressImpl64),
+}
+
+impl MaxGroupGnostic for Compress64Impls {
+    fn get_max_number_of_groups(&self, content_len: usize) -> usize {
+        match self {
+            Compress64Impls::GPU(c) => c.get_max_number_of_groups(content_len),
+            Compress64Impls::CPU(c) => c.get_max_number_of_groups(content_len),
+        }
+    }
+}
+
+#[async_trait]
+impl FinalCompress for Compress64Impls {
+    async fn final_compress(
+        &self,
+        run_buffers: &mut RunBuffers,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        match self {
+            Compress64Impls::GPU(c) => c.final_compress(run_buffers, skip_time).await,
+            Compress64Impls::CPU(c) => c.final_compress(run_buffers, skip_time).await,
+        }
+    }
+}
+
+enum Finalizer64impls {
+    GPU(Finalizer64),
+    CPU(cpu::finalize::CPUFinalizer64),
+}
+#[async_trait]
+impl Finalize for Finalizer64impls {
+    async fn finalize(
+        &self,
+        run_buffers: &mut RunBuffers,
+        padding: usize,
+        skip_time: &mut u128,
+    ) -> Result<CompressResult, ChimpError> {
+        match self {
+            Finalizer64impls::GPU(f) => f.finalize(run_buffers, padding, skip_time).await,
+            Finalizer64impls::CPU(f) => f.finalize(run_buffers, padding, skip_time).await,
+        }
+    }
+}
+#[allow(unused)]
+impl ChimpCompressorBatched64 {
+    pub const MAX_BUFFER_SIZE_BYTES: usize = 134217728;
+    fn compute_s_factory(&self) -> ComputeS64Impls {
+        match self.device_type() {
+            DeviceEnum::GPU => {
+                ComputeS64Impls::GPU(ComputeSImpl::new(self.context.clone(), self.config.clone()))
+            }
+            DeviceEnum::CPU => {
+                ComputeS64Impls::CPU(cpu::compute_s::CpuComputeSImpl::new(self.config.clone()))
+            }
+        }
+    }
+    fn split_by_max_gpu_buffer_size<'a>(&self, values: &'a [f64]) -> Chunks<'a, f64> {
+        split_by_max_storage_buffer_size(
+            values,
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput64>(),
+        )
+    }
+    fn compute_final_compress_factory(&self) -> Compress64Impls {
+        match self.device_type() {
+            &DeviceEnum::GPU => Compress64Impls::GPU(FinalCompressImpl64::new(
+                self.context.clone(),
+                self.config.clone(),
+                false,
+            )),
+            &DeviceEnum::CPU => Compress64Impls::CPU(
+                cpu::chimp_compress::CPUFinalCompressImpl64::new(self.config.clone(), false),
+            ),
+        }
+    }
+    fn compute_finalize_factory(&self) -> Finalizer64impls {
+        match self.device_type() {
+            &DeviceEnum::GPU => {
+                Finalizer64impls::GPU(Finalizer64::new(self.context.clone(), self.config.clone()))
+            }
+            &DeviceEnum::CPU => {
+                Finalizer64impls::CPU(cpu::finalize::CPUFinalizer64::new(self.config.clone()))
+            }
+        }
+    }
+    fn calculate_index_factory(&self) -> CalculateIndexesImpls {
+        match self.device_type() {
+            &DeviceEnum::GPU => CalculateIndexesImpls::GPU(GPUCalculateIndexes64::new(
+                self.context.clone(),
+                self.config.clone(),
+            )),
+            &DeviceEnum::CPU => CalculateIndexesImpls::CPU(
+                cpu::calculate_indexes::CPUCalculateIndexes64::new(self.config.clone()),
+            ),
+        }
+    }
+    pub fn new(context: impl Into<Arc<Context>>) -> Self {
+        Self {
+            context: context.into(),
+            device_type: DeviceEnum::GPU,
+            config: ChimpConfig::default(),
+        }
+    }
+
+    pub fn with_config(self, config: ChimpConfig) -> Self {
+        Self { config, ..self }
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    pub(crate) fn with_device(self, device: impl Into<DeviceEnum>) -> Self {
+        Self {
+            device_type: device.into(),
+            ..self
+        }
+    }
+    pub(crate) fn device_type(&self) -> &DeviceEnum {
+        &self.device_type
+    }
+}
+
+fn splitter(value: f64) -> [f32; 2] {
+    let bits = bytemuck::cast::<f64, u64>(value);
+    [
+        f32::from_bits((bits >> 32) as u32),
+        f32::from_bits(bits as u32),
+    ]
+}
+fn merger(value: [f32; 2]) -> f64 {
+    let high = (value[0].to_bits() as u64) << 32;
+    let low = value[1].to_bits() as u64;
+    bytemuck::cast(high | low)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compressor::{
+        merger, splitter, ChimpCompressorBatched64, ChimpCompressorBatched64Cpu,
+    };
+    use crate::cpu::decompressor::CPUDecompressorBatched64;
+    use crate::decompressor;
+    use compress_utils::config::{ChimpConfig, ConfigError};
+    use compress_utils::context::Context;
+    use compress_utils::cpu_compress::{Compressor, Decompressor};
+    use compress_utils::error::ChimpError;
+    use compress_utils::format::{ChimpHeader, ValueType, HEADER_SIZE};
+    use compress_utils::general_utils::{build_event_times, EventLogType};
+    use compress_utils::integer::{IntegerDecompressor, IntegerEncoding};
+    use compress_utils::lossy::{verify_error_bound, ErrorBound};
+    use compress_utils::stream::{ChimpStreamDecoder, ChimpStreamEncoder};
+    use compress_utils::testing::{
+        assert_checksum_reports_corrupted_block, assert_config_round_trip,
+        assert_corruption_never_panics, assert_range_round_trip, assert_round_trip, sine_values,
+        special_values,
+    };
+    use itertools::Itertools;
+    use pollster::FutureExt;
+    use std::cmp::min;
+    use std::sync::Arc;
+    use std::{env, fs};
+
+    #[test]
+    fn splitter_merger() {
+        let original = 123.456789_f64;
+        let split = splitter(original);
+        let merged = merger(split);
+        assert_eq!(original, merged);
+    }
+
+    #[test]
+    fn splitter_merger2() {
+        let original = 25.0003;
+        let split = splitter(original);
+        let merged = merger(split);
+        assert_eq!(original, merged);
+    }
+
+    #[test]
+    fn test_cpu_compressor_block_layout() {
+        let values = vec![1.5f64; 300];
+        let compressed = ChimpCompressorBatched64Cpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        assert_eq!(compressed.metadata_size(), HEADER_SIZE + 2 * 8);
+        let words = compressed.compressed_value_ref()[HEADER_SIZE..]
+            .chunks(8)
+            .map(|it| u64::from_le_bytes(it.try_into().unwrap()))
+            .collect_vec();
+        // 64 bits for the first value and 2 bits for each of the 255 equal values
+        let block_words = (64 + 255 * 2) / 64 + 2;
+        assert_eq!(words[0], (255 << 32) + (block_words as u64 - 1) * 8);
+        assert_eq!(words[1], 1.5f64.to_bits());
+        assert_eq!(words[block_words] >> 32, 300 - 256 - 1);
+        assert_eq!(words[block_words + 1], 1.5f64.to_bits());
+    }
+
+    #[test]
+    fn test_cpu_round_trip() {
+        let mut values = sine_values(3000);
+        values.extend([
+            0.0,
+            -0.0,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            f64::INFINITY,
+            1.5,
+            1.5,
+        ]);
+        assert_round_trip(
+            &ChimpCompressorBatched64Cpu::default(),
+            &CPUDecompressorBatched64::default(),
+            &values,
+        );
+    }
+
+    #[test]
+    fn test_cpu_special_values_round_trip() {
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        assert_round_trip(
+            &ChimpCompressorBatched64Cpu::default().with_config(config.clone()),
+            &CPUDecompressorBatched64::new(config),
+            &special_values(),
+        );
+    }
+
+    #[test]
+    fn test_cpu_round_trip_with_config() {
+        let config = ChimpConfig::default().with_buffer_size(1024).unwrap();
+        assert_config_round_trip(
+            &ChimpCompressorBatched64Cpu::default().with_config(config.clone()),
+            &CPUDecompressorBatched64::new(config),
+            &CPUDecompressorBatched64::default(),
+            &sine_values::<f64>(3000),
+            1024,
+        );
+    }
+
+    #[test]
+    fn test_cpu_lossy_round_trip_stays_within_the_bound() {
+        let values = (0..3000)
+            .map(|it| f64::sin(it as f64 / 10.0) * 100.0)
+            .collect_vec();
+        let lossless = ChimpCompressorBatched64Cpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        let bound = ErrorBound::absolute(1e-3).unwrap();
+        let config = ChimpConfig::default().with_error_bound(bound);
+        let lossy = ChimpCompressorBatched64Cpu::default()
+            .with_config(config.clone())
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        assert!(lossy.compressed_value_ref().len() < lossless.compressed_value_ref().len() / 2);
+        assert_eq!(
+            ChimpHeader::read(lossy.compressed_value_ref())
+                .unwrap()
+                .error_bound,
+            Some(bound)
+        );
+
+        let decompressed = CPUDecompressorBatched64::new(config)
+            .decompress(lossy.compressed_value_ref())
+            .block_on()
+            .unwrap();
+        let decompressed = decompressed.un_compressed_value();
+        verify_error_bound(lossy.compressed_value_ref(), &values, &decompressed).unwrap();
+        assert!(matches!(
+            verify_error_bound(lossless.compressed_value_ref(), &values, &decompressed),
+            Err(ChimpError::ErrorBoundExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_cpu_integer_round_trip() {
+        // a counter with the odd reset and a jump across the sign bit
+        let counter = (0..3000u64)
+            .map(|it| match it {
+                0..1500 => it * 17,
+                1500..2000 => it - 1500,
+                _ => it.wrapping_sub(1 << 63),
+            })
+            .collect_vec();
+        let gauge = counter.iter().map(|it| *it as i64 / 3 - 500).collect_vec();
+        let xor = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let delta = xor
+            .clone()
+            .with_integer_encoding(IntegerEncoding::ZigZagDelta);
+        for config in [xor, delta] {
+            let compressor = ChimpCompressorBatched64Cpu::new(16384).with_config(config.clone());
+            let decompressor =
+                IntegerDecompressor::new(CPUDecompressorBatched64::new(config.clone()));
+            let compressed = compressor.compress(&counter).block_on().unwrap();
+            let stream = compressed.compressed_value_ref();
+            assert_eq!(
+                ChimpHeader::read(stream).unwrap().value_type(),
+                ValueType::U64
+            );
+            let decompressed: Vec<u64> = decompressor.decompress(stream).block_on().unwrap().into();
+            assert_eq!(decompressed, counter);
+            let range: Vec<u64> = decompressor
+                .decompress_range(stream, 300..1700)
+                .block_on()
+                .unwrap()
+                .into();
+            assert_eq!(range, counter[300..1700]);
+            assert!(matches!(
+                Decompressor::<i64>::decompress(&decompressor, stream).block_on(),
+                Err(ChimpError::WrongValueType {
+                    value_type: ValueType::U64,
+                    ..
+                })
+            ));
+
+            let compressed = compressor.compress(&gauge).block_on().unwrap();
+            let decompressed: Vec<i64> = decompressor
+                .decompress(compressed.compressed_value_ref())
+                .block_on()
+                .unwrap()
+                .into();
+            assert_eq!(decompressed, gauge);
+        }
+
+        let lossy = ChimpCompressorBatched64Cpu::default().with_config(
+            ChimpConfig::default().with_error_bound(ErrorBound::absolute(1.0).unwrap()),
+        );
+        assert!(matches!(
+            lossy.compress(&gauge).block_on(),
+            Err(ChimpError::InvalidConfig(ConfigError::ErrorBoundOnIntegers))
+        ));
+    }
+
+    #[test]
+    fn test_cpu_zigzag_delta_shrinks_counters() {
+        let counter = (0..4096u64).map(|it| 1_000_000 + it * 60).collect_vec();
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let xor = ChimpCompressorBatched64Cpu::default()
+            .with_config(config.clone())
+            .compress(&counter)
+            .block_on()
+            .unwrap();
+        let delta = ChimpCompressorBatched64Cpu::default()
+            .with_config(config.with_integer_encoding(IntegerEncoding::ZigZagDelta))
+            .compress(&counter)
+            .block_on()
+            .unwrap();
+        assert!(delta.compressed_value_ref().len() * 4 < xor.compressed_value_ref().len());
+    }
+
+    #[test]
+    fn test_stream_decoder_reads_windows_of_blocks() {
+        let values = (0..3000)
+            .map(|it| (f64::sin(it as f64 / 10.0) * 100.0).round() / 100.0)
+            .collect_vec();
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let compressed = ChimpCompressorBatched64Cpu::default()
+            .with_config(config.clone())
+            .compress(&values)
+            .block_on()
+            .unwrap()
+            .with_block_checksums()
+            .unwrap()
+            .with_block_index()
+            .unwrap()
+            .compressed_values();
+
+        // 12 blocks, the trailers after them are skipped
+        let windows = ChimpStreamDecoder::new(
+            CPUDecompressorBatched64::new(config.clone()),
+            compressed.as_slice(),
+        )
+        .unwrap()
+        .with_window_blocks(5)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        assert_eq!(
+            windows.iter().map(Vec::len).collect_vec(),
+            vec![5 * 256, 5 * 256, 3000 - 10 * 256]
+        );
+        assert_eq!(windows.concat(), values);
+
+        let decoded = ChimpStreamDecoder::new(
+            CPUDecompressorBatched64::new(config.clone()),
+            compressed.as_slice(),
+        )
+        .unwrap()
+        .values()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        assert_eq!(decoded, values);
+
+        let truncated = &compressed[..HEADER_SIZE + 1000];
+        let error = ChimpStreamDecoder::new(CPUDecompressorBatched64::new(config), truncated)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(matches!(error, ChimpError::CorruptInput { .. }));
+    }
+
+    #[test]
+    fn test_stream_decoder_reads_the_stream_encoder_output() {
+        let values = (0..5000)
+            .map(|it| ((f64::from(it as u16)) / 10.0).sin() * 100.0)
+            .collect_vec();
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let mut encoder = ChimpStreamEncoder::new(
+            ChimpCompressorBatched64Cpu::new(16384).with_config(config.clone()),
+            Vec::new(),
+        )
+        .unwrap();
+        for chunk in values.chunks(1234) {
+            encoder.push(chunk).unwrap();
+        }
+        let stream = encoder.finish().unwrap();
+
+        let decoded = ChimpStreamDecoder::new(CPUDecompressorBatched64::new(config), &stream[..])
+            .unwrap()
+            .with_window_blocks(3)
+            .values()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(decoded, values);
+    }
+
+    #[test]
+    fn test_stream_decoder_rejects_an_oversized_block() {
+        let values = sine_values::<f64>(600);
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let mut compressed = ChimpCompressorBatched64Cpu::default()
+            .with_config(config.clone())
+            .compress(&values)
+            .block_on()
+            .unwrap()
+            .compressed_values();
+        // the rest of the first block is the low half of its prefix
+        compressed[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&0xFFFF_FFF8u32.to_le_bytes());
+
+        let error = ChimpStreamDecoder::new(CPUDecompressorBatched64::new(config), &compressed[..])
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ChimpError::CorruptInput {
+                block: 0,
+                byte_offset: HEADER_SIZE
+            }
+        ));
+    }
+
+    #[test]
+    fn test_stream_decoder_keeps_the_integer_encoding() {
+        let counter = (0..3000u64).map(|it| 1_000_000 + it * 60).collect_vec();
+        let config = ChimpConfig::default()
+            .with_buffer_size(256)
+            .unwrap()
+            .with_integer_encoding(IntegerEncoding::ZigZagDelta)
+            .with_block_checksums(true);
+        let compressed = ChimpCompressorBatched64Cpu::default()
+            .with_config(config.clone())
+            .compress(&counter)
+            .block_on()
+            .unwrap()
+            .with_block_index()
+            .unwrap()
+            .compressed_values();
+
+        let decoded = ChimpStreamDecoder::new(
+            IntegerDecompressor::new(CPUDecompressorBatched64::new(config)),
+            compressed.as_slice(),
+        )
+        .unwrap()
+        .with_window_blocks(5)
+        .values()
+        .collect::<Result<Vec<u64>, _>>()
+        .unwrap();
+        assert_eq!(decoded, counter);
+    }
+
+    #[test]
+    fn test_cpu_checksum_reports_corrupted_block() {
+        let compressed = ChimpCompressorBatched64Cpu::default()
+            .compress(&sine_values::<f64>(3000))
+            .block_on()
+            .unwrap()
+            .with_block_checksums()
+            .unwrap();
+        assert_checksum_reports_corrupted_block(
+            compressed.compressed_values(),
+            &CPUDecompressorBatched64::default(),
+        );
+    }
+
+    #[test]
+    fn test_cpu_decompress_range() {
+        assert_range_round_trip(
+            &ChimpCompressorBatched64Cpu::default(),
+            &CPUDecompressorBatched64::default(),
+            &sine_values::<f64>(3000),
+        );
+    }
+
+    #[test]
+    fn test_cpu_corrupted_stream_never_panics() {
+        let decompressor = CPUDecompressorBatched64::default();
+        let stream = assert_round_trip(
+            &ChimpCompressorBatched64Cpu::default(),
+            &decompressor,
+            &sine_values::<f64>(600),
+        );
+        assert_corruption_never_panics(&stream, &decompressor);
+    }
+
+    #[test]
+    fn test_cpu_compressor_mixed_values() {
+        let values = (0..1000)
+            .map(|it| (f64::sin(it as f64 / 10.0) * 100.0).round() / 100.0)
+            .chain([0.0, -0.0, f64::MAX, f64::MIN_POSITIVE, f64::INFINITY])
+            .collect_vec();
+        let compressed = ChimpCompressorBatched64Cpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        assert_eq!(compressed.metadata_size(), HEADER_SIZE + 4 * 8);
+        assert_eq!(compressed.compressed_value_ref().len() % 8, 0);
+    }
+
+    #[test]
+    fn halfer() {
+        let u64 = [1, 2, 3, 4, 5, 6];
+        let xm = &u64[..u64.len() / 2];
+        let mx = &u64[u64.len() / 2..];
+        assert_eq!(xm.len(), mx.len());
+    }
+    #[test]
+    fn test_decompress_able_buffer() {
+        // let subscriber = tracing_subscriber::fmt()
+        //     .compact()
+        //     .with_env_filter("wgpu_compress_64_batched=info")
+        //     // .with_writer(
+        //     //     OpenOptions::new()
+        //     //         .create(true)
+        //     //         .truncate(true)
+        //     //         .write(true)
+        //     //         .open("run.log")
+        //     //         .unwrap(),
+        //     // )
+        //     .finish();
+        // subscriber.init();
+        let context = Arc::new(
+            Context::initialize_with_adapter("NVIDIA".to_string())
+                .block_on()
+                .unwrap(),
+        );
+        for buffer_size in vec![256, 512, 1024, 2048].into_iter() {
+            let filename_no_io = format!("{buffer_size}_chimp64_output_no_io.txt");
+            if fs::exists(&filename_no_io).unwrap() {
+                fs::remove_file(&filename_no_io).unwrap();
+            }
+            // let filename = format!("{buffer_size}_chimp64_output.txt");
+            // if fs::exists(&filename).unwrap() {
+            //     fs::remove_file(&filename).unwrap();
+            // }
+            let config = ChimpConfig::default()
+                .with_buffer_size(buffer_size)
+                .unwrap();
+            println!("Buffer size: {}", config.buffer_size());
+            // let mut messages = Vec::<EventLogType>::with_capacity(30);
+            let mut messages_no_io = Vec::<EventLogType>::with_capacity(30);
+            let mut values = get_values("city_temperature.csv")
+                .expect("Could not read test values")
+                .to_vec();
+            let mut reader = TimeSeriesReader::new(50_000, values.clone(), 500_000_000);
+            for size_checkpoint in 1..11 {
+                while let Some(block) = reader.next() {
+                    values.extend(block);
+                    if values.len() >= (size_checkpoint * reader.max_size()) / 100 {
+                        break;
+                    }
+                }
+                let value_new = values.clone();
+                println!("Starting compression of {} values", values.len());
+                let time = std::time::Instant::now();
+                let compressor =
+                    ChimpCompressorBatched64::new(context.clone()).with_config(config.clone());
+                let compressed_values2 = compressor.compress(&value_new).block_on().unwrap();
+                let compression_time = time.elapsed().as_millis();
+
+                const SIZE_IN_BYTE: usize = 8;
+                let compression_ratio = (compressed_values2.compressed_value_ref().len()
+                    * SIZE_IN_BYTE) as f64
+                    / value_new.len() as f64;
+                // messages.push(EventLogType::CompressionRatio {values:value_new.len() as u64, ratio:compression_ratio});
+                messages_no_io.push(EventLogType::CompressionRatio {
+                    values: value_new.len() as u64,
+                    ratio: compression_ratio,
+                });
+
+                // messages.push(EventLogType::EncodingTime {values:value_new.len() as u64, time:compression_time});
+                messages_no_io.push(EventLogType::EncodingTime {
+                    values: value_new.len() as u64,
+                    time: compression_time - compressed_values2.skip_time(),
+                });
+
+                let time = std::time::Instant::now();
+                let decompressor = decompressor::ChimpDecompressorBatched64::new(context.clone())
+                    .with_config(config.clone());
+                match decompressor
+                    .decompress(compressed_values2.compressed_value_ref())
+                    .block_on()
+                {
+                    Ok(decompressed_values) => {
+                        let decompression_time = time.elapsed().as_millis();
+
+                        // messages.push(EventLogType::DecompressionTime {values:value_new.len() as u64, time:decompression_time});
+                        messages_no_io.push(EventLogType::DecompressionTime {
+                            values: value_new.len() as u64,
+                            time: decompression_time - decompressed_values.skip_time(),
+                        });
+
+                        assert_eq!(decompressed_values.un_compressed_value(), value_new);
+                    }
+                    Err(err) => {
+                        eprintln!("Decompression error: {err:?}");
+                        panic!("{}", err);
+                    }
+                }
+            }
+
+            let mut writer = csv::Writer::from_path(filename_no_io).unwrap();
+
+            let logs = build_event_times(messages_no_io);
+            for message in logs.iter().sorted_by_key(|it| it.values) {
+                writer.serialize(message).unwrap();
+            }
+        }
+    }
+    #[test]
+    fn test_decompress_able_64() {
+        // let subscriber = tracing_subscriber::fmt()
+        //     .compact()
+        //     .with_env_filter("wgpu_compress_64_batched=info")
+        //     // .with_writer(
+        //     //     OpenOptions::new()
+        //     //         .create(true)
+        //     //         .truncate(true)
+        //     //         .write(true)
+        //     //         .open("run.log")
+        //     //         .unwrap(),
+        //     // )
+        //     .finish();
+        // subscriber.init();
+        let adapter = env::var("CHIMP_GPU_ADAPTER").unwrap_or("NVIDIA".to_string());
+        let context = Arc::new(
+            Context::initialize_with_adapter(adapter)
+                .block_on()
+                .unwrap(),
+        );
+        for file_name in vec![
+            "city_temperature.csv",
+            "SSD_HDD_benchmarks.csv",
+            "Stocks-Germany-sample.txt",
+        ]
+        .into_iter()
+        {
+            println!("{file_name}");
+            let filename = format!("{}_chimp64_output.txt", &file_name);
+            if fs::exists(&filename).unwrap() {
+                fs::remove_file(&filename).unwrap();
+            }
+            let filename_no_io = format!("{}_chimp64_output_no_io.txt", &file_name);
+            if fs::exists(&filename_no_io).unwrap() {
+                fs::remove_file(&filename_no_io).unwrap();
+            }
+            let mut messages = Vec::<EventLogType>::with_capacity(30);
+            let mut messages_no_io = Vec::<EventLogType>::with_capacity(30);
+            let mut values = get_values(file_name)
+                .expect("Could not read test values")
+                .to_vec();
+            let mut reader = TimeSeriesReader::new(50_000, values.clone(), 500_000_000);
+            for size_checkpoint in 1..11 {
+                while let Some(block) = reader.next() {
+                    values.extend(block);
+                    if values.len() >= (size_checkpoint * reader.max_size()) / 100 {
+                        break;
+                    }
+                }
+                let value_new = values.clone();
+
+                println!("Starting compression of {} values", value_new.len());
+                let time = std::time::Instant::now();
+                let compressor = ChimpCompressorBatched64::new(context.clone()); //.with_device(CPU);
+                let compressed_values2 = compressor.compress(&value_new).block_on().unwrap();
+                let compression_time = time.elapsed().as_millis();
+
+                const SIZE_IN_BYTE: usize = 8;
+                let compression_ratio = (compressed_values2.compressed_value_ref().len()
+                    * SIZE_IN_BYTE) as f64
+                    / value_new.len() as f64;
+                messages.push(EventLogType::CompressionRatio {
+                    values: value_new.len() as u64,
+                    ratio: compression_ratio,
+                });
+                messages_no_io.push(EventLogType::CompressionRatio {
+                    values: value_new.len() as u64,
+                    ratio: compression_ratio,
+                });
+
+                messages.push(EventLogType::EncodingTime {
+                    values: value_new.len() as u64,
+                    time: compression_time,
+                });
+                messages_no_io.push(EventLogType::EncodingTime {
+                    values: value_new.len() as u64,
+                    time: compression_time - compressed_values2.skip_time(),
+                });
+
+                let time = std::time::Instant::now();
+                let decompressor = decompressor::GPUDecompressorBatched64::new(context.clone());
+                match decompressor
+                    .decompress(compressed_values2.compressed_value_ref())
+                    .block_on()
+                {
+                    Ok(decompressed_values) => {
+                        let decompression_time = time.elapsed().as_millis();
+
+                        messages.push(EventLogType::DecompressionTime {
+                            values: value_new.len() as u64,
+                            time: decompression_time,
+                        });
+                        messages_no_io.push(EventLogType::DecompressionTime {
+                            values: value_new.len() as u64,
+                            time: decompression_time - decompressed_values.skip_time(),
+                        });
+                        // fs::write("actual.log", decompressed_values.iter().join("\n")).unwrap();
+                        // fs::write("expected.log", value_new.iter().join("\n")).unwrap();
+                        assert_eq!(decompressed_values.un_compressed_value(), value_new);
+                    }
+                    Err(err) => {
+                        eprintln!("Decompression error: {:?}", err);
+                        panic!("{}", err);
+                    }
+                }
+            }
+            let mut writer = csv::Writer::from_path(filename).unwrap();
+
+            let logs = build_event_times(messages);
+            for message in logs.iter().sorted_by_key(|it| it.values) {
+                writer.serialize(message).unwrap();
+            }
+            let mut writer = csv::Writer::from_path(filename_no_io).unwrap();
+
+            let logs = build_event_times(messages_no_io);
+            for message in logs.iter().sorted_by_key(|it| it.values) {
+                writer.serialize(message).unwrap();
+            }
+        }
+    }
+
+    fn get_third(field: &str) -> Option<String> {
+        field
+            .split(",")
+            .collect_vec()
+            .get(2)
+            .map(|it| it.to_string())
+    }
+
+    struct TimeSeriesReader {
+        minimum_block_size: usize,
+        block_size: usize,
+        source_value: Vec<f64>,
+        current_index: usize,
+    }
+
+    impl TimeSeriesReader {
+        pub fn new(block_size: usize, source_value: Vec<f64>, minimum_block_size: usize) -> Self {
+            Self {
+                minimum_block_size,
+                block_size: min(block_size, source_value.len()),
+                source_value,
+                current_index: 0,
+            }
+        }
+        pub fn max_size(&self) -> usize {
+            let len = self.source_value.len();
+            let mut max = len;
+            while max < self.minimum_block_size {
+                max += self.block_size;
+            }
+            max
+        }
+    }
+
+    impl Iterator for TimeSeriesReader {
+        type Item = Vec<f64>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let mut block = Vec::<f64>::with_capacity(self.block_size);
+            if self.current_index <= self.minimum_block_size {
+                if self.block_size + self.current_index > self.source_value.len() {
+                    let remaining = self.block_size + self.current_index - self.source_value.len();
+                    block.extend(&self.source_value[self.current_index..]);
+                    block.extend(&self.source_value[0..remaining]);
+                    self.current_index = remaining - 1;
+                    Some(block)
+                } else {
+                    block.extend(
+                        &self.source_value
+                            [self.current_index..self.current_index + self.block_size],
+                    );
+                    self.current_index += self.block_size;
+                    Some(block)
+                }
+            } else {
+                None
+            }
+        }
+    }
+
+    //noinspection ALL
+    fn get_values(file_name: impl Into<String>) -> anyhow::Result<Vec<f64>> {
+        let dir = env::current_dir()?;
+        let file_path = dir.parent().unwrap().join(file_name.into());
+        let file_txt = fs::read_to_string(file_path)?;
+        let mut values = Vec::new();
+        values.extend(
+            file_txt
+                .split("\n")
+                .map(get_third)
+                .filter(|p| p.is_some())
+                .map(|s| s.unwrap().parse::<f64>().unwrap())
+                .into_iter(),
+        );
+        Ok(values)
+    }
+
+    #[test]
+    fn test_bits() {
+        let value = 83.80002877190708f64.to_bits();
+        let value2 = 83.8f64.to_bits();
+        println!("{value:064b}");
+        println!("{value2:064b}");
+    }
+}
@@ -1,7 +1,9 @@
-use anyhow::Result;
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo, MaxGroupGnostic, Step};
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{padding_to_fit_buffer_count, MaxGroupGnostic, Step};
 use compress_utils::types::S;
 use compress_utils::wgpu_utils::RunBuffers;
 use compress_utils::{execute_compute_shader, step, wgpu_utils, BufferWrapper, WgpuGroupId};
@@ -17,21 +19,23 @@ use wgpu_types::BufferAddress;
 pub trait ComputeS: MaxGroupGnostic {
     async fn compute_s(
         &self,
-        values: &mut [f64],
+        values: &[f64],
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> Result<()>;
+    ) -> Result<(), ChimpError>;
 }
 
 pub struct ComputeSImpl {
     context: Arc<Context>,
+    config: ChimpConfig,
 }
 
 #[allow(dead_code)]
 impl ComputeSImpl {
-    pub fn new(context: impl Into<Arc<Context>>) -> Self {
+    pub fn new(context: impl Into<Arc<Context>>, config: ChimpConfig) -> Self {
         Self {
             context: context.into(),
+            config,
         }
     }
 
@@ -48,13 +52,13 @@ impl ComputeSImpl {
         self.context.adapter()
     }
     pub fn max_work_group_count(&self) -> usize {
-        self.context.get_max_workgroup_size()
+        self.config.max_workgroup_size(&self.context)
     }
 }
 
 impl MaxGroupGnostic for ComputeSImpl {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 
@@ -62,67 +66,58 @@ impl MaxGroupGnostic for ComputeSImpl {
 impl ComputeS for ComputeSImpl {
     async fn compute_s(
         &self,
-        values: &mut [f64],
+        values: &[f64],
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> Result<()> {
+    ) -> Result<(), ChimpError> {
         // Create a shader module and pipeline
         // let workgroup_size = format!("@workgroup_size({})", );
 
-        let temp = include_str!("shaders/compute_s.wgsl")
-            .replace(
-                "@@workgroup_size",
-                &ChimpBufferInfo::get().buffer_size().to_string(),
-            )
-            .to_string();
-
         //Calculating buffer sizes and workgroup counts
-        let workgroup_count = self.get_max_number_of_groups(values.len());
+        // the values are padded with 0's to fill their last buffer
+        let padded_len =
+            values.len() + padding_to_fit_buffer_count(values.len(), self.config.buffer_size());
+        let workgroup_count = self.get_max_number_of_groups(padded_len);
         //info!("The wgpu workgroup size: {}", &workgroup_count);
 
         let size_of_s = size_of::<S>();
-        let bytes = values.len() + 1;
+        let bytes = padded_len + 1;
         //info!("The size of the input values vec: {}", bytes);
 
         let s_buffer_size = (size_of_s * bytes) as BufferAddress;
         //info!("The S buffer size in bytes: {}", s_buffer_size);
 
-        let mut padded_values = Vec::from(values);
-        padded_values.push(0f64);
         let instant = Instant::now();
-        let input_storage_buffer = BufferWrapper::storage_with_content(
-            self.device(),
-            bytemuck::cast_slice(padded_values.as_slice()),
+        let input_storage_buffer = BufferWrapper::pooled_storage_with_padded_content(
+            self.context(),
+            bytemuck::cast_slice(values),
+            (size_of::<f64>() * bytes) as BufferAddress,
             WgpuGroupId::new(0, 1),
             Some("Storage Input Buffer"),
         );
-        let s_storage_buffer = BufferWrapper::storage_with_size(
-            self.device(),
+        let s_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
             s_buffer_size,
             WgpuGroupId::new(0, 0),
             Some("Storage S Buffer"),
         );
-        let chunks_buffer = BufferWrapper::uniform_with_content(
-            self.device(),
-            bytemuck::bytes_of(&ChimpBufferInfo::get().chunks()),
+        let chunks_buffer = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
+            bytemuck::bytes_of(&self.config.buffer_info().chunks()),
             WgpuGroupId::new(0, 2),
             Some("Chunks Buffer"),
         );
         *skip_time += instant.elapsed().as_millis();
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let temp = include_str!("shaders/compute_s.wgsl")
-                .replace(
-                    "@@workgroup_size",
-                    &ChimpBufferInfo::get().buffer_size().to_string(),
+            let temp = shaders::COMPUTE_S
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
                 )
-                .replace("//@workgroup_offset", &offset_decl)
-                .to_string();
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &temp,
@@ -130,7 +125,7 @@ impl ComputeS for ComputeSImpl {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("calculate s pass")
             );
@@ -141,9 +136,9 @@ impl ComputeS for ComputeSImpl {
         //info!("Output result size: {}", output.len());
         let mut output;
 
-        step!(Step::ComputeS, {
-            let s_staging_buffer = BufferWrapper::stage_with_size(
-                self.context().device(),
+        step!(self.config, Step::ComputeS, {
+            let s_staging_buffer = BufferWrapper::pooled_stage_with_size(
+                self.context(),
                 buffers.s_buffer().size() as BufferAddress,
                 None,
             );
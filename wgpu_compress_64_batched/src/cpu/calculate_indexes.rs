@@ -1,40 +1,57 @@
 use crate::calculate_indexes::CalculateIndexes64;
-use anyhow::Result;
 use async_trait::async_trait;
-use compress_utils::context::Context;
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::Step;
+use compress_utils::types::ChimpOutput64;
 use compress_utils::wgpu_utils::RunBuffers;
-use std::sync::Arc;
+use compress_utils::{step, BufferWrapper};
+use itertools::Itertools;
+use std::fs;
 
+#[derive(Debug, Default)]
 pub struct CPUCalculateIndexes64 {
-    context: Arc<Context>,
+    config: ChimpConfig,
 }
 
 #[async_trait]
 impl CalculateIndexes64 for CPUCalculateIndexes64 {
     async fn calculate_indexes(
         &self,
-        input: &mut RunBuffers,
+        buffers: &mut RunBuffers,
         size: u32,
-        skip_time: &mut u128,
-    ) -> Result<()> {
-        // let mut indexes = input
-        //     .chunks(size as usize)
-        //     .map(|chunk| (chunk.iter().map(|it| it.bit_count as u32).sum::<u32>() / 64u32) + 2u32)
-        //     .collect_vec();
-        // (1..indexes.len()).for_each(|it| indexes[it] += indexes[it - 1]);
-        // indexes.insert(0, 0);
-        // step!(Step::CalculateIndexes, {
-        //     indexes.iter().map(|it| format!("{it}")).into_iter()
-        // });
-        Ok(())
+        _skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        self.calculate_buffer_indexes(buffers, size)
     }
 }
+
 impl CPUCalculateIndexes64 {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
     }
 
-    pub fn context(&self) -> &Arc<Context> {
-        &self.context
+    /// The body of [CalculateIndexes64::calculate_indexes], public so the N variants can reuse
+    /// it for their own stage trait
+    pub fn calculate_buffer_indexes(
+        &self,
+        buffers: &mut RunBuffers,
+        size: u32,
+    ) -> Result<(), ChimpError> {
+        let mut indexes = buffers
+            .compressed_buffer()
+            .host_slice::<ChimpOutput64>()
+            .chunks_exact(size as usize)
+            .map(|chunk| (chunk.iter().map(|it| it.bit_count).sum::<u64>() / 64u64) as u32 + 2u32)
+            .collect_vec();
+        (1..indexes.len()).for_each(|it| indexes[it] += indexes[it - 1]);
+        indexes.insert(0, 0);
+        step!(self.config, Step::CalculateIndexes, {
+            indexes.iter().map(|it| format!("{it}\n"))
+        });
+        buffers.set_index_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &indexes,
+        )));
+        Ok(())
     }
 }
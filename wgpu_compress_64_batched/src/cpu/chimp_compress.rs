@@ -2,23 +2,26 @@ use crate::cpu::utils_64;
 use crate::cpu::utils_64::{pseudo_u64_shift, vec2, vec_condition};
 use crate::final_compress::FinalCompress;
 use async_trait::async_trait;
-use compress_utils::context::Context;
-use compress_utils::general_utils::{ChimpBufferInfo, MaxGroupGnostic};
+use bytemuck::Zeroable;
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{MaxGroupGnostic, Step};
 use compress_utils::types::{ChimpOutput64, S};
 use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{step, BufferWrapper};
+use std::cmp::max;
+use std::fs;
 use std::ops::Div;
-use std::sync::Arc;
-use wgpu_compress_32_batched::cpu::finalize::extract_bits;
+use wgpu_compress_32_batched::cpu::bits::extract_bits;
 
-pub struct CPUFinalCompressImpl64(Arc<Context>);
+#[derive(Debug, Default)]
+pub struct CPUFinalCompressImpl64 {
+    config: ChimpConfig,
+}
 
 impl CPUFinalCompressImpl64 {
-    pub fn new(context: Arc<Context>, _debug: bool) -> Self {
-        Self(context)
-    }
-
-    pub fn context(&self) -> &Context {
-        self.0.as_ref()
+    pub fn new(config: ChimpConfig, _debug: bool) -> Self {
+        Self { config }
     }
     fn compress(v: f64, s: S, v_prev: f64, s_prev: S) -> ChimpOutput64 {
         //Conditions
@@ -94,99 +97,57 @@ impl CPUFinalCompressImpl64 {
 
 impl MaxGroupGnostic for CPUFinalCompressImpl64 {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 
 #[async_trait]
 impl FinalCompress for CPUFinalCompressImpl64 {
-    // async fn final_compress(
-    //     &self,
-    //     input: &mut Vec<f64>,
-    //     s_values: &mut Vec<S>,
-    //     padding: usize,
-    // ) -> anyhow::Result<Vec<ChimpOutput64>> {
-    //     let chunks = ChimpBufferInfo::get().chunks();
-    //     let mut output_vec = vec![ChimpOutput64::default(); s_values.len()];
-    //     input.push(0f64);
-    //     let workgroup_count = self.get_max_number_of_groups(input.len());
-    //     let length_without_padding = output_vec.len() - padding - 1;
-    //
-    //     let mut final_output = Vec::<ChimpOutput64>::with_capacity(length_without_padding);
-    //
-    //     for workgroup in 0..max(workgroup_count, 1) {
-    //         for invocation in 0..256 {
-    //             for i in 0..chunks {
-    //                 let index: usize = workgroup * 256 * chunks + invocation + i * 256usize;
-    //                 output_vec[index + 1] = CPUFinalCompressImpl64::compress(
-    //                     input[index + 1],
-    //                     s_values[index + 1],
-    //                     input[index],
-    //                     s_values[index],
-    //                 );
-    //             }
-    //         }
-    //     }
-    //     final_output.extend(output_vec[0..length_without_padding].to_vec());
-    //     for i in 0..workgroup_count {
-    //         let index = i * ChimpBufferInfo::get().buffer_size();
-    //         let mut c = ChimpOutput64::default();
-    //         c.set_lower_bits(bytemuck::cast(input[index]));
-    //         c.set_bit_count(64);
-    //
-    //         final_output[index] = c;
-    //     }
-    //     step!(Step::Compress, {
-    //         final_output
-    //             .iter()
-    //             .enumerate()
-    //             .map(|it| format!("{}:{}\n", it.0, it.1))
-    //             .into_iter()
-    //     });
-    //     Ok(final_output)
-    // }
     async fn final_compress(
         &self,
         buffers: &mut RunBuffers,
-        skip_time: &mut u128,
-    ) -> anyhow::Result<()> {
-        // let chunks = ChimpBufferInfo::get().chunks();
-        // let mut output_vec = vec![ChimpOutput64::default(); s_values.len()];
-        // input.push(0f64);
-        // let workgroup_count = self.get_max_number_of_groups(input.len());
-        // let length_without_padding = output_vec.len() - padding - 1;
-        //
-        // let mut final_output = Vec::<ChimpOutput64>::with_capacity(length_without_padding);
-        //
-        // for workgroup in 0..max(workgroup_count, 1) {
-        //     for invocation in 0..256 {
-        //         for i in 0..chunks {
-        //             let index: usize = workgroup * 256 * chunks + invocation + i * 256usize;
-        //             output_vec[index + 1] = CPUFinalCompressImpl64::compress(
-        //                 input[index + 1],
-        //                 s_values[index + 1],
-        //                 input[index],
-        //                 s_values[index],
-        //             );
-        //         }
-        //     }
-        // }
-        // final_output.extend(output_vec[0..length_without_padding].to_vec());
-        // for i in 0..workgroup_count {
-        //     let index = i * ChimpBufferInfo::get().buffer_size();
-        //     let mut c = ChimpOutput64::default();
-        //     c.set_lower_bits(bytemuck::cast(input[index]));
-        //     c.set_bit_count(64);
-        //
-        //     final_output[index] = c;
-        // }
-        // step!(Step::Compress, {
-        //     final_output
-        //         .iter()
-        //         .enumerate()
-        //         .map(|it| format!("{}:{}\n", it.0, it.1))
-        //         .into_iter()
-        // });
+        _skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        let chunks = self.config.buffer_info().chunks();
+        let final_output = {
+            let input = buffers.input_buffer().host_slice::<f64>();
+            let s_values = buffers.s_buffer().host_slice::<S>();
+            let mut output_vec = vec![ChimpOutput64::zeroed(); s_values.len()];
+            let workgroup_count = self.get_max_number_of_groups(input.len());
+
+            for workgroup in 0..max(workgroup_count, 1) {
+                for invocation in 0..256 {
+                    for i in 0..chunks {
+                        let index: usize = workgroup * 256 * chunks + invocation + i * 256usize;
+                        output_vec[index + 1] = CPUFinalCompressImpl64::compress(
+                            input[index + 1],
+                            s_values[index + 1],
+                            input[index],
+                            s_values[index],
+                        );
+                    }
+                }
+            }
+            for i in 0..workgroup_count {
+                let index = i * self.config.buffer_size();
+                let mut c = ChimpOutput64::zeroed();
+                c.set_lower_bits(bytemuck::cast(input[index]));
+                c.set_bit_count(64);
+
+                output_vec[index] = c;
+            }
+            output_vec
+        };
+        step!(self.config, Step::Compress, {
+            final_output
+                .iter()
+                .enumerate()
+                .map(|it| format!("{}:{}\n", it.0, it.1))
+                .into_iter()
+        });
+        buffers.set_compressed_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &final_output,
+        )));
         Ok(())
     }
 }
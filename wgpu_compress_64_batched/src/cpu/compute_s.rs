@@ -1,100 +1,53 @@
 #[allow(non_snake_case)]
 use crate::compute_s_shader::ComputeS;
 use async_trait::async_trait;
-use compress_utils::context::Context;
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo, MaxGroupGnostic, Step};
-use compress_utils::step;
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{padding_to_fit_buffer_count, MaxGroupGnostic, Step};
 use compress_utils::types::S;
 use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{step, BufferWrapper};
 use std::cmp::max;
 use std::fs;
 use std::ops::Div;
-use std::sync::Arc;
-use wgpu_types::BufferAddress;
 
-pub struct CpuComputeSImpl(Arc<Context>);
+#[derive(Debug, Default)]
+pub struct CpuComputeSImpl {
+    config: ChimpConfig,
+}
 
 impl CpuComputeSImpl {
-    pub fn new(context: impl Into<Arc<Context>>) -> Self {
-        Self(context.into())
-    }
-
-    pub fn context(&self) -> &Context {
-        self.0.as_ref()
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
     }
 }
 
 impl MaxGroupGnostic for CpuComputeSImpl {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 
 #[async_trait]
 impl ComputeS for CpuComputeSImpl {
-    // async fn compute_s(&self, values: &mut [f64]) -> anyhow::Result<Vec<S>> {
-    //     let mut Ss_vec = vec![S::default(); values.len() + 1];
-    //
-    //     //Calculating buffer sizes and workgroup counts
-    //     let workgroup_count = self.get_max_number_of_groups(values.len());
-    //     //info!("The wgpu workgroup size: {}", &workgroup_count);
-    //
-    //     let size_of_s = size_of::<S>();
-    //     let bytes = values.len() + 1;
-    //     //info!("The size of the input values vec: {}", bytes);
-    //
-    //     let s_buffer_size = (size_of_s * bytes) as BufferAddress;
-    //     //info!("The S buffer size in bytes: {}", s_buffer_size);
-    //
-    //     let mut padded_values = Vec::from(values);
-    //     padded_values.push(0f64);
-    //     let chunks = ChimpBufferInfo::get().chunks() as u32;
-    //
-    //     for workgroup in 0u32..max(workgroup_count as u32, 1) {
-    //         for invocation in 0u32..256u32 {
-    //             for i in 0u32..chunks {
-    //                 let index: usize =
-    //                     (workgroup * 256 * chunks + invocation + i * 256u32) as usize;
-    //                 Ss_vec[index + 1] = CpuComputeSImpl::calculate_s(
-    //                     (chunks * 256) as u32,
-    //                     index as u32,
-    //                     padded_values[index],
-    //                     padded_values[index + 1],
-    //                 );
-    //             }
-    //         }
-    //     }
-    //     //info!("Output result size: {}", Ss_vec.len());
-    //     step!(Step::ComputeS, {
-    //         Ss_vec
-    //             .iter()
-    //             .map(|it| format!("{}\n", it.to_string()))
-    //             .into_iter()
-    //     });
-    //     Ok(Ss_vec)
-    // }
     async fn compute_s(
         &self,
-        values: &mut [f64],
+        values: &[f64],
         buffers: &mut RunBuffers,
-        skip_time: &mut u128,
-    ) -> anyhow::Result<()> {
-        let mut Ss_vec = vec![S::default(); values.len() + 1];
+        _skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        // the values are padded with 0's to fill their last buffer
+        let padded_len =
+            values.len() + padding_to_fit_buffer_count(values.len(), self.config.buffer_size());
+        let mut Ss_vec = vec![S::default(); padded_len + 1];
 
         //Calculating buffer sizes and workgroup counts
-        let workgroup_count = self.get_max_number_of_groups(values.len());
+        let workgroup_count = self.get_max_number_of_groups(padded_len);
         //info!("The wgpu workgroup size: {}", &workgroup_count);
 
-        let size_of_s = size_of::<S>();
-        let bytes = values.len() + 1;
-        //info!("The size of the input values vec: {}", bytes);
-
-        let s_buffer_size = (size_of_s * bytes) as BufferAddress;
-        //info!("The S buffer size in bytes: {}", s_buffer_size);
-
         let mut padded_values = Vec::from(values);
-        padded_values.push(0f64);
-        let chunks = ChimpBufferInfo::get().chunks() as u32;
+        padded_values.resize(padded_len + 1, 0f64);
+        let chunks = self.config.buffer_info().chunks() as u32;
 
         for workgroup in 0u32..max(workgroup_count as u32, 1) {
             for invocation in 0u32..256u32 {
@@ -111,12 +64,18 @@ impl ComputeS for CpuComputeSImpl {
             }
         }
         //info!("Output result size: {}", Ss_vec.len());
-        step!(Step::ComputeS, {
+        step!(self.config, Step::ComputeS, {
             Ss_vec
                 .iter()
                 .map(|it| format!("{}\n", it.to_string()))
                 .into_iter()
         });
+        buffers.set_input_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &padded_values,
+        )));
+        buffers.set_s_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &Ss_vec,
+        )));
         Ok(())
     }
 }
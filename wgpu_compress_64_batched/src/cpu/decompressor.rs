@@ -1,95 +1,73 @@
 use crate::cpu::utils_64;
-use crate::ChimpCompressorBatched64;
 use async_trait::async_trait;
-use compress_utils::context::Context;
-use compress_utils::cpu_compress::{DecompressionError, Decompressor};
-use compress_utils::general_utils::{trace_steps, DecompressResult};
-use compress_utils::general_utils::{ChimpBufferInfo, MaxGroupGnostic, Step};
+use compress_utils::config::ChimpConfig;
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, HEADER_SIZE};
+use compress_utils::general_utils::{DecompressResult, MaxGroupGnostic, Step};
 use compress_utils::{step, time_it};
 use itertools::Itertools;
 use log::info;
 use std::cmp::{max, min};
 use std::fs;
-use std::sync::Arc;
+
+/// The error of a block whose bitstream cannot be decoded, [CPUDecompressorBatched64::decompress_block]
+/// replaces it with the position of the block in the stream
+const CORRUPT_BLOCK: ChimpError = ChimpError::CorruptInput {
+    block: 0,
+    byte_offset: 0,
+};
+
+/// Host decoder of the batched 64 bit format, it needs no wgpu device
+#[derive(Debug, Default, Clone)]
 pub struct CPUDecompressorBatched64 {
-    context: Arc<Context>,
+    config: ChimpConfig,
 }
 impl MaxGroupGnostic for CPUDecompressorBatched64 {
-    fn get_max_number_of_groups(&self, _content_len: usize) -> usize {
-        self.context().get_max_workgroup_size()
+    fn get_max_number_of_groups(&self, content_len: usize) -> usize {
+        max(content_len, 1)
     }
 }
 #[async_trait]
 impl Decompressor<f64> for CPUDecompressorBatched64 {
-    async fn decompress(
-        &self,
-        vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f64>, DecompressionError> {
+    async fn decompress(&self, vec: &[u8]) -> Result<DecompressResult<f64>, ChimpError> {
         let mut current_index = 0usize;
-        let mut uncompressed_values = Vec::new();
+        let uncompressed_values;
         let mut total_millis = 0;
+        let (header, vec) = ChimpHeader::split(vec, CodecVariant::Chimp64)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
         time_it!(
             {
+                let words = vec
+                    .chunks_exact(size_of::<u64>())
+                    .map(|it| u64::from_le_bytes(it.try_into().unwrap()))
+                    .collect_vec();
                 let mut vec_window = Vec::new();
                 let mut total_uncompressed_values = 0;
                 let mut input_indexes = Vec::new();
-                while current_index < vec.len() {
-                    while current_index < vec.len() {
-                        let old_index = current_index;
-                        let buffer_value_count = u32::from_be_bytes(
-                            vec[current_index..current_index + size_of::<u32>()]
-                                .try_into()
-                                .unwrap(),
-                        ) as usize
-                            + 1;
-                        current_index += size_of::<u32>();
-
-                        let size_in_bytes = u32::from_be_bytes(
-                            vec[current_index..current_index + size_of::<u32>()]
-                                .try_into()
-                                .unwrap(),
-                        );
-                        current_index += size_of::<u32>();
-                        if vec_window.len() + size_in_bytes as usize
-                            >= ChimpCompressorBatched64::MAX_BUFFER_SIZE_BYTES
-                        {
-                            current_index = old_index;
-                            break;
-                        }
-                        let byte_window_vec =
-                            vec[current_index..current_index + (size_in_bytes as usize)].to_vec();
-                        let mut byte_window = byte_window_vec.as_slice();
-                        assert_eq!(
-                            byte_window.len() % 8,
-                            0,
-                            "Total bytes need to be in batches of 8"
-                        );
-                        while let Some((first_four_bytes, rest)) = byte_window.split_at_checked(8) {
-                            byte_window = rest;
-                            //parse u32 from groups of 8 bytes
-                            let value_u64 =
-                                u64::from_le_bytes(first_four_bytes.try_into().unwrap());
-                            vec_window.push(value_u64);
-                        }
-                        input_indexes.push(vec_window.len() as u32);
-                        current_index += size_in_bytes as usize;
-                        total_uncompressed_values += buffer_value_count
-                    }
-                    input_indexes.insert(0, 0);
-                    //Block is as many buffers fit into the gpu the distinction is made for compatibility reasons
-                    let block_values = self
-                        .decompress_block(
-                            vec_window.as_slice(),
-                            input_indexes.as_slice(),
-                            min(
-                                total_uncompressed_values,
-                                ChimpBufferInfo::get().buffer_size(),
-                            ),
-                        )
-                        .await?;
-
-                    uncompressed_values.extend(block_values[0..total_uncompressed_values].iter());
+                let mut value_counts = Vec::new();
+                while current_index < words.len() {
+                    // count - 1 in the upper half, the size in bytes of the rest in the lower
+                    let buffer_value_count = (words[current_index] >> 32) as usize + 1;
+                    let size = (words[current_index] & 0xFFFFFFFF) as usize / size_of::<u64>();
+                    current_index += 1;
+                    vec_window.extend_from_slice(&words[current_index..current_index + size]);
+                    input_indexes.push(vec_window.len() as u32);
+                    value_counts.push(buffer_value_count as u32);
+                    current_index += size;
+                    total_uncompressed_values += buffer_value_count
                 }
+                input_indexes.insert(0, 0);
+                let block_values = self
+                    .decompress_block(
+                        vec_window.as_slice(),
+                        input_indexes.as_slice(),
+                        value_counts.as_slice(),
+                        self.config.buffer_size(),
+                    )
+                    .await?;
+
+                uncompressed_values = block_values[0..total_uncompressed_values].to_vec();
             },
             total_millis,
             "decompression"
@@ -98,107 +76,66 @@ impl Decompressor<f64> for CPUDecompressorBatched64 {
     }
 }
 impl CPUDecompressorBatched64 {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+
+    /// Decode the blocks starting at the [input_indexes] of [compressed_value_slice], a block that
+    /// cannot be decoded is reported with its index and its offset in the stream, header included
     //noinspection DuplicatedCode
     pub(crate) async fn decompress_block(
         &self,
         compressed_value_slice: &[u64],
         input_indexes: &[u32],
+        value_counts: &[u32],
         buffer_value_count: usize,
-    ) -> Result<Vec<f64>, DecompressionError> {
-        //how many buffers fit into the GPU
-        let workgroup_count = self.get_max_number_of_groups(input_indexes.len());
-
-        //how many iterations I need to fully decompress all the buffers
-        let iterator_count = max((input_indexes.len() - 1) / workgroup_count, 1);
-
-        //input_indexes shows how many buffers of count buffer_value_count, so we use workgroups equal to as many fit in the gpu
-        let mut result = Vec::<f64>::new();
-        //info!("The wgpu workgroup size: {}", &workgroup_count);
-
-        for iteration in 0..iterator_count {
-            //split all the buffers to the chunks each iteration will use
-            let is_last_iteration = iteration == iterator_count - 1;
-            let iteration_input_indexes = if is_last_iteration {
-                input_indexes[iteration * workgroup_count..].to_vec()
-            } else {
-                input_indexes[iteration * workgroup_count..(iteration + 1) * workgroup_count]
-                    .to_vec()
-            };
-            let first_index = iteration_input_indexes[0] as usize;
-            let iteration_compressed_values = if is_last_iteration {
-                compressed_value_slice[first_index..].to_vec()
-            } else {
-                compressed_value_slice
-                    [first_index..(iteration_input_indexes.last().unwrap().to_owned() as usize)]
-                    .to_vec()
-            };
-
-            //info!(
-            // "The size in bytes of the compressed input vec: {}",
-            // iteration_compressed_values.len() * size_of::<u8>()
-            // );
-
-            //info!(
-            // "The uncompressed output values buffer size in bytes: {}",
-            // out_buffer_size
-            // );
-            let out_vec = vec![
-                f64::default();
-                (iteration_input_indexes.len() - 1)
-                    * ChimpBufferInfo::get().buffer_size()
-            ];
+    ) -> Result<Vec<f64>, ChimpError> {
+        let workgroup_count = self.get_max_number_of_groups(input_indexes.len() - 1);
+        let out_vec = vec![f64::default(); workgroup_count * self.config.buffer_size()];
 
-            //info!("Total output values: {}", buffer_value_count);
-            //info!("Total input values: {}", buffer_value_count);
-
-            let in_size = iteration_compressed_values.len();
-            let iteration_workgroup_count = iteration_input_indexes.len() - 1;
-            let mut writer = CPUDecompressWriter64 {
-                input_index_vec: iteration_input_indexes,
-                in_vec: iteration_compressed_values,
-                input_size: in_size as u32,
-                size: buffer_value_count as u32,
-                out_vec,
-            };
-            for workgroup in 0..iteration_workgroup_count {
-                writer.write(
+        let mut writer = CPUDecompressWriter64 {
+            in_vec: compressed_value_slice.to_vec(),
+            out_vec,
+        };
+        for workgroup in 0..input_indexes.len() - 1 {
+            writer
+                .write(
                     input_indexes[workgroup],
                     (workgroup * buffer_value_count) as u32,
+                    value_counts[workgroup],
                 )
-            }
-            result.extend(writer.out_vec);
+                .map_err(|_| ChimpError::CorruptInput {
+                    block: workgroup,
+                    // every block before it adds its one word prefix to the payload
+                    byte_offset: HEADER_SIZE
+                        + (input_indexes[workgroup] as usize + workgroup) * size_of::<u64>(),
+                })?;
         }
-        //info!("Output result size: {}", result.len());
-        step!(Step::Decompress, {
+        let result = writer.out_vec;
+        step!(self.config, Step::Decompress, {
             result.iter().map(|it| it.to_string()).into_iter()
         });
         Ok(result)
     }
-
-    pub fn context(&self) -> &Context {
-        &self.context
-    }
-    pub fn new(context: impl Into<Arc<Context>>) -> Self {
-        Self {
-            context: context.into(),
-        }
-    }
 }
 
 struct CPUDecompressWriter64 {
     out_vec: Vec<f64>,
     in_vec: Vec<u64>,
-    size: u32,
-    input_index_vec: Vec<u32>,
-    input_size: u32,
 }
 struct CurrentInfo {
     current_index: u32,
     current_offset: u32,
 }
 impl CPUDecompressWriter64 {
-    #[allow(clippy::absurd_extreme_comparisons)]
-    fn write(&mut self, input_idx: u32, output_idx: u32) {
+    /// Decode the [value_count] values of the block at [input_idx] into the output from
+    /// [output_idx] on
+    fn write(
+        &mut self,
+        input_idx: u32,
+        output_idx: u32,
+        value_count: u32,
+    ) -> Result<(), ChimpError> {
         //Index of the byte we are in
         let current_index = input_idx + 1u32;
         //Current Remaining offset
@@ -209,7 +146,7 @@ impl CPUDecompressWriter64 {
             current_offset,
         };
 
-        let first_num = self.in_vec[(current_info.current_index - 1u32) as usize];
+        let first_num = self.word(current_info.current_index - 1u32)?;
         let mut last_num: u64 = first_num;
         let mut last_lead = 0u64;
         let mut significant_bits;
@@ -221,21 +158,14 @@ impl CPUDecompressWriter64 {
         current_info.current_offset += 64u32;
         // current_info.current_index+=1;
         let mut value;
-        for _i in 1..self.size {
-            // if we have not finished reading values from the uncompressed buffers
-            if current_info.current_index >= (self.input_size - 1u32)
-                && (current_info.current_offset - 1u32) <= 0u32
-            {
-                break;
-            }
-
+        for _i in 1..value_count {
             //if current bit value==1
-            if self.get_bit_at_index(current_info.current_index, current_info.current_offset)
+            if self.get_bit_at_index(current_info.current_index, current_info.current_offset)?
                 == 1u32
             {
                 current_info = self.decr_counter_capped_at_32(current_info, 1u32);
                 let mut lead = last_lead;
-                if self.get_bit_at_index(current_info.current_index, current_info.current_offset)
+                if self.get_bit_at_index(current_info.current_index, current_info.current_offset)?
                     == 1
                 {
                     current_info = self.decr_counter_capped_at_32(current_info, 1u32);
@@ -243,7 +173,7 @@ impl CPUDecompressWriter64 {
                         current_info.current_index,
                         current_info.current_offset,
                         6u32,
-                    );
+                    )?;
                     current_info = self.decr_counter_capped_at_32(current_info, 6u32);
                 } else {
                     current_info = self.decr_counter_capped_at_32(current_info, 1u32);
@@ -256,7 +186,7 @@ impl CPUDecompressWriter64 {
                     current_info.current_index,
                     current_info.current_offset,
                     significant_bits,
-                );
+                )?;
                 current_info = self.decr_counter_capped_at_32(current_info, significant_bits);
                 value = value ^ last_num;
                 last_num = value;
@@ -267,7 +197,7 @@ impl CPUDecompressWriter64 {
             } else if self.get_bit_at_index(
                 current_info.current_index,
                 current_info.current_offset - 1u32,
-            ) == 1u32
+            )? == 1u32
             {
                 current_info = self.decr_counter_capped_at_32(current_info, 2u32);
 
@@ -275,19 +205,22 @@ impl CPUDecompressWriter64 {
                     current_info.current_index,
                     current_info.current_offset,
                     6u32,
-                );
+                )?;
                 current_info = self.decr_counter_capped_at_32(current_info, 6u32);
 
                 let mut significant_bits = self.reinterpret_num(
                     current_info.current_index,
                     current_info.current_offset,
                     6u32,
-                );
+                )?;
                 current_info = self.decr_counter_capped_at_32(current_info, 6u32);
 
                 if significant_bits == 0u64 {
                     significant_bits = 64;
                 }
+                if lead + significant_bits > 64 {
+                    return Err(CORRUPT_BLOCK);
+                }
 
                 let trail = 64u32 - (lead as u32) - (significant_bits as u32);
 
@@ -295,7 +228,7 @@ impl CPUDecompressWriter64 {
                     current_info.current_index,
                     current_info.current_offset,
                     64u32 - (lead as u32) - trail,
-                );
+                )?;
 
                 current_info =
                     self.decr_counter_capped_at_32(current_info, 64u32 - (lead as u32) - trail);
@@ -315,16 +248,25 @@ impl CPUDecompressWriter64 {
                 current_info = self.decr_counter_capped_at_32(current_info, 2u32);
             }
         }
+        Ok(())
     }
 
-    fn get_bit_at_index(&self, array_index: u32, position: u32) -> u32 {
+    /// The word at [index], a bitstream that reads past the input is corrupted
+    fn word(&self, index: u32) -> Result<u64, ChimpError> {
+        self.in_vec
+            .get(index as usize)
+            .copied()
+            .ok_or(CORRUPT_BLOCK)
+    }
+
+    fn get_bit_at_index(&self, array_index: u32, position: u32) -> Result<u32, ChimpError> {
         let index = if position == 0u32 {
             array_index + 1
         } else {
             array_index
         };
         let f_position = if position == 0u32 { 64u32 } else { position };
-        ((self.in_vec[index as usize] >> (f_position - 1u32)) & 1u64) as u32
+        Ok(((self.word(index)? >> (f_position - 1u32)) & 1u64) as u32)
     }
 
     fn decr_counter_capped_at_32(&self, mut value: CurrentInfo, count: u32) -> CurrentInfo {
@@ -338,22 +280,31 @@ impl CPUDecompressWriter64 {
         value
     }
 
-    fn reinterpret_num(&self, array_index: u32, index: u32, length: u32) -> u64 {
+    fn reinterpret_num(
+        &self,
+        array_index: u32,
+        index: u32,
+        length: u32,
+    ) -> Result<u64, ChimpError> {
         let len = min(length, 64u32);
         if index >= len {
             // Fully within one u64
-            utils_64::extract_bits(self.in_vec[array_index as usize], index - len, len)
+            Ok(utils_64::extract_bits(
+                self.word(array_index)?,
+                index - len,
+                len,
+            ))
         } else {
             // Spans two u64 elements
             let bits_in_second = length - index;
 
-            let first_part = utils_64::extract_bits(self.in_vec[array_index as usize], 0u32, index);
+            let first_part = utils_64::extract_bits(self.word(array_index)?, 0u32, index);
             let second_part = utils_64::extract_bits(
-                self.in_vec[(array_index + 1) as usize],
+                self.word(array_index + 1)?,
                 64u32 - bits_in_second,
                 bits_in_second,
             );
-            (first_part << bits_in_second) | second_part
+            Ok((first_part << bits_in_second) | second_part)
         }
     }
 }
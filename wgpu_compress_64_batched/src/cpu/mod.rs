@@ -1,6 +1,10 @@
+#[cfg(feature = "gpu")]
 pub mod calculate_indexes;
+#[cfg(feature = "gpu")]
 pub mod chimp_compress;
+#[cfg(feature = "gpu")]
 pub mod compute_s;
 pub mod decompressor;
+#[cfg(feature = "gpu")]
 pub mod finalize;
 pub mod utils_64;
@@ -1,10 +1,14 @@
 use crate::cpu::decompressor::CPUDecompressorBatched64;
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::cpu_compress::{DecompressionError, Decompressor};
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant};
 use compress_utils::general_utils::DeviceEnum::GPU;
 use compress_utils::general_utils::{
-    trace_steps, ChimpBufferInfo, DecompressResult, DeviceEnum, MaxGroupGnostic, Step,
+    cast_or_copy, DecompressResult, DeviceEnum, MaxGroupGnostic, Step,
 };
 use compress_utils::{
     execute_compute_shader, step, time_it, wgpu_utils, BufferWrapper, WgpuGroupId,
@@ -34,10 +38,7 @@ impl MaxGroupGnostic for DecompressorImpl {
 
 #[async_trait]
 impl Decompressor<f64> for DecompressorImpl {
-    async fn decompress(
-        &self,
-        vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f64>, DecompressionError> {
+    async fn decompress(&self, vec: &[u8]) -> Result<DecompressResult<f64>, ChimpError> {
         match self {
             DecompressorImpl::GPU(d) => d.decompress(vec).await,
             DecompressorImpl::CPU(d) => d.decompress(vec).await,
@@ -48,13 +49,14 @@ impl Decompressor<f64> for DecompressorImpl {
 pub struct ChimpDecompressorBatched64 {
     context: Arc<Context>,
     device_type: DeviceEnum,
+    config: ChimpConfig,
 }
-impl Default for ChimpDecompressorBatched64 {
-    fn default() -> Self {
-        Self {
-            context: Arc::new(Context::initialize_default_adapter().block_on().unwrap()),
-            device_type: GPU,
-        }
+impl ChimpDecompressorBatched64 {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(Arc::new(
+            Context::initialize_default_adapter().block_on()?,
+        )))
     }
 }
 impl MaxGroupGnostic for ChimpDecompressorBatched64 {
@@ -65,10 +67,7 @@ impl MaxGroupGnostic for ChimpDecompressorBatched64 {
 }
 #[async_trait]
 impl Decompressor<f64> for ChimpDecompressorBatched64 {
-    async fn decompress(
-        &self,
-        vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f64>, DecompressionError> {
+    async fn decompress(&self, vec: &[u8]) -> Result<DecompressResult<f64>, ChimpError> {
         self.decompressor_factory().decompress(vec).await
     }
 }
@@ -77,6 +76,7 @@ impl ChimpDecompressorBatched64 {
         Self {
             context,
             device_type: GPU,
+            config: ChimpConfig::default(),
         }
     }
     pub fn with_device(self, device: impl Into<DeviceEnum>) -> Self {
@@ -85,16 +85,26 @@ impl ChimpDecompressorBatched64 {
             ..self
         }
     }
+    pub fn with_config(self, config: ChimpConfig) -> Self {
+        Self { config, ..self }
+    }
 
     pub fn decompressor_factory(&self) -> DecompressorImpl {
         match self.device_type() {
-            GPU => DecompressorImpl::GPU(GPUDecompressorBatched64::new(self.context.clone())),
+            GPU => DecompressorImpl::GPU(
+                GPUDecompressorBatched64::new(self.context.clone())
+                    .with_config(self.config.clone()),
+            ),
             DeviceEnum::CPU => {
-                DecompressorImpl::CPU(CPUDecompressorBatched64::new(self.context.clone()))
+                DecompressorImpl::CPU(CPUDecompressorBatched64::new(self.config.clone()))
             }
         }
     }
 
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
     pub fn device_type(&self) -> &DeviceEnum {
         &self.device_type
     }
@@ -104,18 +114,23 @@ impl ChimpDecompressorBatched64 {
 impl Decompressor<f64> for GPUDecompressorBatched64 {
     async fn decompress(
         &self,
-        compressed_bytes_vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f64>, DecompressionError> {
+        compressed_bytes_vec: &[u8],
+    ) -> Result<DecompressResult<f64>, ChimpError> {
         let mut current_index = 0usize;
         let mut uncompressed_values = Vec::new();
         let mut total_millis = 0;
         let mut skip_time = 0;
+        let (header, compressed_bytes_vec) =
+            ChimpHeader::split(compressed_bytes_vec, CodecVariant::Chimp64)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        let compressed_words = cast_or_copy::<u64>(compressed_bytes_vec);
         time_it!(
             {
-                let mut compressed_bytes_vec: &[u64] = bytemuck::cast_slice(compressed_bytes_vec);
+                let mut compressed_bytes_vec: &[u64] = &compressed_words;
                 let mut vec_window = Vec::new();
                 let mut total_uncompressed_values = 0;
                 let mut input_indexes = Vec::new();
+                let mut value_counts = Vec::new();
                 while current_index < compressed_bytes_vec.len() {
                     while current_index < compressed_bytes_vec.len() {
                         let old_index = current_index;
@@ -131,7 +146,7 @@ impl Decompressor<f64> for GPUDecompressorBatched64 {
                         }
                         let buffer_value_count = (compressed_bytes_vec[current_index] >> 32) + 1;
                         current_index += 1;
-                        if (input_indexes.len() + 1) * ChimpBufferInfo::get().buffer_size() * 4
+                        if (input_indexes.len() + 1) * self.config.buffer_size() * 4
                             >= self.context.get_max_storage_buffer_size()
                         {
                             current_index = old_index;
@@ -142,6 +157,7 @@ impl Decompressor<f64> for GPUDecompressorBatched64 {
                         );
 
                         input_indexes.push(vec_window.len() as u32);
+                        value_counts.push(buffer_value_count as u32);
                         current_index += size as usize;
                         total_uncompressed_values += buffer_value_count as usize
                     }
@@ -151,10 +167,8 @@ impl Decompressor<f64> for GPUDecompressorBatched64 {
                         .decompress_block(
                             vec_window.as_slice(),
                             input_indexes.as_slice(),
-                            min(
-                                total_uncompressed_values,
-                                ChimpBufferInfo::get().buffer_size(),
-                            ),
+                            value_counts.as_slice(),
+                            self.config.buffer_size(),
                             &mut skip_time,
                         )
                         .await?;
@@ -163,12 +177,13 @@ impl Decompressor<f64> for GPUDecompressorBatched64 {
                     vec_window.clear();
                     total_uncompressed_values = 0;
                     input_indexes.clear();
+                    value_counts.clear();
                 }
             },
             total_millis,
             "decompression"
         );
-        step!(Step::Decompress, {
+        step!(self.config, Step::Decompress, {
             uncompressed_values.iter().map(|it: &f64| it.to_string())
         });
         Ok(DecompressResult(uncompressed_values, skip_time))
@@ -177,17 +192,17 @@ impl Decompressor<f64> for GPUDecompressorBatched64 {
 
 pub struct GPUDecompressorBatched64 {
     context: Arc<Context>,
+    config: ChimpConfig,
 }
 impl MaxGroupGnostic for GPUDecompressorBatched64 {
     fn get_max_number_of_groups(&self, _content_len: usize) -> usize {
-        self.context().get_max_workgroup_size()
+        self.config.max_workgroup_size(self.context())
     }
 }
-impl Default for GPUDecompressorBatched64 {
-    fn default() -> Self {
-        Self {
-            context: Arc::new(Context::initialize_default_adapter().block_on().unwrap()),
-        }
+impl GPUDecompressorBatched64 {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(Context::initialize_default_adapter().block_on()?))
     }
 }
 impl GPUDecompressorBatched64 {
@@ -195,9 +210,10 @@ impl GPUDecompressorBatched64 {
         &self,
         compressed_value_slice: &[u64],
         input_indexes: &[u32],
+        value_counts: &[u32],
         buffer_value_count: usize,
         skip_time: &mut u128,
-    ) -> Result<Vec<f64>, DecompressionError> {
+    ) -> Result<Vec<f64>, ChimpError> {
         //how many buffers fit into the GPU
         let workgroup_count = self.get_max_number_of_groups(input_indexes.len()) * 256;
 
@@ -215,14 +231,20 @@ impl GPUDecompressorBatched64 {
             Some("Storage input Buffer"),
         );
         //info!("Total output values: {}", buffer_value_count);
-        let in_size = BufferWrapper::storage_with_content(
+        let blocks = input_indexes
+            .iter()
+            .zip(value_counts)
+            .flat_map(|(start, count)| [*start, *count])
+            .collect::<Vec<u32>>();
+        let block_storage = BufferWrapper::storage_with_content(
             self.device(),
-            bytemuck::cast_slice(&input_indexes),
+            bytemuck::cast_slice(&blocks),
             WgpuGroupId::new(0, 3),
-            Some("Total bytes input"),
+            Some("Block starts and value counts"),
         );
         let out_buffer_size =
-            (input_indexes.len() - 1) * ChimpBufferInfo::get().buffer_size() * size_of::<f64>();
+            (input_indexes.len() - 1) * self.config.buffer_size() * size_of::<f64>();
+        self.context().check_buffer_size(out_buffer_size)?;
         let out_staging = BufferWrapper::stage_with_size(
             self.device(),
             out_buffer_size as BufferAddress,
@@ -235,14 +257,11 @@ impl GPUDecompressorBatched64 {
             Some("Storage output Buffer"),
         );
         *skip_time += instant.elapsed().as_millis();
-        let workgroup_count = min(workgroup_count, self.context.get_max_workgroup_size());
+        let workgroup_count = min(
+            workgroup_count,
+            self.config.max_workgroup_size(&self.context),
+        );
         for iteration in 0..iterator_count {
-            let util_64 = include_str!("shaders/64_utils.wgsl");
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                iteration * self.context.get_max_workgroup_size()
-            );
-
             //split all the buffers to the chunks each iteration will use
             let is_last_iteration = iteration == iterator_count - 1;
             let offset = iteration * workgroup_count;
@@ -269,21 +288,14 @@ impl GPUDecompressorBatched64 {
                 Some("Total input values"),
             );
 
-            let input_size_uniform = BufferWrapper::uniform_with_content(
-                self.device(),
-                bytemuck::bytes_of(&compressed_value_slice.len()),
-                WgpuGroupId::new(0, 4),
-                Some("Total input buffer length"),
-            );
-
-            let s_offset = format!("let in_offset={offset}u;");
-            let total_threads = format!("let total_threads={}u;", input_indexes.len());
-
-            let shader_code = include_str!("shaders/decompress.wgsl")
-                .replace("//#include(64_utils)", util_64)
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@total_threads", &total_threads)
-                .to_string();
+            let shader_code = shaders::DECOMPRESS
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (iteration * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .bind("total_threads", value_counts.len() as u32)
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &shader_code,
@@ -292,8 +304,7 @@ impl GPUDecompressorBatched64 {
                     &input_storage_buffer,
                     &out_staging,
                     &size_uniform,
-                    &in_size,
-                    &input_size_uniform
+                    &block_storage
                 ],
                 iteration_input_indexes.div_ceil(256),
                 Some("decompress pass")
@@ -316,9 +327,19 @@ impl GPUDecompressorBatched64 {
     pub fn new(context_builder: impl Into<Arc<Context>>) -> Self {
         Self {
             context: context_builder.into(),
+            config: ChimpConfig::default(),
         }
     }
 
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
     pub fn context(&self) -> &Context {
         &self.context
     }
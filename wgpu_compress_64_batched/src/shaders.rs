@@ -0,0 +1,133 @@
+//! The WGSL shaders of the batched 64 bit pipeline along with the parameters each one is
+//! specialized with, see [compress_utils::shader_template]
+use compress_utils::shader_template::Parameter::Override;
+use compress_utils::shader_template::ShaderTemplate;
+
+const UTILS_64: (&str, &str) = ("64_utils", include_str!("shaders/64_utils.wgsl"));
+
+pub const CALCULATE_FINAL_SIZES: ShaderTemplate = ShaderTemplate::new(
+    "calculate_final_sizes",
+    include_str!("shaders/calculate_final_sizes.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")])
+.with_includes(&[UTILS_64]);
+
+pub const CHIMP_COMPRESS: ShaderTemplate = ShaderTemplate::new(
+    "chimp_compress",
+    include_str!("shaders/chimp_compress.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")])
+.with_includes(&[UTILS_64]);
+
+pub const CHIMP_FINALIZE_COMPRESS: ShaderTemplate = ShaderTemplate::new(
+    "chimp_finalize_compress",
+    include_str!("shaders/chimp_finalize_compress.wgsl"),
+)
+.with_parameters(&[
+    Override("workgroup_offset"),
+    Override("total_threads"),
+    Override("last_pass"),
+    Override("last_index"),
+])
+.with_includes(&[UTILS_64]);
+
+pub const COMPUTE_S: ShaderTemplate =
+    ShaderTemplate::new("compute_s", include_str!("shaders/compute_s.wgsl"))
+        .with_parameters(&[Override("workgroup_offset")]);
+
+pub const DECOMPRESS: ShaderTemplate =
+    ShaderTemplate::new("decompress", include_str!("shaders/decompress.wgsl"))
+        .with_parameters(&[Override("workgroup_offset"), Override("total_threads")])
+        .with_includes(&[UTILS_64]);
+
+pub const INITIALIZE_FIRST_PER_BUFFER: ShaderTemplate = ShaderTemplate::new(
+    "initialize_first_per_buffer",
+    include_str!("shaders/initialize_first_per_buffer.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")])
+.with_includes(&[UTILS_64]);
+
+/// Every shader of the pipeline, validated by `compress_utils::shader_validation` in the tests
+pub const ALL: &[ShaderTemplate] = &[
+    CALCULATE_FINAL_SIZES,
+    CHIMP_COMPRESS,
+    CHIMP_FINALIZE_COMPRESS,
+    COMPUTE_S,
+    DECOMPRESS,
+    INITIALIZE_FIRST_PER_BUFFER,
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::calculate_indexes::GPUCalculateIndexes64;
+    use crate::compressor::compress_batches;
+    use crate::compute_s_shader::ComputeSImpl;
+    use crate::decompressor::GPUDecompressorBatched64;
+    use crate::final_compress::FinalCompressImpl64;
+    use crate::finalize::{Finalize, Finalizer64};
+    use crate::{shaders, ChimpCompressorBatched64Cpu};
+    use async_trait::async_trait;
+    use compress_utils::config::ChimpConfig;
+    use compress_utils::context::Context;
+    use compress_utils::cpu_compress::{Compressor, Decompressor};
+    use compress_utils::error::ChimpError;
+    use compress_utils::general_utils::CompressResult;
+    use compress_utils::shader_validation::{
+        check_cached_layouts, seed_index_buffer, validate_templates, ParameterMatrix,
+    };
+    use compress_utils::wgpu_utils::RunBuffers;
+    use itertools::Itertools;
+    use pollster::FutureExt;
+    use std::sync::Arc;
+
+    /// The GPU [Finalizer64], run over the offsets the index calculation writes on a device
+    struct SeededFinalizer(Finalizer64);
+
+    #[async_trait]
+    impl Finalize for SeededFinalizer {
+        async fn finalize(
+            &self,
+            buffers: &mut RunBuffers,
+            padding: usize,
+            skip_time: &mut u128,
+        ) -> Result<CompressResult, ChimpError> {
+            seed_index_buffer(self.0.context(), buffers);
+            self.0.finalize(buffers, padding, skip_time).await
+        }
+    }
+
+    #[test]
+    fn test_shaders_validate_offline() {
+        let validated = validate_templates(shaders::ALL, &ParameterMatrix::default())
+            .unwrap_or_else(|error| panic!("{error}"));
+        assert!(validated > shaders::ALL.len());
+    }
+
+    #[test]
+    fn test_stage_layouts_match_their_shaders() {
+        let context = Arc::new(Context::initialize_noop().block_on().unwrap());
+        let config = ChimpConfig::default();
+        let values = (0..3000).map(|it| it as f64 / 8.0).collect_vec();
+        compress_batches(
+            values.chunks(values.len()),
+            &config,
+            &ComputeSImpl::new(context.clone(), config.clone()),
+            &FinalCompressImpl64::new(context.clone(), config.clone(), false),
+            &GPUCalculateIndexes64::new(context.clone(), config.clone()),
+            &SeededFinalizer(Finalizer64::new(context.clone(), config.clone())),
+        )
+        .block_on()
+        .unwrap();
+        let compressed = ChimpCompressorBatched64Cpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        GPUDecompressorBatched64::new(context.clone())
+            .decompress(compressed.compressed_value_ref())
+            .block_on()
+            .unwrap();
+
+        check_cached_layouts(context.pipeline_cache(), shaders::ALL)
+            .unwrap_or_else(|error| panic!("{error}"));
+    }
+}
@@ -11,13 +11,14 @@ var<uniform> size: u32;
 
 //#include(64_utils)
 
+override workgroup_offset: u32;
+
 
 
 
 @compute
 @workgroup_size(1)
 fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>) {
-    //@workgroup_offset
     var sum=u64(0);
      for (var i=0u;i<size;i++){
         sum += in[(workgroup_offset+workgroup_id.x) *size + i].bit_count;
@@ -23,6 +23,8 @@ var<uniform> chunks: u32; // this is used as both input and output for convenien
 
 //#include(64_utils)
 
+override workgroup_offset: u32;
+
 
 
 
@@ -91,7 +93,6 @@ fn compress(v:f64,s:Ss,v_prev:f64,s_prev:Ss) -> Output64{
 @compute
 @workgroup_size(256)
 fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>,@builtin(local_invocation_id) invocation_id: vec3<u32>) {
-    //@workgroup_offset
 
     for (var i=0u;i<chunks;i++){
         let index:u32=(workgroup_offset+workgroup_id.x) * 256 * chunks + invocation_id.x+i*256u;
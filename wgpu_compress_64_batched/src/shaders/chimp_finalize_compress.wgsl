@@ -21,6 +21,11 @@ var<storage, read_write> in: array<Output64>; // this is used as both input and
 
 //#include(64_utils)
 
+override workgroup_offset: u32;
+override total_threads: u32;
+override last_pass: u32;
+override last_index: u32;
+
 
 
 fn get_fitting(bits_rest_to_write: u32, writeable_output_remaining: u32) -> u32 {
@@ -132,10 +137,6 @@ fn write(idx:u32,out_idx: u32, is_last: u32, last_index: u32)->u32{
 @compute
 @workgroup_size(256)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>,@builtin(num_workgroups) count: vec3<u32>) {
-    //@workgroup_offset
-    //@last_pass
-    //@last_index
-    //@total_threads
     if(workgroup_offset+global_id.x>=total_threads){return;}
     
     //Check if this invocation is running the last batch
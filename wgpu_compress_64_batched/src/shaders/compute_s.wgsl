@@ -1,3 +1,4 @@
+override workgroup_offset: u32;
 
 struct Ss {
     leading:i32,
@@ -69,7 +70,6 @@ fn countTrailingZeros64(x: u64) -> u32 {
 @compute
 @workgroup_size(256)
 fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>,@builtin(local_invocation_id) invocation_id: vec3<u32>) {
-    //@workgroup_offset
     for (var i=0u;i<chunks;i++){
         let index:u32=(workgroup_offset+workgroup_id.x) * 256 * chunks + invocation_id.x+i*256u;
         s_store[index+1] = calculate_s(chunks*256,index,in[index],in[index+1]);
@@ -1,8 +1,9 @@
-use anyhow::Result;
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
+use compress_utils::error::ChimpError;
 use compress_utils::general_utils::Step;
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo};
 use compress_utils::types::ChimpOutput64;
 use compress_utils::wgpu_utils::RunBuffers;
 use compress_utils::{execute_compute_shader, step, wgpu_utils, BufferWrapper, WgpuGroupId};
@@ -21,16 +22,17 @@ pub trait CalculateIndexesN64 {
         input: &mut RunBuffers,
         size: u32,
         skip_time: &mut u128,
-    ) -> Result<()>;
+    ) -> Result<(), ChimpError>;
 }
 
 pub struct GPUNCalculateIndexes64 {
     context: Arc<Context>,
+    config: ChimpConfig,
 }
 
 impl GPUNCalculateIndexes64 {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
     }
 
     pub fn context(&self) -> &Arc<Context> {
@@ -45,15 +47,14 @@ impl CalculateIndexesN64 for GPUNCalculateIndexes64 {
         buffers: &mut RunBuffers,
         size: u32,
         skip_time: &mut u128,
-    ) -> Result<()> {
-        let util_64 = include_str!("shaders/64_utils.wgsl");
+    ) -> Result<(), ChimpError> {
         let input_len = buffers.compressed_buffer().size() / size_of::<ChimpOutput64>();
         let workgroup_count = input_len.div(size as usize);
         let output_buffer_size = (workgroup_count + 1) * size_of::<u32>();
 
         let instant = Instant::now();
-        let mut out_storage_buffer = BufferWrapper::storage_with_size(
-            self.context().device(),
+        let mut out_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
             output_buffer_size as BufferAddress,
             WgpuGroupId::new(0, 0),
             Some("Staging Output Buffer"),
@@ -65,29 +66,23 @@ impl CalculateIndexesN64 for GPUNCalculateIndexes64 {
                 .with_binding(WgpuGroupId::new(0, 1));
         }
 
-        let size_uniform = BufferWrapper::uniform_with_content(
-            self.context().device(),
+        let size_uniform = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
             bytemuck::bytes_of(&size),
             WgpuGroupId::new(0, 2),
             Some("Size Uniform Buffer"),
         );
         *skip_time += instant.elapsed().as_millis();
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let util_64 = include_str!("shaders/64_utils.wgsl");
-            let temp = include_str!("shaders/calculate_final_sizes.wgsl")
-                .replace("//#include(64_utils)", util_64)
-                .replace(
-                    "@@workgroup_size",
-                    &ChimpBufferInfo::get().buffer_size().to_string(),
+            let temp = shaders::CALCULATE_FINAL_SIZES
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
                 )
-                .replace("//@workgroup_offset", &offset_decl)
-                .to_string();
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &temp,
@@ -99,13 +94,13 @@ impl CalculateIndexesN64 for GPUNCalculateIndexes64 {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("calculate indexes pass")
             );
         }
-        let size_uniform = BufferWrapper::uniform_with_content(
-            self.context().device(),
+        let size_uniform = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
             bytemuck::bytes_of(&workgroup_count),
             WgpuGroupId::new(0, 1),
             Some("Size Uniform Buffer"),
@@ -138,9 +133,9 @@ impl CalculateIndexesN64 for GPUNCalculateIndexes64 {
 
         buffers.set_index_buffer(out_storage_buffer);
         let mut output;
-        step!(Step::CalculateIndexes, {
-            let out_stage_buffer = BufferWrapper::stage_with_size(
-                self.context().device(),
+        step!(self.config, Step::CalculateIndexes, {
+            let out_stage_buffer = BufferWrapper::pooled_stage_with_size(
+                self.context(),
                 buffers.index_buffer().size() as BufferAddress,
                 None,
             );
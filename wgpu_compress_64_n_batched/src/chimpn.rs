@@ -5,43 +5,60 @@ use crate::final_compress::{FinalCompressImplN64, FinalCompressN64};
 use crate::finalize::{FinalizeN64, FinalizerN64};
 use crate::previous_indexes::{PreviousIndexesN64, PreviousIndexesN64Impl};
 use async_trait::async_trait;
+use compress_utils::config::{ChimpConfig, ConfigError, DEFAULT_N};
 use compress_utils::context::Context;
-use compress_utils::cpu_compress::{CompressionError, Compressor};
+use compress_utils::cpu_compress::Compressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, HEADER_SIZE};
 use compress_utils::general_utils::{
-    add_padding_to_fit_buffer_count, ChimpBufferInfo, CompressResult, Padding,
+    max_batch_len, padding_to_fit_buffer_count, split_by_max_storage_buffer_size, CompressResult,
 };
-use compress_utils::types::{ChimpOutput, S};
+use compress_utils::impl_integer_compressor;
+use compress_utils::lossy::quantize;
+use compress_utils::stream::BatchedCompressor;
+use compress_utils::types::ChimpOutput;
 use compress_utils::{time_it, wgpu_utils};
-use itertools::Itertools;
 use log::info;
 use pollster::FutureExt;
+use std::slice::Chunks;
 use std::sync::Arc;
-use wgpu_compress_64_batched::add_padding_to_fit_buffer_count_64;
+use wgpu_compress_64_batched::cpu::calculate_indexes::CPUCalculateIndexes64;
+use wgpu_compress_64_batched::cpu::finalize::CPUFinalizer64;
+use wgpu_compress_64_batched::ChimpCompressorBatched64;
 
 #[derive(Debug)]
 pub struct ChimpN64GPUBatched {
     context: Arc<Context>,
-    n: usize,
+    config: ChimpConfig,
 }
 
 impl ChimpN64GPUBatched {
     pub(crate) fn previous_index_factory(&self) -> Box<dyn PreviousIndexesN64 + Send + Sync> {
-        Box::new(PreviousIndexesN64Impl::new(self.context.clone(), self.n))
+        Box::new(PreviousIndexesN64Impl::new(
+            self.context.clone(),
+            self.config.clone(),
+        ))
         // Box::new(cpu::previous_indexes::PreviousIndexesNCPUImpl {
         //     context: self.context.clone(),
         //     n: self.n,
         // })
     }
     pub(crate) fn compute_finalize_factory(&self) -> Box<dyn FinalizeN64 + Send + Sync> {
-        Box::new(FinalizerN64::new(self.context.clone()))
+        Box::new(FinalizerN64::new(self.context.clone(), self.config.clone()))
     }
 
     pub(crate) fn calculate_indexes_factory(&self) -> Box<dyn CalculateIndexesN64 + Send + Sync> {
-        Box::new(GPUNCalculateIndexes64::new(self.context.clone()))
+        Box::new(GPUNCalculateIndexes64::new(
+            self.context.clone(),
+            self.config.clone(),
+        ))
     }
 
     pub(crate) fn compute_final_compress_factory(&self) -> Box<dyn FinalCompressN64 + Send + Sync> {
-        Box::new(FinalCompressImplN64::new(self.context.clone(), self.n))
+        Box::new(FinalCompressImplN64::new(
+            self.context.clone(),
+            self.config.clone(),
+        ))
         // Box::new(cpu::compress::CPUBatchedNCompressImpl {
         //     context: self.context.clone(),
         //     n: self.n,
@@ -49,118 +66,277 @@ impl ChimpN64GPUBatched {
     }
 
     pub(crate) fn compute_s_factory(&self) -> Box<dyn ComputeS + Send + Sync> {
-        Box::new(ComputeSN64Impl::new(self.context.clone(), self.n))
+        Box::new(ComputeSN64Impl::new(
+            self.context.clone(),
+            self.config.clone(),
+        ))
         // Box::new(cpu::compute_s::CPUBatchedNComputeSImpl {
         //     context: self.context.clone(),
         //     n: self.n,
         // })
     }
 
-    pub(crate) fn split_by_max_gpu_buffer_size(&self, vec: &mut Vec<f64>) -> Vec<Vec<f64>> {
-        let max = self.context.get_max_storage_buffer_size();
-        let mut split_by = max / size_of::<S>() - ChimpBufferInfo::get().buffer_size(); //The most costly buffer
-        while ((split_by + 10) * size_of::<S>()) as u64
-            >= self.context.get_max_storage_buffer_size() as u64
-            || ((split_by + 10) * size_of::<ChimpOutput>()) as u64
-                >= self.context.get_max_storage_buffer_size() as u64
-        {
-            split_by -= ChimpBufferInfo::get().buffer_size();
-        }
-        let closest = split_by - split_by % ChimpBufferInfo::get().buffer_size();
+    pub(crate) fn split_by_max_gpu_buffer_size<'a>(&self, values: &'a [f64]) -> Chunks<'a, f64> {
+        split_by_max_storage_buffer_size(
+            values,
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput>(),
+        )
+    }
+
+    pub fn new(context: Arc<Context>, n: usize) -> Result<Self, ConfigError> {
+        Ok(Self {
+            context,
+            config: ChimpConfig::default().with_n(n)?,
+        })
+    }
 
-        let x = vec.chunks(closest).map(|it| it.to_vec()).collect_vec();
-        x
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
     }
 
-    pub fn new(context: Arc<Context>, n: usize) -> Self {
-        Self { context, n }
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
     }
 }
 
 #[async_trait]
 impl Compressor<f64> for ChimpN64GPUBatched {
-    async fn compress(&self, vec: &mut Vec<f64>) -> Result<CompressResult, CompressionError> {
-        let compute_s_impl = self.compute_s_factory();
-        let final_compress_impl = self.compute_final_compress_factory();
-        let calculate_indexes_impl = self.calculate_indexes_factory();
-        let finalize_impl = self.compute_finalize_factory();
-        let previous_index_impl = self.previous_index_factory();
-
-        let iterations = self.split_by_max_gpu_buffer_size(vec);
-        let mut byte_stream = Vec::new();
-        let mut metadata = 0usize;
-        let mut buffers = wgpu_utils::RunBuffers::default();
-        let mut skip_time = 0u128;
-        for iteration_values in iterations {
-            let mut padding = Padding(0);
-            let buffer_size = ChimpBufferInfo::get().buffer_size();
-            let mut values = iteration_values;
-            values = add_padding_to_fit_buffer_count_64(values, buffer_size, &mut padding);
-            let mut total_millis: u128 = 0;
-            // let mut indexes;
-            let output_vec;
-            time_it!(
-                {
-                    previous_index_impl
-                        .calculate_previous_indexes(&mut values, &mut buffers, &mut skip_time)
-                        .await?;
-                },
-                total_millis,
-                "calculation of the previous value to compare stage"
-            );
-            time_it!(
-                {
-                    compute_s_impl
-                        .compute_s(&mut values, &mut buffers, &mut skip_time)
-                        .await?;
-                },
-                total_millis,
-                "s computation stage"
-            );
-            time_it!(
-                {
-                    final_compress_impl
-                        .final_compress(&mut buffers, &mut skip_time)
-                        .await?;
-                },
-                total_millis,
-                "final output stage"
-            );
-            time_it!(
-                {
-                    calculate_indexes_impl
-                        .calculate_indexes(
-                            &mut buffers,
-                            ChimpBufferInfo::get().buffer_size() as u32,
-                            &mut skip_time,
-                        )
-                        .await?;
-                },
-                total_millis,
-                "final output stage"
-            );
-            time_it!(
-                {
-                    output_vec = finalize_impl
-                        .finalize(&mut buffers, padding.0, &mut skip_time)
-                        .await?;
-                },
-                total_millis,
-                "final Result collection"
-            );
-            byte_stream.extend(output_vec.compressed_value_ref());
-            metadata += output_vec.metadata_size()
+    async fn compress(&self, values: &[f64]) -> Result<CompressResult, ChimpError> {
+        let header = self.header(values.len());
+        let iterations = self.split_by_max_gpu_buffer_size(values);
+        let result = compress_batches(
+            iterations,
+            &self.config,
+            self.previous_index_factory().as_ref(),
+            self.compute_s_factory().as_ref(),
+            self.compute_final_compress_factory().as_ref(),
+            self.calculate_indexes_factory().as_ref(),
+            self.compute_finalize_factory().as_ref(),
+        )
+        .await?;
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl BatchedCompressor<f64> for ChimpN64GPUBatched {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput>(),
+        )
+    }
+
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        ChimpHeader::new(
+            CodecVariant::ChimpN64,
+            self.config.n(),
+            self.config.buffer_size(),
+            value_count,
+        )
+        .with_error_bound(self.config.error_bound())
+    }
+}
+
+impl_integer_compressor!(ChimpN64GPUBatched, i64, u64);
+
+/// Run every stage of the pipeline over each of the [iterations] and concatenate the
+/// resulting buffers, without the stream header
+pub(crate) async fn compress_batches(
+    iterations: Chunks<'_, f64>,
+    config: &ChimpConfig,
+    previous_index_impl: &(dyn PreviousIndexesN64 + Send + Sync),
+    compute_s_impl: &(dyn ComputeS + Send + Sync),
+    final_compress_impl: &(dyn FinalCompressN64 + Send + Sync),
+    calculate_indexes_impl: &(dyn CalculateIndexesN64 + Send + Sync),
+    finalize_impl: &(dyn FinalizeN64 + Send + Sync),
+) -> Result<CompressResult, ChimpError> {
+    let mut byte_stream = Vec::new();
+    let mut metadata = 0;
+    let mut buffers = wgpu_utils::RunBuffers::default();
+    let mut skip_time = 0u128;
+    for iteration_values in iterations {
+        let buffer_size = config.buffer_size();
+        let quantized = quantize(iteration_values, config.error_bound());
+        let values = quantized.as_ref();
+        let padding = padding_to_fit_buffer_count(values.len(), buffer_size);
+        let mut total_millis: u128 = 0;
+        let output_vec;
+        time_it!(
+            {
+                previous_index_impl
+                    .calculate_previous_indexes(values, &mut buffers, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "calculation of the previous value to compare stage"
+        );
+        time_it!(
+            {
+                compute_s_impl
+                    .compute_s(values, &mut buffers, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "s computation stage"
+        );
+        time_it!(
+            {
+                final_compress_impl
+                    .final_compress(&mut buffers, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "final output stage"
+        );
+        time_it!(
+            {
+                calculate_indexes_impl
+                    .calculate_indexes(&mut buffers, buffer_size as u32, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "final output stage"
+        );
+        time_it!(
+            {
+                output_vec = finalize_impl
+                    .finalize(&mut buffers, padding, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "final Result collection"
+        );
+        byte_stream.extend(output_vec.compressed_value_ref());
+        metadata += output_vec.metadata_size()
+    }
+    Ok(CompressResult(byte_stream, metadata, skip_time))
+}
+
+/// A [Compressor] running every stage of the ChimpN pipeline on the host.
+///
+/// The output is byte for byte the one of [ChimpN64GPUBatched] on a device whose storage
+/// buffer binding limit is [ChimpN64CpuBatched::max_storage_buffer_size].
+#[derive(Debug, Clone)]
+pub struct ChimpN64CpuBatched {
+    config: ChimpConfig,
+    max_storage_buffer_size: usize,
+}
+
+impl ChimpN64CpuBatched {
+    pub fn new(n: usize) -> Result<Self, ConfigError> {
+        Ok(Self {
+            config: ChimpConfig::default().with_n(n)?,
+            max_storage_buffer_size: ChimpCompressorBatched64::MAX_BUFFER_SIZE_BYTES,
+        })
+    }
+
+    pub fn with_config(self, config: ChimpConfig) -> Self {
+        Self { config, ..self }
+    }
+
+    pub fn with_max_storage_buffer_size(self, max_storage_buffer_size: usize) -> Self {
+        Self {
+            max_storage_buffer_size,
+            ..self
         }
+    }
+
+    pub fn n(&self) -> usize {
+        self.config.n()
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
 
-        Ok(CompressResult(byte_stream, metadata, skip_time))
+    pub fn max_storage_buffer_size(&self) -> usize {
+        self.max_storage_buffer_size
     }
 }
 
-impl Default for ChimpN64GPUBatched {
+impl Default for ChimpN64CpuBatched {
     fn default() -> Self {
         Self {
-            context: Arc::new(Context::initialize_default_adapter().block_on().unwrap()),
-            n: 128,
+            config: ChimpConfig::default(),
+            max_storage_buffer_size: ChimpCompressorBatched64::MAX_BUFFER_SIZE_BYTES,
         }
     }
 }
+
+#[async_trait]
+impl Compressor<f64> for ChimpN64CpuBatched {
+    async fn compress(&self, values: &[f64]) -> Result<CompressResult, ChimpError> {
+        let header = self.header(values.len());
+        let iterations = split_by_max_storage_buffer_size(
+            values,
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput>(),
+        );
+        let result = compress_batches(
+            iterations,
+            &self.config,
+            &cpu::previous_indexes::PreviousIndexesNCPUImpl::new(self.config.clone()),
+            &cpu::compute_s::CPUBatchedNComputeSImpl::new(self.config.clone()),
+            &cpu::compress::CPUBatchedNCompressImpl::new(self.config.clone()),
+            &CPUCalculateIndexes64::new(self.config.clone()),
+            &CPUFinalizer64::new(self.config.clone()),
+        )
+        .await?;
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl BatchedCompressor<f64> for ChimpN64CpuBatched {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput>(),
+        )
+    }
+
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        ChimpHeader::new(
+            CodecVariant::ChimpN64,
+            self.config.n(),
+            self.config.buffer_size(),
+            value_count,
+        )
+        .with_error_bound(self.config.error_bound())
+    }
+}
+
+impl_integer_compressor!(ChimpN64CpuBatched, i64, u64);
+
+impl ChimpN64GPUBatched {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(
+            Arc::new(Context::initialize_default_adapter().block_on()?),
+            DEFAULT_N,
+        )?)
+    }
+}
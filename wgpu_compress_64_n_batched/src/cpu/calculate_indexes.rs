@@ -0,0 +1,17 @@
+use crate::calculate_indexes::CalculateIndexesN64;
+use async_trait::async_trait;
+use compress_utils::error::ChimpError;
+use compress_utils::wgpu_utils::RunBuffers;
+use wgpu_compress_64_batched::cpu::calculate_indexes::CPUCalculateIndexes64;
+
+#[async_trait]
+impl CalculateIndexesN64 for CPUCalculateIndexes64 {
+    async fn calculate_indexes(
+        &self,
+        input: &mut RunBuffers,
+        size: u32,
+        _skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        self.calculate_buffer_indexes(input, size)
+    }
+}
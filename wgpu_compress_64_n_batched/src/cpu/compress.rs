@@ -6,29 +6,27 @@
 
 use crate::final_compress::FinalCompressN64;
 use async_trait::async_trait;
-use compress_utils::context::Context;
-use compress_utils::general_utils::trace_steps;
-use compress_utils::general_utils::{ChimpBufferInfo, MaxGroupGnostic, Step};
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{MaxGroupGnostic, Step};
 use compress_utils::types::{ChimpOutput64, S};
-use compress_utils::wgpu_utils::{get_from_gpu, RunBuffers};
-use compress_utils::{step, BufferWrapper, WgpuGroupId};
-use itertools::Itertools;
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{step, BufferWrapper};
 use std::cmp::max;
 use std::fs;
 use std::ops::Div;
-use std::sync::Arc;
 use wgpu_compress_64_batched::cpu::utils_64;
 use wgpu_compress_64_batched::cpu::utils_64::{pseudo_u64_shift, vec2, vec_condition};
-use wgpu_types::BufferAddress;
 
+/// Host implementation of the `chimp_compress.wgsl` stage
+#[derive(Debug)]
 pub struct CPUBatchedNCompressImpl {
-    pub(crate) context: Arc<Context>,
-    pub(crate) n: usize,
+    config: ChimpConfig,
 }
 
 impl CPUBatchedNCompressImpl {
     pub fn compress(&self, s: S, v: f64, s_prev: S, v_prev: f64, c: usize) -> ChimpOutput64 {
-        let log2n = self.n.ilog2();
+        let log2n = self.config.n().ilog2();
         //Conditions
         let mut trail_gt_6 = (s.tail > 6) as u32;
         let mut trail_le_6 = (s.tail <= 6) as u32;
@@ -106,13 +104,13 @@ impl CPUBatchedNCompressImpl {
         };
     }
 
-    pub fn new(context: Arc<Context>, n: usize) -> Self {
-        Self { context, n }
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
     }
 }
 impl MaxGroupGnostic for CPUBatchedNCompressImpl {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 
@@ -122,86 +120,44 @@ impl FinalCompressN64 for CPUBatchedNCompressImpl {
         &self,
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> anyhow::Result<()> {
-        let staging_buffer = BufferWrapper::stage_with_size(
-            self.context.device(),
-            buffers.previous_index_buffer().size() as BufferAddress,
-            None,
-        );
-        let mut previous_index = get_from_gpu::<u32>(
-            self.context.as_ref(),
-            buffers.previous_index_buffer().buffer(),
-            buffers.previous_index_buffer().size() as BufferAddress,
-            staging_buffer.buffer(),
-        )
-        .await?;
-
-        let input_staging_buffer = BufferWrapper::stage_with_size(
-            self.context.device(),
-            buffers.input_buffer().size() as BufferAddress,
-            None,
-        );
-        let mut values = get_from_gpu::<f64>(
-            self.context.as_ref(),
-            buffers.input_buffer().buffer(),
-            buffers.input_buffer().size() as BufferAddress,
-            input_staging_buffer.buffer(),
-        )
-        .await?;
-        let s_staging_buffer = BufferWrapper::stage_with_size(
-            self.context.device(),
-            buffers.s_buffer().size() as BufferAddress,
-            None,
-        );
-        let mut s_values = get_from_gpu::<S>(
-            self.context.as_ref(),
-            buffers.s_buffer().buffer(),
-            buffers.s_buffer().size() as BufferAddress,
-            s_staging_buffer.buffer(),
-        )
-        .await?;
+    ) -> Result<(), ChimpError> {
+        let previous_index = buffers.previous_index_buffer().host_slice::<u32>();
+        let values = buffers.input_buffer().host_slice::<f64>();
+        let s_values = buffers.s_buffer().host_slice::<S>();
 
         let workgroup_count = self.get_max_number_of_groups(values.len());
 
         let mut compress = vec![ChimpOutput64::default(); values.len()];
 
-        let chunks = ChimpBufferInfo::get().chunks();
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
-        for _ in 0..iterations {
-            for workgroup in (0..workgroup_count) {
-                for invocation in 0..256 {
-                    for i in (0..chunks) {
-                        let index: usize = ((workgroup) * 256 * chunks + invocation + i * 256usize);
-                        let before_index = previous_index[index + 1] as usize;
-                        compress[index + 1] = self.compress(
-                            s_values[index + 1],
-                            values[index + 1],
-                            s_values[index + 1 - before_index],
-                            values[index + 1 - before_index],
-                            max(1, before_index),
-                        );
-                    }
+        let chunks = self.config.buffer_info().chunks();
+        for workgroup in (0..workgroup_count) {
+            for invocation in 0..256 {
+                for i in (0..chunks) {
+                    let index: usize = ((workgroup) * 256 * chunks + invocation + i * 256usize);
+                    let before_index = previous_index[index + 1] as usize;
+                    compress[index + 1] = self.compress(
+                        s_values[index + 1],
+                        values[index + 1],
+                        s_values[index + 1 - before_index],
+                        values[index + 1 - before_index],
+                        max(1, before_index),
+                    );
                 }
             }
-            for workgroup in (0..workgroup_count) {
-                compress[(workgroup) * 256 * chunks] = ChimpOutput64 {
-                    upper_bits: 0,
-                    lower_bits: bytemuck::cast(values[(workgroup) * 256 * chunks]),
-                    bit_count: 64u64,
-                };
-            }
+        }
+        for workgroup in (0..workgroup_count) {
+            compress[(workgroup) * 256 * chunks] = ChimpOutput64 {
+                upper_bits: 0,
+                lower_bits: bytemuck::cast(values[(workgroup) * 256 * chunks]),
+                bit_count: 64u64,
+            };
         }
 
-        let compress_buffer = BufferWrapper::storage_with_content(
-            self.context.device(),
-            bytemuck::cast_slice(&compress),
-            WgpuGroupId::new(0, 0),
-            Some("Input buffer"),
-        );
-        buffers.set_compressed_buffer(compress_buffer);
+        buffers.set_compressed_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &compress,
+        )));
 
-        step!(Step::Compress, {
+        step!(self.config, Step::Compress, {
             compress.into_iter().map(|s| format!("{}\n", s))
         });
         Ok(())
@@ -1,227 +1,150 @@
 use async_trait::async_trait;
-use compress_utils::context::Context;
-use compress_utils::cpu_compress::{DecompressionError, Decompressor};
-use compress_utils::general_utils::{
-    trace_steps, ChimpBufferInfo, DecompressResult, MaxGroupGnostic, Step,
-};
-use compress_utils::{
-    execute_compute_shader, step, time_it, wgpu_utils, BufferWrapper, WgpuGroupId,
-};
+use compress_utils::config::{ChimpConfig, ConfigError};
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, HEADER_SIZE};
+use compress_utils::general_utils::{DecompressResult, MaxGroupGnostic, Step};
+use compress_utils::{step, time_it};
 use itertools::Itertools;
 use log::info;
-use pollster::FutureExt;
 use std::cmp::{max, min};
 use std::fs;
-use std::sync::Arc;
-use std::time::Instant;
-use wgpu::{Device, Queue};
-use wgpu_compress_32_batched::cpu::finalize::extract_bits;
 use wgpu_compress_64_batched::cpu::utils_64;
-use wgpu_compress_64_batched::ChimpCompressorBatched64;
-use wgpu_types::BufferAddress;
+
+/// The error of a block whose bitstream cannot be decoded, [BatchedCPUN64Decompressor::decompress_block]
+/// replaces it with the position of the block in the stream
+const CORRUPT_BLOCK: ChimpError = ChimpError::CorruptInput {
+    block: 0,
+    byte_offset: 0,
+};
 
 #[async_trait]
 impl Decompressor<f64> for BatchedCPUN64Decompressor {
-    #[allow(unused)]
     async fn decompress(
         &self,
-        compressed_bytes_vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f64>, DecompressionError> {
+        compressed_bytes_vec: &[u8],
+    ) -> Result<DecompressResult<f64>, ChimpError> {
         let mut current_index = 0usize;
-        let mut uncompressed_values = Vec::new();
+        let uncompressed_values;
         let mut total_millis = 0;
-        let mut skip_time = 0;
+        let (header, compressed_bytes_vec) =
+            ChimpHeader::split(compressed_bytes_vec, CodecVariant::ChimpN64)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        header.expect_n(self.config.n())?;
         time_it!(
             {
-                let mut compressed_bytes_vec: &[u64] = bytemuck::cast_slice(compressed_bytes_vec);
+                let compressed_bytes_vec = compressed_bytes_vec
+                    .chunks_exact(size_of::<u64>())
+                    .map(|it| u64::from_le_bytes(it.try_into().unwrap()))
+                    .collect_vec();
                 let mut vec_window = Vec::new();
                 let mut total_uncompressed_values = 0;
                 let mut input_indexes = Vec::new();
+                let mut value_counts = Vec::new();
                 while current_index < compressed_bytes_vec.len() {
-                    while current_index < compressed_bytes_vec.len() {
-                        let old_index = current_index;
-                        let size_in_bytes =
-                            (compressed_bytes_vec[current_index] & 0xFFFFFFFF) as usize;
-                        let size = size_in_bytes / size_of::<u64>();
-
-                        if (vec_window.len() + size_in_bytes as usize)
-                            >= self.context.get_max_storage_buffer_size() / size_of::<u64>()
-                        {
-                            current_index = old_index;
-                            break;
-                        }
-                        let buffer_value_count = (compressed_bytes_vec[current_index] >> 32) + 1;
-                        current_index += 1;
-                        if (input_indexes.len() + 1) * ChimpBufferInfo::get().buffer_size() * 4
-                            >= self.context.get_max_storage_buffer_size()
-                        {
-                            current_index = old_index;
-                            break;
-                        }
-                        vec_window.extend(
-                            compressed_bytes_vec[current_index..current_index + size].to_vec(),
-                        );
-
-                        input_indexes.push(vec_window.len() as u32);
-                        current_index += size as usize;
-                        total_uncompressed_values += buffer_value_count as usize
-                    }
-                    input_indexes.insert(0, 0);
-                    //Block is as many buffers fit into the gpu the distinction is made for compatibility reasons
-                    let block_values = self
-                        .decompress_block(
-                            vec_window.as_slice(),
-                            input_indexes.as_slice(),
-                            min(
-                                total_uncompressed_values,
-                                ChimpBufferInfo::get().buffer_size(),
-                            ),
-                            &mut skip_time,
-                        )
-                        .await?;
-
-                    uncompressed_values.extend(block_values[0..total_uncompressed_values].iter());
-                    vec_window.clear();
-                    total_uncompressed_values = 0;
-                    input_indexes.clear();
+                    let size_in_bytes = (compressed_bytes_vec[current_index] & 0xFFFFFFFF) as usize;
+                    let size = size_in_bytes / size_of::<u64>();
+                    let buffer_value_count = (compressed_bytes_vec[current_index] >> 32) + 1;
+                    current_index += 1;
+                    vec_window.extend_from_slice(
+                        &compressed_bytes_vec[current_index..current_index + size],
+                    );
+
+                    input_indexes.push(vec_window.len() as u32);
+                    value_counts.push(buffer_value_count as u32);
+                    current_index += size;
+                    total_uncompressed_values += buffer_value_count as usize
                 }
+                input_indexes.insert(0, 0);
+                let block_values = self.decompress_block(
+                    vec_window.as_slice(),
+                    input_indexes.as_slice(),
+                    value_counts.as_slice(),
+                    self.config.buffer_size(),
+                )?;
+
+                uncompressed_values = block_values[0..total_uncompressed_values].to_vec();
             },
             total_millis,
             "decompression"
         );
-        step!(Step::Decompress, {
+        step!(self.config, Step::Decompress, {
             uncompressed_values.iter().map(|it: &f64| it.to_string())
         });
-        Ok(DecompressResult(uncompressed_values, skip_time))
+        Ok(uncompressed_values.into())
     }
 }
 
+/// Host decoder of the ChimpN 64 bit format, it needs no wgpu device
+#[derive(Debug, Clone)]
 pub struct BatchedCPUN64Decompressor {
-    context: Arc<Context>,
-    n: usize,
+    config: ChimpConfig,
 }
 impl MaxGroupGnostic for BatchedCPUN64Decompressor {
-    fn get_max_number_of_groups(&self, _content_len: usize) -> usize {
-        self.context().get_max_workgroup_size()
+    fn get_max_number_of_groups(&self, content_len: usize) -> usize {
+        max(content_len, 1)
     }
 }
 impl Default for BatchedCPUN64Decompressor {
     fn default() -> Self {
         Self {
-            context: Arc::new(Context::initialize_default_adapter().block_on().unwrap()),
-            n: 64,
+            config: ChimpConfig::default(),
         }
     }
 }
 impl BatchedCPUN64Decompressor {
-    pub(crate) async fn decompress_block(
+    /// Decode the blocks starting at the [input_indexes] of [compressed_value_slice], a block that
+    /// cannot be decoded is reported with its index and its offset in the stream, header included
+    pub(crate) fn decompress_block(
         &self,
         compressed_value_slice: &[u64],
         input_indexes: &[u32],
+        value_counts: &[u32],
         buffer_value_count: usize,
-        skip_time: &mut u128,
-    ) -> Result<Vec<f64>, DecompressionError> {
-        //how many buffers fit into the GPU
-        let workgroup_count = self.get_max_number_of_groups(input_indexes.len());
-
-        //how many iterations I need to fully decompress all the buffers
-        let iterator_count = max((input_indexes.len() - 1) / workgroup_count, 1);
-
-        //input_indexes shows how many buffers of count buffer_value_count, so we use workgroups equal to as many fit in the gpu
-        let mut result = Vec::<f64>::new();
-        //info!("The wgpu workgroup size: {}", &workgroup_count);
-
-        let mut output =
-            vec![0f64; (input_indexes.len() - 1) * ChimpBufferInfo::get().buffer_size()];
-        let mut last_lead_array =
-            vec![0u64; (input_indexes.len() - 1) * ChimpBufferInfo::get().buffer_size()];
+    ) -> Result<Vec<f64>, ChimpError> {
+        let workgroup_count = self.get_max_number_of_groups(input_indexes.len() - 1);
+        let output = vec![0f64; workgroup_count * self.config.buffer_size()];
+        let last_lead_array = vec![0u64; workgroup_count * self.config.buffer_size()];
         let mut writer = CPU64DecompressorNWriter {
-            n: self.n,
-            size: buffer_value_count,
-            input_indexes: input_indexes.to_vec(),
+            n: self.config.n(),
             input: compressed_value_slice.to_vec(),
-            input_size: input_indexes.len() - 1,
             output,
             last_lead_array,
         };
-        let workgroup_count = min(workgroup_count, self.context.get_max_workgroup_size());
-        for iteration in 0..iterator_count {
-            let is_last_iteration = iteration == iterator_count - 1;
-            let offset = iteration * workgroup_count;
-            let next = if is_last_iteration {
-                input_indexes.len()
-            } else {
-                ((iteration + 1) * workgroup_count) + 1
-            };
-            let iteration_input_indexes = if is_last_iteration {
-                input_indexes[iteration * workgroup_count..].to_vec()
-            } else {
-                input_indexes[iteration * workgroup_count..(iteration + 1) * workgroup_count]
-                    .to_vec()
-            };
-            let first_index = iteration_input_indexes[0] as usize;
-
-            let iteration_compressed_values = if is_last_iteration {
-                compressed_value_slice[first_index..].to_vec()
-            } else {
-                compressed_value_slice
-                    [first_index..(iteration_input_indexes.last().unwrap().to_owned() as usize)]
-                    .to_vec()
-            };
-
-            let size_uniform = BufferWrapper::uniform_with_content(
-                self.device(),
-                bytemuck::bytes_of(&buffer_value_count),
-                WgpuGroupId::new(0, 2),
-                Some("Total input values"),
-            );
-
-            let input_size_uniform = BufferWrapper::uniform_with_content(
-                self.device(),
-                bytemuck::bytes_of(&compressed_value_slice.len()),
-                WgpuGroupId::new(0, 4),
-                Some("Total input buffer length"),
-            );
-
-            for workgroup in 0..(iteration_input_indexes.len() - 1) {
-                let offset = iteration * self.context.get_max_workgroup_size();
-                writer.input_size = iteration_compressed_values.len();
-                writer.write(
-                    input_indexes[offset + workgroup] as usize,
-                    (offset + workgroup) * buffer_value_count,
+        for workgroup in 0..input_indexes.len() - 1 {
+            writer
+                .write(
+                    input_indexes[workgroup] as usize,
+                    workgroup * buffer_value_count,
+                    value_counts[workgroup],
                 )
-            }
+                .map_err(|_| ChimpError::CorruptInput {
+                    block: workgroup,
+                    // every block before it adds its one word prefix to the payload
+                    byte_offset: HEADER_SIZE
+                        + (input_indexes[workgroup] as usize + workgroup) * size_of::<u64>(),
+                })?;
         }
-        // let instant = Instant::now();
-        // let result = wgpu_utils::get_from_gpu::<f32>(
-        //     self.context(),
-        //     out_storage_buffer.buffer(),
-        //     out_storage_buffer.size() as BufferAddress,
-        //     out_staging.buffer(),
-        // )
-        // .await?;
-        // *skip_time += instant.elapsed().as_millis();
-
-        //info!("Output result size: {}", result.len());
         Ok(writer.output)
     }
 
-    pub fn new(context_builder: impl Into<Arc<Context>>, n: usize) -> Self {
-        Self {
-            context: context_builder.into(),
-            n,
-        }
+    pub fn new(n: usize) -> Result<Self, ConfigError> {
+        Ok(Self {
+            config: ChimpConfig::default().with_n(n)?,
+        })
     }
 
-    pub fn context(&self) -> &Context {
-        &self.context
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
     }
 
-    pub fn device(&self) -> &Device {
-        self.context.device()
+    pub fn n(&self) -> usize {
+        self.config.n()
     }
-    pub fn queue(&self) -> &Queue {
-        self.context.queue()
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
     }
 }
 
@@ -229,27 +152,22 @@ pub struct CPU64DecompressorNWriter {
     input: Vec<u64>,
     n: usize,
     output: Vec<f64>,
-    input_indexes: Vec<u32>,
     last_lead_array: Vec<u64>,
-    size: usize,
-    input_size: usize,
 }
 impl CPU64DecompressorNWriter {
-    pub fn write(&mut self, input_idx: usize, output_idx: usize) {
-        let n = self.n;
+    /// Decode the [value_count] values of the block at [input_idx] into the output from
+    /// [output_idx] on
+    pub fn write(
+        &mut self,
+        input_idx: usize,
+        output_idx: usize,
+        value_count: u32,
+    ) -> Result<(), ChimpError> {
         let log2n = self.n.ilog2();
 
-        //Index of the byte we are in
-        let mut current_index = input_idx + 1usize;
-        //Current Remaining offset
-        let mut current_offset = 0u32;
-
-        let mut current_info = CurrentInfo::new(current_index as u32, current_offset);
+        let mut current_info = CurrentInfo::new(input_idx as u32 + 1, 0u32);
 
-        let mut first_num = self.input[(current_info.current_index - 1u32) as usize];
-        let mut last_num: u64 = first_num;
-        let mut last_lead = 0u32;
-        let mut significant_bits = 0u32;
+        let first_num = self.word(current_info.current_index - 1u32)?;
 
         let mut output_index = output_idx;
 
@@ -257,22 +175,14 @@ impl CPU64DecompressorNWriter {
         output_index += 1usize;
         current_info.current_offset += 64u32;
 
-        let mut value;
-        for i in 1..self.size {
-            // if we have not finished reading values from the uncompressed buffers
-            if current_info.current_index >= (self.input_size as u32 - 1u32)
-                && current_info.current_offset <= 1u32
-            {
-                break;
-            }
-
+        for _ in 1..value_count {
             //if current bit value==1
-            if self.get_bit_at_index(current_info.current_index, current_info.current_offset)
+            if self.get_bit_at_index(current_info.current_index, current_info.current_offset)?
                 == 1u32
             {
                 current_info = self.decr_counter_capped_at_32(current_info, 1u32);
                 let recalc_lead = self
-                    .get_bit_at_index(current_info.current_index, current_info.current_offset)
+                    .get_bit_at_index(current_info.current_index, current_info.current_offset)?
                     == 1;
                 current_info = self.decr_counter_capped_at_32(current_info, 1u32);
 
@@ -280,31 +190,29 @@ impl CPU64DecompressorNWriter {
                     current_info.current_index,
                     current_info.current_offset,
                     log2n,
-                ) as usize;
+                )? as usize;
                 current_info = self.decr_counter_capped_at_32(current_info, log2n);
-                let mut last_num = bytemuck::cast(self.output[output_index - compare_offset]);
-                let mut lead = self.last_lead_array[output_index - compare_offset];
+                let reference = Self::reference(output_idx, output_index, compare_offset)?;
+                let last_num: u64 = bytemuck::cast(self.output[reference]);
+                let mut lead = self.last_lead_array[reference];
                 if recalc_lead {
                     lead = self.reinterpret_num(
                         current_info.current_index,
                         current_info.current_offset,
                         6u32,
-                    );
+                    )?;
                     current_info = self.decr_counter_capped_at_32(current_info, 6u32);
                 }
-                significant_bits = 64u32 - (lead as u32);
+                let mut significant_bits = 64u32 - (lead as u32);
                 if significant_bits == 0u32 {
                     significant_bits = 64u32;
                 }
-                value = self.reinterpret_num(
+                let value = self.reinterpret_num(
                     current_info.current_index,
                     current_info.current_offset,
-                    (significant_bits) as u32,
-                );
-                current_info =
-                    self.decr_counter_capped_at_32(current_info, (significant_bits) as u32);
-                value = value ^ last_num;
-                last_num = value;
+                    significant_bits,
+                )? ^ last_num;
+                current_info = self.decr_counter_capped_at_32(current_info, significant_bits);
                 self.last_lead_array[output_index] = lead;
 
                 self.output[output_index] = bytemuck::cast(value);
@@ -312,50 +220,52 @@ impl CPU64DecompressorNWriter {
             } else if self.get_bit_at_index(
                 current_info.current_index,
                 current_info.current_offset - 1u32,
-            ) == 1u32
+            )? == 1u32
             {
                 current_info = self.decr_counter_capped_at_32(current_info, 2u32);
                 let compare_offset = self.reinterpret_num(
                     current_info.current_index,
                     current_info.current_offset,
                     log2n,
-                ) as usize;
+                )? as usize;
                 current_info = self.decr_counter_capped_at_32(current_info, log2n);
-                let mut last_num = bytemuck::cast(self.output[output_index - compare_offset]);
+                let reference = Self::reference(output_idx, output_index, compare_offset)?;
+                let last_num: u64 = bytemuck::cast(self.output[reference]);
 
                 let lead = self.reinterpret_num(
                     current_info.current_index,
                     current_info.current_offset,
                     6u32,
-                );
+                )?;
                 current_info = self.decr_counter_capped_at_32(current_info, 6u32);
 
                 let mut significant_bits = self.reinterpret_num(
                     current_info.current_index,
                     current_info.current_offset,
                     6u32,
-                );
+                )?;
                 current_info = self.decr_counter_capped_at_32(current_info, 6u32);
 
                 if significant_bits == 0u64 {
                     significant_bits = 64;
                 }
+                if lead + significant_bits > 64 {
+                    return Err(CORRUPT_BLOCK);
+                }
 
                 let trail = 64u32 - (lead as u32) - (significant_bits as u32);
 
-                value = self.reinterpret_num(
+                let value = self.reinterpret_num(
                     current_info.current_index,
                     current_info.current_offset,
-                    64u32 - (lead as u32) - trail,
-                );
+                    significant_bits as u32,
+                )?;
 
                 current_info =
-                    self.decr_counter_capped_at_32(current_info, 64u32 - (lead as u32) - trail);
+                    self.decr_counter_capped_at_32(current_info, significant_bits as u32);
 
-                value <<= trail;
-                value ^= last_num;
+                let value = (value << trail) ^ last_num;
                 self.last_lead_array[output_index] = lead;
-                last_num = value;
 
                 self.output[output_index] = bytemuck::cast(value);
                 output_index += 1usize;
@@ -365,59 +275,81 @@ impl CPU64DecompressorNWriter {
                     current_info.current_index,
                     current_info.current_offset,
                     log2n,
-                ) as usize;
+                )? as usize;
                 current_info = self.decr_counter_capped_at_32(current_info, log2n);
-                let mut last_num: u64 = bytemuck::cast(self.output[output_index - compare_offset]);
-                let mut lead = self.last_lead_array[output_index - compare_offset];
-                self.output[output_index] = bytemuck::cast(last_num);
+                let reference = Self::reference(output_idx, output_index, compare_offset)?;
+                self.output[output_index] = self.output[reference];
                 self.last_lead_array[output_index] = 64u64;
                 output_index += 1usize;
             }
         }
+        Ok(())
+    }
+
+    /// The word at [index], a bitstream that reads past the input is corrupted
+    fn word(&self, index: u32) -> Result<u64, ChimpError> {
+        self.input.get(index as usize).copied().ok_or(CORRUPT_BLOCK)
     }
 
-    fn get_bit_at_index(&self, array_index: u32, position: u32) -> u32 {
+    /// The index of the value [compare_offset] values before [output_index], a reference before
+    /// the first value of the block starting at [output_idx] is corrupted
+    fn reference(
+        output_idx: usize,
+        output_index: usize,
+        compare_offset: usize,
+    ) -> Result<usize, ChimpError> {
+        output_index
+            .checked_sub(compare_offset)
+            .filter(|it| *it >= output_idx)
+            .ok_or(CORRUPT_BLOCK)
+    }
+
+    fn get_bit_at_index(&self, array_index: u32, position: u32) -> Result<u32, ChimpError> {
         let index = if position == 0u32 {
             array_index + 1
         } else {
             array_index
         };
         let f_position = if position == 0u32 { 64u32 } else { position };
-        ((self.input[index as usize] >> (f_position - 1u32)) & 1u64) as u32
+        Ok(((self.word(index)? >> (f_position - 1u32)) & 1u64) as u32)
     }
 
     fn decr_counter_capped_at_32(&self, mut value: CurrentInfo, count: u32) -> CurrentInfo {
         let corrected_value = (value.current_offset as i32) - count as i32;
-        (value).current_offset = if (corrected_value > 0) {
+        value.current_offset = if corrected_value > 0 {
             corrected_value as u32
         } else {
             (64 + corrected_value) as u32
         };
-        (value).current_index += if (corrected_value <= 0) { 1 } else { 0 }; //1 if it's true and 0 otherwise
-        return (value);
+        value.current_index += if corrected_value <= 0 { 1 } else { 0 }; //1 if it's true and 0 otherwise
+        value
     }
 
-    fn reinterpret_num(&self, array_index: u32, index: u32, length: u32) -> u64 {
+    fn reinterpret_num(
+        &self,
+        array_index: u32,
+        index: u32,
+        length: u32,
+    ) -> Result<u64, ChimpError> {
         let len = min(length, 64u32);
         if index >= len {
             // Fully within one u64
-            return utils_64::extract_bits(
-                self.input[array_index as usize],
-                (index - len) as u32,
+            Ok(utils_64::extract_bits(
+                self.word(array_index)?,
+                index - len,
                 len,
-            );
+            ))
         } else {
-            // Spans two u32 elements
-            let bits_in_first = index;
+            // Spans two u64 elements
             let bits_in_second = length - index;
 
-            let first_part = utils_64::extract_bits(self.input[array_index as usize], 0u32, index);
+            let first_part = utils_64::extract_bits(self.word(array_index)?, 0u32, index);
             let second_part = utils_64::extract_bits(
-                self.input[array_index as usize + 1],
+                self.word(array_index + 1)?,
                 64u32 - bits_in_second,
                 bits_in_second,
             );
-            return (first_part << bits_in_second) | second_part;
+            Ok((first_part << bits_in_second) | second_part)
         }
     }
 }
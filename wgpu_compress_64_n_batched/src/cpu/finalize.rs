@@ -0,0 +1,18 @@
+use crate::finalize::FinalizeN64;
+use async_trait::async_trait;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::CompressResult;
+use compress_utils::wgpu_utils::RunBuffers;
+use wgpu_compress_64_batched::cpu::finalize::CPUFinalizer64;
+
+#[async_trait]
+impl FinalizeN64 for CPUFinalizer64 {
+    async fn finalize(
+        &self,
+        buffers: &mut RunBuffers,
+        padding: usize,
+        _skip_time: &mut u128,
+    ) -> Result<CompressResult, ChimpError> {
+        self.finalize_buffers(buffers, padding).await
+    }
+}
@@ -1,87 +1,68 @@
 use crate::previous_indexes::PreviousIndexesN64;
 use async_trait::async_trait;
-use compress_utils::context::Context;
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo, MaxGroupGnostic, Step};
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{padding_to_fit_buffer_count, MaxGroupGnostic, Step};
 use compress_utils::wgpu_utils::RunBuffers;
 use compress_utils::BufferWrapper;
 use std::fs;
 use std::ops::Div;
-use std::sync::Arc;
 
+/// Host implementation of the `calculate_previous_efficient_index.wgsl` stage
+#[derive(Debug)]
 pub struct PreviousIndexesNCPUImpl {
-    pub(crate) context: Arc<Context>,
-    pub(crate) n: usize,
+    config: ChimpConfig,
 }
 
 impl PreviousIndexesNCPUImpl {
-    pub fn new(context: Arc<Context>, n: usize) -> Self {
-        Self { context, n }
-    }
-
-    pub fn context(&self) -> &Context {
-        self.context.as_ref()
-    }
-    pub fn device(&self) -> &wgpu::Device {
-        self.context.device()
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
     }
 }
 
 impl MaxGroupGnostic for PreviousIndexesNCPUImpl {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 #[async_trait]
 impl PreviousIndexesN64 for PreviousIndexesNCPUImpl {
     async fn calculate_previous_indexes(
         &self,
-        values: &mut [f64],
+        values: &[f64],
         buffers: &mut RunBuffers,
         _skip_time: &mut u128,
-    ) -> anyhow::Result<()> {
-        let workgroup_count = self.get_max_number_of_groups(values.len());
+    ) -> Result<(), ChimpError> {
+        // the values are padded with 0's to fill their last buffer
+        let padded_len =
+            values.len() + padding_to_fit_buffer_count(values.len(), self.config.buffer_size());
+        let workgroup_count = self.get_max_number_of_groups(padded_len);
 
-        let bytes = values.len() + 1;
+        let bytes = padded_len + 1;
 
         let mut padded_values = Vec::from(values);
-        padded_values.push(0f64);
+        padded_values.resize(padded_len + 1, 0f64);
 
-        // let size = ChimpBufferInfo::get().buffer_size();
         let previous = vec![0u32; bytes];
 
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
-
         let mut writer = PreviousWriter {
-            n: self.n as u32,
+            n: self.config.n() as u32,
             input: padded_values,
             previous,
         };
 
-        for i in 0..iterations {
-            let offset = i * self.context.get_max_workgroup_size();
-            for workgroup in 0..workgroup_count {
-                let size = ChimpBufferInfo::get().buffer_size();
-                writer.execute((offset + workgroup) * size, size)
-            }
+        let size = self.config.buffer_size();
+        for workgroup in 0..workgroup_count {
+            writer.execute(workgroup * size, size)
         }
-        let input_storage_buffer = BufferWrapper::storage_with_content(
-            self.device(),
-            bytemuck::cast_slice(&writer.input),
-            (0, 1),
-            Some("Storage self.input Buffer"),
-        );
-        buffers.set_input_buffer(input_storage_buffer);
-        let previous_index_buffer = BufferWrapper::storage_with_content(
-            self.device(),
-            bytemuck::cast_slice(&writer.previous),
-            (0, 3),
-            Some("Previous Index Buffer"),
-        );
-
-        buffers.set_previous_index_buffer(previous_index_buffer);
-
-        if trace_steps().contains(&Step::PreviousIndexes) {
+        buffers.set_input_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &writer.input,
+        )));
+        buffers.set_previous_index_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &writer.previous,
+        )));
+
+        if self.config.traces(&Step::PreviousIndexes) {
             let trace_path = Step::PreviousIndexes.get_trace_file();
             let mut trace_output = String::new();
 
@@ -109,12 +90,15 @@ impl PreviousWriter {
         let set_lsb: u64 = bytemuck::cast(threshold);
         let mut previous_index = 1usize;
         let threshold = 6u32 + (log2n);
+        // The shader reads the values as a `pseudo_u64` whose `hi` field holds the low half of
+        // the little endian f64, so the key and the trailing zeros see the halves swapped
         for step in 1usize + workgroup_start..size + workgroup_start {
-            let value: u64 = bytemuck::cast(self.input[step]);
+            let value: u64 = bytemuck::cast::<f64, u64>(self.input[step]).rotate_left(32);
             let mut key = (value & set_lsb) as usize;
             let curr_index: usize = indices[key] as usize;
             if curr_index > 0 && (step - curr_index) < self.n as usize {
-                let tempXor = value ^ bytemuck::cast::<f64, u64>(self.input[curr_index]);
+                let tempXor =
+                    value ^ bytemuck::cast::<f64, u64>(self.input[curr_index]).rotate_left(32);
                 let trailingZeros = tempXor.trailing_zeros();
 
                 if trailingZeros > threshold {
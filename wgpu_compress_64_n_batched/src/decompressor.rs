@@ -1,9 +1,13 @@
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::{ChimpConfig, ConfigError, DEFAULT_N};
 use compress_utils::context::Context;
-use compress_utils::cpu_compress::{DecompressionError, Decompressor};
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant};
 use compress_utils::general_utils::DeviceEnum::GPU;
 use compress_utils::general_utils::{
-    trace_steps, ChimpBufferInfo, DecompressResult, DeviceEnum, MaxGroupGnostic, Step,
+    cast_or_copy, DecompressResult, DeviceEnum, MaxGroupGnostic, Step,
 };
 use compress_utils::{
     execute_compute_shader, step, time_it, wgpu_utils, BufferWrapper, WgpuGroupId,
@@ -36,7 +40,7 @@ use wgpu_types::BufferAddress;
 //     async fn decompress(
 //         &self,
 //         vec: &mut Vec<u8>,
-//     ) -> Result<DecompressResult<f64>, DecompressionError> {
+//     ) -> Result<DecompressResult<f64>, ChimpError> {
 //         match self {
 //             DecompressorImpl::GPU(d) => d.decompress(vec).await,
 //             DecompressorImpl::CPU(d) => d.decompress(vec).await,
@@ -67,7 +71,7 @@ use wgpu_types::BufferAddress;
 //     async fn decompress(
 //         &self,
 //         vec: &mut Vec<u8>,
-//     ) -> Result<DecompressResult<f64>, DecompressionError> {
+//     ) -> Result<DecompressResult<f64>, ChimpError> {
 //         self.decompressor_factory().decompress(vec).await
 //     }
 // }
@@ -103,18 +107,24 @@ use wgpu_types::BufferAddress;
 impl Decompressor<f64> for GPUDecompressorBatchedN64 {
     async fn decompress(
         &self,
-        compressed_bytes_vec: &mut Vec<u8>,
-    ) -> Result<DecompressResult<f64>, DecompressionError> {
+        compressed_bytes_vec: &[u8],
+    ) -> Result<DecompressResult<f64>, ChimpError> {
         let mut current_index = 0usize;
         let mut uncompressed_values = Vec::new();
         let mut total_millis = 0;
         let mut skip_time = 0;
+        let (header, compressed_bytes_vec) =
+            ChimpHeader::split(compressed_bytes_vec, CodecVariant::ChimpN64)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        header.expect_n(self.config.n())?;
+        let compressed_words = cast_or_copy::<u64>(compressed_bytes_vec);
         time_it!(
             {
-                let mut compressed_bytes_vec: &[u64] = bytemuck::cast_slice(compressed_bytes_vec);
+                let mut compressed_bytes_vec: &[u64] = &compressed_words;
                 let mut vec_window = Vec::new();
                 let mut total_uncompressed_values = 0;
                 let mut input_indexes = Vec::new();
+                let mut value_counts = Vec::new();
                 while current_index < compressed_bytes_vec.len() {
                     while current_index < compressed_bytes_vec.len() {
                         let old_index = current_index;
@@ -130,7 +140,7 @@ impl Decompressor<f64> for GPUDecompressorBatchedN64 {
                         }
                         let buffer_value_count = (compressed_bytes_vec[current_index] >> 32) + 1;
                         current_index += 1;
-                        if (input_indexes.len() + 1) * ChimpBufferInfo::get().buffer_size() * 4
+                        if (input_indexes.len() + 1) * self.config.buffer_size() * 4
                             >= self.context.get_max_storage_buffer_size()
                         {
                             current_index = old_index;
@@ -141,6 +151,7 @@ impl Decompressor<f64> for GPUDecompressorBatchedN64 {
                         );
 
                         input_indexes.push(vec_window.len() as u32);
+                        value_counts.push(buffer_value_count as u32);
                         current_index += size as usize;
                         total_uncompressed_values += buffer_value_count as usize
                     }
@@ -150,10 +161,8 @@ impl Decompressor<f64> for GPUDecompressorBatchedN64 {
                         .decompress_block(
                             vec_window.as_slice(),
                             input_indexes.as_slice(),
-                            min(
-                                total_uncompressed_values,
-                                ChimpBufferInfo::get().buffer_size(),
-                            ),
+                            value_counts.as_slice(),
+                            self.config.buffer_size(),
                             &mut skip_time,
                         )
                         .await?;
@@ -162,12 +171,13 @@ impl Decompressor<f64> for GPUDecompressorBatchedN64 {
                     vec_window.clear();
                     total_uncompressed_values = 0;
                     input_indexes.clear();
+                    value_counts.clear();
                 }
             },
             total_millis,
             "decompression"
         );
-        step!(Step::Decompress, {
+        step!(self.config, Step::Decompress, {
             uncompressed_values.iter().map(|it: &f64| it.to_string())
         });
         Ok(DecompressResult(uncompressed_values, skip_time))
@@ -176,19 +186,20 @@ impl Decompressor<f64> for GPUDecompressorBatchedN64 {
 
 pub struct GPUDecompressorBatchedN64 {
     context: Arc<Context>,
-    n: usize,
+    config: ChimpConfig,
 }
 impl MaxGroupGnostic for GPUDecompressorBatchedN64 {
     fn get_max_number_of_groups(&self, _content_len: usize) -> usize {
-        self.context().get_max_workgroup_size()
+        self.config.max_workgroup_size(self.context())
     }
 }
-impl Default for GPUDecompressorBatchedN64 {
-    fn default() -> Self {
-        Self {
-            context: Arc::new(Context::initialize_default_adapter().block_on().unwrap()),
-            n: 128,
-        }
+impl GPUDecompressorBatchedN64 {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(
+            Context::initialize_default_adapter().block_on()?,
+            DEFAULT_N,
+        )?)
     }
 }
 impl GPUDecompressorBatchedN64 {
@@ -196,9 +207,10 @@ impl GPUDecompressorBatchedN64 {
         &self,
         compressed_value_slice: &[u64],
         input_indexes: &[u32],
+        value_counts: &[u32],
         buffer_value_count: usize,
         skip_time: &mut u128,
-    ) -> Result<Vec<f64>, DecompressionError> {
+    ) -> Result<Vec<f64>, ChimpError> {
         //how many buffers fit into the GPU
         let workgroup_count = self.get_max_number_of_groups(input_indexes.len()) * 256;
 
@@ -214,14 +226,20 @@ impl GPUDecompressorBatchedN64 {
             Some("Storage input Buffer"),
         );
         //info!("Total output values: {}", buffer_value_count);
-        let in_size = BufferWrapper::storage_with_content(
+        let blocks = input_indexes
+            .iter()
+            .zip(value_counts)
+            .flat_map(|(start, count)| [*start, *count])
+            .collect::<Vec<u32>>();
+        let block_storage = BufferWrapper::storage_with_content(
             self.device(),
-            bytemuck::cast_slice(&input_indexes),
+            bytemuck::cast_slice(&blocks),
             WgpuGroupId::new(0, 3),
-            Some("Total bytes input"),
+            Some("Block starts and value counts"),
         );
         let out_buffer_size =
-            (input_indexes.len() - 1) * ChimpBufferInfo::get().buffer_size() * size_of::<f64>();
+            (input_indexes.len() - 1) * self.config.buffer_size() * size_of::<f64>();
+        self.context().check_buffer_size(out_buffer_size)?;
         let out_staging = BufferWrapper::stage_with_size(
             self.device(),
             out_buffer_size as BufferAddress,
@@ -230,7 +248,7 @@ impl GPUDecompressorBatchedN64 {
         let last_lead_storage = BufferWrapper::storage_with_size(
             self.device(),
             out_buffer_size as BufferAddress,
-            WgpuGroupId::new(0, 5),
+            WgpuGroupId::new(0, 4),
             Some("Last Lead storage Buffer"),
         );
 
@@ -241,10 +259,11 @@ impl GPUDecompressorBatchedN64 {
             Some("Storage output Buffer"),
         );
         *skip_time += instant.elapsed().as_millis();
-        let workgroup_count = min(workgroup_count, self.context.get_max_workgroup_size());
+        let workgroup_count = min(
+            workgroup_count,
+            self.config.max_workgroup_size(&self.context),
+        );
         for iteration in 0..iterator_count {
-            let util_64 = include_str!("shaders/64_utils.wgsl");
-
             //split all the buffers to the chunks each iteration will use
             let is_last_iteration = iteration == iterator_count - 1;
             let offset = iteration * workgroup_count;
@@ -270,27 +289,16 @@ impl GPUDecompressorBatchedN64 {
                 WgpuGroupId::new(0, 2),
                 Some("Total input values"),
             );
-
-            let input_size_uniform = BufferWrapper::uniform_with_content(
-                self.device(),
-                bytemuck::bytes_of(&compressed_value_slice.len()),
-                WgpuGroupId::new(0, 4),
-                Some("Total input buffer length"),
-            );
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                iteration * self.context.get_max_workgroup_size()
-            );
-            let n = format!("let n={}u;", self.n);
-            let log2n = format!("let log2n={}u;", self.n.ilog2());
-            let total_threads = format!("let total_threads={}u;", input_indexes.len());
-            let shader_code = include_str!("shaders/decompress.wgsl")
-                .replace("//#include(64_utils)", util_64)
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@n", &n)
-                .replace("//@log2n", &log2n)
-                .replace("//@total_threads", &total_threads)
-                .to_string();
+            let shader_code = shaders::DECOMPRESS
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (iteration * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .bind("n", self.config.n() as u32)
+                .bind("log2n", self.config.n().ilog2() as u32)
+                .bind("total_threads", value_counts.len() as u32)
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &shader_code,
@@ -300,8 +308,7 @@ impl GPUDecompressorBatchedN64 {
                     &input_storage_buffer,
                     &out_staging,
                     &size_uniform,
-                    &in_size,
-                    &input_size_uniform
+                    &block_storage
                 ],
                 iteration_input_indexes.div_ceil(256),
                 Some("decompress pass")
@@ -321,11 +328,20 @@ impl GPUDecompressorBatchedN64 {
         Ok(result)
     }
 
-    pub fn new(context_builder: impl Into<Arc<Context>>, n: usize) -> Self {
-        Self {
+    pub fn new(context_builder: impl Into<Arc<Context>>, n: usize) -> Result<Self, ConfigError> {
+        Ok(Self {
             context: context_builder.into(),
-            n,
-        }
+            config: ChimpConfig::default().with_n(n)?,
+        })
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
     }
 
     pub fn context(&self) -> &Context {
@@ -1,6 +1,9 @@
+use crate::shaders;
 use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
 use compress_utils::context::Context;
-use compress_utils::general_utils::{trace_steps, ChimpBufferInfo, MaxGroupGnostic, Step};
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{MaxGroupGnostic, Step};
 use compress_utils::types::ChimpOutput64;
 use compress_utils::wgpu_utils::RunBuffers;
 use compress_utils::{execute_compute_shader, wgpu_utils, BufferWrapper, WgpuGroupId};
@@ -18,17 +21,17 @@ pub trait FinalCompressN64: MaxGroupGnostic {
         &self,
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> anyhow::Result<()>;
+    ) -> Result<(), ChimpError>;
 }
 
 pub struct FinalCompressImplN64 {
     context: Arc<Context>,
-    n: usize,
+    config: ChimpConfig,
 }
 
 impl FinalCompressImplN64 {
-    pub fn new(context: Arc<Context>, n: usize) -> Self {
-        Self { context, n }
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
     }
 
     pub fn context(&self) -> &Context {
@@ -38,7 +41,7 @@ impl FinalCompressImplN64 {
 
 impl MaxGroupGnostic for FinalCompressImplN64 {
     fn get_max_number_of_groups(&self, content_len: usize) -> usize {
-        content_len.div(ChimpBufferInfo::get().buffer_size())
+        content_len.div(self.config.buffer_size())
     }
 }
 
@@ -48,11 +51,7 @@ impl FinalCompressN64 for FinalCompressImplN64 {
         &self,
         buffers: &mut RunBuffers,
         skip_time: &mut u128,
-    ) -> anyhow::Result<()> {
-        let utils_64 = include_str!("shaders/64_utils.wgsl");
-        let temp = include_str!("shaders/chimp_compress.wgsl")
-            .replace("//#include(64_utils)", utils_64)
-            .to_string();
+    ) -> Result<(), ChimpError> {
         let size_of_output = size_of::<ChimpOutput64>();
         let input_length = buffers.input_buffer().size() / size_of::<f64>();
 
@@ -61,8 +60,8 @@ impl FinalCompressN64 for FinalCompressImplN64 {
         let workgroup_count = self.get_max_number_of_groups(input_length);
 
         let instant = Instant::now();
-        let mut output_storage_buffer = BufferWrapper::storage_with_size(
-            self.context().device(),
+        let mut output_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
             output_buffer_size,
             WgpuGroupId::new(0, 2),
             Some("Storage Output Buffer"),
@@ -86,25 +85,17 @@ impl FinalCompressN64 for FinalCompressImplN64 {
                 .chunks_uniform_mut()
                 .with_binding(WgpuGroupId::new(0, 3));
         }
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let last_pass = format!(
-                "let last_pass={}u;",
-                if i == iterations - 1 { 1 } else { 0 }
-            );
-            let utils_64 = include_str!("shaders/64_utils.wgsl");
-            let log2n = format!("let log2n={}u;", self.n.ilog2());
-            let temp = include_str!("shaders/chimp_compress.wgsl")
-                .replace("//#include(64_utils)", utils_64)
-                .replace("//@workgroup_offset", &offset_decl)
-                .replace("//@last_pass", &last_pass)
-                .replace("//@log2n", &log2n)
-                .to_string();
+            let temp = shaders::CHIMP_COMPRESS
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .bind("log2n", self.config.n().ilog2() as u32)
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
                 &temp,
@@ -118,7 +109,7 @@ impl FinalCompressN64 for FinalCompressImplN64 {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("compress pass")
             );
@@ -136,25 +127,19 @@ impl FinalCompressN64 for FinalCompressImplN64 {
                 .with_binding(WgpuGroupId::new(0, 1));
         }
 
-        let initialize_shadder = include_str!("shaders/initialize_first_per_buffer.wgsl");
-        let iterations = workgroup_count / self.context.get_max_workgroup_size() + 1;
-        let last_size = workgroup_count % self.context.get_max_workgroup_size();
+        let iterations = workgroup_count / self.config.max_workgroup_size(&self.context) + 1;
+        let last_size = workgroup_count % self.config.max_workgroup_size(&self.context);
         for i in 0..iterations {
-            let offset_decl = format!(
-                "let workgroup_offset={}u;",
-                i * self.context.get_max_workgroup_size()
-            );
-            let last_pass = format!(
-                "let last_pass={}u;",
-                if i == iterations - 1 { 1 } else { 0 }
-            );
-            let log2n = format!("let log2n={}u;", self.n.ilog2());
+            let initialize_shader = shaders::INITIALIZE_FIRST_PER_BUFFER
+                .specialize()
+                .bind(
+                    "workgroup_offset",
+                    (i * self.config.max_workgroup_size(&self.context)) as u32,
+                )
+                .finish()?;
             execute_compute_shader!(
                 self.context(),
-                &initialize_shadder
-                    .replace("//@workgroup_offset", &offset_decl)
-                    .replace("//#include(64_utils)", utils_64)
-                    .replace("//@log2n", &log2n),
+                &initialize_shader,
                 vec![
                     output_storage_buffer.with_binding(WgpuGroupId::new(0, 0)),
                     buffers.input_buffer(),
@@ -163,15 +148,15 @@ impl FinalCompressN64 for FinalCompressImplN64 {
                 if i == iterations - 1 {
                     last_size
                 } else {
-                    self.context.get_max_workgroup_size()
+                    self.config.max_workgroup_size(&self.context)
                 },
                 Some("initialize pass")
             );
         }
         buffers.set_compressed_buffer(output_storage_buffer);
-        if trace_steps().contains(&Step::Compress) {
-            let output_staging_buffer = BufferWrapper::stage_with_size(
-                self.context().device(),
+        if self.config.traces(&Step::Compress) {
+            let output_staging_buffer = BufferWrapper::pooled_stage_with_size(
+                self.context(),
                 buffers.compressed_buffer().size() as BufferAddress,
                 None,
             );
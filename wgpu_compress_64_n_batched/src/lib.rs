@@ -1,26 +1,172 @@
+#[cfg(feature = "gpu")]
 pub mod calculate_indexes;
+#[cfg(feature = "gpu")]
 pub mod chimpn;
+#[cfg(feature = "gpu")]
 pub mod compute_s_shader;
 pub mod cpu;
+#[cfg(feature = "gpu")]
 pub mod decompressor;
+#[cfg(feature = "gpu")]
 pub mod final_compress;
+#[cfg(feature = "gpu")]
 pub mod finalize;
+#[cfg(feature = "gpu")]
 pub mod previous_indexes;
+#[cfg(feature = "gpu")]
+pub mod shaders;
 
 #[cfg(test)]
 mod tests {
-    use crate::chimpn::ChimpN64GPUBatched;
+    use crate::chimpn::{ChimpN64CpuBatched, ChimpN64GPUBatched};
+    use crate::cpu::decompressor::BatchedCPUN64Decompressor;
     use crate::decompressor::GPUDecompressorBatchedN64;
+    use compress_utils::config::{ChimpConfig, ConfigError};
     use compress_utils::context::Context;
     use compress_utils::cpu_compress::{Compressor, Decompressor};
+    use compress_utils::format::{ChimpHeader, HEADER_SIZE};
     use compress_utils::general_utils::EventLogType::DecompressionTime;
     use compress_utils::general_utils::{build_event_times, EventLogType};
+    use compress_utils::testing::{
+        assert_config_round_trip, assert_corruption_never_panics, assert_range_round_trip,
+        assert_round_trip, sine_values, special_values,
+    };
     use itertools::Itertools;
     use pollster::FutureExt;
     use std::cmp::min;
     use std::sync::Arc;
     use std::{env, fs};
-    use tracing_subscriber::fmt::MakeWriter;
+
+    #[test]
+    fn test_cpu_compressor_block_layout() {
+        let values = vec![1.5f64; 300];
+        let compressed = ChimpN64CpuBatched::new(128)
+            .unwrap()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        let header = ChimpHeader::read(compressed.compressed_value_ref()).unwrap();
+        assert_eq!(header.n, 128);
+        assert_eq!(compressed.metadata_size(), HEADER_SIZE + 2 * 8);
+        let words = compressed.compressed_value_ref()[HEADER_SIZE..]
+            .chunks(8)
+            .map(|it| u64::from_le_bytes(it.try_into().unwrap()))
+            .collect_vec();
+        // every equal value is stored as `00` followed by the 7 bit previous index 1
+        let block_words = (64 + 255 * 9) / 64 + 2;
+        assert_eq!(words[0], (255 << 32) + (block_words as u64 - 1) * 8);
+        assert_eq!(words[1], 1.5f64.to_bits());
+        assert_eq!(
+            words[2],
+            [55, 46, 37, 28, 19, 10, 1]
+                .iter()
+                .map(|it| 1u64 << it)
+                .sum()
+        );
+        assert_eq!(words[block_words] >> 32, 300 - 256 - 1);
+        assert_eq!(words[block_words + 1], 1.5f64.to_bits());
+    }
+
+    #[test]
+    fn test_cpu_round_trip() {
+        let mut values = sine_values(3000);
+        values.extend([
+            0.0,
+            -0.0,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            f64::INFINITY,
+            1.5,
+            1.5,
+        ]);
+        for n in [32, 64, 128] {
+            assert_round_trip(
+                &ChimpN64CpuBatched::new(n).unwrap(),
+                &BatchedCPUN64Decompressor::new(n).unwrap(),
+                &values,
+            );
+        }
+    }
+
+    #[test]
+    fn test_cpu_special_values_round_trip() {
+        for n in [16, 128] {
+            let config = ChimpConfig::default()
+                .with_buffer_size(256)
+                .unwrap()
+                .with_n(n)
+                .unwrap();
+            assert_round_trip(
+                &ChimpN64CpuBatched::default().with_config(config.clone()),
+                &BatchedCPUN64Decompressor::default().with_config(config),
+                &special_values(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_cpu_round_trip_with_config() {
+        let config = ChimpConfig::default()
+            .with_buffer_size(512)
+            .unwrap()
+            .with_n(16)
+            .unwrap();
+        let header = assert_config_round_trip(
+            &ChimpN64CpuBatched::default().with_config(config.clone()),
+            &BatchedCPUN64Decompressor::default().with_config(config),
+            &BatchedCPUN64Decompressor::default(),
+            &sine_values::<f64>(3000),
+            512,
+        );
+        assert_eq!(header.n, 16);
+    }
+
+    #[test]
+    fn test_cpu_decompress_range() {
+        assert_range_round_trip(
+            &ChimpN64CpuBatched::new(64).unwrap(),
+            &BatchedCPUN64Decompressor::new(64).unwrap(),
+            &sine_values::<f64>(3000),
+        );
+    }
+
+    #[test]
+    fn test_cpu_rejects_invalid_n() {
+        assert_eq!(
+            ChimpN64CpuBatched::new(48).unwrap_err(),
+            ConfigError::InvalidN(48)
+        );
+        assert_eq!(
+            BatchedCPUN64Decompressor::new(1).unwrap_err(),
+            ConfigError::InvalidN(1)
+        );
+    }
+
+    #[test]
+    fn test_cpu_corrupted_stream_never_panics() {
+        let decompressor = BatchedCPUN64Decompressor::new(64).unwrap();
+        let stream = assert_round_trip(
+            &ChimpN64CpuBatched::new(64).unwrap(),
+            &decompressor,
+            &sine_values::<f64>(600),
+        );
+        assert_corruption_never_panics(&stream, &decompressor);
+    }
+
+    #[test]
+    fn test_cpu_compressor_mixed_values() {
+        let values = (0..1000)
+            .map(|it| (f64::sin(it as f64 / 10.0) * 100.0).round() / 100.0)
+            .chain([0.0, -0.0, f64::MAX, f64::MIN_POSITIVE, f64::INFINITY])
+            .collect_vec();
+        let compressed = ChimpN64CpuBatched::new(128)
+            .unwrap()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        assert_eq!(compressed.metadata_size(), HEADER_SIZE + 4 * 8);
+        assert_eq!(compressed.compressed_value_ref().len() % 8, 0);
+    }
 
     #[test]
     fn test_decompress_able() {
@@ -43,10 +189,12 @@ mod tests {
                 .block_on()
                 .unwrap(),
         );
-        unsafe {
-            env::set_var("CHIMP_BUFFER_SIZE", "1024".to_string());
-        }
         for n in [32, 64, 128] {
+            let config = ChimpConfig::default()
+                .with_buffer_size(1024)
+                .unwrap()
+                .with_n(n)
+                .unwrap();
             for file_name in vec![
                 "city_temperature.csv",
                 "SSD_HDD_benchmarks.csv",
@@ -76,12 +224,13 @@ mod tests {
                             break;
                         }
                     }
-                    let mut value_new = values.to_vec();
+                    let value_new = values.to_vec();
                     println!("Starting compression of {} values", value_new.len());
                     let time = std::time::Instant::now();
-                    let compressor = ChimpN64GPUBatched::new(context.clone(), n);
-                    let mut compressed_values2 =
-                        compressor.compress(&mut value_new).block_on().unwrap();
+                    let compressor = ChimpN64GPUBatched::new(context.clone(), n)
+                        .unwrap()
+                        .with_config(config.clone());
+                    let compressed_values2 = compressor.compress(&value_new).block_on().unwrap();
                     let compression_time = time.elapsed().as_millis();
                     const SIZE_IN_BYTE: usize = 8;
                     let compression_ratio = (compressed_values2.compressed_value_ref().len()
@@ -108,9 +257,11 @@ mod tests {
                     });
 
                     let time = std::time::Instant::now();
-                    let decompressor = GPUDecompressorBatchedN64::new(context.clone(), n);
+                    let decompressor = GPUDecompressorBatchedN64::new(context.clone(), n)
+                        .unwrap()
+                        .with_config(config.clone());
                     match decompressor
-                        .decompress(compressed_values2.compressed_value_mut())
+                        .decompress(compressed_values2.compressed_value_ref())
                         .block_on()
                     {
                         Ok(decompressed_values) => {
@@ -162,7 +313,6 @@ mod tests {
     struct TimeSeriesReader {
         minimum_block_size: usize,
         block_size: usize,
-        current_block: usize,
         source_value: Vec<f64>,
         current_index: usize,
     }
@@ -172,7 +322,6 @@ mod tests {
             Self {
                 minimum_block_size,
                 block_size: min(block_size, source_value.len()),
-                current_block: 0,
                 source_value,
                 current_index: 0,
             }
@@ -17,6 +17,11 @@ var<uniform> size: u32; // this is used as both input and output for convenience
 //@indices_size
 
 //#include(64_utils)
+
+override workgroup_offset: u32;
+override n: u32;
+override full_size: u32;
+
 @group(0)
 @binding(3)
 var<storage, read_write> id_to_write: array<u32>; // this is used as both input and output for convenience
@@ -27,9 +32,6 @@ struct pseudo_u64{
 }
 
 fn find_most_similar_previous_value(workgroup_start:u32) {
-   //@n
-   //@size
-   //@full_size
    let log2n=log2(f32(n));
    let setlsb=u32(pow(2, log2n + 1) - 1);
    var previousIndex=1u;
@@ -87,6 +89,5 @@ fn to_u64(x:pseudo_u64)->u64{
 @compute
 @workgroup_size(1)
 fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>) {
-    //@workgroup_offset
     find_most_similar_previous_value((workgroup_offset+workgroup_id.x)*size);
 }
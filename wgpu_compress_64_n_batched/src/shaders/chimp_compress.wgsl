@@ -28,8 +28,10 @@ var<storage, read_write> comprare_with: array<u32>; // this is used as both inpu
 
 //#include(64_utils)
 
+override workgroup_offset: u32;
+override log2n: u32;
+
 fn compress(v:f64,s:Ss,v_prev:f64,s_prev:Ss,c:u32) -> Output64{
-    //@log2n
     //Conditions
     var trail_gt_6=u32(s.trailing>6);
     var trail_le_6=u32(s.trailing<=6);
@@ -99,7 +101,6 @@ fn compress(v:f64,s:Ss,v_prev:f64,s_prev:Ss,c:u32) -> Output64{
 @compute
 @workgroup_size(256)
 fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>,@builtin(local_invocation_id) invocation_id: vec3<u32>) {
-    //@workgroup_offset
 
     for (var i=0u;i<chunks;i++){
         let index:u32=(workgroup_offset+workgroup_id.x) * 256 * chunks + invocation_id.x+i*256u;
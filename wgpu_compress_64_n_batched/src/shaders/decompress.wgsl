@@ -16,13 +16,10 @@ var<uniform> size: u32;
 
 @group(0)
 @binding(3)
-var<storage, read_write> input_index: array<u32>;
+var<storage, read_write> blocks: array<vec2<u32>>; // the first word and the value count of each block
 
 @group(0)
 @binding(4)
-var<uniform> input_size: u32;
-@group(0)
-@binding(5)
 var<storage,read_write> last_lead_array: array<u64>;
 
 struct CurrentInfo{
@@ -32,10 +29,13 @@ struct CurrentInfo{
 
 //#include(64_utils)
 
-fn write(input_idx:u32,output_idx:u32){
+override workgroup_offset: u32;
+override n: u32;
+override log2n: u32;
+override total_threads: u32;
+
+fn write(input_idx:u32,output_idx:u32,count:u32){
 
-    //@n
-    //@log2n
     
     //Index of the byte we are in
     var current_index=input_idx+1u;
@@ -56,11 +56,7 @@ fn write(input_idx:u32,output_idx:u32){
     current_info.current_offset+=64u;
     // current_info.current_index+=1;
     var value=u64(0u);
-    for (var i: u32 = 1u; i < size; i++) {
-        // if we have not finished reading values from the uncompressed buffers
-        if current_info.current_index>=(input_size - 1u) && (current_info.current_offset - 1u) <=0u{
-            break;
-        }
+    for (var i: u32 = 1u; i < count; i++) {
         
         //if current bit value==1
         if get_bit_at_index(current_info.current_index,current_info.current_offset)==1u {
@@ -174,8 +170,7 @@ fn reinterpret_num(array_index:u32,index:u32,length:u32)->u64{
 @compute
 @workgroup_size(256)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    //@workgroup_offset
-    //@total_threads
     if(workgroup_offset+global_id.x>=total_threads){return;}
-    write(input_index[workgroup_offset+global_id.x],(workgroup_offset+global_id.x)*size);
+    let block=workgroup_offset+global_id.x;
+    write(blocks[block].x,block*size,blocks[block].y);
 }
\ No newline at end of file
@@ -9,10 +9,12 @@ var<storage, read_write> input:array<f64>; // input values
 @binding(2)
 var<uniform> chunks:u32; // how many iterations per buffer
 //#include(64_utils)
+
+override workgroup_offset: u32;
+
 @compute
 @workgroup_size(1)
 fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>) {
-    //@workgroup_offset
 
     out[(workgroup_offset+workgroup_id.x ) * 256 * chunks]= Output64(u64(0u),bitcast<u64>(input[(workgroup_offset+workgroup_id.x) * 256 * chunks]),u64(64u));
 }
\ No newline at end of file
@@ -0,0 +1,61 @@
+use crate::cpu::delta_of_delta::BitReader;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{BlockIndex, ChimpHeader, CodecVariant, HEADER_SIZE};
+use compress_utils::general_utils::{cast_or_copy, DecompressResult, Step};
+use compress_utils::step;
+use std::fs;
+
+/// Host decoder of the delta of delta timestamp format, it needs no wgpu device
+#[derive(Debug, Default, Clone)]
+pub struct TimestampDecompressorCpu {
+    config: ChimpConfig,
+}
+
+impl TimestampDecompressorCpu {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+}
+
+#[async_trait]
+impl Decompressor<i64> for TimestampDecompressorCpu {
+    async fn decompress(&self, bytes: &[u8]) -> Result<DecompressResult<i64>, ChimpError> {
+        let (header, blocks) = ChimpHeader::split(bytes, CodecVariant::DeltaOfDelta64)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        let index = BlockIndex::scan(&header, blocks)?;
+        let words = cast_or_copy::<u64>(blocks);
+        let mut values = Vec::with_capacity(index.value_count() as usize);
+        for (block, entry) in index.entries().iter().enumerate() {
+            let prefix = entry.byte_offset as usize / size_of::<u64>();
+            let count = (words[prefix] >> 32) as usize + 1;
+            let block_words = (words[prefix] & 0xFFFFFFFF) as usize / size_of::<u64>();
+            let corrupted = || ChimpError::CorruptInput {
+                block,
+                byte_offset: HEADER_SIZE + entry.byte_offset as usize,
+            };
+            let block_words = &words[prefix + 1..prefix + 1 + block_words];
+            let (&first, bits) = block_words.split_first().ok_or_else(corrupted)?;
+            let mut reader = BitReader::new(bits);
+            let mut value = first;
+            let mut delta = 0u64;
+            values.push(value as i64);
+            for _ in 1..count {
+                let delta_of_delta = reader.read_delta_of_delta().ok_or_else(corrupted)?;
+                delta = delta.wrapping_add(delta_of_delta);
+                value = value.wrapping_add(delta);
+                values.push(value as i64);
+            }
+        }
+        step!(self.config, Step::Decompress, {
+            values.iter().map(|it| format!("{it}\n"))
+        });
+        Ok(values.into())
+    }
+}
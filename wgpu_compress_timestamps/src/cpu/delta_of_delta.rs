@@ -0,0 +1,149 @@
+use crate::delta_of_delta::DeltaOfDelta;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{padding_to_fit_buffer_count, Step};
+use compress_utils::types::ChimpOutput64;
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{step, BufferWrapper};
+use std::fs;
+
+/// The delta of delta ranges below 64 bits as `(code, code bits, payload bits)`, a delta of
+/// delta outside of all of them is written in full after the code `1111`
+const BUCKETS: [(u64, u32, u32); 3] = [(0b10, 2, 7), (0b110, 3, 9), (0b1110, 4, 12)];
+
+/// The code of the value at [position] of its buffer, the one at [index] of [values]. The
+/// first value of a buffer is stored as is and the first delta of a buffer is taken against 0
+pub(crate) fn encode(values: &[u64], index: usize, position: usize) -> ChimpOutput64 {
+    if position == 0 {
+        return ChimpOutput64 {
+            upper_bits: 0,
+            lower_bits: values[index],
+            bit_count: 64,
+        };
+    }
+    let previous_delta = if position > 1 {
+        values[index - 1].wrapping_sub(values[index - 2])
+    } else {
+        0
+    };
+    let delta_of_delta = values[index]
+        .wrapping_sub(values[index - 1])
+        .wrapping_sub(previous_delta);
+    if delta_of_delta == 0 {
+        return ChimpOutput64 {
+            upper_bits: 0,
+            lower_bits: 0,
+            bit_count: 1,
+        };
+    }
+    for (code, code_bits, payload_bits) in BUCKETS {
+        let half_range = 1u64 << (payload_bits - 1);
+        if delta_of_delta.wrapping_add(half_range) < 2 * half_range {
+            return ChimpOutput64 {
+                upper_bits: 0,
+                lower_bits: (code << payload_bits) | (delta_of_delta & (2 * half_range - 1)),
+                bit_count: (code_bits + payload_bits) as u64,
+            };
+        }
+    }
+    ChimpOutput64 {
+        upper_bits: 0b1111,
+        lower_bits: delta_of_delta,
+        bit_count: 68,
+    }
+}
+
+/// Reads a block bit stream most significant bit first
+pub(crate) struct BitReader<'a> {
+    words: &'a [u64],
+    word: usize,
+    bit: u32,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(words: &'a [u64]) -> Self {
+        Self {
+            words,
+            word: 0,
+            bit: 0,
+        }
+    }
+
+    /// The next [count] bits, [None] when the block ends before them
+    fn read(&mut self, count: u32) -> Option<u64> {
+        let mut result = 0u64;
+        let mut remaining = count;
+        while remaining > 0 {
+            let take = (64 - self.bit).min(remaining);
+            let chunk = (self.words.get(self.word)? << self.bit) >> (64 - take);
+            result = result.checked_shl(take).unwrap_or(0) | chunk;
+            self.bit += take;
+            if self.bit == 64 {
+                self.word += 1;
+                self.bit = 0;
+            }
+            remaining -= take;
+        }
+        Some(result)
+    }
+
+    pub(crate) fn read_delta_of_delta(&mut self) -> Option<u64> {
+        if self.read(1)? == 0 {
+            return Some(0);
+        }
+        for (_, _, payload_bits) in BUCKETS {
+            if self.read(1)? == 0 {
+                let sign = 1u64 << (payload_bits - 1);
+                return Some((self.read(payload_bits)? ^ sign).wrapping_sub(sign));
+            }
+        }
+        self.read(64)
+    }
+}
+
+/// Host implementation of the delta of delta stage
+#[derive(Debug, Default)]
+pub struct CPUDeltaOfDelta {
+    config: ChimpConfig,
+}
+
+impl CPUDeltaOfDelta {
+    pub fn new(config: ChimpConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl DeltaOfDelta for CPUDeltaOfDelta {
+    async fn delta_of_delta(
+        &self,
+        values: &[i64],
+        buffers: &mut RunBuffers,
+        _skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        let buffer_size = self.config.buffer_size();
+        // the values are padded with 0's to fill their last buffer
+        let padded_len = values.len() + padding_to_fit_buffer_count(values.len(), buffer_size);
+        let mut padded_values: Vec<u64> = bytemuck::cast_slice(values).to_vec();
+        padded_values.resize(padded_len + 1, 0);
+
+        let mut output = vec![ChimpOutput64::default(); padded_len + 1];
+        for (index, output) in output.iter_mut().enumerate().take(padded_len) {
+            *output = encode(&padded_values, index, index % buffer_size);
+        }
+        step!(self.config, Step::Compress, {
+            output
+                .iter()
+                .enumerate()
+                .map(|it| format!("{}:{}\n", it.0, it.1))
+        });
+        buffers.set_input_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &padded_values,
+        )));
+        buffers.set_compressed_buffer(BufferWrapper::host_with_content(bytemuck::cast_slice(
+            &output,
+        )));
+        Ok(())
+    }
+}
@@ -0,0 +1,2 @@
+pub mod decompressor;
+pub mod delta_of_delta;
@@ -0,0 +1,188 @@
+use crate::shaders;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::cpu_compress::Decompressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{BlockIndex, ChimpHeader, CodecVariant};
+use compress_utils::general_utils::{cast_or_copy, DecompressResult, MaxGroupGnostic, Step};
+use compress_utils::{
+    execute_compute_shader, step, time_it, wgpu_utils, BufferWrapper, WgpuGroupId,
+};
+use log::info;
+use pollster::FutureExt;
+use std::cmp::max;
+use std::fs;
+use std::sync::Arc;
+use std::time::Instant;
+use wgpu_types::BufferAddress;
+
+/// Decodes the delta of delta timestamp format on the gpu, one invocation per block
+pub struct TimestampDecompressor {
+    context: Arc<Context>,
+    config: ChimpConfig,
+}
+
+impl TimestampDecompressor {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(Context::initialize_default_adapter().block_on()?))
+    }
+}
+
+impl MaxGroupGnostic for TimestampDecompressor {
+    fn get_max_number_of_groups(&self, _content_len: usize) -> usize {
+        self.config.max_workgroup_size(self.context())
+    }
+}
+
+impl TimestampDecompressor {
+    pub fn new(context: impl Into<Arc<Context>>) -> Self {
+        Self {
+            context: context.into(),
+            config: ChimpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Decode the [block_offsets] word offsets of the blocks in [words], every block
+    /// decoding to at most a buffer of values
+    async fn decompress_window(
+        &self,
+        words: &[u64],
+        block_offsets: &[u32],
+        skip_time: &mut u128,
+    ) -> Result<Vec<u64>, ChimpError> {
+        let instant = Instant::now();
+        let out_buffer_size = block_offsets.len() * self.config.buffer_size() * size_of::<u64>();
+        self.context().check_buffer_size(out_buffer_size)?;
+        let out_storage_buffer = BufferWrapper::storage_with_size(
+            self.context().device(),
+            out_buffer_size as BufferAddress,
+            WgpuGroupId::new(0, 0),
+            Some("Storage output Buffer"),
+        );
+        let out_staging = BufferWrapper::stage_with_size(
+            self.context().device(),
+            out_buffer_size as BufferAddress,
+            Some("Staging output Buffer"),
+        );
+        let input_storage_buffer = BufferWrapper::storage_with_content(
+            self.context().device(),
+            bytemuck::cast_slice(words),
+            WgpuGroupId::new(0, 1),
+            Some("Storage input Buffer"),
+        );
+        let offsets_buffer = BufferWrapper::storage_with_content(
+            self.context().device(),
+            bytemuck::cast_slice(block_offsets),
+            WgpuGroupId::new(0, 2),
+            Some("Block offsets Buffer"),
+        );
+        let size_uniform = BufferWrapper::uniform_with_content(
+            self.context().device(),
+            bytemuck::bytes_of(&(self.config.buffer_size() as u32)),
+            WgpuGroupId::new(0, 3),
+            Some("Buffer size"),
+        );
+        *skip_time += instant.elapsed().as_millis();
+
+        let shader = shaders::DECOMPRESS
+            .specialize()
+            .bind("total_threads", block_offsets.len() as u32)
+            .finish()?;
+        execute_compute_shader!(
+            self.context(),
+            &shader,
+            vec![
+                &out_storage_buffer,
+                &input_storage_buffer,
+                &offsets_buffer,
+                &size_uniform
+            ],
+            block_offsets.len().div_ceil(256),
+            Some("timestamp decompress pass")
+        );
+
+        let instant = Instant::now();
+        let result = wgpu_utils::get_from_gpu::<u64>(
+            self.context(),
+            out_storage_buffer.buffer(),
+            out_storage_buffer.size() as BufferAddress,
+            out_staging.buffer(),
+        )
+        .await?;
+        *skip_time += instant.elapsed().as_millis();
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl Decompressor<i64> for TimestampDecompressor {
+    async fn decompress(&self, bytes: &[u8]) -> Result<DecompressResult<i64>, ChimpError> {
+        let (header, blocks) = ChimpHeader::split(bytes, CodecVariant::DeltaOfDelta64)?;
+        header.expect_buffer_size(self.config.buffer_size())?;
+        let index = BlockIndex::scan(&header, blocks)?;
+        let words = cast_or_copy::<u64>(blocks);
+        let buffer_size = self.config.buffer_size();
+        let max_words = self.context.get_max_storage_buffer_size() / size_of::<u64>();
+        // every block takes one invocation and decodes to at most a buffer of values
+        let max_blocks = (max_words / buffer_size)
+            .min(256 * self.get_max_number_of_groups(index.entries().len()));
+
+        let mut values = Vec::with_capacity(index.value_count() as usize);
+        let mut skip_time = 0;
+        let mut total_millis = 0;
+        time_it!(
+            {
+                let entries = index.entries();
+                let mut first = 0;
+                while first < entries.len() {
+                    let start = entries[first].byte_offset as usize / size_of::<u64>();
+                    let mut last = first;
+                    let mut end = start;
+                    while last < entries.len() && last - first < max_blocks {
+                        let prefix = entries[last].byte_offset as usize / size_of::<u64>();
+                        let block_end = prefix + 1 + (words[prefix] & 0xFFFFFFFF) as usize / 8;
+                        if last > first && block_end - start > max_words {
+                            break;
+                        }
+                        end = block_end;
+                        last += 1;
+                    }
+                    let block_offsets: Vec<u32> = entries[first..last]
+                        .iter()
+                        .map(|it| (it.byte_offset as usize / size_of::<u64>() - start) as u32)
+                        .collect();
+                    let window = self
+                        .decompress_window(&words[start..end], &block_offsets, &mut skip_time)
+                        .await?;
+                    for (block, offset) in block_offsets.iter().enumerate() {
+                        let count = (words[start + *offset as usize] >> 32) as usize + 1;
+                        let output = &window[block * buffer_size..block * buffer_size + count];
+                        values.extend(output.iter().map(|it| *it as i64));
+                    }
+                    first = last;
+                }
+            },
+            total_millis,
+            "timestamp decompression"
+        );
+        step!(self.config, Step::Decompress, {
+            values.iter().map(|it| format!("{it}\n"))
+        });
+        Ok(DecompressResult(values, skip_time))
+    }
+}
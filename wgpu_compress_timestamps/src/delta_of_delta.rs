@@ -0,0 +1,127 @@
+use crate::shaders;
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::error::ChimpError;
+use compress_utils::general_utils::{padding_to_fit_buffer_count, Step};
+use compress_utils::types::ChimpOutput64;
+use compress_utils::wgpu_utils::RunBuffers;
+use compress_utils::{execute_compute_shader, step, wgpu_utils, BufferWrapper, WgpuGroupId};
+use std::cmp::max;
+use std::fs;
+use std::sync::Arc;
+use std::time::Instant;
+use wgpu_types::BufferAddress;
+
+/// The first stage of the timestamp pipeline, it writes the code of every value to the
+/// compressed buffer in the layout the [wgpu_compress_64_batched] index and finalize stages
+/// read
+#[async_trait]
+pub trait DeltaOfDelta {
+    async fn delta_of_delta(
+        &self,
+        values: &[i64],
+        buffers: &mut RunBuffers,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError>;
+}
+
+pub struct GPUDeltaOfDelta {
+    context: Arc<Context>,
+    config: ChimpConfig,
+}
+
+impl GPUDeltaOfDelta {
+    pub fn new(context: Arc<Context>, config: ChimpConfig) -> Self {
+        Self { context, config }
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+}
+
+#[async_trait]
+impl DeltaOfDelta for GPUDeltaOfDelta {
+    async fn delta_of_delta(
+        &self,
+        values: &[i64],
+        buffers: &mut RunBuffers,
+        skip_time: &mut u128,
+    ) -> Result<(), ChimpError> {
+        // the values are padded with 0's to fill their last buffer
+        let padded_len =
+            values.len() + padding_to_fit_buffer_count(values.len(), self.config.buffer_size());
+        let workgroup_count = padded_len / self.config.buffer_size();
+
+        let instant = Instant::now();
+        let input_storage_buffer = BufferWrapper::pooled_storage_with_padded_content(
+            self.context(),
+            bytemuck::cast_slice(values),
+            (size_of::<i64>() * (padded_len + 1)) as BufferAddress,
+            WgpuGroupId::new(0, 1),
+            Some("Storage Input Buffer"),
+        );
+        let output_storage_buffer = BufferWrapper::pooled_storage_with_size(
+            self.context(),
+            (size_of::<ChimpOutput64>() * (padded_len + 1)) as BufferAddress,
+            WgpuGroupId::new(0, 0),
+            Some("Storage Output Buffer"),
+        );
+        let chunks_buffer = BufferWrapper::pooled_uniform_with_content(
+            self.context(),
+            bytemuck::bytes_of(&self.config.buffer_info().chunks()),
+            WgpuGroupId::new(0, 2),
+            Some("Chunks Buffer"),
+        );
+        *skip_time += instant.elapsed().as_millis();
+        let max_workgroup_size = self.config.max_workgroup_size(&self.context);
+        let iterations = workgroup_count / max_workgroup_size + 1;
+        let last_size = workgroup_count % max_workgroup_size;
+        for i in 0..iterations {
+            let shader = shaders::DELTA_OF_DELTA
+                .specialize()
+                .bind("workgroup_offset", (i * max_workgroup_size) as u32)
+                .finish()?;
+            execute_compute_shader!(
+                self.context(),
+                &shader,
+                vec![
+                    &output_storage_buffer,
+                    &input_storage_buffer,
+                    &chunks_buffer
+                ],
+                if i == iterations - 1 {
+                    last_size
+                } else {
+                    max_workgroup_size
+                },
+                Some("delta of delta pass")
+            );
+        }
+        buffers.set_input_buffer(input_storage_buffer);
+        buffers.set_compressed_buffer(output_storage_buffer);
+        buffers.set_chunks(chunks_buffer);
+
+        let output;
+        step!(self.config, Step::Compress, {
+            let output_staging = BufferWrapper::pooled_stage_with_size(
+                self.context(),
+                buffers.compressed_buffer().size() as BufferAddress,
+                None,
+            );
+            output = wgpu_utils::get_from_gpu::<ChimpOutput64>(
+                self.context(),
+                buffers.compressed_buffer().buffer(),
+                buffers.compressed_buffer().size() as BufferAddress,
+                output_staging.buffer(),
+            )
+            .await?;
+            output
+                .iter()
+                .enumerate()
+                .map(|it| format!("{}:{}\n", it.0, it.1))
+        });
+        Ok(())
+    }
+}
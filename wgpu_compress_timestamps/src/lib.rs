@@ -0,0 +1,378 @@
+//! Delta of delta compression of `i64` timestamps, in the style of the Gorilla paper.
+//!
+//! The values are split in buffers like the 64 bit batched Chimp codec, and every buffer is
+//! a block with the same prefix and layout, so the stream header, block index, range
+//! decoding and streaming of [compress_utils] work unchanged. The first value of a buffer is
+//! stored as is and every following value as the difference of its delta to the previous
+//! one, the first delta of a buffer taken against 0:
+//!
+//! | delta of delta  | code   | bits |
+//! |-----------------|--------|------|
+//! | 0               | `0`    | 1    |
+//! | [-64, 63]       | `10`   | 9    |
+//! | [-256, 255]     | `110`  | 12   |
+//! | [-2048, 2047]   | `1110` | 16   |
+//! | anything else   | `1111` | 68   |
+//!
+//! The arithmetic wraps, so any `i64` sequence round trips. Only the first stage is specific
+//! to this codec, the index and finalize stages are the ones of [wgpu_compress_64_batched].
+//! [series] pairs a timestamp stream with the stream of the values taken at them.
+use crate::cpu::delta_of_delta::CPUDeltaOfDelta;
+use crate::delta_of_delta::{DeltaOfDelta, GPUDeltaOfDelta};
+use async_trait::async_trait;
+use compress_utils::config::ChimpConfig;
+use compress_utils::context::Context;
+use compress_utils::cpu_compress::Compressor;
+use compress_utils::error::ChimpError;
+use compress_utils::format::{ChimpHeader, CodecVariant, HEADER_SIZE};
+use compress_utils::general_utils::{
+    max_batch_len, padding_to_fit_buffer_count, split_by_max_storage_buffer_size, CompressResult,
+};
+use compress_utils::stream::BatchedCompressor;
+use compress_utils::types::ChimpOutput64;
+use compress_utils::{time_it, wgpu_utils};
+use log::info;
+use pollster::FutureExt;
+use std::slice::Chunks;
+use std::sync::Arc;
+use wgpu_compress_64_batched::calculate_indexes::{CalculateIndexes64, GPUCalculateIndexes64};
+use wgpu_compress_64_batched::cpu::calculate_indexes::CPUCalculateIndexes64;
+use wgpu_compress_64_batched::cpu::finalize::CPUFinalizer64;
+use wgpu_compress_64_batched::finalize::{Finalize, Finalizer64};
+
+pub mod cpu;
+pub mod decompressor;
+pub mod delta_of_delta;
+pub mod series;
+pub mod shaders;
+
+/// Compresses timestamps with every stage of the pipeline on the gpu
+#[derive(Debug)]
+pub struct TimestampCompressor {
+    context: Arc<Context>,
+    config: ChimpConfig,
+}
+
+impl TimestampCompressor {
+    /// On the default adapter, see [Context::initialize_default_adapter]
+    pub fn try_default() -> Result<Self, ChimpError> {
+        Ok(Self::new(Context::initialize_default_adapter().block_on()?))
+    }
+}
+
+impl TimestampCompressor {
+    pub fn new(context: impl Into<Arc<Context>>) -> Self {
+        Self {
+            context: context.into(),
+            config: ChimpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+}
+
+#[async_trait]
+impl Compressor<i64> for TimestampCompressor {
+    async fn compress(&self, values: &[i64]) -> Result<CompressResult, ChimpError> {
+        let header = self.header(values.len());
+        let iterations = split_by_max_storage_buffer_size(
+            values,
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput64>(),
+        );
+        let result = compress_batches(
+            iterations,
+            &self.config,
+            &GPUDeltaOfDelta::new(self.context.clone(), self.config.clone()),
+            &GPUCalculateIndexes64::new(self.context.clone(), self.config.clone()),
+            &Finalizer64::new(self.context.clone(), self.config.clone()),
+        )
+        .await?;
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl BatchedCompressor<i64> for TimestampCompressor {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.context.get_max_storage_buffer_size(),
+            size_of::<ChimpOutput64>(),
+        )
+    }
+
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        ChimpHeader::new(
+            CodecVariant::DeltaOfDelta64,
+            0,
+            self.config.buffer_size(),
+            value_count,
+        )
+    }
+}
+
+/// A [Compressor] running every stage of the pipeline on the host.
+///
+/// The output is byte for byte the one of [TimestampCompressor] on a device whose storage
+/// buffer binding limit is [TimestampCompressorCpu::max_storage_buffer_size].
+#[derive(Debug, Clone)]
+pub struct TimestampCompressorCpu {
+    max_storage_buffer_size: usize,
+    config: ChimpConfig,
+}
+
+impl Default for TimestampCompressorCpu {
+    fn default() -> Self {
+        Self::new(wgpu_compress_64_batched::ChimpCompressorBatched64::MAX_BUFFER_SIZE_BYTES)
+    }
+}
+
+impl TimestampCompressorCpu {
+    pub fn new(max_storage_buffer_size: usize) -> Self {
+        Self {
+            max_storage_buffer_size,
+            config: ChimpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ChimpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn max_storage_buffer_size(&self) -> usize {
+        self.max_storage_buffer_size
+    }
+
+    pub fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+}
+
+#[async_trait]
+impl Compressor<i64> for TimestampCompressorCpu {
+    async fn compress(&self, values: &[i64]) -> Result<CompressResult, ChimpError> {
+        let header = self.header(values.len());
+        let iterations = split_by_max_storage_buffer_size(
+            values,
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput64>(),
+        );
+        let result = compress_batches(
+            iterations,
+            &self.config,
+            &CPUDeltaOfDelta::new(self.config.clone()),
+            &CPUCalculateIndexes64::new(self.config.clone()),
+            &CPUFinalizer64::new(self.config.clone()),
+        )
+        .await?;
+        Ok(CompressResult(
+            header.prepend_to(result.0),
+            HEADER_SIZE + result.1,
+            result.2,
+        )
+        .with_trailers(&self.config)?)
+    }
+}
+
+impl BatchedCompressor<i64> for TimestampCompressorCpu {
+    fn config(&self) -> &ChimpConfig {
+        &self.config
+    }
+
+    fn batch_len(&self) -> usize {
+        max_batch_len(
+            self.config.buffer_size(),
+            self.max_storage_buffer_size,
+            size_of::<ChimpOutput64>(),
+        )
+    }
+
+    fn header(&self, value_count: usize) -> ChimpHeader {
+        ChimpHeader::new(
+            CodecVariant::DeltaOfDelta64,
+            0,
+            self.config.buffer_size(),
+            value_count,
+        )
+    }
+}
+
+/// Run every stage of the pipeline over each of the [iterations] and concatenate the
+/// resulting buffers, without the stream header
+async fn compress_batches(
+    iterations: Chunks<'_, i64>,
+    config: &ChimpConfig,
+    delta_of_delta_impl: &(impl DeltaOfDelta + Sync),
+    indexes_impl: &(impl CalculateIndexes64 + Sync),
+    finalize_impl: &(impl Finalize + Sync),
+) -> Result<CompressResult, ChimpError> {
+    let mut byte_stream = Vec::new();
+    let mut metadata = 0;
+    let mut skip_time = 0;
+    let mut buffers = wgpu_utils::RunBuffers::default();
+    for values in iterations {
+        let mut total_millis = 0;
+        let output_vec: CompressResult;
+        let buffer_size = config.buffer_size();
+        let padding = padding_to_fit_buffer_count(values.len(), buffer_size);
+        time_it!(
+            {
+                delta_of_delta_impl
+                    .delta_of_delta(values, &mut buffers, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "delta of delta stage"
+        );
+        time_it!(
+            {
+                indexes_impl
+                    .calculate_indexes(&mut buffers, buffer_size as u32, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "calculate trim size stage"
+        );
+        time_it!(
+            {
+                output_vec = finalize_impl
+                    .finalize(&mut buffers, padding, &mut skip_time)
+                    .await?;
+            },
+            total_millis,
+            "trimming stage"
+        );
+        byte_stream.extend(output_vec.compressed_value_ref());
+        metadata += output_vec.metadata_size();
+    }
+    Ok(CompressResult(byte_stream, metadata, skip_time))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::decompressor::TimestampDecompressorCpu;
+    use crate::TimestampCompressorCpu;
+    use compress_utils::config::ChimpConfig;
+    use compress_utils::cpu_compress::{Compressor, Decompressor};
+    use compress_utils::error::ChimpError;
+    use compress_utils::format::{BlockIndex, ChimpHeader, CodecVariant, HEADER_SIZE};
+    use compress_utils::stream::{ChimpStreamDecoder, ChimpStreamEncoder};
+    use itertools::Itertools;
+    use pollster::FutureExt;
+
+    fn timestamps(count: usize) -> Vec<i64> {
+        let mut value = 1_700_000_000_000i64;
+        (0..count)
+            .map(|it| {
+                // mostly regular with jitter, gaps and the odd jump across the whole range
+                value = value.wrapping_add(match it % 97 {
+                    0 => 1 << 40,
+                    13 => -(1 << 20),
+                    50 => 3000,
+                    _ => 1000 + (it as i64 * 7919) % 61 - 30,
+                });
+                value
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cpu_round_trip() {
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let mut values = timestamps(3000);
+        values.extend([i64::MIN, i64::MAX, 0, i64::MIN, -1, 1, i64::MAX]);
+        let compressed = TimestampCompressorCpu::new(16384)
+            .with_config(config.clone())
+            .compress(&values)
+            .block_on()
+            .unwrap()
+            .compressed_values();
+
+        let header = ChimpHeader::read(&compressed).unwrap();
+        assert_eq!(header.codec, CodecVariant::DeltaOfDelta64);
+        assert_eq!(header.value_count, values.len() as u64);
+        let index = BlockIndex::scan(&header, &compressed[HEADER_SIZE..]).unwrap();
+        assert_eq!(index.entries().len(), values.len().div_ceil(256));
+
+        let decompressed = TimestampDecompressorCpu::new(config)
+            .decompress(&compressed)
+            .block_on()
+            .unwrap()
+            .un_compressed_value();
+        assert_eq!(decompressed, values);
+    }
+
+    #[test]
+    fn test_cpu_regular_timestamps_take_a_bit_each() {
+        let values = (0..256 * 4).map(|it| it * 1000).collect_vec();
+        let compressed = TimestampCompressorCpu::default()
+            .with_config(ChimpConfig::default().with_buffer_size(256).unwrap())
+            .compress(&values)
+            .block_on()
+            .unwrap()
+            .compressed_values();
+        // the first value takes 64 bits, the second 16 and the other 254 a single one, so
+        // a block is 334 / 64 + 2 words like the blocks of the 64 bit chimp codec
+        assert_eq!(compressed.len(), HEADER_SIZE + 4 * 7 * 8);
+    }
+
+    #[test]
+    fn test_cpu_stream_round_trip_and_truncation() {
+        let values = timestamps(5000);
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let mut encoder = ChimpStreamEncoder::new(
+            TimestampCompressorCpu::new(16384).with_config(config.clone()),
+            Vec::new(),
+        )
+        .unwrap();
+        for chunk in values.chunks(1234) {
+            encoder.push(chunk).unwrap();
+        }
+        let stream = encoder.finish().unwrap();
+
+        let decoded =
+            ChimpStreamDecoder::new(TimestampDecompressorCpu::new(config.clone()), &stream[..])
+                .unwrap()
+                .with_window_blocks(3)
+                .values()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+        assert_eq!(decoded, values);
+
+        let range = TimestampDecompressorCpu::new(config.clone())
+            .decompress_range(&stream, 700..1900)
+            .block_on()
+            .unwrap()
+            .un_compressed_value();
+        assert_eq!(range, values[700..1900]);
+
+        let error = TimestampDecompressorCpu::new(config)
+            .decompress(&stream[..HEADER_SIZE + 1000])
+            .block_on()
+            .unwrap_err();
+        assert!(matches!(error, ChimpError::CorruptInput { .. }));
+    }
+}
@@ -0,0 +1,189 @@
+//! A container pairing a timestamp stream with the stream of the values taken at them.
+//!
+//! | bytes          | content                                              |
+//! |----------------|------------------------------------------------------|
+//! | 0..4           | magic `CHTS`                                         |
+//! | 4..6           | container version, little endian                     |
+//! | 6..8           | reserved, zero                                       |
+//! | 8..16          | length `n` of the timestamp stream, little endian    |
+//! | 16..16+n       | the timestamp stream                                 |
+//! | up to a word   | zero padding, so the value stream starts 8 byte aligned |
+//! | rest           | the value stream                                     |
+//!
+//! Both streams are complete chimp streams with their own header, so either half can be
+//! handed to its decompressor on its own.
+use compress_utils::cpu_compress::{Compressor, Decompressor};
+use compress_utils::error::ChimpError;
+use compress_utils::format::FormatError;
+
+pub const SERIES_MAGIC: [u8; 4] = *b"CHTS";
+pub const SERIES_VERSION: u16 = 1;
+pub const SERIES_HEADER_SIZE: usize = 16;
+
+/// Compresses `(timestamp, value)` pairs into one container, the timestamps with [TS] and
+/// the values with [V]
+#[derive(Debug, Clone)]
+pub struct SeriesCompressor<TS, V> {
+    timestamps: TS,
+    values: V,
+}
+
+impl<TS: Compressor<i64> + Sync, V: Sync> SeriesCompressor<TS, V> {
+    pub fn new(timestamps: TS, values: V) -> Self {
+        Self { timestamps, values }
+    }
+
+    /// Fails with [ChimpError::SeriesLengthMismatch] unless there is a value per timestamp
+    pub async fn compress<T>(&self, timestamps: &[i64], values: &[T]) -> Result<Vec<u8>, ChimpError>
+    where
+        V: Compressor<T>,
+    {
+        if timestamps.len() != values.len() {
+            return Err(ChimpError::SeriesLengthMismatch {
+                timestamps: timestamps.len(),
+                values: values.len(),
+            });
+        }
+        let timestamp_stream = self
+            .timestamps
+            .compress(timestamps)
+            .await?
+            .compressed_values();
+        let value_stream = self.values.compress(values).await?.compressed_values();
+
+        let mut container = Vec::with_capacity(
+            SERIES_HEADER_SIZE + timestamp_stream.len() + 8 + value_stream.len(),
+        );
+        container.extend(SERIES_MAGIC);
+        container.extend(SERIES_VERSION.to_le_bytes());
+        container.extend([0u8; 2]);
+        container.extend((timestamp_stream.len() as u64).to_le_bytes());
+        container.extend(timestamp_stream);
+        container.resize(container.len().next_multiple_of(8), 0);
+        container.extend(value_stream);
+        Ok(container)
+    }
+}
+
+/// Reads the containers of [SeriesCompressor], the timestamps with [TS] and the values
+/// with [V]
+#[derive(Debug, Clone)]
+pub struct SeriesDecompressor<TS, V> {
+    timestamps: TS,
+    values: V,
+}
+
+impl<TS: Decompressor<i64> + Sync, V: Sync> SeriesDecompressor<TS, V> {
+    pub fn new(timestamps: TS, values: V) -> Self {
+        Self { timestamps, values }
+    }
+
+    /// The timestamps and values of a container. A truncated container fails with
+    /// [ChimpError::CorruptInput] on block 0 for the timestamp stream and 1 for the value one
+    pub async fn decompress<T: Send>(&self, bytes: &[u8]) -> Result<(Vec<i64>, Vec<T>), ChimpError>
+    where
+        V: Decompressor<T>,
+    {
+        if bytes.len() < SERIES_HEADER_SIZE {
+            return Err(FormatError::TooShort { len: bytes.len() }.into());
+        }
+        if bytes[0..4] != SERIES_MAGIC {
+            return Err(FormatError::BadMagic.into());
+        }
+        let version = u16::from_le_bytes(bytes[4..6].try_into().unwrap());
+        if version == 0 || version > SERIES_VERSION {
+            return Err(ChimpError::UnsupportedVersion(version));
+        }
+        let timestamp_len = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) as usize;
+        let timestamp_end = SERIES_HEADER_SIZE
+            .checked_add(timestamp_len)
+            .filter(|end| *end <= bytes.len())
+            .ok_or(ChimpError::CorruptInput {
+                block: 0,
+                byte_offset: SERIES_HEADER_SIZE,
+            })?;
+        let value_start = timestamp_end.next_multiple_of(8);
+        if value_start > bytes.len() {
+            return Err(ChimpError::CorruptInput {
+                block: 1,
+                byte_offset: timestamp_end,
+            });
+        }
+
+        let timestamps = self
+            .timestamps
+            .decompress(&bytes[SERIES_HEADER_SIZE..timestamp_end])
+            .await?
+            .un_compressed_value();
+        let values = self
+            .values
+            .decompress(&bytes[value_start..])
+            .await?
+            .un_compressed_value();
+        if timestamps.len() != values.len() {
+            return Err(ChimpError::SeriesLengthMismatch {
+                timestamps: timestamps.len(),
+                values: values.len(),
+            });
+        }
+        Ok((timestamps, values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::decompressor::TimestampDecompressorCpu;
+    use crate::TimestampCompressorCpu;
+    use compress_utils::config::ChimpConfig;
+    use pollster::FutureExt;
+    use wgpu_compress_64_batched::cpu::decompressor::CPUDecompressorBatched64;
+    use wgpu_compress_64_batched::ChimpCompressorBatched64Cpu;
+
+    #[test]
+    fn series_round_trip() {
+        let config = ChimpConfig::default().with_buffer_size(256).unwrap();
+        let timestamps = (0..1000)
+            .map(|it| 1_000_000 + it * 15 + it % 3)
+            .collect::<Vec<i64>>();
+        let values = (0..1000)
+            .map(|it| (it as f64 / 7.0).cos())
+            .collect::<Vec<_>>();
+        let compressor = SeriesCompressor::new(
+            TimestampCompressorCpu::default().with_config(config.clone()),
+            ChimpCompressorBatched64Cpu::default().with_config(config.clone()),
+        );
+        let container = compressor
+            .compress(&timestamps, &values)
+            .block_on()
+            .unwrap();
+        assert_eq!(container[0..4], SERIES_MAGIC);
+
+        let decompressor = SeriesDecompressor::new(
+            TimestampDecompressorCpu::new(config.clone()),
+            CPUDecompressorBatched64::new(config),
+        );
+        let (decoded_timestamps, decoded_values) = decompressor
+            .decompress::<f64>(&container)
+            .block_on()
+            .unwrap();
+        assert_eq!(decoded_timestamps, timestamps);
+        assert_eq!(decoded_values, values);
+
+        assert!(matches!(
+            compressor.compress(&timestamps, &values[1..]).block_on(),
+            Err(ChimpError::SeriesLengthMismatch {
+                timestamps: 1000,
+                values: 999
+            })
+        ));
+        assert!(matches!(
+            decompressor.decompress::<f64>(&container[..100]).block_on(),
+            Err(ChimpError::CorruptInput { block: 0, .. })
+        ));
+        assert!(matches!(
+            decompressor.decompress::<f64>(b"CHMP").block_on(),
+            Err(ChimpError::Format(FormatError::TooShort { len: 4 }))
+        ));
+    }
+}
@@ -0,0 +1,95 @@
+//! The WGSL shaders of the timestamp pipeline along with the parameters each one is
+//! specialized with, see [compress_utils::shader_template]
+use compress_utils::shader_template::Parameter::Override;
+use compress_utils::shader_template::ShaderTemplate;
+
+const UTILS_64: (&str, &str) = ("64_utils", include_str!("shaders/64_utils.wgsl"));
+
+pub const DECOMPRESS: ShaderTemplate =
+    ShaderTemplate::new("decompress", include_str!("shaders/decompress.wgsl"))
+        .with_parameters(&[Override("total_threads")]);
+
+pub const DELTA_OF_DELTA: ShaderTemplate = ShaderTemplate::new(
+    "delta_of_delta",
+    include_str!("shaders/delta_of_delta.wgsl"),
+)
+.with_parameters(&[Override("workgroup_offset")])
+.with_includes(&[UTILS_64]);
+
+/// Every shader of the pipeline, validated by `compress_utils::shader_validation` in the tests
+pub const ALL: &[ShaderTemplate] = &[DECOMPRESS, DELTA_OF_DELTA];
+
+#[cfg(test)]
+mod tests {
+    use crate::compress_batches;
+    use crate::decompressor::TimestampDecompressor;
+    use crate::delta_of_delta::GPUDeltaOfDelta;
+    use crate::{shaders, TimestampCompressorCpu};
+    use async_trait::async_trait;
+    use compress_utils::config::ChimpConfig;
+    use compress_utils::context::Context;
+    use compress_utils::cpu_compress::{Compressor, Decompressor};
+    use compress_utils::error::ChimpError;
+    use compress_utils::general_utils::CompressResult;
+    use compress_utils::shader_validation::{
+        check_cached_layouts, seed_index_buffer, validate_templates, ParameterMatrix,
+    };
+    use compress_utils::wgpu_utils::RunBuffers;
+    use itertools::Itertools;
+    use pollster::FutureExt;
+    use std::sync::Arc;
+    use wgpu_compress_64_batched::calculate_indexes::GPUCalculateIndexes64;
+    use wgpu_compress_64_batched::finalize::{Finalize, Finalizer64};
+
+    /// The GPU [Finalizer64], run over the offsets the index calculation writes on a device
+    struct SeededFinalizer(Finalizer64);
+
+    #[async_trait]
+    impl Finalize for SeededFinalizer {
+        async fn finalize(
+            &self,
+            buffers: &mut RunBuffers,
+            padding: usize,
+            skip_time: &mut u128,
+        ) -> Result<CompressResult, ChimpError> {
+            seed_index_buffer(self.0.context(), buffers);
+            self.0.finalize(buffers, padding, skip_time).await
+        }
+    }
+
+    #[test]
+    fn test_shaders_validate_offline() {
+        let validated = validate_templates(shaders::ALL, &ParameterMatrix::default())
+            .unwrap_or_else(|error| panic!("{error}"));
+        assert!(validated > shaders::ALL.len());
+    }
+
+    #[test]
+    fn test_stage_layouts_match_their_shaders() {
+        let context = Arc::new(Context::initialize_noop().block_on().unwrap());
+        let config = ChimpConfig::default();
+        let values = (0..3000)
+            .map(|it| 1_700_000_000_000 + it * 1000)
+            .collect_vec();
+        compress_batches(
+            values.chunks(values.len()),
+            &config,
+            &GPUDeltaOfDelta::new(context.clone(), config.clone()),
+            &GPUCalculateIndexes64::new(context.clone(), config.clone()),
+            &SeededFinalizer(Finalizer64::new(context.clone(), config.clone())),
+        )
+        .block_on()
+        .unwrap();
+        let compressed = TimestampCompressorCpu::default()
+            .compress(&values)
+            .block_on()
+            .unwrap();
+        TimestampDecompressor::new(context.clone())
+            .decompress(compressed.compressed_value_ref())
+            .block_on()
+            .unwrap();
+
+        check_cached_layouts(context.pipeline_cache(), shaders::ALL)
+            .unwrap_or_else(|error| panic!("{error}"));
+    }
+}
@@ -0,0 +1,87 @@
+override total_threads: u32;
+
+@group(0)
+@binding(0)
+var<storage, read_write> out: array<u64>; // size values per block
+
+@group(0)
+@binding(1)
+var<storage, read_write> in: array<u64>; // the blocks, each starting with its prefix word
+
+@group(0)
+@binding(2)
+var<storage, read_write> block_offsets: array<u32>; // the word offset of every block prefix
+
+@group(0)
+@binding(3)
+var<uniform> size: u32; // the buffer size
+
+struct BitReader{
+    word:u32,
+    bit:u32,
+}
+
+// Read the next count bits of the block, most significant bit first
+fn read_bits(reader:ptr<function,BitReader>,count:u32)->u64{
+    var result=u64(0u);
+    var remaining=count;
+    while remaining>0u {
+        let take=min(64u-(*reader).bit,remaining);
+        let chunk=(in[(*reader).word]<<(*reader).bit)>>(64u-take);
+        if take==64u {
+            result=chunk;
+        } else {
+            result=(result<<take)|chunk;
+        }
+        (*reader).bit+=take;
+        if (*reader).bit==64u {
+            (*reader).word+=1u;
+            (*reader).bit=0u;
+        }
+        remaining-=take;
+    }
+    return result;
+}
+
+fn sign_extend(value:u64,bit_count:u32)->u64{
+    let sign=u64(1u)<<(bit_count-1u);
+    return (value^sign)-sign;
+}
+
+fn read_delta_of_delta(reader:ptr<function,BitReader>)->u64{
+    if read_bits(reader,1u)==u64(0u) {
+        return u64(0u);
+    }
+    if read_bits(reader,1u)==u64(0u) {
+        return sign_extend(read_bits(reader,7u),7u);
+    }
+    if read_bits(reader,1u)==u64(0u) {
+        return sign_extend(read_bits(reader,9u),9u);
+    }
+    if read_bits(reader,1u)==u64(0u) {
+        return sign_extend(read_bits(reader,12u),12u);
+    }
+    return read_bits(reader,64u);
+}
+
+fn write(block:u32){
+    let prefix_index=block_offsets[block];
+    let count=u32(in[prefix_index]>>32u)+1u;
+    var reader=BitReader(prefix_index+2u,0u);
+    var value=in[prefix_index+1u];
+    var delta=u64(0u);
+    out[block*size]=value;
+    for (var i=1u;i<count;i++){
+        delta+=read_delta_of_delta(&reader);
+        value+=delta;
+        out[block*size+i]=value;
+    }
+}
+
+@compute
+@workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if(global_id.x>=total_threads){return;}
+
+    write(global_id.x);
+}
@@ -0,0 +1,52 @@
+@group(0)
+@binding(0)
+var<storage, read_write> out: array<Output64>; // the code of every value
+
+@group(0)
+@binding(1)
+var<storage, read_write> in: array<u64>; // the timestamps, as two's complement words
+
+@group(0)
+@binding(2)
+var<uniform> chunks: u32; // how many iterations per buffer
+
+//#include(64_utils)
+
+override workgroup_offset: u32;
+
+// The code of the value at index, which is the value at position of its buffer. The first value
+// of a buffer is stored as is and the first delta of a buffer is taken against 0
+fn encode(index: u32, position: u32) -> Output64 {
+    if position == 0u {
+        return Output64(u64(0u), in[index], u64(64u));
+    }
+    var previous_delta = u64(0u);
+    if position > 1u {
+        previous_delta = in[index - 1u] - in[index - 2u];
+    }
+    // wrapping arithmetic, the range checks below read the result as two's complement
+    let dod = in[index] - in[index - 1u] - previous_delta;
+    if dod == u64(0u) {
+        return Output64(u64(0u), u64(0u), u64(1u));
+    }
+    if dod + u64(64u) < u64(128u) {
+        return Output64(u64(0u), (u64(2u) << 7u) | (dod & u64(0x7fu)), u64(9u));
+    }
+    if dod + u64(256u) < u64(512u) {
+        return Output64(u64(0u), (u64(6u) << 9u) | (dod & u64(0x1ffu)), u64(12u));
+    }
+    if dod + u64(2048u) < u64(4096u) {
+        return Output64(u64(0u), (u64(14u) << 12u) | (dod & u64(0xfffu)), u64(16u));
+    }
+    return Output64(u64(15u), dod, u64(68u));
+}
+
+@compute
+@workgroup_size(256)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>,@builtin(local_invocation_id) invocation_id: vec3<u32>) {
+    for (var i=0u;i<chunks;i++){
+        let position:u32=invocation_id.x+i*256u;
+        let index:u32=(workgroup_offset+workgroup_id.x) * 256 * chunks + position;
+        out[index] = encode(index, position);
+    }
+}